@@ -605,6 +605,9 @@ pub enum FormatPredicateKind {
 
     /// Is the referenced data object colocated?
     IsColocatedData,
+
+    /// Is the offset of the referenced data object's symbol zero?
+    IsZeroSymbolOffset,
 }
 
 #[derive(Clone, Hash, PartialEq, Eq)]
@@ -680,6 +683,12 @@ impl FormatPredicateNode {
             FormatPredicateKind::IsColocatedData => {
                 format!("predicates::is_colocated_data({}, func)", self.member_name)
             }
+            FormatPredicateKind::IsZeroSymbolOffset => {
+                format!(
+                    "predicates::is_zero_symbol_offset({}, func)",
+                    self.member_name
+                )
+            }
         }
     }
 }
@@ -930,6 +939,15 @@ impl InstructionPredicate {
         ))
     }
 
+    pub fn new_is_zero_symbol_offset(format_registry: &FormatRegistry) -> InstructionPredicateNode {
+        let format = format_registry.get(format_registry.by_name("UnaryGlobalValue"));
+        InstructionPredicateNode::FormatPredicate(FormatPredicateNode::new(
+            format,
+            "global_value",
+            FormatPredicateKind::IsZeroSymbolOffset,
+        ))
+    }
+
     pub fn and(mut self, new_node: InstructionPredicateNode) -> Self {
         let node = self.node;
         let mut and_nodes = match node {
@@ -10,6 +10,7 @@ use crate::cdsl::types::{BVType, LaneType, SpecialType, ValueType};
 
 const MAX_LANES: u16 = 256;
 const MAX_BITS: u16 = 64;
+const MAX_INT_BITS: u16 = 128;
 const MAX_BITVEC: u16 = MAX_BITS * MAX_LANES;
 
 /// Type variables can be used in place of concrete types when defining
@@ -169,7 +170,7 @@ impl TypeVar {
             }
             DerivedFunc::DoubleWidth => {
                 assert!(
-                    ts.ints.len() == 0 || *ts.ints.iter().max().unwrap() < MAX_BITS,
+                    ts.ints.len() == 0 || *ts.ints.iter().max().unwrap() < MAX_INT_BITS,
                     "can't double all integer types"
                 );
                 assert!(
@@ -487,7 +488,12 @@ impl TypeSet {
     /// Return a TypeSet describing the image of self across doublewidth.
     fn double_width(&self) -> TypeSet {
         let mut copy = self.clone();
-        copy.ints = NumSet::from_iter(self.ints.iter().filter(|&&x| x < MAX_BITS).map(|&x| x * 2));
+        copy.ints = NumSet::from_iter(
+            self.ints
+                .iter()
+                .filter(|&&x| x < MAX_INT_BITS)
+                .map(|&x| x * 2),
+        );
         copy.floats = NumSet::from_iter(
             self.floats
                 .iter()
@@ -605,7 +611,7 @@ impl TypeSet {
                 let mut copy = self.clone();
                 copy.bitvecs = NumSet::new();
                 if self.bools.contains(&1) {
-                    copy.ints = NumSet::from_iter(vec![8, 16, 32, 64]);
+                    copy.ints = NumSet::from_iter(vec![8, 16, 32, 64, 128]);
                     copy.floats = NumSet::from_iter(vec![32, 64]);
                 } else {
                     copy.ints = &self.bools - &NumSet::from_iter(vec![1]);
@@ -622,12 +628,12 @@ impl TypeSet {
             DerivedFunc::DoubleVector => self.half_vector(),
             DerivedFunc::ToBitVec => {
                 let all_lanes = range_to_set(Some(1..MAX_LANES));
-                let all_ints = range_to_set(Some(8..MAX_BITS));
+                let all_ints = range_to_set(Some(8..MAX_INT_BITS));
                 let all_floats = range_to_set(Some(32..64));
                 let all_bools = range_to_set(Some(1..MAX_BITS));
 
                 let mut lanes = range_to_set(Some(1..MAX_LANES));
-                let mut ints = range_to_set(Some(8..MAX_BITS));
+                let mut ints = range_to_set(Some(8..MAX_INT_BITS));
                 let mut floats = range_to_set(Some(32..64));
                 let mut bools = range_to_set(Some(1..MAX_BITS));
 
@@ -824,7 +830,7 @@ impl TypeSetBuilder {
 
         TypeSet::new(
             range_to_set(self.simd_lanes.to_range(min_lanes..MAX_LANES, Some(1))),
-            range_to_set(self.ints.to_range(8..MAX_BITS, None)),
+            range_to_set(self.ints.to_range(8..MAX_INT_BITS, None)),
             range_to_set(self.floats.to_range(32..64, None)),
             bools,
             range_to_set(self.bitvecs.to_range(1..MAX_BITVEC, None)),
@@ -914,7 +920,7 @@ fn test_typevar_builder() {
     let type_set = TypeSetBuilder::new().ints(Interval::All).build();
     assert_eq!(type_set.lanes, num_set![1]);
     assert!(type_set.floats.is_empty());
-    assert_eq!(type_set.ints, num_set![8, 16, 32, 64]);
+    assert_eq!(type_set.ints, num_set![8, 16, 32, 64, 128]);
     assert!(type_set.bools.is_empty());
     assert!(type_set.bitvecs.is_empty());
     assert!(type_set.specials.is_empty());
@@ -971,7 +977,7 @@ fn test_typevar_builder() {
 #[test]
 #[should_panic]
 fn test_typevar_builder_too_high_bound_panic() {
-    TypeSetBuilder::new().ints(16..2 * MAX_BITS).build();
+    TypeSetBuilder::new().ints(16..2 * MAX_INT_BITS).build();
 }
 
 #[test]
@@ -1065,7 +1071,11 @@ fn test_forward_images() {
     );
     assert_eq!(
         TypeSetBuilder::new().ints(32..64).build().double_width(),
-        TypeSetBuilder::new().ints(64..64).build()
+        TypeSetBuilder::new().ints(64..128).build()
+    );
+    assert_eq!(
+        TypeSetBuilder::new().ints(64..128).build().double_width(),
+        TypeSetBuilder::new().ints(128..128).build()
     );
     assert_eq!(
         TypeSetBuilder::new().floats(32..32).build().double_width(),
@@ -1169,7 +1179,7 @@ fn test_backward_images() {
     // Half width.
     assert_eq!(
         TypeSetBuilder::new()
-            .ints(64..64)
+            .ints(128..128)
             .floats(64..64)
             .bools(64..64)
             .build()
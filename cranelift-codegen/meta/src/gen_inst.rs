@@ -69,6 +69,9 @@ fn gen_formats(registry: &FormatRegistry, fmt: &mut Formatter) {
 /// `ValueList` to store the additional information out of line.
 fn gen_instruction_data(registry: &FormatRegistry, fmt: &mut Formatter) {
     fmt.line("#[derive(Clone, Debug)]");
+    fmt.line(
+        "#[cfg_attr(feature = \"enable-serde\", derive(serde::Serialize, serde::Deserialize))]",
+    );
     fmt.line("#[allow(missing_docs)]");
     fmt.line("pub enum InstructionData {");
     fmt.indent(|fmt| {
@@ -404,6 +407,9 @@ fn gen_opcodes<'a>(all_inst: &AllInstructions, formats: &FormatRegistry, fmt: &m
     "#,
     );
     fmt.line("#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]");
+    fmt.line(
+        "#[cfg_attr(feature = \"enable-serde\", derive(serde::Serialize, serde::Deserialize))]",
+    );
 
     // We explicitly set the discriminant of the first variant to 1, which allows us to take
     // advantage of the NonZero optimization, meaning that wrapping enums can use the 0
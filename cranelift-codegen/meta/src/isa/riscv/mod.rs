@@ -5,7 +5,7 @@ use crate::cdsl::regs::{IsaRegs, IsaRegsBuilder, RegBankBuilder, RegClassBuilder
 use crate::cdsl::settings::{PredicateNode, SettingGroup, SettingGroupBuilder};
 
 use crate::shared::types::Float::{F32, F64};
-use crate::shared::types::Int::{I32, I64};
+use crate::shared::types::Int::{I128, I32, I64};
 use crate::shared::Definitions as SharedDefinitions;
 
 mod encodings;
@@ -103,11 +103,13 @@ pub fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
 
     let expand = shared_defs.transform_groups.by_name("expand");
     let narrow = shared_defs.transform_groups.by_name("narrow");
+    let narrow_i128 = shared_defs.transform_groups.by_name("narrow_i128");
     rv_32.legalize_monomorphic(expand);
     rv_32.legalize_default(narrow);
     rv_32.legalize_type(I32, expand);
     rv_32.legalize_type(F32, expand);
     rv_32.legalize_type(F64, expand);
+    rv_32.legalize_type(I128, narrow_i128);
 
     rv_64.legalize_monomorphic(expand);
     rv_64.legalize_default(narrow);
@@ -115,6 +117,7 @@ pub fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
     rv_64.legalize_type(I64, expand);
     rv_64.legalize_type(F32, expand);
     rv_64.legalize_type(F64, expand);
+    rv_64.legalize_type(I128, narrow_i128);
 
     let recipes = recipes::define(shared_defs, &regs);
 
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::cdsl::ast::Literal;
 use crate::cdsl::encodings::{Encoding, EncodingBuilder};
 use crate::cdsl::instructions::{
     BoundInstruction, InstSpec, Instruction, InstructionGroup, InstructionPredicate,
@@ -9,7 +10,7 @@ use crate::cdsl::instructions::{
 };
 use crate::cdsl::recipes::{EncodingRecipe, EncodingRecipeNumber, Recipes};
 use crate::cdsl::settings::{SettingGroup, SettingPredicateNumber};
-use crate::cdsl::types::ValueType;
+use crate::cdsl::types::{LaneType, ValueType};
 use crate::shared::types::Bool::{B1, B16, B32, B64, B8};
 use crate::shared::types::Float::{F32, F64};
 use crate::shared::types::Int::{I16, I32, I64, I8};
@@ -278,6 +279,10 @@ pub fn define(
     let adjust_sp_down = shared.by_name("adjust_sp_down");
     let adjust_sp_down_imm = shared.by_name("adjust_sp_down_imm");
     let adjust_sp_up_imm = shared.by_name("adjust_sp_up_imm");
+    let atomic_cas = shared.by_name("atomic_cas");
+    let atomic_load = shared.by_name("atomic_load");
+    let atomic_rmw = shared.by_name("atomic_rmw");
+    let atomic_store = shared.by_name("atomic_store");
     let band = shared.by_name("band");
     let band_imm = shared.by_name("band_imm");
     let band_not = shared.by_name("band_not");
@@ -309,6 +314,7 @@ pub fn define(
     let fcvt_from_sint = shared.by_name("fcvt_from_sint");
     let fdemote = shared.by_name("fdemote");
     let fdiv = shared.by_name("fdiv");
+    let fence = shared.by_name("fence");
     let ffcmp = shared.by_name("ffcmp");
     let fill = shared.by_name("fill");
     let floor = shared.by_name("floor");
@@ -388,8 +394,11 @@ pub fn define(
     let x86_bsf = x86.by_name("x86_bsf");
     let x86_bsr = x86.by_name("x86_bsr");
     let x86_cvtt2si = x86.by_name("x86_cvtt2si");
+    let x86_elf_tls_get_addr = x86.by_name("x86_elf_tls_get_addr");
+    let x86_elf_tls_ie_addr = x86.by_name("x86_elf_tls_ie_addr");
     let x86_fmax = x86.by_name("x86_fmax");
     let x86_fmin = x86.by_name("x86_fmin");
+    let x86_macho_tls_get_addr = x86.by_name("x86_macho_tls_get_addr");
     let x86_pop = x86.by_name("x86_pop");
     let x86_pshufd = x86.by_name("x86_pshufd");
     let x86_pshufb = x86.by_name("x86_pshufb");
@@ -409,6 +418,9 @@ pub fn define(
     let rec_brfd = r.template("brfd");
     let rec_brib = r.template("brib");
     let rec_brid = r.template("brid");
+    let rec_atomic_ld = r.template("atomic_ld");
+    let rec_atomic_st = r.template("atomic_st");
+    let rec_atomic_st_mfence = r.template("atomic_st_mfence");
     let rec_bsf_and_bsr = r.template("bsf_and_bsr");
     let rec_call_id = r.template("call_id");
     let rec_call_plt_id = r.template("call_plt_id");
@@ -417,6 +429,9 @@ pub fn define(
     let rec_copysp = r.template("copysp");
     let rec_div = r.template("div");
     let rec_debugtrap = r.recipe("debugtrap");
+    let rec_elf_tls_get_addr = r.recipe("elf_tls_get_addr");
+    let rec_elf_tls_ie = r.recipe("elf_tls_ie");
+    let rec_fence_nop = r.recipe("fence_nop");
     let rec_f32imm_z = r.template("f32imm_z");
     let rec_f64imm_z = r.template("f64imm_z");
     let rec_fa = r.template("fa");
@@ -464,7 +479,11 @@ pub fn define(
     let rec_ldWithIndex = r.template("ldWithIndex");
     let rec_ldWithIndexDisp32 = r.template("ldWithIndexDisp32");
     let rec_ldWithIndexDisp8 = r.template("ldWithIndexDisp8");
+    let rec_lock_cmpxchg = r.template("lock_cmpxchg");
+    let rec_lock_rmw = r.template("lock_rmw");
     let rec_mulx = r.template("mulx");
+    let rec_macho_tls_get_addr = r.recipe("macho_tls_get_addr");
+    let rec_mfence = r.template("mfence");
     let rec_null = r.recipe("null");
     let rec_null_fpr = r.recipe("null_fpr");
     let rec_pcrel_fnaddr8 = r.template("pcrel_fnaddr8");
@@ -829,6 +848,46 @@ pub fn define(
         e.enc_i32_i64_ld_st(istore16, false, recipe.opcodes(vec![0x66, 0x89]));
     }
 
+    // Atomic memory accesses. Only 32-bit and 64-bit accesses are supported. The recipes have
+    // predicates on the ordering, and the unsupported `atomic_rmw` operations are legalized into
+    // compare-and-swap loops.
+    e.enc_i32_i64_ld_st(atomic_load, true, rec_atomic_ld.opcodes(vec![0x8b]));
+    e.enc_i32_i64_ld_st(atomic_store, true, rec_atomic_st.opcodes(vec![0x89]));
+    e.enc_i32_i64_ld_st(atomic_store, true, rec_atomic_st_mfence.opcodes(vec![0x89]));
+    e.enc_i32_i64_ld_st(atomic_cas, true, rec_lock_cmpxchg.opcodes(vec![0x0f, 0xb1]));
+
+    let atomic_rmw_op = shared_defs.operand_kinds.by_name("atomic_rmw_op");
+    let f_atomic_rmw = formats.get(formats.by_name("AtomicRmw"));
+    for &(op, opcodes) in &[("add", &[0x0f, 0xc1][..]), ("xchg", &[0x87][..])] {
+        let is_op = InstructionPredicate::new_is_field_equal(
+            f_atomic_rmw,
+            "op",
+            Literal::enumerator_for(atomic_rmw_op, op).to_rust_code(),
+        );
+        let template = rec_lock_rmw.opcodes(opcodes.to_vec());
+        e.enc32_instp(
+            atomic_rmw.bind(I32).bind_any(),
+            template.nonrex(),
+            is_op.clone(),
+        );
+        e.enc64_instp(
+            atomic_rmw.bind(I32).bind_any(),
+            template.rex(),
+            is_op.clone(),
+        );
+        e.enc64_instp(
+            atomic_rmw.bind(I32).bind_any(),
+            template.nonrex(),
+            is_op.clone(),
+        );
+        e.enc64_instp(atomic_rmw.bind(I64).bind_any(), template.rex().w(), is_op);
+    }
+
+    e.enc32(fence, rec_mfence.opcodes(vec![0x0f, 0xae]));
+    e.enc64(fence, rec_mfence.opcodes(vec![0x0f, 0xae]));
+    e.enc32_rec(fence, rec_fence_nop, 0);
+    e.enc64_rec(fence, rec_fence_nop, 0);
+
     // Byte stores are more complicated because the registers they can address
     // depends of the presence of a REX prefix. The st*_abcd recipes fall back to
     // the corresponding st* recipes when a REX prefix is applied.
@@ -1142,13 +1201,23 @@ pub fn define(
         },
     );
 
-    // PIC, non-colocated.
-    e.enc64_isap(
+    // PIC, non-colocated. The GOT entry holds the address of the symbol itself, so symbols with
+    // an offset have no encoding. The legalizer adds their offset separately.
+    e.enc64_func(
         symbol_value.bind(I64),
         rec_got_gvaddr8.opcodes(vec![0x8b]).rex().w(),
-        is_pic,
+        |encoding| {
+            encoding
+                .isa_predicate(is_pic)
+                .inst_predicate(InstructionPredicate::new_is_zero_symbol_offset(formats))
+        },
     );
 
+    // Thread local storage.
+    e.enc64_rec(x86_elf_tls_get_addr, rec_elf_tls_get_addr, 0);
+    e.enc64_rec(x86_elf_tls_ie_addr, rec_elf_tls_ie, 0);
+    e.enc64_rec(x86_macho_tls_get_addr, rec_macho_tls_get_addr, 0);
+
     // Stack addresses.
     //
     // TODO: Add encoding rules for stack_load and stack_store, so that they
@@ -1614,10 +1683,13 @@ pub fn define(
         e.enc64_isap(instruction, template, use_sse2);
     }
 
+    // SIMD vectors are 128 bits wide, so they can only hold lanes of up to 64 bits.
+    let allowed_simd_type = |t: &LaneType| t.lane_bits() >= 8 && t.lane_bits() < 128;
+
     // SIMD scalar_to_vector; this uses MOV to copy the scalar value to an XMM register; according
     // to the Intel manual: "When the destination operand is an XMM register, the source operand is
     // written to the low doubleword of the register and the regiser is zero-extended to 128 bits."
-    for ty in ValueType::all_lane_types().filter(|t| t.lane_bits() >= 8) {
+    for ty in ValueType::all_lane_types().filter(allowed_simd_type) {
         let number_of_lanes = 128 / ty.lane_bits();
         let instruction = scalar_to_vector.bind_vector(ty, number_of_lanes).bind(ty);
         let template = rec_frurm.opcodes(vec![0x66, 0x0f, 0x6e]); // MOVD/MOVQ
@@ -1657,8 +1729,9 @@ pub fn define(
     }
 
     // SIMD bitcast all 128-bit vectors to each other (for legalizing splat.x16x8)
-    for from_type in ValueType::all_lane_types().filter(|t| t.lane_bits() >= 8) {
-        for to_type in ValueType::all_lane_types().filter(|t| t.lane_bits() >= 8 && *t != from_type)
+    for from_type in ValueType::all_lane_types().filter(allowed_simd_type) {
+        for to_type in
+            ValueType::all_lane_types().filter(|t| allowed_simd_type(t) && *t != from_type)
         {
             let instruction = raw_bitcast
                 .bind_vector(to_type, 128 / to_type.lane_bits())
@@ -5,9 +5,9 @@ use crate::cdsl::instructions::{
     AllInstructions, InstructionBuilder as Inst, InstructionGroup, InstructionGroupBuilder,
 };
 use crate::cdsl::operands::{create_operand as operand, create_operand_doc as operand_doc};
-use crate::cdsl::types::ValueType;
+use crate::cdsl::types::{LaneType, ValueType};
 use crate::cdsl::typevar::{Interval, TypeSetBuilder, TypeVar};
-use crate::shared::{immediates, types, OperandKinds};
+use crate::shared::{entities, immediates, types, OperandKinds};
 
 pub fn define(
     mut all_instructions: &mut AllInstructions,
@@ -290,5 +290,69 @@ pub fn define(
         .operands_out(vec![a]),
     );
 
+    let entities = OperandKinds::from(entities::define());
+    let global_value = entities.by_name("global_value");
+    let i64_t: &TypeVar = &ValueType::from(LaneType::from(types::Int::I64)).into();
+    let GV = &operand("GV", global_value);
+    let addr = &operand("addr", i64_t);
+
+    ig.push(
+        Inst::new(
+            "x86_elf_tls_get_addr",
+            r#"
+    Elf tls get addr -- This implements the GD TLS model for ELF. The llvm asm is:
+
+    ```text
+    data16
+    leaq    gv@tlsgd(%rip), %rdi
+    data16
+    data16
+    rex64
+    callq   __tls_get_addr@PLT
+    ```
+
+    This clobbers all the registers a call to `__tls_get_addr` may clobber.
+    "#,
+        )
+        .operands_in(vec![GV])
+        .operands_out(vec![addr])
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "x86_elf_tls_ie_addr",
+            r#"
+    Elf tls ie addr -- This implements the IE TLS model for ELF. The llvm asm is:
+
+    ```text
+    movq    %fs:0, %reg
+    addq    gv@gottpoff(%rip), %reg
+    ```
+    "#,
+        )
+        .operands_in(vec![GV])
+        .operands_out(vec![addr]),
+    );
+
+    ig.push(
+        Inst::new(
+            "x86_macho_tls_get_addr",
+            r#"
+    Mach-O tls get addr -- This implements TLS access for Mach-O. The llvm asm is:
+
+    ```text
+    movq    gv@tlv(%rip), %rdi
+    callq   *(%rdi)
+    ```
+
+    This clobbers all the registers a call through the thread variable descriptor may clobber.
+    "#,
+        )
+        .operands_in(vec![GV])
+        .operands_out(vec![addr])
+        .other_side_effects(true),
+    );
+
     ig.build()
 }
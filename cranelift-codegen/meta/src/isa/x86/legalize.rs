@@ -46,6 +46,7 @@ pub fn define(shared: &mut SharedDefinitions, x86_instructions: &InstructionGrou
     let smulhi = insts.by_name("smulhi");
     let splat = insts.by_name("splat");
     let srem = insts.by_name("srem");
+    let tls_value = insts.by_name("tls_value");
     let udiv = insts.by_name("udiv");
     let umulhi = insts.by_name("umulhi");
     let ushr_imm = insts.by_name("ushr_imm");
@@ -155,6 +156,9 @@ pub fn define(shared: &mut SharedDefinitions, x86_instructions: &InstructionGrou
     group.custom_legalize(fcvt_to_sint_sat, "expand_fcvt_to_sint_sat");
     group.custom_legalize(fcvt_to_uint_sat, "expand_fcvt_to_uint_sat");
 
+    // The TLS access sequence depends on the `tls_model` setting.
+    group.custom_legalize(tls_value, "expand_tls_value");
+
     // Count leading and trailing zeroes, for baseline x86_64
     let c_minus_one = var("c_minus_one");
     let c_thirty_one = var("c_thirty_one");
@@ -3,7 +3,7 @@ use crate::cdsl::isa::TargetIsa;
 
 use crate::shared::types::Bool::B1;
 use crate::shared::types::Float::{F32, F64};
-use crate::shared::types::Int::{I16, I32, I64, I8};
+use crate::shared::types::Int::{I128, I16, I32, I64, I8};
 use crate::shared::Definitions as SharedDefinitions;
 
 mod encodings;
@@ -29,6 +29,7 @@ pub fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
 
     let expand_flags = shared_defs.transform_groups.by_name("expand_flags");
     let narrow = shared_defs.transform_groups.by_name("narrow");
+    let narrow_i128 = shared_defs.transform_groups.by_name("narrow_i128");
     let widen = shared_defs.transform_groups.by_name("widen");
     let x86_narrow = shared_defs.transform_groups.by_name("x86_narrow");
     let x86_expand = shared_defs.transform_groups.by_name("x86_expand");
@@ -41,6 +42,7 @@ pub fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
     x86_32.legalize_type(I32, x86_expand);
     x86_32.legalize_type(F32, x86_expand);
     x86_32.legalize_type(F64, x86_expand);
+    x86_32.legalize_type(I128, narrow_i128);
 
     x86_64.legalize_monomorphic(expand_flags);
     x86_64.legalize_default(x86_narrow);
@@ -51,6 +53,7 @@ pub fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
     x86_64.legalize_type(I64, x86_expand);
     x86_64.legalize_type(F32, x86_expand);
     x86_64.legalize_type(F64, x86_expand);
+    x86_64.legalize_type(I128, narrow_i128);
 
     let recipes = recipes::define(shared_defs, &settings, &regs);
 
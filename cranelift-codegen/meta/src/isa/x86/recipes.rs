@@ -357,6 +357,10 @@ pub fn define<'shared>(
     let stack_fpr32 = Stack::new(fpr);
 
     // Format shorthands, prefixed with f_.
+    let f_atomic_cas = formats.by_name("AtomicCas");
+    let f_atomic_load = formats.by_name("AtomicLoad");
+    let f_atomic_rmw = formats.by_name("AtomicRmw");
+    let f_atomic_store = formats.by_name("AtomicStore");
     let f_binary = formats.by_name("Binary");
     let f_binary_imm = formats.by_name("BinaryImm");
     let f_branch = formats.by_name("Branch");
@@ -368,6 +372,7 @@ pub fn define<'shared>(
     let f_call_indirect = formats.by_name("CallIndirect");
     let f_copy_special = formats.by_name("CopySpecial");
     let f_extract_lane = formats.by_name("ExtractLane"); // TODO this would preferably retrieve a BinaryImm8 format but because formats are compared structurally and ExtractLane has the same structure this is impossible--if we rename ExtractLane, it may even impact parsing
+    let f_fence = formats.by_name("Fence");
     let f_float_compare = formats.by_name("FloatCompare");
     let f_float_cond = formats.by_name("FloatCond");
     let f_float_cond_trap = formats.by_name("FloatCondTrap");
@@ -1115,9 +1120,10 @@ pub fn define<'shared>(
             .emit(
                 r#"
                     {{PUT_OP}}(bits | (out_reg0 & 7), rex1(out_reg0), sink);
+                    let offset: i64 = func.global_values[global_value].symbol_offset().into();
                     sink.reloc_external(Reloc::Abs4,
                                         &func.global_values[global_value].symbol_name(),
-                                        0);
+                                        offset);
                     sink.put4(0);
                 "#,
             ),
@@ -1130,9 +1136,10 @@ pub fn define<'shared>(
             .emit(
                 r#"
                     {{PUT_OP}}(bits | (out_reg0 & 7), rex1(out_reg0), sink);
+                    let offset: i64 = func.global_values[global_value].symbol_offset().into();
                     sink.reloc_external(Reloc::Abs8,
                                         &func.global_values[global_value].symbol_name(),
-                                        0);
+                                        offset);
                     sink.put8(0);
                 "#,
             ),
@@ -1148,15 +1155,19 @@ pub fn define<'shared>(
                     modrm_rm(5, out_reg0, sink);
                     // The addend adjusts for the difference between the end of the
                     // instruction and the beginning of the immediate field.
+                    let offset: i64 = func.global_values[global_value].symbol_offset().into();
                     sink.reloc_external(Reloc::X86PCRel4,
                                         &func.global_values[global_value].symbol_name(),
-                                        -4);
+                                        offset - 4);
                     sink.put4(0);
                 "#,
             ),
     );
 
-    // XX+rd iq with Abs8 globalsym relocation.
+    // XX+rd iq with GOTPCRel4 globalsym relocation.
+    //
+    // The GOT entry holds the address of the symbol itself, so this is only used for symbols
+    // without an offset.
     recipes.add_template_recipe(
         EncodingRecipeBuilder::new("got_gvaddr8", f_unary_global_value, 5)
             .operands_out(vec![gpr])
@@ -2851,5 +2862,271 @@ pub fn define<'shared>(
             ),
     );
 
+    {
+        // Atomic memory accesses. They only support a register address without offset.
+        let ordering = shared_defs.operand_kinds.by_name("ordering");
+        let has_ordering = |format, names: &[&'static str]| {
+            names
+                .iter()
+                .fold(InstructionPredicate::new(), |pred, &name| {
+                    pred.or(InstructionPredicate::new_is_field_equal(
+                        format,
+                        "ordering",
+                        Literal::enumerator_for(ordering, name).to_rust_code(),
+                    ))
+                })
+        };
+
+        // XX /r atomic load. Plain loads are atomic and have acquire semantics on x86.
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("atomic_ld", f_atomic_load, 1)
+                .operands_in(vec![gpr])
+                .operands_out(vec![gpr])
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_sib_or_offset_for_in_reg_0")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        {{PUT_OP}}(bits, rex2(in_reg0, out_reg0), sink);
+                        if needs_sib_byte(in_reg0) {
+                            modrm_sib(out_reg0, sink);
+                            sib_noindex(in_reg0, sink);
+                        } else if needs_offset(in_reg0) {
+                            modrm_disp8(in_reg0, out_reg0, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg0, out_reg0, sink);
+                        }
+                    "#,
+                ),
+        );
+
+        // XX /r atomic store. Plain stores are atomic and have release semantics on x86.
+        let format = formats.get(f_atomic_store);
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("atomic_st", f_atomic_store, 1)
+                .operands_in(vec![gpr, gpr])
+                .inst_predicate(has_ordering(format, &["relaxed", "release"]))
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_sib_or_offset_for_in_reg_1")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        if needs_sib_byte(in_reg1) {
+                            modrm_sib(in_reg0, sink);
+                            sib_noindex(in_reg1, sink);
+                        } else if needs_offset(in_reg1) {
+                            modrm_disp8(in_reg1, in_reg0, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg1, in_reg0, sink);
+                        }
+                    "#,
+                ),
+        );
+
+        // XX /r atomic store followed by mfence. A sequentially consistent store must not be
+        // reordered with later loads, which x86 allows for plain stores.
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("atomic_st_mfence", f_atomic_store, 4)
+                .operands_in(vec![gpr, gpr])
+                .inst_predicate(has_ordering(format, &["seq_cst"]))
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_sib_or_offset_for_in_reg_1")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        if needs_sib_byte(in_reg1) {
+                            modrm_sib(in_reg0, sink);
+                            sib_noindex(in_reg1, sink);
+                        } else if needs_offset(in_reg1) {
+                            modrm_disp8(in_reg1, in_reg0, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg1, in_reg0, sink);
+                        }
+                        // mfence
+                        sink.put1(0x0f);
+                        sink.put1(0xae);
+                        sink.put1(0xf0);
+                    "#,
+                ),
+        );
+
+        // lock XX /r read-modify-write, returning the old value in the register operand.
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("lock_rmw", f_atomic_rmw, 2)
+                .operands_in(vec![gpr, gpr])
+                .operands_out(vec![1])
+                .compute_size("size_plus_maybe_sib_or_offset_for_in_reg_0")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0); // lock
+                        {{PUT_OP}}(bits, rex2(in_reg0, in_reg1), sink);
+                        if needs_sib_byte(in_reg0) {
+                            modrm_sib(in_reg1, sink);
+                            sib_noindex(in_reg0, sink);
+                        } else if needs_offset(in_reg0) {
+                            modrm_disp8(in_reg0, in_reg1, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg0, in_reg1, sink);
+                        }
+                    "#,
+                ),
+        );
+
+        // lock cmpxchg with the expected and returned values in %rax.
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("lock_cmpxchg", f_atomic_cas, 2)
+                .operands_in(vec![
+                    OperandConstraint::RegClass(gpr),
+                    OperandConstraint::FixedReg(reg_rax),
+                    OperandConstraint::RegClass(gpr),
+                ])
+                .operands_out(vec![reg_rax])
+                .compute_size("size_plus_maybe_sib_or_offset_for_in_reg_0")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0); // lock
+                        {{PUT_OP}}(bits, rex2(in_reg0, in_reg2), sink);
+                        if needs_sib_byte(in_reg0) {
+                            modrm_sib(in_reg2, sink);
+                            sib_noindex(in_reg0, sink);
+                        } else if needs_offset(in_reg0) {
+                            modrm_disp8(in_reg0, in_reg2, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg0, in_reg2, sink);
+                        }
+                    "#,
+                ),
+        );
+
+        // mfence for sequentially consistent fences.
+        let format = formats.get(f_fence);
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("mfence", f_fence, 1)
+                .inst_predicate(has_ordering(format, &["seq_cst"]))
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        {{PUT_OP}}(bits, BASE_REX, sink);
+                        sink.put1(0xf0);
+                    "#,
+                ),
+        );
+
+        // The other fences only prevent compiler reordering, since x86 doesn't reorder loads
+        // with loads or stores with stores.
+        recipes.add_recipe(
+            EncodingRecipeBuilder::new("fence_nop", f_fence, 0)
+                .inst_predicate(has_ordering(format, &["acquire", "release", "acq_rel"]))
+                .clobbers_flags(false)
+                .emit(""),
+        );
+    }
+
+    recipes.add_recipe(
+        EncodingRecipeBuilder::new("elf_tls_get_addr", f_unary_global_value, 16)
+            // Only the return value is modelled here. The regalloc treats this instruction like a
+            // call, so the registers clobbered by `__tls_get_addr` don't hold any live values.
+            .operands_out(vec![reg_rax])
+            .emit(
+                r#"
+                    // output %rax
+                    // clobbers %rdi
+
+                    // Those data16 prefixes are necessary to pad to 16 bytes.
+
+                    // data16 lea gv@tlsgd(%rip),%rdi
+                    sink.put1(0x66); // data16
+                    sink.put1(0b01001000); // rex.w
+                    const LEA: u8 = 0x8d;
+                    sink.put1(LEA); // lea
+                    modrm_riprel(0b111/*out_reg0*/, sink); // 0x3d
+                    sink.reloc_external(Reloc::ElfX86_64TlsGd,
+                                        &func.global_values[global_value].symbol_name(),
+                                        -4);
+                    sink.put4(0);
+
+                    // data16 data16 callq __tls_get_addr-4
+                    sink.put1(0x66); // data16
+                    sink.put1(0x66); // data16
+                    sink.put1(0b01001000); // rex.w
+                    sink.put1(0xe8); // call
+                    sink.reloc_external(Reloc::X86CallPLTRel4,
+                                        &ExternalName::LibCall(LibCall::ElfTlsGetAddr),
+                                        -4);
+                    sink.put4(0);
+                "#,
+            ),
+    );
+
+    recipes.add_recipe(
+        EncodingRecipeBuilder::new("elf_tls_ie", f_unary_global_value, 16)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    // mov %fs:0,%out_reg0
+                    sink.put1(0x64); // fs:
+                    sink.put1(rex2(0, out_reg0) | 0x08); // rex.w
+                    sink.put1(0x8b); // mov
+                    modrm_sib(out_reg0, sink);
+                    // A SIB base of 101 with mod 00 means a 32-bit displacement without base.
+                    sib_noindex(0b101, sink);
+                    sink.put4(0);
+
+                    // add gv@gottpoff(%rip),%out_reg0
+                    sink.put1(rex2(0, out_reg0) | 0x08); // rex.w
+                    sink.put1(0x03); // add
+                    modrm_riprel(out_reg0, sink);
+                    sink.reloc_external(Reloc::ElfX86_64GotTpOff,
+                                        &func.global_values[global_value].symbol_name(),
+                                        -4);
+                    sink.put4(0);
+                "#,
+            ),
+    );
+
+    recipes.add_recipe(
+        EncodingRecipeBuilder::new("macho_tls_get_addr", f_unary_global_value, 9)
+            // Like `elf_tls_get_addr`, this is treated as a call by the regalloc.
+            .operands_out(vec![reg_rax])
+            .emit(
+                r#"
+                    // output %rax
+                    // clobbers %rdi
+
+                    // movq gv@tlv(%rip), %rdi
+                    sink.put1(0x48); // rex
+                    sink.put1(0x8b); // mov
+                    modrm_riprel(0b111/*out_reg0*/, sink); // 0x3d
+                    sink.reloc_external(Reloc::MachOX86_64Tlv,
+                                        &func.global_values[global_value].symbol_name(),
+                                        -4);
+                    sink.put4(0);
+
+                    // callq *(%rdi)
+                    sink.put1(0xff);
+                    sink.put1(0x17);
+                "#,
+            ),
+    );
+
     recipes
 }
@@ -15,6 +15,8 @@ pub fn define(immediates: &OperandKinds, entities: &OperandKinds) -> FormatRegis
     let offset32 = immediates.by_name("offset32");
     let trapcode = immediates.by_name("trapcode");
     let regunit = immediates.by_name("regunit");
+    let atomic_rmw_op = immediates.by_name("atomic_rmw_op");
+    let ordering = immediates.by_name("ordering");
 
     // Shorthands for entities.
     let global_value = entities.by_name("global_value");
@@ -132,6 +134,41 @@ pub fn define(immediates: &OperandKinds, entities: &OperandKinds) -> FormatRegis
             .varargs()
             .imm(offset32),
     );
+
+    // Atomic memory accesses.
+    registry.insert(
+        Builder::new("AtomicLoad")
+            .imm(memflags)
+            .imm(ordering)
+            .value(),
+    );
+    registry.insert(
+        Builder::new("AtomicStore")
+            .imm(memflags)
+            .imm(ordering)
+            .value()
+            .value(),
+    );
+    registry.insert(
+        Builder::new("AtomicRmw")
+            .imm(memflags)
+            .imm(atomic_rmw_op)
+            .imm(ordering)
+            .value()
+            .value()
+            .typevar_operand(1),
+    );
+    registry.insert(
+        Builder::new("AtomicCas")
+            .imm(memflags)
+            .imm(ordering)
+            .value()
+            .value()
+            .value()
+            .typevar_operand(1),
+    );
+    registry.insert(Builder::new("Fence").imm(ordering));
+
     registry.insert(Builder::new("StackLoad").imm(stack_slot).imm(offset32));
     registry.insert(
         Builder::new("StackStore")
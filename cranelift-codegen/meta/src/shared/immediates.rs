@@ -141,5 +141,39 @@ pub fn define() -> Vec<OperandKind> {
         .build();
     kinds.push(trapcode);
 
+    // The operation performed by an atomic read-modify-write instruction.
+    //
+    // This enumerated operand kind is used for the `atomic_rmw` instruction and corresponds to the
+    // `ir::AtomicRmwOp` Rust type.
+    let mut atomic_rmw_op_values = HashMap::new();
+    atomic_rmw_op_values.insert("add", "Add");
+    atomic_rmw_op_values.insert("sub", "Sub");
+    atomic_rmw_op_values.insert("and", "And");
+    atomic_rmw_op_values.insert("or", "Or");
+    atomic_rmw_op_values.insert("xor", "Xor");
+    atomic_rmw_op_values.insert("xchg", "Xchg");
+    let atomic_rmw_op = Builder::new_enum("atomic_rmw_op", atomic_rmw_op_values)
+        .doc("An atomic read-modify-write operation.")
+        .default_member("op")
+        .rust_type("ir::AtomicRmwOp")
+        .build();
+    kinds.push(atomic_rmw_op);
+
+    // A memory ordering constraint for atomic instructions.
+    //
+    // This corresponds to the `ir::AtomicOrdering` Rust type.
+    let mut ordering_values = HashMap::new();
+    ordering_values.insert("relaxed", "Relaxed");
+    ordering_values.insert("acquire", "Acquire");
+    ordering_values.insert("release", "Release");
+    ordering_values.insert("acq_rel", "AcqRel");
+    ordering_values.insert("seq_cst", "SeqCst");
+    let ordering = Builder::new_enum("ordering", ordering_values)
+        .doc("A memory ordering constraint.")
+        .default_member("ordering")
+        .rust_type("ir::AtomicOrdering")
+        .build();
+    kinds.push(ordering);
+
     return kinds;
 }
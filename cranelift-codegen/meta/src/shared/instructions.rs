@@ -36,6 +36,8 @@ pub fn define(
     let ieee64 = immediates.by_name("ieee64");
     let boolean = immediates.by_name("boolean");
     let regunit = immediates.by_name("regunit");
+    let atomic_rmw_op = immediates.by_name("atomic_rmw_op");
+    let ordering = immediates.by_name("ordering");
 
     let ebb = entities.by_name("ebb");
     let jump_table = entities.by_name("jump_table");
@@ -866,6 +868,111 @@ pub fn define(
         .can_store(true),
     );
 
+    let AtomicMem = &TypeVar::new(
+        "AtomicMem",
+        "An integer type that can be accessed atomically",
+        TypeSetBuilder::new().ints(32..64).build(),
+    );
+
+    let x = &operand_doc("x", AtomicMem, "Value to be stored");
+    let a = &operand_doc("a", AtomicMem, "Value loaded");
+    let p = &operand("p", iAddr);
+    let Ordering = &operand_doc("Ordering", ordering, "Memory ordering constraint");
+    let Op = &operand_doc("Op", atomic_rmw_op, "Operation to perform");
+
+    ig.push(
+        Inst::new(
+            "atomic_load",
+            r#"
+        Atomically load from memory at ``p``.
+
+        The address must be aligned to the size of the loaded type. The
+        ordering can't be ``release`` or ``acq_rel``.
+        "#,
+        )
+        .operands_in(vec![MemFlags, Ordering, p])
+        .operands_out(vec![a])
+        .can_load(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_store",
+            r#"
+        Atomically store ``x`` to memory at ``p``.
+
+        The address must be aligned to the size of the stored type. The
+        ordering can't be ``acquire`` or ``acq_rel``.
+        "#,
+        )
+        .operands_in(vec![MemFlags, Ordering, x, p])
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    let x = &operand_doc("x", AtomicMem, "Operand of the operation");
+    let a = &operand_doc("a", AtomicMem, "Value in memory before the operation");
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw",
+            r#"
+        Atomically read-modify-write memory at ``p``.
+
+        Load the value at ``p``, combine it with ``x`` according to ``Op``,
+        and store the result back to ``p``, all as a single atomic
+        operation. The loaded value is returned.
+
+        The address must be aligned to the size of the accessed type.
+        "#,
+        )
+        .operands_in(vec![MemFlags, Op, Ordering, p, x])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    let e = &operand_doc("e", AtomicMem, "Expected value in memory");
+    let x = &operand_doc("x", AtomicMem, "Value to be stored");
+
+    ig.push(
+        Inst::new(
+            "atomic_cas",
+            r#"
+        Atomically compare and swap the value in memory at ``p``.
+
+        If the value in memory at ``p`` is equal to ``e``, store ``x`` to
+        ``p``, all as a single atomic operation. The value in memory before
+        the operation is returned, so the store happened if and only if
+        ``a == e``.
+
+        The address must be aligned to the size of the accessed type. The
+        ordering applies whether or not the store happens.
+        "#,
+        )
+        .operands_in(vec![MemFlags, Ordering, p, e, x])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "fence",
+            r#"
+        A memory fence.
+
+        Memory accesses can't be reordered across the fence as described by
+        ``Ordering``, which can't be ``relaxed``.
+        "#,
+        )
+        .operands_in(vec![Ordering])
+        .other_side_effects(true),
+    );
+
     let x = &operand_doc("x", Mem, "Value to be stored");
     let a = &operand_doc("a", Mem, "Value loaded");
     let Offset = &operand_doc("Offset", offset32, "In-bounds offset into stack slot");
@@ -946,6 +1053,19 @@ pub fn define(
         .operands_out(vec![a]),
     );
 
+    ig.push(
+        Inst::new(
+            "tls_value",
+            r#"
+        Compute the value of global GV, which is a TLS (thread local storage) value.
+
+        The address is computed according to the `tls_model` setting.
+        "#,
+        )
+        .operands_in(vec![GV])
+        .operands_out(vec![a]),
+    );
+
     let HeapOffset = &TypeVar::new(
         "HeapOffset",
         "An unsigned heap offset",
@@ -3024,7 +3144,7 @@ pub fn define(
         "WideInt",
         "An integer type with lanes from `i16` upwards",
         TypeSetBuilder::new()
-            .ints(16..64)
+            .ints(16..128)
             .simd_lanes(Interval::All)
             .build(),
     );
@@ -3052,9 +3172,9 @@ pub fn define(
 
     let NarrowInt = &TypeVar::new(
         "NarrowInt",
-        "An integer type with lanes type to `i32`",
+        "An integer type with lanes type to `i64`",
         TypeSetBuilder::new()
-            .ints(8..32)
+            .ints(8..64)
             .simd_lanes(Interval::All)
             .build(),
     );
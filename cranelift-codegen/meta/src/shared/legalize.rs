@@ -20,6 +20,17 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
     "#,
     );
 
+    let mut narrow_i128 = TransformGroupBuilder::new(
+        "narrow_i128",
+        r#"
+        Legalize 128-bit integer instructions by narrowing.
+
+        The transformations in the 'narrow_i128' group express `i128`
+        operations in terms of `i64` halves, in addition to the ones in the
+        'narrow' group.
+    "#,
+    );
+
     let mut widen = TransformGroupBuilder::new(
         "widen",
         r#"
@@ -111,6 +122,7 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
     let uload16 = insts.by_name("uload16");
     let ushr = insts.by_name("ushr");
     let ushr_imm = insts.by_name("ushr_imm");
+    let umulhi = insts.by_name("umulhi");
     let urem = insts.by_name("urem");
     let urem_imm = insts.by_name("urem_imm");
     let trapif = insts.by_name("trapif");
@@ -141,6 +153,9 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
     expand.custom_legalize(insts.by_name("stack_load"), "expand_stack_load");
     expand.custom_legalize(insts.by_name("stack_store"), "expand_stack_store");
 
+    // Atomic read-modify-write operations without native support.
+    expand.custom_legalize(insts.by_name("atomic_rmw"), "expand_atomic_rmw");
+
     // List of immediates.
     let imm64 = immediates.by_name("imm64");
     let ieee32 = immediates.by_name("ieee32");
@@ -240,6 +255,70 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
         ],
     );
 
+    narrow_i128.legalize(
+        def!(a = bnot(x)),
+        vec![
+            def!((xl, xh) = isplit(x)),
+            def!(al = bnot(xl)),
+            def!(ah = bnot(xh)),
+            def!(a = iconcat(al, ah)),
+        ],
+    );
+
+    // The high half of the product gets the cross products and the carry out of the low half.
+    narrow_i128.legalize(
+        def!(a = imul(x, y)),
+        vec![
+            def!((xl, xh) = isplit(x)),
+            def!((yl, yh) = isplit(y)),
+            def!(a1 = imul(xh, yl)),
+            def!(a2 = imul(xl, yh)),
+            def!(a3 = iadd(a1, a2)),
+            def!(a4 = umulhi(xl, yl)),
+            def!(ah = iadd(a3, a4)),
+            def!(al = imul(xl, yl)),
+            def!(a = iconcat(al, ah)),
+        ],
+    );
+
+    // Immediate operands are materialized so the instruction can be narrowed as a whole.
+    for &(inst_imm, inst) in &[
+        (iadd_imm, iadd),
+        (imul_imm, imul),
+        (band_imm, band),
+        (bor_imm, bor),
+        (bxor_imm, bxor),
+    ] {
+        narrow_i128.legalize(
+            def!(a = inst_imm(x, y)),
+            vec![def!(a1 = iconst(y)), def!(a = inst(x, a1))],
+        );
+    }
+
+    narrow_i128.legalize(
+        def!(a = irsub_imm(y, x)),
+        vec![def!(a1 = iconst(x)), def!(a = isub(a1, y))],
+    );
+
+    narrow_i128.legalize(
+        def!(a = icmp_imm(cc, x, y)),
+        vec![def!(a1 = iconst(y)), def!(a = icmp(cc, x, a1))],
+    );
+
+    for &(inst_imm, inst) in &[(ishl_imm, ishl), (sshr_imm, sshr), (ushr_imm, ushr)] {
+        narrow_i128.legalize(
+            def!(a = inst_imm(x, y)),
+            vec![def!(a1 = iconst.I32(y)), def!(a = inst(x, a1))],
+        );
+    }
+
+    // Constants, comparisons, and shifts are narrowed in Rust.
+    narrow_i128.custom_legalize(iconst, "narrow_iconst");
+    narrow_i128.custom_legalize(icmp, "narrow_icmp");
+    narrow_i128.custom_legalize(ishl, "narrow_shift");
+    narrow_i128.custom_legalize(ushr, "narrow_shift");
+    narrow_i128.custom_legalize(sshr, "narrow_shift");
+
     // Widen instructions with one input operand.
     for &op in &[bnot, popcnt] {
         for &int_ty in &[I8, I16] {
@@ -766,7 +845,7 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
 
     let mut groups = TransformGroups::new();
 
-    narrow.build_and_add_to(&mut groups);
+    let narrow_id = narrow.build_and_add_to(&mut groups);
     let expand_id = expand.build_and_add_to(&mut groups);
 
     // Expansions using CPU flags.
@@ -808,6 +887,9 @@ pub fn define(insts: &InstructionGroup, immediates: &OperandKinds) -> TransformG
     // When it's all migrated, we can put this next to the narrow/expand build_and_add_to calls
     // above.
     widen.build_and_add_to(&mut groups);
+    narrow_i128
+        .chain_with(narrow_id)
+        .build_and_add_to(&mut groups);
 
     groups
 }
@@ -11,8 +11,11 @@ pub fn define() -> SettingGroup {
         - default: Very profitable optimizations enabled, none slow.
         - best: Enable all optimizations
         - fastest: Optimize for compile time by disabling most optimizations.
+        - size: Optimize for code size. This enables the default optimizations, selects the
+          shortest instruction encodings, inverts conditional branches over jumps, and avoids
+          alignment padding between functions.
         "#,
-        vec!["default", "best", "fastest"],
+        vec!["default", "best", "fastest", "size"],
     );
 
     settings.add_bool(
@@ -35,6 +38,21 @@ pub fn define() -> SettingGroup {
         false,
     );
 
+    settings.add_enum(
+        "tls_model",
+        r#"
+            Defines the model used to access thread local storage (TLS) values:
+
+            - none: TLS values are not supported.
+            - elf_gd: The ELF general-dynamic model, which calls `__tls_get_addr`.
+            - elf_ie: The ELF initial-exec model, which loads the thread pointer offset from
+              the GOT. This only works for TLS values defined in the executable or in
+              libraries loaded at startup.
+            - macho: The Mach-O thread local variable descriptor model.
+        "#,
+        vec!["none", "elf_gd", "elf_ie", "macho"],
+    );
+
     settings.add_bool(
         "colocated_libcalls",
         r#"
@@ -62,6 +80,20 @@ pub fn define() -> SettingGroup {
         false,
     );
 
+    settings.add_bool(
+        "inline_wide_divrem",
+        r#"
+            Expand division and remainder of integers twice as wide as registers
+            inline.
+
+            By default, these are runtime library calls. When enabled, they are
+            expanded as loops computing one bit of the quotient per iteration
+            instead, which doesn't need any runtime library but is larger and
+            slower. This currently applies to `i64` on 32-bit targets.
+            "#,
+        false,
+    );
+
     settings.add_bool(
         "enable_float",
         r#"
@@ -160,5 +192,32 @@ pub fn define() -> SettingGroup {
         true,
     );
 
+    // Heap access options.
+
+    settings.add_bool(
+        "enable_heap_access_spectre_mitigation",
+        r#"
+            Harden heap bounds checks against Spectre v1.
+
+            The offset of a heap access is masked to zero when its bounds check
+            fails, so that a mispredicted branch to the trap can't be used to
+            speculatively access memory outside of the heap.
+        "#,
+        false,
+    );
+
+    // Scheduling options.
+
+    settings.add_bool(
+        "enable_post_ra_scheduling",
+        r#"
+            Reorder instructions after register allocation to hide latencies.
+
+            This runs a list scheduler over each EBB, using the latency model of
+            the target ISA. It is mostly beneficial for in-order cores.
+        "#,
+        false,
+    );
+
     settings.build()
 }
@@ -3,7 +3,8 @@
 //! This module provides functions and data structures that are useful for implementing the
 //! `TargetIsa::legalize_signature()` method.
 
-use crate::ir::{AbiParam, ArgumentExtension, ArgumentLoc, Type};
+use crate::ir::stackslot::StackSize;
+use crate::ir::{AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Type};
 use core::cmp::Ordering;
 use std::vec::Vec;
 
@@ -22,6 +23,12 @@ pub enum ArgAction {
     /// This action can split an integer type into two smaller integer arguments, or it can split a
     /// SIMD vector into halves.
     Convert(ValueConversion),
+
+    /// Pass a struct argument in registers, as parts with the given types and locations.
+    ///
+    /// The first part holds the first pointer-sized chunk of the struct, and the second part, if
+    /// any, holds the rest of it.
+    StructParts((Type, ArgumentLoc), Option<(Type, ArgumentLoc)>),
 }
 
 impl From<ArgumentLoc> for ArgAction {
@@ -114,6 +121,21 @@ pub fn legalize_args<AA: ArgAssigner>(args: &mut Vec<AbiParam>, aa: &mut AA) {
                     args.insert(argno + 1, new_arg);
                 }
             }
+            // Replace this struct argument with its parts, which are already assigned.
+            ArgAction::StructParts((value_type, location), rest) => {
+                args[argno].value_type = value_type;
+                args[argno].location = location;
+                argno += 1;
+                if let Some((value_type, location)) = rest {
+                    let part = AbiParam {
+                        value_type,
+                        location,
+                        ..arg
+                    };
+                    args.insert(argno, part);
+                    argno += 1;
+                }
+            }
         }
     }
 }
@@ -179,6 +201,17 @@ pub fn legalize_abi_value(have: Type, arg: &AbiParam) -> ValueConversion {
     }
 }
 
+/// Get the size of the stack slot holding the argument `arg` when it is passed on the stack.
+///
+/// A struct passed by value occupies its whole size, and the slot also has room for the pointer
+/// to the struct which the legalizer spills there before copying the struct over it.
+pub fn stack_arg_size(arg: &AbiParam) -> StackSize {
+    match arg.purpose {
+        ArgumentPurpose::StructArgument(size, _) => size.max(arg.value_type.bytes()),
+        _ => arg.value_type.bytes(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
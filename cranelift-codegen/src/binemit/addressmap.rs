@@ -0,0 +1,127 @@
+//! Mapping from machine code offsets to source locations.
+//!
+//! Frontends attach a `SourceLoc` to the instructions they generate, such as the offset of the
+//! WebAssembly operator an instruction was translated from. The `AddressMap` of a compiled
+//! function maps the offsets of its machine code back to those source locations, so that
+//! runtimes can produce backtraces and source-level profiles.
+
+use super::CodeOffset;
+use crate::ir::{Function, SourceLoc};
+use crate::isa::TargetIsa;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+use std::vec::Vec;
+
+/// The source locations of the machine code of a compiled function.
+///
+/// The map is a list of the code offsets at which the source location changes, in increasing
+/// order. Code before the first entry, such as the prologue, has the default source location, as
+/// does code without a source location after an entry with the default location.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct AddressMap {
+    entries: Vec<(CodeOffset, SourceLoc)>,
+}
+
+impl AddressMap {
+    /// Build the address map of `func`, which must have been compiled for `isa`.
+    ///
+    /// Returns an empty map if the code layout of `func` hasn't been computed.
+    pub fn new(func: &Function, isa: &dyn TargetIsa) -> Self {
+        let mut entries = Vec::new();
+        if func.offsets.is_empty() || func.srclocs.is_empty() {
+            return Self { entries };
+        }
+
+        let encinfo = isa.encoding_info();
+        let mut last = SourceLoc::default();
+        for ebb in func.layout.ebbs() {
+            for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+                // Instructions without any code don't have an address.
+                let srcloc = func.srclocs[inst];
+                if size > 0 && srcloc != last {
+                    entries.push((offset, srcloc));
+                    last = srcloc;
+                }
+            }
+        }
+        Self { entries }
+    }
+
+    /// Get the code offsets at which the source location changes, and the source location of
+    /// the code from each of them.
+    pub fn entries(&self) -> &[(CodeOffset, SourceLoc)] {
+        &self.entries
+    }
+
+    /// Get the source location of the machine code at `offset`.
+    pub fn lookup(&self, offset: CodeOffset) -> SourceLoc {
+        match self.entries.binary_search_by_key(&offset, |&(o, _)| o) {
+            Ok(idx) => self.entries[idx].1,
+            Err(0) => SourceLoc::default(),
+            Err(idx) => self.entries[idx - 1].1,
+        }
+    }
+
+    /// Is this map empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, AbiParam, ExternalName, InstBuilder, Signature, TrapCode};
+    use crate::isa::{self, CallConv};
+    use crate::settings;
+    use crate::Context;
+    use core::str::FromStr;
+    use target_lexicon::triple;
+
+    #[test]
+    fn address_map() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("map"), sig);
+        let ebb0 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+        pos.set_srcloc(SourceLoc::new(10));
+        let v1 = pos.ins().iadd_imm(v0, 1);
+        pos.set_srcloc(SourceLoc::new(20));
+        pos.ins().trapz(v1, TrapCode::User(0));
+        pos.set_srcloc(SourceLoc::new(30));
+        pos.ins().return_(&[v1]);
+
+        let mut ctx = Context::for_function(func);
+        assert!(ctx.address_map(&*isa).is_empty());
+        let info = ctx.compile(&*isa).unwrap();
+        let map = ctx.address_map(&*isa);
+        let srclocs = map
+            .entries()
+            .iter()
+            .map(|&(_, srcloc)| srcloc.bits())
+            .collect::<Vec<_>>();
+        // The epilogue inserted before the return doesn't have a source location.
+        let epilogue = SourceLoc::default().bits();
+        assert_eq!(srclocs, [10, 20, 30, epilogue, 30]);
+
+        // The prologue comes before the first instruction with a source location.
+        let (start, _) = map.entries()[0];
+        assert!(start > 0);
+        assert_eq!(map.lookup(0), SourceLoc::default());
+        assert_eq!(map.lookup(start), SourceLoc::new(10));
+        let (trap, _) = map.entries()[1];
+        assert_eq!(map.lookup(trap - 1), SourceLoc::new(10));
+        assert_eq!(map.lookup(trap), SourceLoc::new(20));
+        assert_eq!(map.lookup(info.code_size - 1), SourceLoc::new(30));
+    }
+}
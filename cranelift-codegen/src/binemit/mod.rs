@@ -3,10 +3,12 @@
 //! The `binemit` module contains code for translating Cranelift's intermediate representation into
 //! binary machine code.
 
+mod addressmap;
 mod memorysink;
 mod relaxation;
 mod shrink;
 
+pub use self::addressmap::AddressMap;
 pub use self::memorysink::{MemoryCodeSink, NullTrapSink, RelocSink, TrapSink};
 pub use self::relaxation::relax_branches;
 pub use self::shrink::shrink_instructions;
@@ -27,7 +29,7 @@ pub type CodeOffset = u32;
 pub type Addend = i64;
 
 /// Relocation kinds for every ISA
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum Reloc {
     /// absolute 4-byte
@@ -50,6 +52,13 @@ pub enum Reloc {
     Arm64Call,
     /// RISC-V call target
     RiscvCall,
+
+    /// Elf x86_64 32 bit signed PC relative offset to two GOT entries for GD symbol.
+    ElfX86_64TlsGd,
+    /// Elf x86_64 32 bit signed PC relative offset to GOT entry for IE symbol.
+    ElfX86_64GotTpOff,
+    /// Mach-O x86_64 32 bit signed PC relative offset to a `__thread_vars` entry.
+    MachOX86_64Tlv,
 }
 
 impl fmt::Display for Reloc {
@@ -65,6 +74,10 @@ impl fmt::Display for Reloc {
             Reloc::X86CallPLTRel4 => write!(f, "CallPLTRel4"),
             Reloc::X86GOTPCRel4 => write!(f, "GOTPCRel4"),
             Reloc::Arm32Call | Reloc::Arm64Call | Reloc::RiscvCall => write!(f, "Call"),
+
+            Reloc::ElfX86_64TlsGd => write!(f, "ElfX86_64TlsGd"),
+            Reloc::ElfX86_64GotTpOff => write!(f, "ElfX86_64GotTpOff"),
+            Reloc::MachOX86_64Tlv => write!(f, "MachOX86_64Tlv"),
         }
     }
 }
@@ -74,7 +87,8 @@ impl fmt::Display for Reloc {
 /// The code starts at offset 0 and is followed optionally by relocatable jump tables and copyable
 /// (raw binary) read-only data.  Any padding between sections is always part of the section that
 /// precedes the boundary between the sections.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct CodeInfo {
     /// Number of bytes of machine code (the code starts at offset 0).
     pub code_size: CodeOffset,
@@ -26,13 +26,35 @@
 //!     jump ebb17
 //! ebb23:
 //! ```
+//!
+//! # Branch inversion
+//!
+//! When optimizing for size, the opposite transformation is applied to conditional branches over
+//! jumps, so the jump can be replaced by a fall-through:
+//!
+//! ```clif
+//!     brz v1, ebb23
+//!     jump ebb17
+//! ebb23:
+//! ```
+//!
+//! becomes:
+//!
+//! ```clif
+//!     brnz v1, ebb17
+//!     fallthrough ebb23
+//! ebb23:
+//! ```
 
 use crate::binemit::{CodeInfo, CodeOffset};
 use crate::cursor::{Cursor, FuncCursor};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::CondCode;
 use crate::ir::{Function, InstructionData, Opcode};
 use crate::isa::{EncInfo, TargetIsa};
 use crate::iterators::IteratorExtras;
 use crate::regalloc::RegDiversions;
+use crate::settings::OptLevel;
 use crate::timing;
 use crate::CodegenResult;
 use log::debug;
@@ -40,7 +62,11 @@ use log::debug;
 /// Relax branches and compute the final layout of EBB headers in `func`.
 ///
 /// Fill in the `func.offsets` table so the function is ready for binary emission.
-pub fn relax_branches(func: &mut Function, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
+pub fn relax_branches(
+    func: &mut Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &dyn TargetIsa,
+) -> CodegenResult<CodeInfo> {
     let _tt = timing::relax_branches();
 
     let encinfo = isa.encoding_info();
@@ -49,7 +75,11 @@ pub fn relax_branches(func: &mut Function, isa: &dyn TargetIsa) -> CodegenResult
     func.offsets.clear();
     func.offsets.resize(func.dfg.num_ebbs());
 
-    // Start by inserting fall through instructions.
+    // Start by inserting fall through instructions, after making room for more of them when
+    // optimizing for size.
+    if isa.flags().opt_level() == OptLevel::Size {
+        invert_branches(func, cfg, isa);
+    }
     fallthroughs(func);
 
     let mut offset = 0;
@@ -164,6 +194,90 @@ fn fallthroughs(func: &mut Function) {
     }
 }
 
+/// Invert conditional branches to the layout successor which are followed by a `jump`, so the
+/// `jump` can be converted to a fall-through.
+///
+/// The inverted branch keeps the operand constraints of the original, so this is safe to do after
+/// register allocation. Branches whose inverted condition has no such encoding are left alone.
+fn invert_branches(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &dyn TargetIsa) {
+    let encinfo = isa.encoding_info();
+    let divert = RegDiversions::new();
+
+    for (ebb, succ) in func.layout.ebbs().adjacent_pairs() {
+        let jump = func.layout.last_inst(ebb).expect("EBB has no terminator.");
+        let dest = match func.dfg[jump] {
+            InstructionData::Jump {
+                opcode: Opcode::Jump,
+                destination,
+                ..
+            } if destination != succ => destination,
+            _ => continue,
+        };
+        let branch = match func.layout.prev_inst(jump) {
+            Some(branch) if func.dfg[branch].branch_destination() == Some(succ) => branch,
+            _ => continue,
+        };
+
+        let mut inverted = func.dfg[branch].clone();
+        match inverted {
+            InstructionData::Branch { ref mut opcode, .. } => {
+                *opcode = match *opcode {
+                    Opcode::Brz => Opcode::Brnz,
+                    Opcode::Brnz => Opcode::Brz,
+                    _ => continue,
+                }
+            }
+            InstructionData::BranchInt { ref mut cond, .. } => *cond = cond.inverse(),
+            InstructionData::BranchFloat { ref mut cond, .. } => *cond = cond.inverse(),
+            InstructionData::BranchIcmp { ref mut cond, .. } => *cond = cond.inverse(),
+            _ => continue,
+        }
+
+        let ctrl_type = func.dfg.ctrl_typevar(branch);
+        let constraints = encinfo.operand_constraints(func.encodings[branch]);
+        let enc = match isa
+            .legal_encodings(func, &inverted, ctrl_type)
+            .filter(|&enc| encinfo.operand_constraints(enc) == constraints)
+            .min_by_key(|&enc| encinfo.byte_size(enc, branch, &divert, func))
+        {
+            Some(enc) => enc,
+            None => continue,
+        };
+        debug!(
+            "Inverting {} over jump to {}",
+            func.dfg.display_inst(branch, isa),
+            dest
+        );
+
+        // Swap the destinations and their arguments, keeping the fixed arguments of the branch.
+        let num_fixed = inverted.opcode().constraints().num_fixed_value_arguments();
+        let branch_args = func.dfg.inst_args(branch).to_vec();
+        let jump_args = func.dfg.inst_args(jump).to_vec();
+        func.dfg[branch] = inverted;
+        func.encodings[branch] = enc;
+
+        let mut args = func.dfg[branch].take_value_list().unwrap();
+        args.clear(&mut func.dfg.value_lists);
+        args.extend(
+            branch_args[..num_fixed].iter().chain(&jump_args).cloned(),
+            &mut func.dfg.value_lists,
+        );
+        func.dfg[branch].put_value_list(args);
+        *func.dfg[branch].branch_destination_mut().unwrap() = dest;
+
+        let mut args = func.dfg[jump].take_value_list().unwrap();
+        args.clear(&mut func.dfg.value_lists);
+        args.extend(
+            branch_args[num_fixed..].iter().cloned(),
+            &mut func.dfg.value_lists,
+        );
+        func.dfg[jump].put_value_list(args);
+        *func.dfg[jump].branch_destination_mut().unwrap() = succ;
+
+        cfg.recompute_ebb(func, ebb);
+    }
+}
+
 /// Relax the branch instruction at `cur` so it can cover the range `offset - dest_offset`.
 ///
 /// Return the size of the replacement instructions up to and including the location where `cur` is
@@ -0,0 +1,489 @@
+//! Caching of compiled functions.
+//!
+//! Compiling the same function with the same settings always produces the same machine code, so
+//! embedders that compile the same functions over and over again, such as the functions of a
+//! large WebAssembly module on every run, can save the compiled code and reuse it.
+//!
+//! When a `CacheBackend` is installed in a `Context`, `Context::compile` computes a `CacheKey`
+//! from the text of the function, the edge frequencies, the target triple, and the settings of
+//! the ISA, and asks the backend for a matching `CachedFunction`. On a hit, the function isn't
+//! compiled at all: `Context::emit_to_memory` copies the cached machine code and replays the
+//! cached relocations and traps into the sinks it is given, and `Context::address_map` returns
+//! the cached address map. On a miss, the function is compiled
+//! and the result is inserted into the cache.
+//!
+//! Functions with value labels are never cached, since the value label ranges are computed from
+//! the state of the register allocator.
+
+use crate::binemit::{
+    Addend, AddressMap, CodeInfo, CodeOffset, MemoryCodeSink, Reloc, RelocSink, TrapSink,
+};
+use crate::ebb_layout::EdgeFrequencies;
+use crate::ir::{ExternalName, Function, JumpTable, SourceLoc, TrapCode};
+use crate::isa::TargetIsa;
+use core::fmt::{self, Write};
+use core::ptr;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+use std::vec::Vec;
+
+/// Storage for compiled functions, provided by the embedder.
+///
+/// A backend is shared by all the contexts it is installed in, possibly on several threads, so
+/// it must use interior mutability to insert new functions.
+pub trait CacheBackend: Send + Sync {
+    /// Get the compiled function stored under `key`, if any.
+    fn get(&self, key: &CacheKey) -> Option<CachedFunction>;
+
+    /// Store the compiled function `func` under `key`.
+    fn insert(&self, key: &CacheKey, func: &CachedFunction);
+}
+
+/// A 128-bit hash identifying a function and the settings it is compiled with.
+///
+/// The hash is stable across processes and hosts, so it can be used to index a cache on disk. It
+/// also covers the version of Cranelift, since a different version may generate different code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct CacheKey([u8; 16]);
+
+impl CacheKey {
+    /// Compute the key for compiling `func` for `isa`, or `None` if it can't be cached.
+    pub(crate) fn compute(
+        func: &Function,
+        edge_frequencies: &EdgeFrequencies,
+        isa: &dyn TargetIsa,
+    ) -> Option<Self> {
+        if func.dfg.values_labels.is_some() {
+            return None;
+        }
+
+        let mut counts = edge_frequencies.iter().collect::<Vec<_>>();
+        counts.sort_unstable();
+
+        let mut hasher = StableHasher::new();
+        // The text of the function and the settings are separated by lines, so they can't be
+        // confused with each other.
+        writeln!(hasher, "cranelift {}", crate::VERSION).ok()?;
+        writeln!(hasher, "{} {}", isa.triple(), isa.name()).ok()?;
+        writeln!(hasher, "{}", isa).ok()?;
+        for (from, to, count) in counts {
+            writeln!(hasher, "{} -> {}: {}", from, to, count).ok()?;
+        }
+        write!(hasher, "{}", func.display(isa)).ok()?;
+        Some(CacheKey(hasher.finish().to_le_bytes()))
+    }
+
+    /// Get the bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl fmt::Display for CacheKey {
+    /// Format the key as 32 hexadecimal digits, which is suitable for a file name.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// 128-bit FNV-1a hash of the text written to it.
+///
+/// Unlike the hashers in the standard library, the result is guaranteed to never change.
+struct StableHasher(u128);
+
+impl StableHasher {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    fn new() -> Self {
+        StableHasher(Self::OFFSET_BASIS)
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Write for StableHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.0 = (self.0 ^ u128::from(byte)).wrapping_mul(Self::PRIME);
+        }
+        Ok(())
+    }
+}
+
+/// A relocation recorded while emitting a function.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum CachedReloc {
+    /// A relocation referencing an EBB, passed to `RelocSink::reloc_ebb`.
+    Ebb(CodeOffset, Reloc, CodeOffset),
+    /// A relocation referencing an external symbol, passed to `RelocSink::reloc_external`.
+    External(CodeOffset, Reloc, ExternalName, Addend),
+    /// A relocation referencing a jump table, passed to `RelocSink::reloc_jt`.
+    JumpTable(CodeOffset, Reloc, JumpTable),
+}
+
+/// A trap site recorded while emitting a function.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct CachedTrap {
+    /// Offset of the trapping instruction.
+    pub offset: CodeOffset,
+    /// Source location of the trapping instruction.
+    pub srcloc: SourceLoc,
+    /// Reason for the trap.
+    pub code: TrapCode,
+}
+
+/// The machine code of a compiled function, along with its relocations, trap sites, and address
+/// map.
+///
+/// When the `enable-serde` feature is enabled, this can be serialized to store it on disk.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct CachedFunction {
+    /// The sizes of the code and read-only data.
+    pub info: CodeInfo,
+    /// The machine code and read-only data, before relocation.
+    pub code: Vec<u8>,
+    /// The relocations to apply to `code`.
+    pub relocs: Vec<CachedReloc>,
+    /// The trap sites in `code`.
+    pub traps: Vec<CachedTrap>,
+    /// The source locations of `code`.
+    pub address_map: AddressMap,
+}
+
+impl CachedFunction {
+    /// Emit the compiled function `func`, recording its relocations and traps.
+    pub(crate) fn record(isa: &dyn TargetIsa, func: &Function, info: CodeInfo) -> Self {
+        let mut code = vec![0; info.total_size as usize];
+        let mut relocs = RelocRecorder(Vec::new());
+        let mut traps = TrapRecorder(Vec::new());
+        let emitted = unsafe {
+            let mut sink = MemoryCodeSink::new(code.as_mut_ptr(), &mut relocs, &mut traps);
+            isa.emit_function_to_memory(func, &mut sink);
+            sink.info
+        };
+        debug_assert!(emitted == info);
+        Self {
+            info,
+            code,
+            relocs: relocs.0,
+            traps: traps.0,
+            address_map: AddressMap::new(func, isa),
+        }
+    }
+
+    /// Is this consistent with itself?
+    ///
+    /// Entries read from a corrupted cache may not be.
+    pub(crate) fn is_valid(&self) -> bool {
+        let info = &self.info;
+        let code_size = info.code_size;
+        // A field of `size` bytes at `offset` must be within the code.
+        let in_code = |offset: CodeOffset, size: CodeOffset| {
+            offset <= code_size && size <= code_size - offset
+        };
+        let sizes_valid = self.code.len() == info.total_size as usize
+            && code_size
+                .checked_add(info.jumptables_size)
+                .and_then(|size| size.checked_add(info.rodata_size))
+                == Some(info.total_size);
+        let relocs_valid = self.relocs.iter().all(|reloc| match *reloc {
+            CachedReloc::Ebb(offset, kind, ebb_offset) => {
+                in_code(offset, reloc_size(kind)) && ebb_offset <= code_size
+            }
+            CachedReloc::External(offset, kind, _, _) | CachedReloc::JumpTable(offset, kind, _) => {
+                in_code(offset, reloc_size(kind))
+            }
+        });
+        let traps_valid = self.traps.iter().all(|trap| trap.offset < code_size);
+        // The address map is searched by offset, so its entries must be sorted.
+        let entries = self.address_map.entries();
+        let address_map_valid = entries.iter().all(|&(offset, _)| offset < code_size)
+            && entries.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        sizes_valid && relocs_valid && traps_valid && address_map_valid
+    }
+
+    /// Copy the machine code to the memory at `mem`, and replay the relocations and traps.
+    ///
+    /// This function is unsafe since it does not perform bounds checking on the memory buffer,
+    /// and it can't guarantee that the `mem` pointer is valid.
+    pub unsafe fn emit_to_memory(
+        &self,
+        mem: *mut u8,
+        relocs: &mut dyn RelocSink,
+        traps: &mut dyn TrapSink,
+    ) -> CodeInfo {
+        ptr::copy_nonoverlapping(self.code.as_ptr(), mem, self.code.len());
+        for reloc in &self.relocs {
+            match *reloc {
+                CachedReloc::Ebb(offset, kind, ebb_offset) => {
+                    relocs.reloc_ebb(offset, kind, ebb_offset)
+                }
+                CachedReloc::External(offset, kind, ref name, addend) => {
+                    relocs.reloc_external(offset, kind, name, addend)
+                }
+                CachedReloc::JumpTable(offset, kind, jt) => relocs.reloc_jt(offset, kind, jt),
+            }
+        }
+        for trap in &self.traps {
+            traps.trap(trap.offset, trap.srcloc, trap.code);
+        }
+        self.info
+    }
+}
+
+/// Get the number of bytes patched by a relocation of kind `reloc`.
+fn reloc_size(reloc: Reloc) -> CodeOffset {
+    match reloc {
+        Reloc::Abs8 => 8,
+        Reloc::Abs4
+        | Reloc::X86PCRel4
+        | Reloc::X86PCRelRodata4
+        | Reloc::X86CallPCRel4
+        | Reloc::X86CallPLTRel4
+        | Reloc::X86GOTPCRel4
+        | Reloc::Arm32Call
+        | Reloc::Arm64Call
+        | Reloc::RiscvCall
+        | Reloc::ElfX86_64TlsGd
+        | Reloc::ElfX86_64GotTpOff
+        | Reloc::MachOX86_64Tlv => 4,
+    }
+}
+
+struct RelocRecorder(Vec<CachedReloc>);
+
+impl RelocSink for RelocRecorder {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.0.push(CachedReloc::Ebb(offset, reloc, ebb_offset));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.0
+            .push(CachedReloc::External(offset, reloc, name.clone(), addend));
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.0.push(CachedReloc::JumpTable(offset, reloc, jt));
+    }
+}
+
+struct TrapRecorder(Vec<CachedTrap>);
+
+impl TrapSink for TrapRecorder {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        self.0.push(CachedTrap {
+            offset,
+            srcloc,
+            code,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, AbiParam, ExtFuncData, InstBuilder, Signature};
+    use crate::isa::{self, CallConv};
+    use crate::settings::{self, Configurable};
+    use crate::Context;
+    use core::str::FromStr;
+    use std::string::ToString;
+    use std::sync::{Arc, Mutex};
+    use target_lexicon::triple;
+
+    #[derive(Default)]
+    struct TestCache {
+        funcs: Mutex<Vec<(CacheKey, CachedFunction)>>,
+        hits: Mutex<usize>,
+    }
+
+    impl CacheBackend for TestCache {
+        fn get(&self, key: &CacheKey) -> Option<CachedFunction> {
+            let funcs = self.funcs.lock().unwrap();
+            let found = funcs
+                .iter()
+                .find(|&(k, _)| k == key)
+                .map(|(_, f)| f.clone());
+            if found.is_some() {
+                *self.hits.lock().unwrap() += 1;
+            }
+            found
+        }
+
+        fn insert(&self, key: &CacheKey, func: &CachedFunction) {
+            self.funcs.lock().unwrap().push((*key, func.clone()));
+        }
+    }
+
+    /// A function that calls an external function and can trap.
+    fn make_function() -> Function {
+        let mut func = Function::with_name_signature(
+            ExternalName::user(0, 0),
+            Signature::new(CallConv::SystemV),
+        );
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let sig = func.import_signature(func.signature.clone());
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sig,
+            colocated: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+        let v1 = pos.ins().udiv(v0, v0);
+        let call = pos.ins().call(callee, &[v1]);
+        let v2 = pos.func.dfg.first_result(call);
+        pos.ins().return_(&[v2]);
+        func
+    }
+
+    fn emit(ctx: &mut Context, isa: &dyn TargetIsa) -> CachedFunction {
+        let info = ctx.compile(isa).unwrap();
+        let mut code = vec![0; info.total_size as usize];
+        let mut relocs = RelocRecorder(Vec::new());
+        let mut traps = TrapRecorder(Vec::new());
+        unsafe { ctx.emit_to_memory(isa, code.as_mut_ptr(), &mut relocs, &mut traps) };
+        CachedFunction {
+            info,
+            code,
+            relocs: relocs.0,
+            traps: traps.0,
+            address_map: ctx.address_map(isa),
+        }
+    }
+
+    #[test]
+    fn reuse_compiled_code() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let cache = Arc::new(TestCache::default());
+
+        let mut ctx = Context::for_function(make_function());
+        ctx.cache = Some(cache.clone());
+        let compiled = emit(&mut ctx, &*isa);
+        assert_eq!(*cache.hits.lock().unwrap(), 0);
+        assert_eq!(cache.funcs.lock().unwrap().len(), 1);
+        assert_eq!(cache.funcs.lock().unwrap()[0].1, compiled);
+        assert!(compiled.relocs.iter().any(|r| match r {
+            CachedReloc::External(_, _, name, _) => *name == ExternalName::user(0, 1),
+            _ => false,
+        }));
+        assert!(compiled
+            .traps
+            .iter()
+            .any(|t| t.code == TrapCode::IntegerDivisionByZero));
+
+        // The same function is found in the cache, and emitted identically. Only the name and
+        // signature of the uncompiled function are kept.
+        let mut ctx = Context::for_function(make_function());
+        ctx.cache = Some(cache.clone());
+        assert_eq!(emit(&mut ctx, &*isa), compiled);
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+        assert!(ctx.stats.cache_hit);
+        assert_eq!(ctx.stats.code_info, Some(compiled.info));
+        assert_eq!(ctx.func.name, ExternalName::user(0, 0));
+        assert_eq!(ctx.func.signature, make_function().signature);
+        assert_eq!(ctx.func.layout.entry_block(), None);
+
+        // Different settings produce a different key.
+        let mut flags = settings::builder();
+        flags.set("opt_level", "best").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut ctx = Context::for_function(make_function());
+        ctx.cache = Some(cache.clone());
+        emit(&mut ctx, &*isa);
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+        assert_eq!(cache.funcs.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reject_corrupted_functions() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut func = make_function();
+        let ebb0 = func.layout.entry_block().unwrap();
+        let insts = func.layout.ebb_insts(ebb0).collect::<Vec<_>>();
+        for (i, inst) in insts.into_iter().enumerate() {
+            func.srclocs[inst] = SourceLoc::new(i as u32 + 1);
+        }
+        let mut ctx = Context::for_function(func);
+        let compiled = emit(&mut ctx, &*isa);
+        assert!(compiled.is_valid());
+        assert!(!compiled.address_map.is_empty());
+
+        let is_valid = |corrupt: &dyn Fn(&mut CachedFunction)| {
+            let mut func = compiled.clone();
+            corrupt(&mut func);
+            func.is_valid()
+        };
+        assert!(!is_valid(&|f| {
+            f.code.pop();
+        }));
+        assert!(!is_valid(&|f| f.info.code_size += 1));
+        assert!(!is_valid(&|f| match f.relocs[0] {
+            CachedReloc::External(ref mut offset, ..) => *offset = f.info.code_size - 2,
+            _ => panic!("expected an external relocation"),
+        }));
+        assert!(!is_valid(&|f| f.relocs.push(CachedReloc::Ebb(
+            0,
+            Reloc::X86PCRel4,
+            f.info.code_size + 1
+        ))));
+        assert!(!is_valid(&|f| f.traps[0].offset = f.info.code_size));
+
+        // Move the end of the code into the read-only data, up to the last address map entry.
+        let shrink_code = |f: &mut CachedFunction, code_size: CodeOffset| {
+            f.relocs.clear();
+            f.traps.clear();
+            f.info.rodata_size += f.info.code_size - code_size;
+            f.info.code_size = code_size;
+        };
+        let last = compiled.address_map.entries().last().unwrap().0;
+        assert!(is_valid(&|f| shrink_code(f, last + 1)));
+        assert!(!is_valid(&|f| shrink_code(f, last)));
+    }
+
+    #[test]
+    fn key() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let func = make_function();
+        let mut frequencies = EdgeFrequencies::new();
+        let key = CacheKey::compute(&func, &frequencies, &*isa).unwrap();
+        assert_eq!(key.to_string().len(), 32);
+        assert_eq!(CacheKey::compute(&func, &frequencies, &*isa), Some(key));
+
+        let ebb0 = func.layout.entry_block().unwrap();
+        frequencies.add(ebb0, ebb0, 1);
+        assert_ne!(CacheKey::compute(&func, &frequencies, &*isa), Some(key));
+
+        let mut func = func;
+        func.dfg.collect_debug_info();
+        assert_eq!(CacheKey::compute(&func, &frequencies, &*isa), None);
+    }
+}
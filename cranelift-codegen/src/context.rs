@@ -10,12 +10,14 @@
 //! single ISA instance.
 
 use crate::binemit::{
-    relax_branches, shrink_instructions, CodeInfo, MemoryCodeSink, RelocSink, TrapSink,
+    relax_branches, shrink_instructions, AddressMap, CodeInfo, MemoryCodeSink, RelocSink, TrapSink,
 };
+use crate::cache::{CacheBackend, CacheKey, CachedFunction};
 use crate::dce::do_dce;
 use crate::dominator_tree::DominatorTree;
+use crate::ebb_layout::{do_ebb_layout, EdgeFrequencies};
 use crate::flowgraph::ControlFlowGraph;
-use crate::ir::Function;
+use crate::ir::{ArgumentPurpose, Function, Opcode};
 use crate::isa::TargetIsa;
 use crate::legalize_function;
 use crate::licm::do_licm;
@@ -23,7 +25,8 @@ use crate::loop_analysis::LoopAnalysis;
 use crate::nan_canonicalization::do_nan_canonicalization;
 use crate::postopt::do_postopt;
 use crate::regalloc;
-use crate::result::CodegenResult;
+use crate::result::{CodegenError, CodegenResult};
+use crate::scheduler::do_scheduling;
 use crate::settings::{FlagsOrIsa, OptLevel};
 use crate::simple_gvn::do_simple_gvn;
 use crate::simple_preopt::do_preopt;
@@ -31,6 +34,7 @@ use crate::timing;
 use crate::unreachable_code::eliminate_unreachable_code;
 use crate::value_label::{build_value_labels_ranges, ComparableSourceLoc, ValueLabelsRanges};
 use crate::verifier::{verify_context, verify_locations, VerifierErrors, VerifierResult};
+use std::sync::Arc;
 use std::vec::Vec;
 
 /// Persistent data structures and compilation pipeline.
@@ -49,6 +53,81 @@ pub struct Context {
 
     /// Loop analysis of `func`.
     pub loop_analysis: LoopAnalysis,
+
+    /// Profiled execution counts of the control flow edges in `func`.
+    ///
+    /// When available, these are used to place the EBBs of the function. Leave empty if there is
+    /// no profile.
+    pub edge_frequencies: EdgeFrequencies,
+
+    /// Cache of compiled functions consulted by `compile`.
+    ///
+    /// When `compile` finds `func` in the cache, it doesn't compile it, and `emit_to_memory` and
+    /// `address_map` use the cached function instead. In that case, the body of `func` and the
+    /// analyses above are cleared, since there is no compiled function to inspect, so only the
+    /// name and signature of `func` are left. Functions which collect debug information are never
+    /// looked up.
+    pub cache: Option<Arc<dyn CacheBackend>>,
+
+    /// Statistics about the last compilation of `func` by `compile`.
+    pub stats: CompileStats,
+
+    /// Whether `compile` counts the instructions of `func` for `stats`.
+    ///
+    /// Counting walks the whole function several times, so it is disabled by default, and the
+    /// counts are then left at zero.
+    pub collect_stats: bool,
+
+    /// The function found in the cache by the last call to `compile`.
+    cached: Option<CachedFunction>,
+}
+
+/// Statistics about the compilation of a function.
+///
+/// These are collected by `Context::compile` for every function, so that they can be aggregated
+/// over many functions. The instruction counts are only collected when `Context::collect_stats`
+/// is set.
+#[derive(Clone, Debug, Default)]
+pub struct CompileStats {
+    /// Time spent in each compilation pass.
+    ///
+    /// These timings are also added to the accumulated timings of the current thread.
+    pub pass_times: timing::PassTimes,
+
+    /// Whether the compiled function was found in the cache, in which case no passes were run and
+    /// the other statistics about the passes are zero.
+    pub cache_hit: bool,
+
+    /// Number of instructions in the function before legalization.
+    pub insts_before_legalize: usize,
+
+    /// Number of instructions in the function after legalization.
+    pub insts_after_legalize: usize,
+
+    /// Number of `spill` instructions inserted by the register allocator.
+    pub spills: usize,
+
+    /// Number of `fill` instructions inserted by the register allocator.
+    pub fills: usize,
+
+    /// Information about the function's code and read-only data, if it compiled successfully.
+    pub code_info: Option<CodeInfo>,
+}
+
+/// Count the instructions in `func`, and the `spill` and `fill` instructions among them.
+fn count_insts(func: &Function) -> (usize, usize, usize) {
+    let mut counts = (0, 0, 0);
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            counts.0 += 1;
+            match func.dfg[inst].opcode() {
+                Opcode::Spill => counts.1 += 1,
+                Opcode::Fill => counts.2 += 1,
+                _ => {}
+            }
+        }
+    }
+    counts
 }
 
 impl Context {
@@ -71,6 +150,11 @@ impl Context {
             domtree: DominatorTree::new(),
             regalloc: regalloc::Context::new(),
             loop_analysis: LoopAnalysis::new(),
+            edge_frequencies: EdgeFrequencies::new(),
+            cache: None,
+            stats: CompileStats::default(),
+            collect_stats: false,
+            cached: None,
         }
     }
 
@@ -81,6 +165,9 @@ impl Context {
         self.domtree.clear();
         self.regalloc.clear();
         self.loop_analysis.clear();
+        self.edge_frequencies.clear();
+        self.stats = CompileStats::default();
+        self.cached = None;
     }
 
     /// Compile the function, and emit machine code into a `Vec<u8>`.
@@ -116,9 +203,67 @@ impl Context {
     /// represented by `isa`. This does not include the final step of emitting machine code into a
     /// code sink.
     ///
+    /// If a `cache` is installed, it is looked up first, and the compiled function is inserted
+    /// into it on a miss. On a hit, the body of `func` is cleared. See `cache`.
+    ///
+    /// Statistics about the compilation are left in `stats`.
+    ///
     /// Returns information about the function's code and read-only data.
     pub fn compile(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
+        // Time the passes of this compilation separately from those accumulated so far.
+        #[cfg(feature = "std")]
+        let saved_times = timing::take_current();
+        self.stats = CompileStats::default();
+        let result = self.compile_timed(isa);
+        self.stats.pass_times = timing::take_current();
+        #[cfg(feature = "std")]
+        {
+            timing::add_to_current(&saved_times);
+            timing::add_to_current(&self.stats.pass_times);
+        }
+        self.stats.code_info = result.as_ref().ok().cloned();
+        result
+    }
+
+    /// Compile the function within the `compile` pass timing.
+    fn compile_timed(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
         let _tt = timing::compile();
+        self.cached = None;
+        let key = match self.cache {
+            Some(_) => self.cache_key(isa),
+            None => None,
+        };
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if let Some(cached) = cache.get(key).filter(CachedFunction::is_valid) {
+                let info = cached.info;
+                // Don't leave the uncompiled function around to be mistaken for the compiled one.
+                let name = self.func.name.clone();
+                let signature = self.func.signature.clone();
+                self.clear();
+                self.func.name = name;
+                self.func.signature = signature;
+                self.cached = Some(cached);
+                self.stats.cache_hit = true;
+                return Ok(info);
+            }
+        }
+
+        let info = self.compile_uncached(isa)?;
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            cache.insert(key, &CachedFunction::record(isa, &self.func, info));
+        }
+        Ok(info)
+    }
+
+    /// Compute the key identifying the function in a compilation cache.
+    ///
+    /// Returns `None` if the function can't be cached.
+    pub fn cache_key(&self, isa: &dyn TargetIsa) -> Option<CacheKey> {
+        CacheKey::compute(&self.func, &self.edge_frequencies, isa)
+    }
+
+    /// Run all the compilation passes, without looking up the cache.
+    fn compile_uncached(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
         self.verify_if(isa)?;
 
         self.compute_cfg();
@@ -128,7 +273,13 @@ impl Context {
         if isa.flags().enable_nan_canonicalization() {
             self.canonicalize_nans(isa)?;
         }
+        if self.collect_stats {
+            self.stats.insts_before_legalize = count_insts(&self.func).0;
+        }
         self.legalize(isa)?;
+        if self.collect_stats {
+            self.stats.insts_after_legalize = count_insts(&self.func).0;
+        }
         if isa.flags().opt_level() != OptLevel::Fastest {
             self.postopt(isa)?;
         }
@@ -142,10 +293,25 @@ impl Context {
         self.eliminate_unreachable_code(isa)?;
         if isa.flags().opt_level() != OptLevel::Fastest {
             self.dce(isa)?;
+            self.ebb_layout(isa)?;
+        }
+        if self.collect_stats {
+            let (_, spills, fills) = count_insts(&self.func);
+            self.regalloc(isa)?;
+            let (_, spills_after, fills_after) = count_insts(&self.func);
+            self.stats.spills = spills_after.saturating_sub(spills);
+            self.stats.fills = fills_after.saturating_sub(fills);
+        } else {
+            self.regalloc(isa)?;
+        }
+        // The value label ranges are computed from the register allocator's live ranges, which
+        // would no longer match the code after scheduling.
+        if isa.flags().enable_post_ra_scheduling() && self.func.dfg.values_labels.is_none() {
+            self.schedule(isa)?;
         }
-        self.regalloc(isa)?;
         self.prologue_epilogue(isa)?;
-        if isa.flags().opt_level() == OptLevel::Best {
+        let opt_level = isa.flags().opt_level();
+        if opt_level == OptLevel::Best || opt_level == OptLevel::Size {
             self.shrink_instructions(isa)?;
         }
         self.relax_branches(isa)
@@ -157,6 +323,8 @@ impl Context {
     /// code is returned by `compile` above.
     ///
     /// The machine code is not relocated. Instead, any relocations are emitted into `relocs`.
+    /// If `compile` found the function in the cache, the cached code is copied to `mem`, and the
+    /// cached relocations and traps are emitted.
     ///
     /// This function is unsafe since it does not perform bounds checking on the memory buffer,
     /// and it can't guarantee that the `mem` pointer is valid.
@@ -170,11 +338,25 @@ impl Context {
         traps: &mut dyn TrapSink,
     ) -> CodeInfo {
         let _tt = timing::binemit();
+        if let Some(cached) = &self.cached {
+            return cached.emit_to_memory(mem, relocs, traps);
+        }
         let mut sink = MemoryCodeSink::new(mem, relocs, traps);
         isa.emit_function_to_memory(&self.func, &mut sink);
         sink.info
     }
 
+    /// Get the source locations of the machine code emitted by `emit_to_memory`.
+    ///
+    /// If `compile` found the function in the cache, the cached address map is returned. The map
+    /// is empty if the function hasn't been compiled.
+    pub fn address_map(&self, isa: &dyn TargetIsa) -> AddressMap {
+        match &self.cached {
+            Some(cached) => cached.address_map.clone(),
+            None => AddressMap::new(&self.func, isa),
+        }
+    }
+
     /// Run the verifier on the function.
     ///
     /// Also check that the dominator tree and control flow graph are consistent with the function.
@@ -244,6 +426,25 @@ impl Context {
         // TODO: Avoid doing this when legalization doesn't actually mutate the CFG.
         self.domtree.clear();
         self.loop_analysis.clear();
+        if !isa.supports_tls() && self.func.global_values.values().any(|gv| gv.is_tls()) {
+            return Err(CodegenError::Unsupported(
+                "thread-local global values without a tls_model setting for this target",
+            ));
+        }
+        let signatures = Some(&self.func.signature)
+            .into_iter()
+            .chain(self.func.dfg.signatures.values());
+        for sig in signatures {
+            for param in &sig.params {
+                if let ArgumentPurpose::StructArgument(size, _) = param.purpose {
+                    if !isa.supports_struct_argument(sig.call_conv, size) {
+                        return Err(CodegenError::Unsupported(
+                            "struct arguments that the calling convention passes in registers",
+                        ));
+                    }
+                }
+            }
+        }
         legalize_function(&mut self.func, &mut self.cfg, isa);
         self.verify_if(isa)
     }
@@ -304,12 +505,29 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Reorder the EBBs of the function, moving cold EBBs to the end of the function.
+    pub fn ebb_layout<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        if do_ebb_layout(&mut self.func, &mut self.cfg, &self.edge_frequencies) {
+            // Branches may have been inverted, which invalidates the dominator tree.
+            self.compute_domtree();
+        }
+        self.verify_if(fisa)
+    }
+
     /// Run the register allocator.
     pub fn regalloc(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         self.regalloc
             .run(isa, &mut self.func, &self.cfg, &mut self.domtree)
     }
 
+    /// Reorder the instructions of each EBB to hide latencies after register allocation.
+    pub fn schedule(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
+        do_scheduling(&mut self.func, isa);
+        self.verify_if(isa)?;
+        self.verify_locations_if(isa)?;
+        Ok(())
+    }
+
     /// Insert prologue and epilogues after computing the stack frame layout.
     pub fn prologue_epilogue(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         isa.prologue_epilogue(&mut self.func)?;
@@ -329,7 +547,11 @@ impl Context {
     /// Run the branch relaxation pass and return information about the function's code and
     /// read-only data.
     pub fn relax_branches(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
-        let info = relax_branches(&mut self.func, isa)?;
+        let info = relax_branches(&mut self.func, &mut self.cfg, isa)?;
+        // Branches inverted when optimizing for size are dominated by different instructions.
+        if isa.flags().opt_level() == OptLevel::Size {
+            self.compute_domtree();
+        }
         self.verify_if(isa)?;
         self.verify_locations_if(isa)?;
         Ok(info)
@@ -347,3 +569,211 @@ impl Context {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{self, types, AbiParam, ExternalName, InstBuilder, MemFlags, Signature};
+    use crate::isa::{self, CallConv};
+    use crate::settings::{self, Configurable};
+    use core::str::FromStr;
+    use target_lexicon::triple;
+
+    #[test]
+    fn unsupported_stack_limit() {
+        // A function with a stack frame, checked against a limit stored in the VM context.
+        let limited = |ty| {
+            let mut sig = Signature::new(CallConv::SystemV);
+            sig.params
+                .push(AbiParam::special(ty, ir::ArgumentPurpose::VMContext));
+            let mut func = Function::with_name_signature(ExternalName::testcase("limit"), sig);
+            let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+            func.stack_limit = Some(func.create_global_value(ir::GlobalValueData::Load {
+                base: vmctx,
+                offset: 0.into(),
+                global_type: ty,
+                readonly: true,
+            }));
+            func.create_stack_slot(ir::StackSlotData::new(ir::StackSlotKind::ExplicitSlot, 64));
+            let ebb0 = func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            pos.func.dfg.append_ebb_param(ebb0, ty);
+            pos.ins().return_(&[]);
+            func
+        };
+        let flags = settings::Flags::new(settings::builder());
+
+        // RISC-V doesn't implement stack limit checks.
+        let isa = isa::lookup(triple!("riscv32"))
+            .unwrap()
+            .finish(flags.clone());
+        let mut ctx = Context::for_function(limited(types::I32));
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::Unsupported("stack limit checks"))
+        );
+
+        // 32-bit x86 passes the VM context on the stack.
+        let isa = isa::lookup(triple!("i686")).unwrap().finish(flags.clone());
+        let mut ctx = Context::for_function(limited(types::I32));
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::Unsupported(
+                "stack limit checks with parameters passed on the stack"
+            ))
+        );
+
+        let isa = isa::lookup(triple!("x86_64")).unwrap().finish(flags);
+        let mut ctx = Context::for_function(limited(types::I64));
+        assert!(ctx.compile(&*isa).is_ok());
+    }
+
+    #[test]
+    fn unsupported_tls() {
+        // A function returning the address of a thread-local symbol.
+        let tls_address = || {
+            let mut sig = Signature::new(CallConv::SystemV);
+            sig.returns.push(AbiParam::new(types::I64));
+            let mut func = Function::with_name_signature(ExternalName::testcase("tls"), sig);
+            let gv = func.create_global_value(ir::GlobalValueData::Symbol {
+                name: ExternalName::testcase("var"),
+                offset: 0.into(),
+                colocated: false,
+                tls: true,
+            });
+            let ebb0 = func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let addr = pos.ins().global_value(types::I64, gv);
+            pos.ins().return_(&[addr]);
+            func
+        };
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut ctx = Context::for_function(tls_address());
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::Unsupported(
+                "thread-local global values without a tls_model setting for this target"
+            ))
+        );
+
+        let mut flags = settings::builder();
+        flags.set("tls_model", "elf_gd").unwrap();
+        flags.enable("is_pic").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut ctx = Context::for_function(tls_address());
+        assert!(ctx.compile(&*isa).is_ok());
+    }
+
+    #[test]
+    fn struct_arguments() {
+        // A function taking a struct of `size` bytes by value.
+        let struct_arg = |ty, call_conv, size| {
+            let mut sig = Signature::new(call_conv);
+            sig.params.push(AbiParam::special(
+                ty,
+                ArgumentPurpose::StructArgument(size, 0),
+            ));
+            let mut func = Function::with_name_signature(ExternalName::testcase("sarg"), sig);
+            let ebb0 = func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let ptr = pos.func.dfg.append_ebb_param(ebb0, ty);
+            let v = pos.ins().load(ty, MemFlags::new(), ptr, 0);
+            pos.ins().store(MemFlags::new(), v, ptr, 0);
+            pos.ins().return_(&[]);
+            func
+        };
+        let flags = settings::Flags::new(settings::builder());
+
+        // x86 passes small structs in registers, and the rest on the stack or by reference.
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(flags.clone());
+        for &(call_conv, size) in &[
+            (CallConv::SystemV, 1),
+            (CallConv::SystemV, 16),
+            (CallConv::SystemV, 17),
+            (CallConv::WindowsFastcall, 8),
+            (CallConv::WindowsFastcall, 3),
+        ] {
+            let mut ctx = Context::for_function(struct_arg(types::I64, call_conv, size));
+            assert!(ctx.compile(&*isa).is_ok());
+        }
+
+        // RISC-V doesn't classify structs that fit in two registers yet, including those in the
+        // signatures of callees.
+        let isa = isa::lookup(triple!("riscv64")).unwrap().finish(flags);
+        let unsupported = Err(CodegenError::Unsupported(
+            "struct arguments that the calling convention passes in registers",
+        ));
+        let mut ctx = Context::for_function(struct_arg(types::I64, CallConv::SystemV, 16));
+        assert_eq!(ctx.compile(&*isa), unsupported);
+
+        let mut func = struct_arg(types::I64, CallConv::SystemV, 24);
+        let mut callee = Signature::new(CallConv::SystemV);
+        callee.params.push(AbiParam::special(
+            types::I64,
+            ArgumentPurpose::StructArgument(8, 0),
+        ));
+        func.import_signature(callee);
+        let mut ctx = Context::for_function(func);
+        assert_eq!(ctx.compile(&*isa), unsupported);
+    }
+
+    #[test]
+    fn compile_stats() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        // A function keeping a value live across a call, which clobbers all the registers.
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        let mut func = Function::with_name_signature(ExternalName::testcase("stats"), sig);
+        let callee = func.import_signature(Signature::new(CallConv::SystemV));
+        let ebb0 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+        pos.ins().call_indirect(callee, v0, &[]);
+        let v1 = pos.ins().iadd_imm(v0, 1);
+        pos.ins().return_(&[v1]);
+
+        let mut ctx = Context::for_function(func.clone());
+        ctx.collect_stats = true;
+        let info = ctx.compile(&*isa).unwrap();
+        let stats = &ctx.stats;
+        assert!(!stats.cache_hit);
+        assert_eq!(stats.insts_before_legalize, 3);
+        assert!(stats.insts_after_legalize >= 3);
+        assert_eq!((stats.spills, stats.fills), (1, 2));
+        assert_eq!(stats.code_info, Some(info));
+        assert!(stats
+            .pass_times
+            .iter()
+            .any(|(name, _, _)| name == "regalloc"));
+        assert!(
+            timing::take_current().get("compile").unwrap().total
+                >= stats.pass_times.get("compile").unwrap().total
+        );
+
+        ctx.clear();
+        assert_eq!(ctx.stats.code_info, None);
+
+        // Instructions aren't counted unless requested.
+        let mut ctx = Context::for_function(func);
+        assert_eq!(ctx.compile(&*isa).unwrap(), info);
+        assert_eq!(ctx.stats.insts_before_legalize, 0);
+        assert_eq!((ctx.stats.spills, ctx.stats.fills), (0, 0));
+        assert_eq!(ctx.stats.code_info, Some(info));
+    }
+}
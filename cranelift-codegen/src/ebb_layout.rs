@@ -0,0 +1,275 @@
+//! EBB layout optimization.
+//!
+//! This pass decides the final order of the EBBs in a function. EBBs that are expected to execute
+//! rarely, either because they have been marked as cold with `Layout::set_cold()` or because a
+//! profile says they never execute, are sunk to the end of the function. This keeps the hot code
+//! together and lets the conditional branches on the hot path fall through to their likely
+//! successor.
+//!
+//! When edge frequencies are available, hot EBBs are also chained together so that each EBB is
+//! followed by its most frequently executed successor.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::entity::EntitySet;
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::{Ebb, Function, Opcode};
+use crate::simple_preopt::branch_order;
+use crate::timing;
+use crate::HashMap;
+use std::vec::Vec;
+
+/// Execution counts of control flow edges, typically collected by a profiler.
+///
+/// Edges are identified by their source and destination EBBs. An edge without a recorded count
+/// is considered to have an unknown frequency, not a zero frequency.
+#[derive(Clone, Debug, Default)]
+pub struct EdgeFrequencies {
+    counts: HashMap<(Ebb, Ebb), u64>,
+}
+
+impl EdgeFrequencies {
+    /// Create a new empty set of edge frequencies.
+    pub fn new() -> Self {
+        Self {
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Clear all recorded counts.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    /// Have no counts been recorded?
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Record that the edge from `from` to `to` was taken `count` times.
+    ///
+    /// Counts recorded for the same edge accumulate.
+    pub fn add(&mut self, from: Ebb, to: Ebb, count: u64) {
+        let total = self.counts.entry((from, to)).or_insert(0);
+        *total = total.saturating_add(count);
+    }
+
+    /// Get the recorded execution count of the edge from `from` to `to`.
+    pub fn get(&self, from: Ebb, to: Ebb) -> Option<u64> {
+        self.counts.get(&(from, to)).cloned()
+    }
+
+    /// Iterate over the recorded `(from, to, count)` edges, in no particular order.
+    pub(crate) fn iter<'a>(&'a self) -> impl Iterator<Item = (Ebb, Ebb, u64)> + 'a {
+        self.counts
+            .iter()
+            .map(|(&(from, to), &count)| (from, to, count))
+    }
+}
+
+/// Reorder the EBBs in `func` so that cold EBBs come last and hot paths fall through.
+///
+/// Returns `true` if the function was changed. Since branches may have been inverted, the
+/// dominator tree must be recomputed in that case.
+pub fn do_ebb_layout(
+    func: &mut Function,
+    cfg: &mut ControlFlowGraph,
+    freqs: &EdgeFrequencies,
+) -> bool {
+    let _tt = timing::ebb_layout();
+    debug_assert!(cfg.is_valid());
+
+    let entry = match func.layout.entry_block() {
+        Some(entry) => entry,
+        None => return false,
+    };
+
+    if freqs.is_empty() && !func.layout.ebbs().any(|ebb| func.layout.is_cold(ebb)) {
+        return false;
+    }
+
+    // Fall-through instructions tie an EBB to its layout successor, so don't touch functions
+    // that already contain them.
+    if has_fallthroughs(func) {
+        return false;
+    }
+
+    let mut hot = Vec::new();
+    let mut cold = Vec::new();
+    for ebb in func.layout.ebbs() {
+        if ebb != entry && (func.layout.is_cold(ebb) || never_executed(ebb, cfg, freqs)) {
+            cold.push(ebb);
+        } else {
+            hot.push(ebb);
+        }
+    }
+
+    let mut order = if freqs.is_empty() {
+        hot
+    } else {
+        chain_hot_ebbs(&hot, cfg, freqs)
+    };
+    order.extend(cold);
+
+    if func.layout.ebbs().eq(order.iter().cloned()) {
+        return false;
+    }
+    func.layout.reorder_ebbs(&order);
+
+    // Now that the EBBs are in place, rearrange the terminating branches so the unconditional
+    // jump to the layout successor can become a fall-through.
+    let mut pos = FuncCursor::new(func);
+    while let Some(ebb) = pos.next_ebb() {
+        if let Some(inst) = pos.func.layout.last_inst(ebb) {
+            branch_order(&mut pos, cfg, ebb, inst);
+        }
+    }
+
+    true
+}
+
+/// Does `func` contain any instructions that fall through to the next EBB?
+fn has_fallthroughs(func: &Function) -> bool {
+    func.layout.ebbs().any(|ebb| {
+        func.layout
+            .last_inst(ebb)
+            .map_or(false, |inst| match func.dfg[inst].opcode() {
+                Opcode::Fallthrough | Opcode::FallthroughReturn => true,
+                _ => false,
+            })
+    })
+}
+
+/// Does the profile say that `ebb` is never executed?
+///
+/// This is only the case when a zero count has been recorded for every incoming edge.
+fn never_executed(ebb: Ebb, cfg: &ControlFlowGraph, freqs: &EdgeFrequencies) -> bool {
+    let mut preds = cfg.pred_iter(ebb).peekable();
+    preds.peek().is_some() && preds.all(|pred| freqs.get(pred.ebb, ebb) == Some(0))
+}
+
+/// Order the `hot` EBBs so that each one is followed by its most frequently executed successor,
+/// when that successor hasn't been placed already.
+///
+/// The chains are started in the original layout order, so the entry block remains first.
+fn chain_hot_ebbs(hot: &[Ebb], cfg: &ControlFlowGraph, freqs: &EdgeFrequencies) -> Vec<Ebb> {
+    let mut is_hot = EntitySet::new();
+    for &ebb in hot {
+        is_hot.insert(ebb);
+    }
+
+    let mut placed = EntitySet::new();
+    let mut order = Vec::with_capacity(hot.len());
+    for &head in hot {
+        if placed.contains(head) {
+            continue;
+        }
+        let mut ebb = head;
+        loop {
+            placed.insert(ebb);
+            order.push(ebb);
+
+            let next = cfg
+                .succ_iter(ebb)
+                .filter(|&succ| is_hot.contains(succ) && !placed.contains(succ))
+                .filter_map(|succ| match freqs.get(ebb, succ) {
+                    Some(count) if count > 0 => Some((count, succ)),
+                    _ => None,
+                })
+                .max_by_key(|&(count, _)| count);
+
+            match next {
+                Some((_, succ)) => ebb = succ,
+                None => break,
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::{InstBuilder, TrapCode};
+
+    /// Build a function with a conditional branch to a trapping EBB:
+    ///
+    /// ```clif
+    /// ebb0(v0: i32):
+    ///     brz v0, ebb2
+    ///     jump ebb1
+    /// ebb1:
+    ///     trap user0
+    /// ebb2:
+    ///     return
+    /// ```
+    fn make_function() -> (Function, [Ebb; 3]) {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, I32);
+            pos.ins().brz(v0, ebb2, &[]);
+            pos.ins().jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            pos.ins().trap(TrapCode::User(0));
+            pos.insert_ebb(ebb2);
+            pos.ins().return_(&[]);
+        }
+        (func, [ebb0, ebb1, ebb2])
+    }
+
+    #[test]
+    fn no_cold_ebbs() {
+        let (mut func, [ebb0, ebb1, ebb2]) = make_function();
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        assert!(!do_ebb_layout(&mut func, &mut cfg, &EdgeFrequencies::new()));
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb1, ebb2]);
+    }
+
+    #[test]
+    fn sink_cold_ebb() {
+        let (mut func, [ebb0, ebb1, ebb2]) = make_function();
+        func.layout.set_cold(ebb1);
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        assert!(do_ebb_layout(&mut func, &mut cfg, &EdgeFrequencies::new()));
+
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb2, ebb1]);
+
+        // The branch to the cold EBB is now the conditional one.
+        let mut insts = func.layout.ebb_insts(ebb0);
+        let br = insts.next().unwrap();
+        let jump = insts.next().unwrap();
+        assert_eq!(func.dfg[br].opcode(), Opcode::Brnz);
+        assert_eq!(func.dfg[br].branch_destination(), Some(ebb1));
+        assert_eq!(func.dfg[jump].branch_destination(), Some(ebb2));
+    }
+
+    #[test]
+    fn entry_stays_first() {
+        let (mut func, [ebb0, ebb1, ebb2]) = make_function();
+        func.layout.set_cold(ebb0);
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        assert!(!do_ebb_layout(&mut func, &mut cfg, &EdgeFrequencies::new()));
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb1, ebb2]);
+    }
+
+    #[test]
+    fn profile_driven() {
+        let (mut func, [ebb0, ebb1, ebb2]) = make_function();
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        let mut freqs = EdgeFrequencies::new();
+        freqs.add(ebb0, ebb1, 0);
+        freqs.add(ebb0, ebb2, 1000);
+        assert!(do_ebb_layout(&mut func, &mut cfg, &freqs));
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb2, ebb1]);
+    }
+}
@@ -0,0 +1,154 @@
+//! Immediates for atomic memory instructions.
+
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// The operation performed by an `atomic_rmw` instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum AtomicRmwOp {
+    /// Add the operand to the value in memory.
+    Add,
+    /// Subtract the operand from the value in memory.
+    Sub,
+    /// Bitwise and of the operand and the value in memory.
+    And,
+    /// Bitwise or of the operand and the value in memory.
+    Or,
+    /// Bitwise xor of the operand and the value in memory.
+    Xor,
+    /// Replace the value in memory with the operand.
+    Xchg,
+}
+
+impl Display for AtomicRmwOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::AtomicRmwOp::*;
+        f.write_str(match *self {
+            Add => "add",
+            Sub => "sub",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            Xchg => "xchg",
+        })
+    }
+}
+
+impl FromStr for AtomicRmwOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::AtomicRmwOp::*;
+        match s {
+            "add" => Ok(Add),
+            "sub" => Ok(Sub),
+            "and" => Ok(And),
+            "or" => Ok(Or),
+            "xor" => Ok(Xor),
+            "xchg" => Ok(Xchg),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The memory ordering constraint of an atomic instruction.
+///
+/// These have the same meaning as the orderings of the C++11 memory model.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum AtomicOrdering {
+    /// The access is atomic, but imposes no ordering on other memory accesses.
+    Relaxed,
+    /// No memory accesses following the instruction can be moved before it.
+    Acquire,
+    /// No memory accesses preceding the instruction can be moved after it.
+    Release,
+    /// Both `Acquire` and `Release`.
+    AcqRel,
+    /// `AcqRel`, and all sequentially consistent instructions are observed in the same order by
+    /// all threads.
+    SeqCst,
+}
+
+impl AtomicOrdering {
+    /// Does this ordering have acquire semantics?
+    pub fn is_acquire(self) -> bool {
+        match self {
+            AtomicOrdering::Acquire | AtomicOrdering::AcqRel | AtomicOrdering::SeqCst => true,
+            AtomicOrdering::Relaxed | AtomicOrdering::Release => false,
+        }
+    }
+
+    /// Does this ordering have release semantics?
+    pub fn is_release(self) -> bool {
+        match self {
+            AtomicOrdering::Release | AtomicOrdering::AcqRel | AtomicOrdering::SeqCst => true,
+            AtomicOrdering::Relaxed | AtomicOrdering::Acquire => false,
+        }
+    }
+}
+
+impl Display for AtomicOrdering {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::AtomicOrdering::*;
+        f.write_str(match *self {
+            Relaxed => "relaxed",
+            Acquire => "acquire",
+            Release => "release",
+            AcqRel => "acq_rel",
+            SeqCst => "seq_cst",
+        })
+    }
+}
+
+impl FromStr for AtomicOrdering {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::AtomicOrdering::*;
+        match s {
+            "relaxed" => Ok(Relaxed),
+            "acquire" => Ok(Acquire),
+            "release" => Ok(Release),
+            "acq_rel" => Ok(AcqRel),
+            "seq_cst" => Ok(SeqCst),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn display() {
+        for &op in &[
+            AtomicRmwOp::Add,
+            AtomicRmwOp::Sub,
+            AtomicRmwOp::And,
+            AtomicRmwOp::Or,
+            AtomicRmwOp::Xor,
+            AtomicRmwOp::Xchg,
+        ] {
+            assert_eq!(op.to_string().parse(), Ok(op));
+        }
+        assert_eq!("nand".parse::<AtomicRmwOp>(), Err(()));
+
+        for &ordering in &[
+            AtomicOrdering::Relaxed,
+            AtomicOrdering::Acquire,
+            AtomicOrdering::Release,
+            AtomicOrdering::AcqRel,
+            AtomicOrdering::SeqCst,
+        ] {
+            assert_eq!(ordering.to_string().parse(), Ok(ordering));
+        }
+        assert_eq!(AtomicOrdering::AcqRel.to_string(), "acq_rel");
+        assert_eq!("consume".parse::<AtomicOrdering>(), Err(()));
+    }
+}
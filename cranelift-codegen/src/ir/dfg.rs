@@ -18,6 +18,8 @@ use core::iter;
 use core::mem;
 use core::ops::{Index, IndexMut};
 use core::u16;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// A data flow graph defines all instructions and extended basic blocks in a function as well as
@@ -28,6 +30,7 @@ use std::collections::HashMap;
 /// `Layout` data structure which forms the other half of the function representation.
 ///
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct DataFlowGraph {
     /// Data about all of the instructions in the function, including opcodes and operands.
     /// The instructions in this map are not in program order. That is tracked by `Layout`, along
@@ -399,6 +402,7 @@ impl ValueDef {
 
 /// Internal table storage for extended values.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 enum ValueData {
     /// Value is defined by an instruction.
     Inst { ty: Type, num: u16, inst: Inst },
@@ -895,6 +899,7 @@ impl DataFlowGraph {
 /// branches to this EBB must provide matching arguments, and the arguments to the entry EBB must
 /// match the function arguments.
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 struct EbbData {
     /// List of parameters to this EBB.
     params: ValueList,
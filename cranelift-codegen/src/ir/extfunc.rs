@@ -9,6 +9,8 @@ use crate::ir::{ArgumentLoc, ExternalName, SigRef, Type};
 use crate::isa::{CallConv, RegInfo, RegUnit};
 use core::fmt;
 use core::str::FromStr;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// Function signature.
@@ -19,6 +21,7 @@ use std::vec::Vec;
 /// A signature can optionally include ISA-specific ABI information which specifies exactly how
 /// arguments and return values are passed.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct Signature {
     /// The arguments passed to the function.
     pub params: Vec<AbiParam>,
@@ -97,6 +100,7 @@ impl fmt::Display for Signature {
 /// This describes the value type being passed to or from a function along with flags that affect
 /// how the argument is passed.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct AbiParam {
     /// Type of the argument value.
     pub value_type: Type,
@@ -199,6 +203,7 @@ impl fmt::Display for AbiParam {
 /// On some architectures, small integer function arguments are extended to the width of a
 /// general-purpose register.
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum ArgumentExtension {
     /// No extension, high bits are indeterminate.
     None,
@@ -216,6 +221,7 @@ pub enum ArgumentExtension {
 ///
 /// The argument purpose is used to indicate any special meaning of an argument or return value.
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum ArgumentPurpose {
     /// A normal user program value passed to or from a function.
     Normal,
@@ -270,23 +276,42 @@ pub enum ArgumentPurpose {
     /// This is a pointer to a stack limit. It is used to check the current stack pointer
     /// against. Can only appear once in a signature.
     StackLimit,
-}
 
-/// Text format names of the `ArgumentPurpose` variants.
-static PURPOSE_NAMES: [&str; 8] = [
-    "normal",
-    "sret",
-    "link",
-    "fp",
-    "csr",
-    "vmctx",
-    "sigid",
-    "stack_limit",
-];
+    /// A struct passed by value, with the given size in bytes and a mask of its bytes that don't
+    /// hold integer data.
+    ///
+    /// The argument value is a pointer to the struct. Calling conventions that pass aggregates
+    /// in memory, such as System V on x86-64, copy the struct into the outgoing argument area on
+    /// the stack, and the callee receives a pointer to its incoming copy. Conventions that pass
+    /// large aggregates by reference, such as `WindowsFastcall`, pass the pointer unchanged, so
+    /// it must point to a copy that the callee is allowed to modify.
+    ///
+    /// Small structs are passed in registers by most C ABIs, which classify them by the types of
+    /// their fields. Bit `i` of the mask is set when byte `i` of the struct is part of a floating
+    /// point field or is padding. Only the first 16 bytes are described, and the fields are
+    /// expected to be naturally aligned. The legalizer loads the register-sized parts of such a
+    /// struct at a call, and the callee receives a pointer to a stack slot holding them.
+    ///
+    /// This argument kind can only appear as a parameter.
+    StructArgument(u32, u16),
+}
 
 impl fmt::Display for ArgumentPurpose {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(PURPOSE_NAMES[*self as usize])
+        match *self {
+            ArgumentPurpose::Normal => f.write_str("normal"),
+            ArgumentPurpose::StructReturn => f.write_str("sret"),
+            ArgumentPurpose::Link => f.write_str("link"),
+            ArgumentPurpose::FramePointer => f.write_str("fp"),
+            ArgumentPurpose::CalleeSaved => f.write_str("csr"),
+            ArgumentPurpose::VMContext => f.write_str("vmctx"),
+            ArgumentPurpose::SignatureId => f.write_str("sigid"),
+            ArgumentPurpose::StackLimit => f.write_str("stack_limit"),
+            ArgumentPurpose::StructArgument(size, 0) => write!(f, "sarg({})", size),
+            ArgumentPurpose::StructArgument(size, mask) => {
+                write!(f, "sarg({}, {:#x})", size, mask)
+            }
+        }
     }
 }
 
@@ -302,6 +327,21 @@ impl FromStr for ArgumentPurpose {
             "vmctx" => Ok(ArgumentPurpose::VMContext),
             "sigid" => Ok(ArgumentPurpose::SignatureId),
             "stack_limit" => Ok(ArgumentPurpose::StackLimit),
+            _ if s.starts_with("sarg(") && s.ends_with(')') => {
+                let mut fields = s[5..s.len() - 1].split(',').map(str::trim);
+                let size = fields.next().ok_or(())?.parse().map_err(|_| ())?;
+                let mask = match fields.next() {
+                    None => 0,
+                    Some(mask) if mask.starts_with("0x") => {
+                        u16::from_str_radix(&mask[2..], 16).map_err(|_| ())?
+                    }
+                    Some(_) => return Err(()),
+                };
+                if fields.next().is_some() {
+                    return Err(());
+                }
+                Ok(ArgumentPurpose::StructArgument(size, mask))
+            }
             _ => Err(()),
         }
     }
@@ -311,6 +351,7 @@ impl FromStr for ArgumentPurpose {
 ///
 /// Information about a function that can be called directly with a direct `call` instruction.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct ExtFuncData {
     /// Name of the external function.
     pub name: ExternalName,
@@ -359,11 +400,30 @@ mod tests {
             ArgumentPurpose::VMContext,
             ArgumentPurpose::SignatureId,
             ArgumentPurpose::StackLimit,
+            ArgumentPurpose::StructArgument(24, 0),
+            ArgumentPurpose::StructArgument(12, 0xff0),
+        ];
+        let names = [
+            "normal",
+            "sret",
+            "link",
+            "fp",
+            "csr",
+            "vmctx",
+            "sigid",
+            "stack_limit",
+            "sarg(24)",
+            "sarg(12, 0xff0)",
         ];
-        for (&e, &n) in all_purpose.iter().zip(PURPOSE_NAMES.iter()) {
+        for (&e, &n) in all_purpose.iter().zip(names.iter()) {
             assert_eq!(e.to_string(), n);
             assert_eq!(Ok(e), n.parse());
         }
+        assert_eq!("sarg".parse::<ArgumentPurpose>(), Err(()));
+        assert_eq!("sarg(x)".parse::<ArgumentPurpose>(), Err(()));
+        assert_eq!("sarg(12, 255)".parse::<ArgumentPurpose>(), Err(()));
+        assert_eq!("sarg(12, 0x10000)".parse::<ArgumentPurpose>(), Err(()));
+        assert_eq!("24)".parse::<ArgumentPurpose>(), Err(()));
     }
 
     #[test]
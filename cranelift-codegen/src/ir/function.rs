@@ -18,6 +18,8 @@ use crate::regalloc::RegDiversions;
 use crate::value_label::ValueLabelsRanges;
 use crate::write::write_function;
 use core::fmt;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "basic-blocks")]
 use crate::ir::{Inst, Opcode};
@@ -27,6 +29,7 @@ use crate::ir::{Inst, Opcode};
 /// Functions can be cloned, but it is not a very fast operation.
 /// The clone will have all the same entity numbers as the original.
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct Function {
     /// Name of this function. Mostly used by `.clif` files.
     pub name: ExternalName,
@@ -77,6 +80,16 @@ pub struct Function {
     /// Track the original source location for each instruction. The source locations are not
     /// interpreted by Cranelift, only preserved.
     pub srclocs: SourceLocs,
+
+    /// Global value holding the lowest address the stack pointer may reach.
+    ///
+    /// When present, the prologue checks that the stack frame of the function fits above the
+    /// limit, and traps with `TrapCode::StackOverflow` otherwise. The global value must be computed
+    /// from the `vmctx` parameter with `load` and `iadd_imm` global values. This is an alternative
+    /// to a `stack_limit` parameter. It is currently only supported by the x86 System V and fastcall
+    /// calling conventions, with the `vmctx` parameter passed in a register; compilation fails with
+    /// `CodegenError::Unsupported` otherwise.
+    pub stack_limit: Option<ir::GlobalValue>,
 }
 
 impl Function {
@@ -97,6 +110,7 @@ impl Function {
             offsets: SecondaryMap::new(),
             jt_offsets: SecondaryMap::new(),
             srclocs: SecondaryMap::new(),
+            stack_limit: None,
         }
     }
 
@@ -114,6 +128,7 @@ impl Function {
         self.locations.clear();
         self.offsets.clear();
         self.srclocs.clear();
+        self.stack_limit = None;
     }
 
     /// Create a new empty, anonymous function with a Fast calling convention.
@@ -183,6 +198,15 @@ impl Function {
             .map(|i| self.dfg.ebb_params(entry)[i])
     }
 
+    /// Is this a leaf function, which doesn't call any other function?
+    pub fn is_leaf(&self) -> bool {
+        !self.layout.ebbs().any(|ebb| {
+            self.layout
+                .ebb_insts(ebb)
+                .any(|inst| self.dfg[inst].opcode().is_call())
+        })
+    }
+
     /// Get an iterator over the instructions in `ebb`, including offsets and encoded instruction
     /// sizes.
     ///
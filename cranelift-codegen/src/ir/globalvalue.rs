@@ -4,9 +4,12 @@ use crate::ir::immediates::{Imm64, Offset32};
 use crate::ir::{ExternalName, GlobalValue, Type};
 use crate::isa::TargetIsa;
 use core::fmt;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 /// Information about a global value declaration.
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum GlobalValueData {
     /// Value is the address of the VM context struct.
     VMContext,
@@ -63,6 +66,10 @@ pub enum GlobalValueData {
         /// away, after linking? If so, references to it can avoid going through a GOT. Note that
         /// symbols meant to be preemptible cannot be colocated.
         colocated: bool,
+
+        /// Does this symbol refer to a thread local storage value? The address of a TLS symbol is
+        /// different in each thread, and it is computed according to the `tls_model` setting.
+        tls: bool,
     },
 }
 
@@ -75,6 +82,22 @@ impl GlobalValueData {
         }
     }
 
+    /// Assume that `self` is an `GlobalValueData::Symbol` and return its offset.
+    pub fn symbol_offset(&self) -> Imm64 {
+        match *self {
+            GlobalValueData::Symbol { offset, .. } => offset,
+            _ => panic!("only symbols have offsets"),
+        }
+    }
+
+    /// Is this a thread-local symbol?
+    pub fn is_tls(&self) -> bool {
+        match *self {
+            GlobalValueData::Symbol { tls, .. } => tls,
+            _ => false,
+        }
+    }
+
     /// Return the type of this global.
     pub fn global_type(&self, isa: &dyn TargetIsa) -> Type {
         match *self {
@@ -113,11 +136,13 @@ impl fmt::Display for GlobalValueData {
                 ref name,
                 offset,
                 colocated,
+                tls,
             } => {
                 write!(
                     f,
-                    "symbol {}{}",
+                    "symbol {}{}{}",
                     if colocated { "colocated " } else { "" },
+                    if tls { "tls " } else { "" },
                     name
                 )?;
                 let offset_val: i64 = offset.into();
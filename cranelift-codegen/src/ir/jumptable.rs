@@ -6,12 +6,15 @@
 use crate::ir::entities::Ebb;
 use core::fmt::{self, Display, Formatter};
 use core::slice::{Iter, IterMut};
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// Contents of a jump table.
 ///
 /// All jump tables use 0-based indexing and densely populated.
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct JumpTableData {
     // Table entries.
     table: Vec<Ebb>,
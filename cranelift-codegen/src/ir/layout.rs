@@ -11,6 +11,8 @@ use crate::timing;
 use core::cmp;
 use core::iter::{IntoIterator, Iterator};
 use log::debug;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 /// The `Layout` struct determines the layout of EBBs and instructions in a function. It does not
 /// contain definitions of instructions or EBBs, but depends on `Inst` and `Ebb` entity references
@@ -26,6 +28,7 @@ use log::debug;
 /// dependencies, so part of the semantics of the program are determined by the layout.
 ///
 #[derive(Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct Layout {
     /// Linked list nodes for the layout order of EBBs Forms a doubly linked list, terminated in
     /// both ends by `None`.
@@ -459,15 +462,58 @@ impl Layout {
     pub fn next_ebb(&self, ebb: Ebb) -> Option<Ebb> {
         self.ebbs[ebb].next.expand()
     }
+
+    /// Mark `ebb` as cold.
+    ///
+    /// Cold EBBs are expected to be executed rarely, so the code generator is free to move them
+    /// out of line, after the hot code of the function. An EBB can be marked as cold before it is
+    /// inserted in the layout.
+    pub fn set_cold(&mut self, ebb: Ebb) {
+        self.ebbs[ebb].cold = true;
+    }
+
+    /// Is `ebb` marked as cold?
+    pub fn is_cold(&self, ebb: Ebb) -> bool {
+        self.ebbs[ebb].cold
+    }
+
+    /// Rearrange the EBBs in the layout to follow `order`.
+    ///
+    /// The EBBs keep their instructions. `order` must contain every EBB currently in the layout
+    /// exactly once, and the first EBB of `order` becomes the new entry block.
+    pub fn reorder_ebbs(&mut self, order: &[Ebb]) {
+        debug_assert_eq!(
+            order.len(),
+            self.ebbs().count(),
+            "The new order must contain all of the EBBs in the layout"
+        );
+        debug_assert!(order.iter().all(|&ebb| self.is_ebb_inserted(ebb)));
+        let mut prev: Option<Ebb> = None;
+        for &ebb in order {
+            self.ebbs[ebb].prev = prev.into();
+            match prev {
+                None => self.first_ebb = Some(ebb),
+                Some(p) => self.ebbs[p].next = ebb.into(),
+            }
+            prev = Some(ebb);
+        }
+        if let Some(last) = prev {
+            self.ebbs[last].next = None.into();
+        }
+        self.last_ebb = prev;
+        self.full_renumber();
+    }
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 struct EbbNode {
     prev: PackedOption<Ebb>,
     next: PackedOption<Ebb>,
     first_inst: PackedOption<Inst>,
     last_inst: PackedOption<Inst>,
     seq: SequenceNumber,
+    cold: bool,
 }
 
 /// Iterate over EBBs in layout order. See `Layout::ebbs()`.
@@ -692,6 +738,7 @@ impl Layout {
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 struct InstNode {
     // The Ebb containing this instruction, or `None` if the instruction is not yet inserted.
     ebb: PackedOption<Ebb>,
@@ -1175,4 +1222,48 @@ mod tests {
         assert_eq!(layout.is_ebb_gap(i1, e1), false);
         assert_eq!(layout.is_ebb_gap(i2, e1), false);
     }
+
+    #[test]
+    fn reorder_ebbs() {
+        let mut layout = Layout::new();
+
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+
+        let i0 = Inst::new(0);
+        let i1 = Inst::new(1);
+        let i2 = Inst::new(2);
+        let i3 = Inst::new(3);
+
+        layout.append_ebb(e0);
+        layout.append_ebb(e1);
+        layout.append_ebb(e2);
+        layout.append_inst(i0, e0);
+        layout.append_inst(i1, e1);
+        layout.append_inst(i2, e1);
+        layout.append_inst(i3, e2);
+
+        assert!(!layout.is_cold(e1));
+        layout.set_cold(e1);
+        assert!(layout.is_cold(e1));
+
+        layout.reorder_ebbs(&[e0, e2, e1]);
+        let v: Vec<Ebb> = layout.ebbs().collect();
+        assert_eq!(v, [e0, e2, e1]);
+        assert_eq!(layout.last_ebb(), Some(e1));
+        assert_eq!(layout.prev_ebb(e1), Some(e2));
+        assert!(layout.is_cold(e1));
+        let v: Vec<Inst> = layout.ebb_insts(e1).collect();
+        assert_eq!(v, [i1, i2]);
+        assert_eq!(layout.cmp(i3, i1), Ordering::Less);
+        assert_eq!(layout.cmp(e1, i3), Ordering::Greater);
+
+        layout.reorder_ebbs(&[e2, e1, e0]);
+        let v: Vec<Ebb> = layout.ebbs().collect();
+        assert_eq!(v, [e2, e1, e0]);
+        assert_eq!(layout.entry_block(), Some(e2));
+        assert_eq!(layout.prev_ebb(e2), None);
+        assert_eq!(layout.cmp(i0, i2), Ordering::Greater);
+    }
 }
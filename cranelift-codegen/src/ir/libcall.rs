@@ -40,12 +40,22 @@ pub enum LibCall {
     NearestF32,
     /// nearest.f64
     NearestF64,
+    /// udiv.i64
+    UdivI64,
+    /// sdiv.i64
+    SdivI64,
+    /// urem.i64
+    UremI64,
+    /// srem.i64
+    SremI64,
     /// libc.memcpy
     Memcpy,
     /// libc.memset
     Memset,
     /// libc.memmove
     Memmove,
+    /// Elf __tls_get_addr
+    ElfTlsGetAddr,
 }
 
 impl fmt::Display for LibCall {
@@ -68,9 +78,14 @@ impl FromStr for LibCall {
             "TruncF64" => Ok(LibCall::TruncF64),
             "NearestF32" => Ok(LibCall::NearestF32),
             "NearestF64" => Ok(LibCall::NearestF64),
+            "UdivI64" => Ok(LibCall::UdivI64),
+            "SdivI64" => Ok(LibCall::SdivI64),
+            "UremI64" => Ok(LibCall::UremI64),
+            "SremI64" => Ok(LibCall::SremI64),
             "Memcpy" => Ok(LibCall::Memcpy),
             "Memset" => Ok(LibCall::Memset),
             "Memmove" => Ok(LibCall::Memmove),
+            "ElfTlsGetAddr" => Ok(LibCall::ElfTlsGetAddr),
             _ => Err(()),
         }
     }
@@ -97,6 +112,13 @@ impl LibCall {
                 Opcode::Nearest => LibCall::NearestF64,
                 _ => return None,
             },
+            types::I64 => match opcode {
+                Opcode::Udiv => LibCall::UdivI64,
+                Opcode::Sdiv => LibCall::SdivI64,
+                Opcode::Urem => LibCall::UremI64,
+                Opcode::Srem => LibCall::SremI64,
+                _ => return None,
+            },
             _ => return None,
         })
     }
@@ -1,6 +1,8 @@
 //! Memory operation flags.
 
 use core::fmt;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 enum FlagBit {
     Notrap,
@@ -16,6 +18,7 @@ const NAMES: [&str; 3] = ["notrap", "aligned", "readonly"];
 /// certain optimizations that need to make additional assumptions. Generally, the semantics of a
 /// program does not change when a flag is removed, but adding a flag will.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct MemFlags {
     bits: u8,
 }
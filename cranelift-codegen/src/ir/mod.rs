@@ -1,5 +1,6 @@
 //! Representation of Cranelift IR functions.
 
+mod atomics;
 mod builder;
 pub mod condcodes;
 pub mod dfg;
@@ -23,6 +24,7 @@ mod trapcode;
 pub mod types;
 mod valueloc;
 
+pub use crate::ir::atomics::{AtomicOrdering, AtomicRmwOp};
 pub use crate::ir::builder::{InsertBuilder, InstBuilder, InstBuilderBase, InstInserterBase};
 pub use crate::ir::dfg::{DataFlowGraph, ValueDef};
 pub use crate::ir::entities::{
@@ -53,6 +55,8 @@ pub use crate::ir::valueloc::{ArgumentLoc, ValueLoc};
 use crate::binemit;
 use crate::entity::{entity_impl, PrimaryMap, SecondaryMap};
 use crate::isa;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 /// Map of value locations.
 pub type ValueLocations = SecondaryMap<Value, ValueLoc>;
@@ -74,11 +78,13 @@ pub type SourceLocs = SecondaryMap<Inst, SourceLoc>;
 
 /// Marked with a label value.
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct ValueLabel(u32);
 entity_impl!(ValueLabel, "val");
 
 /// A label of a Value.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct ValueLabelStart {
     /// Source location when it is in effect
     pub from: SourceLoc,
@@ -89,6 +95,7 @@ pub struct ValueLabelStart {
 
 /// Value label assignements: label starts or value aliases.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum ValueLabelAssignments {
     /// Original value labels assigned at transform.
     Starts(std::vec::Vec<ValueLabelStart>),
@@ -11,6 +11,8 @@ use core::fmt;
 use core::ops::{Index, IndexMut};
 use core::slice;
 use core::str::FromStr;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// The size of an object on the stack, or the size of a stack frame.
@@ -38,6 +40,7 @@ fn spill_size(ty: Type) -> StackSize {
 
 /// The kind of a stack slot.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum StackSlotKind {
     /// A spill slot. This is a stack slot created by the register allocator.
     SpillSlot,
@@ -98,6 +101,7 @@ impl fmt::Display for StackSlotKind {
 
 /// Contents of a stack slot.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct StackSlotData {
     /// The kind of stack slot.
     pub kind: StackSlotKind,
@@ -150,6 +154,7 @@ impl fmt::Display for StackSlotData {
 ///
 /// Keep track of all the stack slots used by a function.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct StackSlots {
     /// All allocated stack slots.
     slots: PrimaryMap<StackSlot, StackSlotData>,
@@ -262,24 +267,22 @@ impl StackSlots {
         self.push(StackSlotData::new(StackSlotKind::SpillSlot, spill_size(ty)))
     }
 
-    /// Create a stack slot representing an incoming function argument.
-    pub fn make_incoming_arg(&mut self, ty: Type, offset: StackOffset) -> StackSlot {
-        let mut data = StackSlotData::new(StackSlotKind::IncomingArg, ty.bytes());
+    /// Create a stack slot representing an incoming function argument of `size` bytes.
+    pub fn make_incoming_arg(&mut self, size: StackSize, offset: StackOffset) -> StackSlot {
+        let mut data = StackSlotData::new(StackSlotKind::IncomingArg, size);
         debug_assert!(offset <= StackOffset::max_value() - data.size as StackOffset);
         data.offset = Some(offset);
         self.push(data)
     }
 
-    /// Get a stack slot representing an outgoing argument.
+    /// Get a stack slot representing an outgoing argument of `size` bytes.
     ///
     /// This may create a new stack slot, or reuse an existing outgoing stack slot with the
     /// requested offset and size.
     ///
     /// The requested offset is relative to this function's stack pointer immediately before making
     /// the call.
-    pub fn get_outgoing_arg(&mut self, ty: Type, offset: StackOffset) -> StackSlot {
-        let size = ty.bytes();
-
+    pub fn get_outgoing_arg(&mut self, size: StackSize, offset: StackOffset) -> StackSlot {
         // Look for an existing outgoing stack slot with the same offset and size.
         let inspos = match self.outgoing.binary_search_by_key(&(offset, size), |&ss| {
             (self[ss].offset.unwrap(), self[ss].size)
@@ -364,9 +367,9 @@ mod tests {
     fn outgoing() {
         let mut sss = StackSlots::new();
 
-        let ss0 = sss.get_outgoing_arg(types::I32, 8);
-        let ss1 = sss.get_outgoing_arg(types::I32, 4);
-        let ss2 = sss.get_outgoing_arg(types::I64, 8);
+        let ss0 = sss.get_outgoing_arg(4, 8);
+        let ss1 = sss.get_outgoing_arg(4, 4);
+        let ss2 = sss.get_outgoing_arg(8, 8);
 
         assert_eq!(sss[ss0].offset, Some(8));
         assert_eq!(sss[ss0].size, 4);
@@ -377,9 +380,9 @@ mod tests {
         assert_eq!(sss[ss2].offset, Some(8));
         assert_eq!(sss[ss2].size, 8);
 
-        assert_eq!(sss.get_outgoing_arg(types::I32, 8), ss0);
-        assert_eq!(sss.get_outgoing_arg(types::I32, 4), ss1);
-        assert_eq!(sss.get_outgoing_arg(types::I64, 8), ss2);
+        assert_eq!(sss.get_outgoing_arg(4, 8), ss0);
+        assert_eq!(sss.get_outgoing_arg(4, 4), ss1);
+        assert_eq!(sss.get_outgoing_arg(8, 8), ss2);
     }
 
     #[test]
@@ -13,13 +13,14 @@ use crate::binemit::{emit_function, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{EncInfo, LatencyModel, RegClass, RegInfo, TargetIsa};
 use crate::regalloc;
 use core::fmt;
 use std::boxed::Box;
 use target_lexicon::{Architecture, Triple};
 
 #[allow(dead_code)]
+#[derive(Clone)]
 struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
@@ -73,10 +74,26 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn boxed_clone(&self) -> Box<dyn TargetIsa> {
+        Box::new(self.clone())
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
+    fn latency_model(&self) -> LatencyModel {
+        // Typical latencies of a small in-order core like the Cortex-A7.
+        LatencyModel {
+            alu: 1,
+            mul: 3,
+            div: 12,
+            load: 3,
+            float: 4,
+            float_div: 18,
+        }
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
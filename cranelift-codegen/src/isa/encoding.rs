@@ -5,6 +5,8 @@ use crate::ir::{Function, Inst};
 use crate::isa::constraints::{BranchRange, RecipeConstraints};
 use crate::regalloc::RegDiversions;
 use core::fmt;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 /// Bits needed to encode an instruction as binary machine code.
 ///
@@ -13,6 +15,7 @@ use core::fmt;
 /// operands to encoded bits. The encoding bits provide additional information to the recipe,
 /// typically parts of the opcode.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct Encoding {
     recipe: u16,
     bits: u16,
@@ -0,0 +1,111 @@
+//! Instruction latency models.
+//!
+//! The post-regalloc scheduler needs to know how many cycles it takes before the result of an
+//! instruction can be used by another instruction. A `LatencyModel` gives a rough answer by
+//! sorting opcodes into a few classes with a latency each. This is only an approximation of a
+//! real pipeline, but it is enough to move independent instructions in between a long-latency
+//! instruction and its first use.
+
+use crate::ir::Opcode;
+
+/// Result latencies, in cycles, of the instruction classes of a simple in-order pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyModel {
+    /// Simple integer arithmetic, logic, and moves.
+    pub alu: u8,
+    /// Integer multiplication.
+    pub mul: u8,
+    /// Integer division and remainder.
+    pub div: u8,
+    /// Loads from memory, including fills from stack slots.
+    pub load: u8,
+    /// Floating point arithmetic and conversions.
+    pub float: u8,
+    /// Floating point division and square root.
+    pub float_div: u8,
+}
+
+impl LatencyModel {
+    /// Get the number of cycles until the results of an `opcode` instruction are available.
+    pub fn latency(&self, opcode: Opcode) -> u32 {
+        let cycles = match opcode {
+            Opcode::Imul
+            | Opcode::ImulImm
+            | Opcode::Umulhi
+            | Opcode::Smulhi
+            | Opcode::X86Umulx
+            | Opcode::X86Smulx => self.mul,
+            Opcode::Udiv
+            | Opcode::Sdiv
+            | Opcode::Urem
+            | Opcode::Srem
+            | Opcode::UdivImm
+            | Opcode::SdivImm
+            | Opcode::UremImm
+            | Opcode::SremImm
+            | Opcode::X86Udivmodx
+            | Opcode::X86Sdivmodx => self.div,
+            Opcode::Fdiv | Opcode::Sqrt => self.float_div,
+            Opcode::Fcmp
+            | Opcode::Ffcmp
+            | Opcode::Fadd
+            | Opcode::Fsub
+            | Opcode::Fmul
+            | Opcode::Fma
+            | Opcode::Fmin
+            | Opcode::Fmax
+            | Opcode::Ceil
+            | Opcode::Floor
+            | Opcode::Trunc
+            | Opcode::Nearest
+            | Opcode::Fpromote
+            | Opcode::Fdemote
+            | Opcode::FcvtToUint
+            | Opcode::FcvtToUintSat
+            | Opcode::FcvtToSint
+            | Opcode::FcvtToSintSat
+            | Opcode::FcvtFromUint
+            | Opcode::FcvtFromSint
+            | Opcode::X86Cvtt2si
+            | Opcode::X86Fmin
+            | Opcode::X86Fmax => self.float,
+            Opcode::Fill => self.load,
+            _ if opcode.can_load() => self.load,
+            _ => self.alu,
+        };
+        u32::from(cycles)
+    }
+}
+
+impl Default for LatencyModel {
+    /// A generic model for targets that don't provide their own.
+    fn default() -> Self {
+        Self {
+            alu: 1,
+            mul: 3,
+            div: 20,
+            load: 3,
+            float: 4,
+            float_div: 20,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let model = LatencyModel::default();
+        assert_eq!(model.latency(Opcode::Iadd), 1);
+        assert_eq!(model.latency(Opcode::Imul), 3);
+        assert_eq!(model.latency(Opcode::Sdiv), 20);
+        assert_eq!(model.latency(Opcode::Load), 3);
+        assert_eq!(model.latency(Opcode::Uload8Complex), 3);
+        assert_eq!(model.latency(Opcode::Fill), 3);
+        assert_eq!(model.latency(Opcode::Fadd), 4);
+        assert_eq!(model.latency(Opcode::Sqrt), 20);
+        assert_eq!(model.latency(Opcode::Store), 1);
+    }
+}
@@ -51,6 +51,7 @@ pub use crate::isa::constraints::{
     BranchRange, ConstraintKind, OperandConstraint, RecipeConstraints,
 };
 pub use crate::isa::encoding::{base_size, EncInfo, Encoding};
+pub use crate::isa::latency::LatencyModel;
 pub use crate::isa::registers::{regs_overlap, RegClass, RegClassIndex, RegInfo, RegUnit};
 pub use crate::isa::stack::{StackBase, StackBaseMask, StackRef};
 
@@ -59,7 +60,7 @@ use crate::flowgraph;
 use crate::ir;
 use crate::isa::enc_tables::Encodings;
 use crate::regalloc;
-use crate::result::CodegenResult;
+use crate::result::{CodegenError, CodegenResult};
 use crate::settings;
 use crate::settings::SetResult;
 use crate::timing;
@@ -84,6 +85,7 @@ mod call_conv;
 mod constraints;
 mod enc_tables;
 mod encoding;
+mod latency;
 pub mod registers;
 mod stack;
 
@@ -199,7 +201,7 @@ impl TargetFrontendConfig {
 
 /// Methods that are specialized to a target ISA. Implies a Display trait that shows the
 /// shared flags, as well as any isa-specific flags.
-pub trait TargetIsa: fmt::Display + Sync {
+pub trait TargetIsa: fmt::Display + Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
@@ -209,6 +211,9 @@ pub trait TargetIsa: fmt::Display + Sync {
     /// Get the ISA-independent flags that were used to make this trait object.
     fn flags(&self) -> &settings::Flags;
 
+    /// Make a copy of this ISA with the same settings, which can be moved to another thread.
+    fn boxed_clone(&self) -> Box<dyn TargetIsa>;
+
     /// Get the default calling convention of this target.
     fn default_call_conv(&self) -> CallConv {
         CallConv::triple_default(self.triple())
@@ -252,9 +257,36 @@ pub trait TargetIsa: fmt::Display + Sync {
         false
     }
 
+    /// Can thread-local global values be accessed with the current settings?
+    fn supports_tls(&self) -> bool {
+        false
+    }
+
+    /// Can a struct of `size` bytes be passed as an `ArgumentPurpose::StructArgument` parameter
+    /// with `call_conv`?
+    ///
+    /// Structs which the C ABI of the calling convention passes in registers can't be, unless the
+    /// ISA classifies them. They must be passed as their register-sized parts instead.
+    fn supports_struct_argument(&self, _call_conv: CallConv, _size: u32) -> bool {
+        true
+    }
+
+    /// Does `inst` call a function behind the scenes, clobbering registers like a call does?
+    ///
+    /// The register allocator spills the values live across such instructions, as it does for
+    /// calls.
+    fn calls_implicitly(&self, _inst: &ir::InstructionData) -> bool {
+        false
+    }
+
     /// Get a data structure describing the registers in this ISA.
     fn register_info(&self) -> RegInfo;
 
+    /// Get the instruction latencies used by the post-regalloc scheduler.
+    fn latency_model(&self) -> LatencyModel {
+        LatencyModel::default()
+    }
+
     /// Returns an iterator over legal encodings for the instruction.
     fn legal_encodings<'a>(
         &'a self,
@@ -340,6 +372,16 @@ pub trait TargetIsa: fmt::Display + Sync {
         use crate::ir::stackslot::{StackOffset, StackSize};
         use crate::stack_layout::layout_stack;
 
+        // This default implementation doesn't insert stack limit checks.
+        if func.stack_limit.is_some()
+            || func
+                .signature
+                .special_param_index(ir::ArgumentPurpose::StackLimit)
+                .is_some()
+        {
+            return Err(CodegenError::Unsupported("stack limit checks"));
+        }
+
         let word_size = StackSize::from(self.pointer_bytes());
 
         // Account for the SpiderMonkey standard prologue pushes.
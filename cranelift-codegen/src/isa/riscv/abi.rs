@@ -95,6 +95,13 @@ pub fn legalize_signature(
 ) {
     let bits = triple.pointer_width().unwrap().bits();
 
+    // Structs that don't fit in two registers are passed by reference.
+    for param in &mut sig.params {
+        if let ArgumentPurpose::StructArgument(..) = param.purpose {
+            param.purpose = ArgumentPurpose::Normal;
+        }
+    }
+
     let mut args = Args::new(bits, isa_flags.enable_e());
     legalize_args(&mut sig.params, &mut args);
 
@@ -115,6 +122,13 @@ pub fn legalize_signature(
     }
 }
 
+/// Can a struct of `size` bytes be passed by value in memory?
+///
+/// Structs that fit in two registers are passed in registers, by the types of their fields.
+pub fn supports_struct_argument(triple: &Triple, size: u32) -> bool {
+    size > 2 * u32::from(triple.pointer_width().unwrap().bytes())
+}
+
 /// Get register class for a type appearing in a legalized signature.
 pub fn regclass_for_abi_type(ty: Type) -> RegClass {
     if ty.is_float() {
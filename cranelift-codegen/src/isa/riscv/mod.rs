@@ -13,13 +13,14 @@ use crate::binemit::{emit_function, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{CallConv, EncInfo, LatencyModel, RegClass, RegInfo, TargetIsa};
 use crate::regalloc;
 use core::fmt;
 use std::boxed::Box;
 use target_lexicon::{PointerWidth, Triple};
 
 #[allow(dead_code)]
+#[derive(Clone)]
 struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
@@ -67,10 +68,26 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn boxed_clone(&self) -> Box<dyn TargetIsa> {
+        Box::new(self.clone())
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
+    fn latency_model(&self) -> LatencyModel {
+        // Typical latencies of a single-issue in-order core like Rocket.
+        LatencyModel {
+            alu: 1,
+            mul: 4,
+            div: 34,
+            load: 3,
+            float: 4,
+            float_div: 20,
+        }
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
@@ -99,6 +116,10 @@ impl TargetIsa for Isa {
         abi::legalize_signature(sig, &self.triple, &self.isa_flags, current)
     }
 
+    fn supports_struct_argument(&self, _call_conv: CallConv, size: u32) -> bool {
+        abi::supports_struct_argument(&self.triple, size)
+    }
+
     fn regclass_for_abi_type(&self, ty: ir::Type) -> RegClass {
         abi::regclass_for_abi_type(ty)
     }
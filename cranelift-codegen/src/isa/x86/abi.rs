@@ -14,7 +14,7 @@ use crate::ir::{
 };
 use crate::isa::{CallConv, RegClass, RegUnit, TargetIsa};
 use crate::regalloc::RegisterSet;
-use crate::result::CodegenResult;
+use crate::result::{CodegenError, CodegenResult};
 use crate::stack_layout::layout_stack;
 use core::i32;
 use target_lexicon::{PointerWidth, Triple};
@@ -77,12 +77,87 @@ impl Args {
             isa_flags: isa_flags.clone(),
         }
     }
+
+    /// Try to assign registers to the parts of a struct argument of `size` bytes, where `mask`
+    /// has the bits of the bytes that don't hold integer data set.
+    fn struct_parts(&mut self, size: u32, mask: u16) -> Option<ArgAction> {
+        if self.pointer_bits != 64 {
+            return None;
+        }
+
+        let mut parts = [None; 2];
+        if self.call_conv == CallConv::WindowsFastcall {
+            // Structs of 1, 2, 4 or 8 bytes are passed like an integer of the same size.
+            match size {
+                1 | 2 | 4 | 8 => parts[0] = Some(ir::types::I64),
+                _ => return None,
+            }
+        } else {
+            // System V classifies each eightbyte of a struct of up to two eightbytes as INTEGER
+            // when it holds any integer data, and as SSE otherwise.
+            if size == 0 || size > 16 {
+                return None;
+            }
+            for (i, part) in parts
+                .iter_mut()
+                .enumerate()
+                .take(if size > 8 { 2 } else { 1 })
+            {
+                let bytes = (size - 8 * i as u32).min(8);
+                let all = (1 << bytes) - 1;
+                let sse = (u32::from(mask) >> (8 * i)) & all == all;
+                *part = Some(match bytes {
+                    8 if sse => ir::types::F64,
+                    4 if sse => ir::types::F32,
+                    _ => ir::types::I64,
+                });
+            }
+        }
+
+        // The whole struct is passed in memory when there aren't enough registers left for it.
+        let gprs = parts.iter().flatten().filter(|ty| !ty.is_float()).count();
+        let fprs = parts.iter().flatten().filter(|ty| ty.is_float()).count();
+        let fpr_used = if self.call_conv == CallConv::WindowsFastcall {
+            self.gpr_used
+        } else {
+            self.fpr_used
+        };
+        if self.gpr_used + gprs > self.gpr.len() || fpr_used + fprs > self.fpr_limit {
+            return None;
+        }
+
+        let mut assign = |ty: ir::Type| {
+            let reg = if ty.is_float() {
+                self.fpr_used += 1;
+                FPR.unit(self.fpr_used - 1)
+            } else {
+                self.gpr_used += 1;
+                self.gpr[self.gpr_used - 1] as RegUnit
+            };
+            (ty, ArgumentLoc::Reg(reg))
+        };
+        let first = assign(parts[0].unwrap());
+        Some(ArgAction::StructParts(first, parts[1].map(assign)))
+    }
 }
 
 impl ArgAssigner for Args {
     fn assign(&mut self, arg: &AbiParam) -> ArgAction {
         let ty = arg.value_type;
 
+        // Structs passed by value are copied to the stack, in eightbytes on x86-64, unless they
+        // are small enough to be passed in registers.
+        if let ArgumentPurpose::StructArgument(size, mask) = arg.purpose {
+            if let Some(parts) = self.struct_parts(size, mask) {
+                return parts;
+            }
+            let loc = ArgumentLoc::Stack(self.offset as i32);
+            let align = u32::from(self.pointer_bytes);
+            self.offset += (size + align - 1) & !(align - 1);
+            debug_assert!(self.offset <= i32::MAX as u32);
+            return loc.into();
+        }
+
         // Vectors should stay in vector registers unless SIMD is not enabled--then they are split
         if ty.is_vector() {
             if self.shared_flags.enable_simd() {
@@ -168,6 +243,18 @@ pub fn legalize_signature(
     let bits;
     let mut args;
 
+    if sig.call_conv == CallConv::WindowsFastcall {
+        // Structs that don't fit in a register are passed by reference on Windows.
+        for param in &mut sig.params {
+            if let ArgumentPurpose::StructArgument(size, _) = param.purpose {
+                match size {
+                    1 | 2 | 4 | 8 => {}
+                    _ => param.purpose = ArgumentPurpose::Normal,
+                }
+            }
+        }
+    }
+
     match triple.pointer_width().unwrap() {
         PointerWidth::U16 => panic!(),
         PointerWidth::U32 => {
@@ -198,6 +285,23 @@ pub fn legalize_signature(
         }
     }
 
+    // The struct return pointer is passed as the first integer argument on x86-64, and returned
+    // in `rax` when the function doesn't return anything else.
+    if let Some(sret) = sig.special_param_index(ArgumentPurpose::StructReturn) {
+        if bits == 64 {
+            if !sig.params[sret].location.is_assigned() {
+                sig.params[sret].location = ArgumentLoc::Reg(args.gpr[0] as RegUnit);
+            }
+            args.gpr_used = 1;
+            if sig.returns.is_empty() {
+                sig.returns.push(AbiParam::special(
+                    sig.params[sret].value_type,
+                    ArgumentPurpose::StructReturn,
+                ));
+            }
+        }
+    }
+
     legalize_args(&mut sig.params, &mut args);
 
     let (regs, fpr_limit) = if sig.call_conv == CallConv::WindowsFastcall {
@@ -333,6 +437,13 @@ fn baldrdash_prologue_epilogue(func: &mut ir::Function, isa: &dyn TargetIsa) ->
         "baldrdash does not expect cranelift to emit stack probes"
     );
 
+    // SpiderMonkey checks for stack overflow itself.
+    if func.stack_limit.is_some() || func.special_param(ArgumentPurpose::StackLimit).is_some() {
+        return Err(CodegenError::Unsupported(
+            "stack limit checks with the baldrdash calling convention",
+        ));
+    }
+
     // Baldrdash on 32-bit x86 always aligns its stack pointer to 16 bytes.
     let stack_align = 16;
     let word_size = StackSize::from(isa.pointer_bytes());
@@ -410,7 +521,7 @@ fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &dyn TargetIsa) -> C
     // Set up the cursor and insert the prologue
     let entry_ebb = func.layout.entry_block().expect("missing entry block");
     let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
-    insert_common_prologue(&mut pos, local_stack_size, reg_type, &csrs, isa);
+    insert_common_prologue(&mut pos, local_stack_size, reg_type, &csrs, isa)?;
 
     // Reset the cursor and insert the epilogue
     let mut pos = pos.at_position(CursorPosition::Nowhere);
@@ -465,7 +576,7 @@ fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &dyn TargetIsa) -> C
     // Set up the cursor and insert the prologue
     let entry_ebb = func.layout.entry_block().expect("missing entry block");
     let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
-    insert_common_prologue(&mut pos, local_stack_size, reg_type, &csrs, isa);
+    insert_common_prologue(&mut pos, local_stack_size, reg_type, &csrs, isa)?;
 
     // Reset the cursor and insert the epilogue
     let mut pos = pos.at_position(CursorPosition::Nowhere);
@@ -482,19 +593,20 @@ fn insert_common_prologue(
     reg_type: ir::types::Type,
     csrs: &RegisterSet,
     isa: &dyn TargetIsa,
-) {
-    if stack_size > 0 {
-        // Check if there is a special stack limit parameter. If so insert stack check.
-        if let Some(stack_limit_arg) = pos.func.special_param(ArgumentPurpose::StackLimit) {
+) -> CodegenResult<()> {
+    // A leaf function without a stack frame of its own can't run out of stack by recursion, so
+    // it only needs to be checked if it has a frame or calls other functions.
+    if stack_size > 0 || !pos.func.is_leaf() {
+        if let Some(stack_limit) = stack_limit_value(pos)? {
             // Total stack size is the size of all stack area used by the function, including
             // pushed CSRs, frame pointer.
             // Also, the size of a return address, implicitly pushed by a x86 `call` instruction,
             // also should be accounted for.
-            // TODO: Check if the function body actually contains a `call` instruction.
             let word_size = isa.pointer_bytes();
-            let total_stack_size = (csrs.iter(GPR).len() + 1 + 1) as i64 * word_size as i64;
+            let total_stack_size =
+                (csrs.iter(GPR).len() + 1 + 1) as i64 * word_size as i64 + stack_size;
 
-            insert_stack_check(pos, total_stack_size, stack_limit_arg);
+            insert_stack_check(pos, total_stack_size, stack_limit);
         }
     }
 
@@ -562,18 +674,95 @@ fn insert_common_prologue(
             pos.ins().adjust_sp_down_imm(Imm64::new(stack_size));
         }
     }
+
+    Ok(())
+}
+
+/// Get the stack limit of the function in %rax, if it has a `stack_limit` parameter or global
+/// value.
+fn stack_limit_value(pos: &mut EncCursor) -> CodegenResult<Option<ir::Value>> {
+    let rax = ir::ValueLoc::Reg(RU::rax as RegUnit);
+    let limit = match pos.func.special_param(ArgumentPurpose::StackLimit) {
+        Some(arg) => register_param(pos, arg)?,
+        None => match pos.func.stack_limit {
+            Some(gv) => global_value_in(pos, gv, rax)?,
+            None => return Ok(None),
+        },
+    };
+    if pos.func.locations[limit] == rax {
+        Ok(Some(limit))
+    } else {
+        let copy = pos.ins().copy(limit);
+        pos.func.locations[copy] = rax;
+        Ok(Some(copy))
+    }
+}
+
+/// Check that the entry block parameter `param` is passed in a register.
+///
+/// The stack limit is checked before the stack frame is set up, so parameters passed on the stack,
+/// as on 32-bit x86, can't be used to compute it.
+fn register_param(pos: &EncCursor, param: ir::Value) -> CodegenResult<ir::Value> {
+    match pos.func.locations[param] {
+        ir::ValueLoc::Reg(_) => Ok(param),
+        _ => Err(CodegenError::Unsupported(
+            "stack limit checks with parameters passed on the stack",
+        )),
+    }
+}
+
+/// Compute the global value `gv` at the start of the function, using only the `vmctx` parameter
+/// and the `scratch` register.
+fn global_value_in(
+    pos: &mut EncCursor,
+    gv: ir::GlobalValue,
+    scratch: ir::ValueLoc,
+) -> CodegenResult<ir::Value> {
+    match pos.func.global_values[gv] {
+        ir::GlobalValueData::VMContext => {
+            let vmctx = pos
+                .func
+                .special_param(ArgumentPurpose::VMContext)
+                .expect("missing vmctx parameter");
+            register_param(pos, vmctx)
+        }
+        ir::GlobalValueData::Load {
+            base,
+            offset,
+            global_type,
+            ..
+        } => {
+            let base = global_value_in(pos, base, scratch)?;
+            let value = pos
+                .ins()
+                .load(global_type, ir::MemFlags::trusted(), base, offset);
+            pos.func.locations[value] = scratch;
+            Ok(value)
+        }
+        ir::GlobalValueData::IAddImm { base, offset, .. } => {
+            let mut base = global_value_in(pos, base, scratch)?;
+            // The addition overwrites its operand, which mustn't be the `vmctx` parameter.
+            if pos.func.locations[base] != scratch {
+                base = pos.ins().copy(base);
+                pos.func.locations[base] = scratch;
+            }
+            let value = pos.ins().iadd_imm(base, offset);
+            pos.func.locations[value] = scratch;
+            Ok(value)
+        }
+        ir::GlobalValueData::Symbol { .. } => Err(CodegenError::Unsupported(
+            "stack limits computed from symbols",
+        )),
+    }
 }
 
 /// Insert a check that generates a trap if the stack pointer goes
-/// below a value in `stack_limit_arg`.
-fn insert_stack_check(pos: &mut EncCursor, stack_size: i64, stack_limit_arg: ir::Value) {
+/// below a value in `stack_limit`, which must be in %rax.
+fn insert_stack_check(pos: &mut EncCursor, stack_size: i64, stack_limit: ir::Value) {
     use crate::ir::condcodes::IntCC;
 
-    // Copy `stack_limit_arg` into a %rax and use it for calculating
-    // a SP threshold.
-    let stack_limit_copy = pos.ins().copy(stack_limit_arg);
-    pos.func.locations[stack_limit_copy] = ir::ValueLoc::Reg(RU::rax as RegUnit);
-    let sp_threshold = pos.ins().iadd_imm(stack_limit_copy, stack_size);
+    // Use the stack limit for calculating a SP threshold.
+    let sp_threshold = pos.ins().iadd_imm(stack_limit, stack_size);
     pos.func.locations[sp_threshold] = ir::ValueLoc::Reg(RU::rax as RegUnit);
 
     // If the stack pointer currently reaches the SP threshold or below it then after opening
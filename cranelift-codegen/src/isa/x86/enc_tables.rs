@@ -893,3 +893,40 @@ fn expand_fcvt_to_uint_sat(
     cfg.recompute_ebb(pos.func, uint_large_ebb);
     cfg.recompute_ebb(pos.func, done);
 }
+
+/// Expand the `tls_value` instruction into the access sequence selected by the `tls_model`
+/// setting.
+///
+/// `Context::legalize` rejects functions with thread-local global values when the target doesn't
+/// support them, so the setting is known to be valid here.
+fn expand_tls_value(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    isa: &dyn TargetIsa,
+) {
+    use crate::settings::TlsModel;
+
+    debug_assert!(isa.supports_tls());
+
+    let gv = match func.dfg[inst] {
+        ir::InstructionData::UnaryGlobalValue {
+            opcode: ir::Opcode::TlsValue,
+            global_value,
+        } => global_value,
+        _ => panic!("Expected tls_value: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    match isa.flags().tls_model() {
+        TlsModel::None => unreachable!("tls_model flag is not set"),
+        TlsModel::ElfGd => {
+            func.dfg.replace(inst).x86_elf_tls_get_addr(gv);
+        }
+        TlsModel::ElfIe => {
+            func.dfg.replace(inst).x86_elf_tls_ie_addr(gv);
+        }
+        TlsModel::Macho => {
+            func.dfg.replace(inst).x86_macho_tls_get_addr(gv);
+        }
+    }
+}
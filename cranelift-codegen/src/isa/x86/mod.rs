@@ -22,6 +22,7 @@ use std::boxed::Box;
 use target_lexicon::{PointerWidth, Triple};
 
 #[allow(dead_code)]
+#[derive(Clone)]
 struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
@@ -69,6 +70,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn boxed_clone(&self) -> Box<dyn TargetIsa> {
+        Box::new(self.clone())
+    }
+
     fn uses_cpu_flags(&self) -> bool {
         true
     }
@@ -77,6 +82,18 @@ impl TargetIsa for Isa {
         true
     }
 
+    fn supports_tls(&self) -> bool {
+        self.triple.pointer_width().unwrap() == PointerWidth::U64
+            && self.shared_flags.tls_model() != shared_settings::TlsModel::None
+    }
+
+    fn calls_implicitly(&self, inst: &ir::InstructionData) -> bool {
+        match inst.opcode() {
+            ir::Opcode::X86ElfTlsGetAddr | ir::Opcode::X86MachoTlsGetAddr => true,
+            _ => false,
+        }
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -16,14 +16,17 @@
 //!
 //! Between the two phases, preamble signatures and call/return arguments don't match. This
 //! intermediate state doesn't type check.
+//!
+//! Calls passing struct arguments in registers are rewritten already in the first phase, because
+//! the parts of a struct can have the same types as the pointer to it.
 
-use crate::abi::{legalize_abi_value, ValueConversion};
+use crate::abi::{legalize_abi_value, stack_arg_size, ValueConversion};
 use crate::cursor::{Cursor, FuncCursor};
 use crate::flowgraph::ControlFlowGraph;
 use crate::ir::instructions::CallInfo;
 use crate::ir::{
-    AbiParam, ArgumentLoc, ArgumentPurpose, DataFlowGraph, Ebb, Function, Inst, InstBuilder,
-    SigRef, Signature, Type, Value, ValueLoc,
+    types, AbiParam, ArgumentLoc, ArgumentPurpose, DataFlowGraph, Ebb, Function, Inst, InstBuilder,
+    MemFlags, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Type, Value, ValueLoc,
 };
 use crate::isa::TargetIsa;
 use crate::legalizer::split::{isplit, vsplit};
@@ -32,19 +35,55 @@ use std::vec::Vec;
 
 /// Legalize all the function signatures in `func`.
 ///
-/// This changes all signatures to be ABI-compliant with full `ArgumentLoc` annotations. It doesn't
-/// change the entry block arguments, calls, or return instructions, so this can leave the function
-/// in a state with type discrepancies.
-pub fn legalize_signatures(func: &mut Function, isa: &dyn TargetIsa) {
+/// This changes all signatures to be ABI-compliant with full `ArgumentLoc` annotations, and
+/// legalizes the entry block arguments to match. Apart from calls passing struct arguments in
+/// registers, it doesn't change calls or return instructions, so this can leave the function in a
+/// state with type discrepancies.
+pub fn legalize_signatures(func: &mut Function, cfg: &ControlFlowGraph, isa: &dyn TargetIsa) {
     legalize_signature(&mut func.signature, true, isa);
-    for sig_data in func.dfg.signatures.values_mut() {
+    let mut struct_sigs = Vec::new();
+    for (sig_ref, sig_data) in func.dfg.signatures.iter_mut() {
         legalize_signature(sig_data, false, isa);
+        if sig_data.params.iter().any(is_struct_part) {
+            struct_sigs.push(sig_ref);
+        }
     }
 
     if let Some(entry) = func.layout.entry_block() {
         legalize_entry_params(func, entry);
         spill_entry_params(func, entry);
     }
+
+    if !struct_sigs.is_empty() {
+        legalize_struct_calls(func, cfg, &struct_sigs);
+    }
+}
+
+/// Is `arg` a part of a struct argument passed in registers?
+fn is_struct_part(arg: &AbiParam) -> bool {
+    match arg.purpose {
+        ArgumentPurpose::StructArgument(..) => arg.location.is_reg(),
+        _ => false,
+    }
+}
+
+/// Legalize all the calls in `func` whose signature is one of `sigs`.
+///
+/// The pointer to a struct passed in registers has the same type as its first part, so the calls
+/// can't be told apart from legalized ones later.
+fn legalize_struct_calls(func: &mut Function, cfg: &ControlFlowGraph, sigs: &[SigRef]) {
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            if let Some(sig_ref) = pos.func.dfg.call_signature(inst) {
+                if sigs.contains(&sig_ref) {
+                    pos.use_srcloc(inst);
+                    let inst = legalize_call_abi(&mut pos, cfg, inst, sig_ref);
+                    pos.goto_inst(inst);
+                }
+            }
+        }
+    }
 }
 
 /// Legalize the libcall signature, which we may generate on the fly after
@@ -92,7 +131,25 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
 
         let abi_type = pos.func.signature.params[abi_arg];
         let arg_type = pos.func.dfg.value_type(arg);
-        if arg_type == abi_type.value_type {
+        if is_struct_part(&abi_type) {
+            // The struct was passed in registers. Store its parts into a stack slot and point
+            // `arg` at it.
+            let num_parts = num_struct_parts(&abi_type, arg_type);
+            let size = num_parts * arg_type.bytes();
+            let ss = pos
+                .func
+                .create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size));
+            pos.ins().with_result(arg).stack_addr(arg_type, ss, 0);
+            let mut flags = MemFlags::new();
+            flags.set_notrap();
+            for part in 0..num_parts {
+                let ty = pos.func.signature.params[abi_arg].value_type;
+                let v = pos.func.dfg.append_ebb_param(entry, ty);
+                let offset = (part * arg_type.bytes()) as i32;
+                pos.ins().store(flags, v, arg, offset);
+                abi_arg += 1;
+            }
+        } else if arg_type == abi_type.value_type {
             // No value translation is necessary, this argument matches the ABI type.
             // Just use the original EBB argument value. This is the most common case.
             pos.func.dfg.attach_ebb_param(entry, arg);
@@ -116,6 +173,8 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
                     debug_assert!(!has_stack_limit, "Multiple stack_limit arguments found");
                     has_stack_limit = true;
                 }
+                // Struct arguments are used in the argument area by `spill_entry_params()`.
+                ArgumentPurpose::StructArgument(..) => {}
                 _ => panic!("Unexpected special-purpose arg {}", abi_type),
             }
             abi_arg += 1;
@@ -176,6 +235,9 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
                 debug_assert!(!has_stack_limit, "Multiple stack_limit parameters found");
                 has_stack_limit = true;
             }
+            ArgumentPurpose::StructArgument(..) => {
+                panic!("Unexpected struct arg {}", arg);
+            }
         }
 
         // Just create entry block values to match here. We will use them in `handle_return_abi()`
@@ -490,6 +552,27 @@ fn legalize_inst_arguments<ArgType>(
         let old_value = vlist
             .get(old_arg_offset + old_arg, &pos.func.dfg.value_lists)
             .unwrap();
+
+        // A struct passed in registers is loaded from the pointer to it, one part at a time.
+        let abi_type = get_abi_type(pos.func, abi_arg);
+        if is_struct_part(&abi_type) {
+            let ptr_type = pos.func.dfg.value_type(old_value);
+            let num_parts = num_struct_parts(&abi_type, ptr_type);
+            for part in 0..num_parts {
+                let ty = get_abi_type(pos.func, abi_arg).value_type;
+                let offset = part * ptr_type.bytes();
+                let v = if part + 1 < num_parts {
+                    load_struct_part(pos, ty, old_value, offset, ptr_type.bytes())
+                } else {
+                    let size = struct_size(&abi_type);
+                    load_struct_part(pos, ty, old_value, offset, size - offset)
+                };
+                vlist.as_mut_slice(&mut pos.func.dfg.value_lists)[num_fixed_values + abi_arg] = v;
+                abi_arg += 1;
+            }
+            continue;
+        }
+
         let mut put_arg = |func: &mut Function, arg| {
             let abi_type = get_abi_type(func, abi_arg);
             if func.dfg.value_type(arg) == abi_type.value_type {
@@ -528,6 +611,26 @@ pub fn handle_call_abi(mut inst: Inst, func: &mut Function, cfg: &ControlFlowGra
         Err(s) => s,
     };
 
+    inst = legalize_call_abi(pos, cfg, inst, sig_ref);
+
+    // Go back and insert spills for any stack arguments.
+    pos.goto_inst(inst);
+    spill_call_arguments(pos);
+
+    // Yes, we changed stuff.
+    true
+}
+
+/// Insert ABI conversion code for the arguments and results of the call `inst` at `pos`, which
+/// has the legalized signature `sig_ref`.
+///
+/// Returns the possibly new instruction representing the call.
+fn legalize_call_abi(
+    pos: &mut FuncCursor,
+    cfg: &ControlFlowGraph,
+    mut inst: Inst,
+    sig_ref: SigRef,
+) -> Inst {
     // OK, we need to fix the call arguments to match the ABI signature.
     let abi_args = pos.func.dfg.signatures[sig_ref].params.len();
     legalize_inst_arguments(pos, cfg, abi_args, |func, abi_arg| {
@@ -538,6 +641,19 @@ pub fn handle_call_abi(mut inst: Inst, func: &mut Function, cfg: &ControlFlowGra
         inst = legalize_inst_results(pos, |func, abi_res| {
             func.dfg.signatures[sig_ref].returns[abi_res]
         });
+
+        // Add unused results for any special-purpose return values, such as a returned `sret`
+        // pointer, that were appended to the legalized signature.
+        let num_results = pos.func.dfg.inst_results(inst).len();
+        for abi_res in num_results..pos.func.dfg.signatures[sig_ref].returns.len() {
+            let abi_type = pos.func.dfg.signatures[sig_ref].returns[abi_res];
+            debug_assert_ne!(
+                abi_type.purpose,
+                ArgumentPurpose::Normal,
+                "Missing call result"
+            );
+            pos.func.dfg.append_result(inst, abi_type.value_type);
+        }
     }
 
     debug_assert!(
@@ -548,12 +664,7 @@ pub fn handle_call_abi(mut inst: Inst, func: &mut Function, cfg: &ControlFlowGra
         pos.func.dfg.signatures[sig_ref]
     );
 
-    // Go back and insert spills for any stack arguments.
-    pos.goto_inst(inst);
-    spill_call_arguments(pos);
-
-    // Yes, we changed stuff.
-    true
+    inst
 }
 
 /// Insert ABI conversion code before and after the return instruction at `inst`.
@@ -644,12 +755,29 @@ pub fn handle_return_abi(inst: Inst, func: &mut Function, cfg: &ControlFlowGraph
 /// Values that are passed into the function on the stack must be assigned to an `IncomingArg`
 /// stack slot already during legalization.
 fn spill_entry_params(func: &mut Function, entry: Ebb) {
+    let mut struct_args = Vec::new();
     for (abi, &arg) in func.signature.params.iter().zip(func.dfg.ebb_params(entry)) {
         if let ArgumentLoc::Stack(offset) = abi.location {
-            let ss = func.stack_slots.make_incoming_arg(abi.value_type, offset);
-            func.locations[arg] = ValueLoc::Stack(ss);
+            let ss = func
+                .stack_slots
+                .make_incoming_arg(stack_arg_size(abi), offset);
+            if let ArgumentPurpose::StructArgument(..) = abi.purpose {
+                struct_args.push((arg, ss));
+            } else {
+                func.locations[arg] = ValueLoc::Stack(ss);
+            }
         }
     }
+
+    // A struct passed by value on the stack is used in place, so the pointer to it is computed
+    // from its incoming stack slot.
+    let mut pos = FuncCursor::new(func).at_first_inst(entry);
+    for (arg, ss) in struct_args {
+        let ty = pos.func.dfg.value_type(arg);
+        let param = pos.func.dfg.replace_ebb_param(arg, ty);
+        pos.func.locations[param] = ValueLoc::Stack(ss);
+        pos.ins().with_result(arg).stack_addr(ty, ss, 0);
+    }
 }
 
 /// Assign stack slots to outgoing function arguments on the stack.
@@ -687,9 +815,9 @@ fn spill_call_arguments(pos: &mut FuncCursor) -> bool {
                         // Assign `arg` to a new stack slot, unless it's already in the correct
                         // slot. The legalization needs to be idempotent, so we should see a
                         // correct outgoing slot on the second pass.
-                        let ss = stack_slots.get_outgoing_arg(abi.value_type, offset);
+                        let ss = stack_slots.get_outgoing_arg(stack_arg_size(abi), offset);
                         if locations[arg] != ValueLoc::Stack(ss) {
-                            Some((idx, arg, ss))
+                            Some((idx, arg, ss, abi.purpose))
                         } else {
                             None
                         }
@@ -705,12 +833,99 @@ fn spill_call_arguments(pos: &mut FuncCursor) -> bool {
     }
 
     // Insert the spill instructions and rewrite call arguments.
-    for (idx, arg, ss) in arglist {
+    for (idx, arg, ss, purpose) in arglist {
         let stack_val = pos.ins().spill(arg);
         pos.func.locations[stack_val] = ValueLoc::Stack(ss);
         pos.func.dfg.inst_variable_args_mut(inst)[idx] = stack_val;
+
+        // A struct passed by value is copied over the spilled pointer to it.
+        if let ArgumentPurpose::StructArgument(size, _) = purpose {
+            copy_struct_argument(pos, arg, ss, size);
+        }
     }
 
     // We changed stuff.
     true
 }
+
+/// Copy the `size` bytes of the struct at `src` into the stack slot `ss` by inserting
+/// instructions at `pos`.
+fn copy_struct_argument(pos: &mut FuncCursor, src: Value, ss: StackSlot, size: u32) {
+    let addr_ty = pos.func.dfg.value_type(src);
+    let dst = pos.ins().stack_addr(addr_ty, ss, 0);
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+
+    let mut offset = 0;
+    while offset < size {
+        let chunk = size - offset;
+        let disp = offset as i32;
+        offset += if chunk >= addr_ty.bytes() {
+            let v = pos.ins().load(addr_ty, flags, src, disp);
+            pos.ins().store(flags, v, dst, disp);
+            addr_ty.bytes()
+        } else if chunk >= 4 {
+            let v = pos.ins().load(types::I32, flags, src, disp);
+            pos.ins().store(flags, v, dst, disp);
+            4
+        } else if chunk >= 2 {
+            let v = pos.ins().uload16(types::I32, flags, src, disp);
+            pos.ins().istore16(flags, v, dst, disp);
+            2
+        } else {
+            let v = pos.ins().uload8(types::I32, flags, src, disp);
+            pos.ins().istore8(flags, v, dst, disp);
+            1
+        };
+    }
+}
+
+/// Get the size in bytes of the struct argument `arg`.
+fn struct_size(arg: &AbiParam) -> u32 {
+    match arg.purpose {
+        ArgumentPurpose::StructArgument(size, _) => size,
+        _ => panic!("Expected struct argument, got {}", arg),
+    }
+}
+
+/// Get the number of parts of the struct argument `arg` passed in registers, when pointers have
+/// the type `ptr_type`. A struct passed in registers is never empty.
+fn num_struct_parts(arg: &AbiParam, ptr_type: Type) -> u32 {
+    1 + (struct_size(arg) - 1) / ptr_type.bytes()
+}
+
+/// Load the `bytes` bytes at `offset` of the struct at `src` as a value of type `ty` by inserting
+/// instructions at `pos`.
+///
+/// An integer part smaller than `ty` is zero-extended, without reading past the end of the struct.
+fn load_struct_part(pos: &mut FuncCursor, ty: Type, src: Value, offset: u32, bytes: u32) -> Value {
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    if bytes == ty.bytes() {
+        return pos.ins().load(ty, flags, src, offset as i32);
+    }
+
+    debug_assert!(ty.is_int());
+    let mut value = None;
+    let mut done = 0;
+    while done < bytes {
+        let disp = (offset + done) as i32;
+        let (v, chunk) = if bytes - done >= 4 {
+            (pos.ins().uload32(flags, src, disp), 4)
+        } else if bytes - done >= 2 {
+            (pos.ins().uload16(ty, flags, src, disp), 2)
+        } else {
+            (pos.ins().uload8(ty, flags, src, disp), 1)
+        };
+        let v = match value {
+            Some(low) => {
+                let high = pos.ins().ishl_imm(v, i64::from(8 * done));
+                pos.ins().bor(low, high)
+            }
+            None => v,
+        };
+        value = Some(v);
+        done += chunk;
+    }
+    value.unwrap()
+}
@@ -0,0 +1,187 @@
+//! Expanding 64-bit division and remainder on 32-bit targets as loops.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::IntCC;
+use crate::ir::types::{I32, I64};
+use crate::ir::{self, InstBuilder, TrapCode, Value};
+use crate::isa::TargetIsa;
+use crate::legalizer::split;
+
+/// Try to expand the `i64` division or remainder `inst` as a loop on a 32-bit target, returning
+/// true if successful.
+///
+/// The loop computes one bit of the quotient per iteration using 32-bit operations only. This
+/// expansion is only used when the `inline_wide_divrem` setting is enabled; otherwise the
+/// instruction is expanded as a runtime library call instead.
+pub fn expand_divrem_loop(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &dyn TargetIsa,
+) -> bool {
+    let (opcode, x, y) = match func.dfg[inst] {
+        ir::InstructionData::Binary { opcode, args } => match opcode {
+            ir::Opcode::Udiv | ir::Opcode::Urem | ir::Opcode::Sdiv | ir::Opcode::Srem => {
+                (opcode, args[0], args[1])
+            }
+            _ => return false,
+        },
+        _ => return false,
+    };
+    if !isa.flags().inline_wide_divrem()
+        || func.dfg.ctrl_typevar(inst) != I64
+        || isa.pointer_bits() != 32
+    {
+        return false;
+    }
+    let is_signed = opcode == ir::Opcode::Sdiv || opcode == ir::Opcode::Srem;
+    let is_rem = opcode == ir::Opcode::Urem || opcode == ir::Opcode::Srem;
+
+    // Split the EBB around `inst`:
+    //
+    //     v2 = udiv v0, v1
+    //
+    // Becomes:
+    //
+    //     (check operands and take their absolute values)
+    //     jump loop_ebb(64, 0, 0, nl, nh)
+    //
+    //   loop_ebb(i, rl, rh, nl, nh):
+    //     (shift the next bit of n into r, and subtract d from r if it fits)
+    //     brnz i1, loop_ebb(i1, rl2, rh2, nl2, nh2)
+    //     jump done_ebb
+    //
+    //   done_ebb:
+    //     v2 = iconcat nl2, nh2
+    let old_ebb = func.layout.pp_ebb(inst);
+    let loop_ebb = func.dfg.make_ebb();
+    let done_ebb = func.dfg.make_ebb();
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (xl, xh) = split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), x);
+    let (yl, yh) = split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), y);
+
+    let y_bits = pos.ins().bor(yl, yh);
+    pos.ins().trapz(y_bits, TrapCode::IntegerDivisionByZero);
+    if opcode == ir::Opcode::Sdiv {
+        // The quotient of the smallest integer and -1 overflows. Both halves of -1 are all ones.
+        let y_ones = pos.ins().band(yl, yh);
+        let y_not_minus_one = pos.ins().iadd_imm(y_ones, 1);
+        let x_high = pos.ins().bxor_imm(xh, i64::from(i32::min_value()));
+        let x_not_min = pos.ins().bor(x_high, xl);
+        let no_overflow = pos.ins().bor(x_not_min, y_not_minus_one);
+        pos.ins().trapz(no_overflow, TrapCode::IntegerOverflow);
+    }
+
+    // Signed operands are divided by their absolute values.
+    let (x_sign, y_sign) = if is_signed {
+        (
+            Some(pos.ins().sshr_imm(xh, 31)),
+            Some(pos.ins().sshr_imm(yh, 31)),
+        )
+    } else {
+        (None, None)
+    };
+    let (nl, nh) = negate_if(&mut pos, xl, xh, x_sign);
+    let (dl, dh) = negate_if(&mut pos, yl, yh, y_sign);
+
+    let count = pos.ins().iconst(I32, 64);
+    let zero = pos.ins().iconst(I32, 0);
+    pos.ins().jump(loop_ebb, &[count, zero, zero, nl, nh]);
+
+    pos.insert_ebb(loop_ebb);
+    let i = pos.func.dfg.append_ebb_param(loop_ebb, I32);
+    let rl = pos.func.dfg.append_ebb_param(loop_ebb, I32);
+    let rh = pos.func.dfg.append_ebb_param(loop_ebb, I32);
+    let nl = pos.func.dfg.append_ebb_param(loop_ebb, I32);
+    let nh = pos.func.dfg.append_ebb_param(loop_ebb, I32);
+
+    // Shift the remainder and the numerator left as one 128-bit number. The numerator is
+    // replaced by the quotient one bit at a time. The remainder is smaller than the divisor, so
+    // it fits in 65 bits after shifting.
+    let carry = pos.ins().ushr_imm(rh, 31);
+    let rh = shift_in(&mut pos, rh, rl);
+    let rl = shift_in(&mut pos, rl, nh);
+    let nh = shift_in(&mut pos, nh, nl);
+    let nl = pos.ins().ishl_imm(nl, 1);
+
+    // Subtract the divisor from the remainder, unless it is smaller than the divisor. The
+    // difference fits in 64 bits.
+    let low_borrow = pos.ins().icmp(IntCC::UnsignedLessThan, rl, dl);
+    let low_borrow = pos.ins().bint(I32, low_borrow);
+    let high_less = pos.ins().icmp(IntCC::UnsignedLessThan, rh, dh);
+    let high_less = pos.ins().bint(I32, high_less);
+    let high_equal = pos.ins().icmp(IntCC::Equal, rh, dh);
+    let high_equal = pos.ins().bint(I32, high_equal);
+    let equal_borrow = pos.ins().band(high_equal, low_borrow);
+    let less = pos.ins().bor(high_less, equal_borrow);
+    let fits = pos.ins().bxor_imm(carry, 1);
+    let less = pos.ins().band(less, fits);
+    // The mask is all ones when subtracting, and zero otherwise.
+    let mask = pos.ins().iadd_imm(less, -1);
+    let sl = pos.ins().isub(rl, dl);
+    let sh = pos.ins().isub(rh, dh);
+    let sh = pos.ins().isub(sh, low_borrow);
+    let rl = select_mask(&mut pos, mask, sl, rl);
+    let rh = select_mask(&mut pos, mask, sh, rh);
+    let bit = pos.ins().band_imm(mask, 1);
+    let nl = pos.ins().bor(nl, bit);
+
+    let i = pos.ins().iadd_imm(i, -1);
+    pos.ins().brnz(i, loop_ebb, &[i, rl, rh, nl, nh]);
+    pos.ins().jump(done_ebb, &[]);
+
+    pos.insert_ebb(done_ebb);
+    let (lo, hi, sign) = if is_rem {
+        (rl, rh, x_sign)
+    } else {
+        let sign = match (x_sign, y_sign) {
+            (Some(x_sign), Some(y_sign)) => Some(pos.ins().bxor(x_sign, y_sign)),
+            _ => None,
+        };
+        (nl, nh, sign)
+    };
+    let (lo, hi) = negate_if(&mut pos, lo, hi, sign);
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+
+    cfg.recompute_ebb(pos.func, old_ebb);
+    cfg.recompute_ebb(pos.func, loop_ebb);
+    cfg.recompute_ebb(pos.func, done_ebb);
+    true
+}
+
+/// Negate the 64-bit number with the halves `lo` and `hi` if `sign` is all ones, or leave it
+/// alone if `sign` is zero or missing.
+fn negate_if(pos: &mut FuncCursor, lo: Value, hi: Value, sign: Option<Value>) -> (Value, Value) {
+    let sign = match sign {
+        Some(sign) => sign,
+        None => return (lo, hi),
+    };
+
+    // Compute `(n ^ sign) - sign`, with `sign` extended to 64 bits.
+    let lo = pos.ins().bxor(lo, sign);
+    let hi = pos.ins().bxor(hi, sign);
+    let borrow = pos.ins().icmp(IntCC::UnsignedLessThan, lo, sign);
+    let borrow = pos.ins().bint(I32, borrow);
+    let lo = pos.ins().isub(lo, sign);
+    let hi = pos.ins().isub(hi, sign);
+    let hi = pos.ins().isub(hi, borrow);
+    (lo, hi)
+}
+
+/// Shift `x` left by one, shifting in the most significant bit of `y`.
+fn shift_in(pos: &mut FuncCursor, x: Value, y: Value) -> Value {
+    let x = pos.ins().ishl_imm(x, 1);
+    let y = pos.ins().ushr_imm(y, 31);
+    pos.ins().bor(x, y)
+}
+
+/// Select `x` if `mask` is all ones, or `y` if it is zero.
+fn select_mask(pos: &mut FuncCursor, mask: Value, x: Value, y: Value) -> Value {
+    let diff = pos.ins().bxor(x, y);
+    let diff = pos.ins().band(diff, mask);
+    pos.ins().bxor(y, diff)
+}
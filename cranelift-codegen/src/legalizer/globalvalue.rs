@@ -1,7 +1,8 @@
 //! Legalization of global values.
 //!
 //! This module exports the `expand_global_value` function which transforms a `global_value`
-//! instruction into code that depends on the kind of global value referenced.
+//! instruction into code that depends on the kind of global value referenced, and the
+//! `expand_symbol_offset` function which splits the offset off a `symbol_value` instruction.
 
 use crate::cursor::{Cursor, FuncCursor};
 use crate::flowgraph::ControlFlowGraph;
@@ -125,5 +126,82 @@ fn load_addr(
 /// Expand a `global_value` instruction for a symbolic name global.
 fn symbol(inst: ir::Inst, func: &mut ir::Function, gv: ir::GlobalValue, isa: &dyn TargetIsa) {
     let ptr_ty = isa.pointer_type();
-    func.dfg.replace(inst).symbol_value(ptr_ty, gv);
+    let (offset, colocated, tls): (i64, _, _) = match func.global_values[gv] {
+        ir::GlobalValueData::Symbol {
+            offset,
+            colocated,
+            tls,
+            ..
+        } => (offset.into(), colocated, tls),
+        _ => panic!("Wanted a symbol global value"),
+    };
+
+    // The address of a TLS symbol, and in PIC mode the address of a symbol that isn't colocated,
+    // is found through a GOT entry which can only hold the address of the symbol itself. Any
+    // offset must be added separately.
+    if offset != 0 && (tls || (isa.flags().is_pic() && !colocated)) {
+        let base = symbol_base(func, gv);
+        let mut pos = FuncCursor::new(func).at_inst(inst);
+        pos.use_srcloc(inst);
+        let base_addr = if tls {
+            pos.ins().tls_value(ptr_ty, base)
+        } else {
+            pos.ins().symbol_value(ptr_ty, base)
+        };
+        pos.func.dfg.replace(inst).iadd_imm(base_addr, offset);
+    } else if tls {
+        func.dfg.replace(inst).tls_value(ptr_ty, gv);
+    } else {
+        func.dfg.replace(inst).symbol_value(ptr_ty, gv);
+    }
+}
+
+/// Split a `symbol_value` instruction without an encoding, whose symbol has an offset, into the
+/// address of the symbol itself and an `iadd_imm` of the offset.
+///
+/// In PIC mode, the address of a symbol that isn't colocated is loaded from a GOT entry, which
+/// can only hold the address of the symbol itself. Returns false if `inst` isn't such an
+/// instruction.
+pub fn expand_symbol_offset(inst: ir::Inst, func: &mut ir::Function) -> bool {
+    let gv = match func.dfg[inst] {
+        ir::InstructionData::UnaryGlobalValue {
+            opcode: ir::Opcode::SymbolValue,
+            global_value,
+        } => global_value,
+        _ => return false,
+    };
+    let offset: i64 = match func.global_values[gv] {
+        ir::GlobalValueData::Symbol { offset, .. } => offset.into(),
+        _ => return false,
+    };
+    if offset == 0 {
+        return false;
+    }
+
+    let ty = func.dfg.ctrl_typevar(inst);
+    let base = symbol_base(func, gv);
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let base_addr = pos.ins().symbol_value(ty, base);
+    pos.func.dfg.replace(inst).iadd_imm(base_addr, offset);
+    true
+}
+
+/// Create a global value for the symbol of `gv` without its offset.
+fn symbol_base(func: &mut ir::Function, gv: ir::GlobalValue) -> ir::GlobalValue {
+    let data = match func.global_values[gv] {
+        ir::GlobalValueData::Symbol {
+            ref name,
+            colocated,
+            tls,
+            ..
+        } => ir::GlobalValueData::Symbol {
+            name: name.clone(),
+            offset: ir::immediates::Imm64::new(0),
+            colocated,
+            tls,
+        },
+        _ => panic!("Wanted a symbol global value"),
+    };
+    func.create_global_value(data)
 }
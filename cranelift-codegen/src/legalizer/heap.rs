@@ -14,7 +14,7 @@ pub fn expand_heap_addr(
     inst: ir::Inst,
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
-    _isa: &dyn TargetIsa,
+    isa: &dyn TargetIsa,
 ) {
     // Unpack the instruction.
     let (heap, offset, access_size) = match func.dfg[inst] {
@@ -30,13 +30,27 @@ pub fn expand_heap_addr(
         _ => panic!("Wanted heap_addr: {}", func.dfg.display_inst(inst, None)),
     };
 
+    let spectre_mitigation = isa.flags().enable_heap_access_spectre_mitigation();
     match func.heaps[heap].style {
-        ir::HeapStyle::Dynamic { bound_gv } => {
-            dynamic_addr(inst, heap, offset, access_size, bound_gv, func)
-        }
-        ir::HeapStyle::Static { bound } => {
-            static_addr(inst, heap, offset, access_size, bound.into(), func, cfg)
-        }
+        ir::HeapStyle::Dynamic { bound_gv } => dynamic_addr(
+            inst,
+            heap,
+            offset,
+            access_size,
+            bound_gv,
+            spectre_mitigation,
+            func,
+        ),
+        ir::HeapStyle::Static { bound } => static_addr(
+            inst,
+            heap,
+            offset,
+            access_size,
+            bound.into(),
+            spectre_mitigation,
+            func,
+            cfg,
+        ),
     }
 }
 
@@ -47,6 +61,7 @@ fn dynamic_addr(
     offset: ir::Value,
     access_size: u32,
     bound_gv: ir::GlobalValue,
+    spectre_mitigation: bool,
     func: &mut ir::Function,
 ) {
     let access_size = u64::from(access_size);
@@ -76,13 +91,19 @@ fn dynamic_addr(
         let access_size_val = pos.ins().iconst(offset_ty, access_size as i64);
         let (adj_offset, overflow) = pos.ins().iadd_cout(offset, access_size_val);
         pos.ins().trapnz(overflow, ir::TrapCode::HeapOutOfBounds);
-        oob = pos
+        let adj_oob = pos
             .ins()
             .icmp(IntCC::UnsignedGreaterThan, adj_offset, bound);
+        oob = if spectre_mitigation {
+            pos.ins().bor(overflow, adj_oob)
+        } else {
+            adj_oob
+        };
     }
     pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
 
-    compute_addr(inst, heap, addr_ty, offset, offset_ty, pos.func);
+    let guard = if spectre_mitigation { Some(oob) } else { None };
+    compute_addr(inst, heap, addr_ty, offset, offset_ty, guard, pos.func);
 }
 
 /// Expand a `heap_addr` for a static heap.
@@ -92,6 +113,7 @@ fn static_addr(
     offset: ir::Value,
     access_size: u32,
     bound: u64,
+    spectre_mitigation: bool,
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
 ) {
@@ -121,6 +143,7 @@ fn static_addr(
 
     // We may be able to omit the check entirely for 32-bit offsets if the heap bound is 4 GB or
     // more.
+    let mut guard = None;
     if offset_ty != ir::types::I32 || limit < 0xffff_ffff {
         let oob = if limit & 1 == 1 {
             // Prefer testing `offset >= limit - 1` when limit is odd because an even number is
@@ -132,23 +155,38 @@ fn static_addr(
                 .icmp_imm(IntCC::UnsignedGreaterThan, offset, limit as i64)
         };
         pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
+        if spectre_mitigation {
+            guard = Some(oob);
+        }
     }
 
-    compute_addr(inst, heap, addr_ty, offset, offset_ty, pos.func);
+    compute_addr(inst, heap, addr_ty, offset, offset_ty, guard, pos.func);
 }
 
 /// Emit code for the base address computation of a `heap_addr` instruction.
+///
+/// If `oob` is given, it is the result of the bounds check and `offset` is masked to zero when it
+/// is true. This doesn't depend on the outcome of the branch to the trap, so the heap can't be
+/// accessed out of bounds even if that branch is mispredicted.
 fn compute_addr(
     inst: ir::Inst,
     heap: ir::Heap,
     addr_ty: ir::Type,
     mut offset: ir::Value,
     offset_ty: ir::Type,
+    oob: Option<ir::Value>,
     func: &mut ir::Function,
 ) {
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
+    // Mask the offset with all zeros when out of bounds, and all ones otherwise.
+    if let Some(oob) = oob {
+        let oob = pos.ins().bint(offset_ty, oob);
+        let mask = pos.ins().iadd_imm(oob, -1);
+        offset = pos.ins().band(offset, mask);
+    }
+
     // Convert `offset` to `addr_ty`.
     if offset_ty != addr_ty {
         let labels_value = offset;
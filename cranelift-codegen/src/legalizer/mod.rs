@@ -20,10 +20,12 @@ use crate::ir::types::I32;
 use crate::ir::{self, InstBuilder, MemFlags};
 use crate::isa::TargetIsa;
 use crate::predicates;
+use crate::settings::OptLevel;
 use crate::timing;
 
 mod boundary;
 mod call;
+mod divrem;
 mod globalvalue;
 mod heap;
 mod libcall;
@@ -31,7 +33,8 @@ mod split;
 mod table;
 
 use self::call::expand_call;
-use self::globalvalue::expand_global_value;
+use self::divrem::expand_divrem_loop;
+use self::globalvalue::{expand_global_value, expand_symbol_offset};
 use self::heap::expand_heap_addr;
 use self::libcall::expand_as_libcall;
 use self::table::expand_table_addr;
@@ -72,7 +75,18 @@ fn legalize_inst(
                 return true;
             }
 
-            // We don't have any pattern expansion for this instruction either.
+            // We don't have any pattern expansion for this instruction either. The address of a
+            // symbol may only be encodable without its offset.
+            if expand_symbol_offset(inst, pos.func) {
+                return true;
+            }
+
+            // Division of integers wider than registers is expanded as a loop if the settings ask
+            // for it.
+            if expand_divrem_loop(inst, pos.func, cfg, isa) {
+                return true;
+            }
+
             // Try converting it to a library call as a last resort.
             expand_as_libcall(inst, pos.func, isa)
         }
@@ -88,7 +102,7 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
     let _tt = timing::legalize();
     debug_assert!(cfg.is_valid());
 
-    boundary::legalize_signatures(func, isa);
+    boundary::legalize_signatures(func, cfg, isa);
 
     func.encodings.resize(func.dfg.num_insts());
 
@@ -130,7 +144,7 @@ fn expand_cond_trap(
     inst: ir::Inst,
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
-    _isa: &dyn TargetIsa,
+    isa: &dyn TargetIsa,
 ) {
     // Parse the instruction.
     let trapz;
@@ -178,8 +192,12 @@ fn expand_cond_trap(
     pos.use_srcloc(inst);
     pos.ins().jump(new_ebb_trap, &[]);
 
-    // Insert the new label and the unconditional trap terminator.
+    // Insert the new label and the unconditional trap terminator. Traps are expected to be rare,
+    // so the trap EBB can be placed out of line when optimizing.
     pos.insert_ebb(new_ebb_trap);
+    if isa.flags().opt_level() != OptLevel::Fastest {
+        pos.func.layout.set_cold(new_ebb_trap);
+    }
     pos.ins().trap(code);
 
     // Insert the new label and resume the execution when the trap fails.
@@ -363,6 +381,92 @@ fn expand_select(
     cfg.recompute_ebb(pos.func, old_ebb);
 }
 
+/// Expand an `atomic_rmw` instruction that the ISA can't encode directly.
+///
+/// Subtraction is turned into the addition of the negated operand, which may be supported.
+/// Everything else is implemented with a compare-and-swap loop.
+fn expand_atomic_rmw(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (flags, op, ordering, p, x) = match func.dfg[inst] {
+        ir::InstructionData::AtomicRmw {
+            opcode: ir::Opcode::AtomicRmw,
+            flags,
+            op,
+            ordering,
+            args,
+        } => (flags, op, ordering, args[0], args[1]),
+        _ => panic!("Expected atomic_rmw: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    if op == ir::AtomicRmwOp::Sub {
+        let mut pos = FuncCursor::new(func).at_inst(inst);
+        pos.use_srcloc(inst);
+        let neg = pos.ins().irsub_imm(x, 0);
+        pos.func
+            .dfg
+            .replace(inst)
+            .atomic_rmw(flags, ir::AtomicRmwOp::Add, ordering, p, neg);
+        return;
+    }
+
+    // Replace `result = atomic_rmw op p, x` with:
+    //
+    //     v0 = atomic_load relaxed p
+    //     jump loop_ebb(v0)
+    //   loop_ebb(old):
+    //     new = op old, x
+    //     result = atomic_cas p, old, new
+    //     br_icmp ne result, old, loop_ebb(result)
+    //     jump done_ebb
+    //   done_ebb:
+    //     ..
+    let old_ebb = func.layout.pp_ebb(inst);
+    let ty = func.dfg.ctrl_typevar(inst);
+    let result = func.dfg.first_result(inst);
+    func.dfg.clear_results(inst);
+    let loop_ebb = func.dfg.make_ebb();
+    let done_ebb = func.dfg.make_ebb();
+    let old = func.dfg.append_ebb_param(loop_ebb, ty);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let initial = pos
+        .ins()
+        .atomic_load(ty, flags, ir::AtomicOrdering::Relaxed, p);
+    pos.func.dfg.replace(inst).jump(loop_ebb, &[initial]);
+
+    let mut pos = pos.after_inst(inst);
+    pos.insert_ebb(loop_ebb);
+    let new = match op {
+        ir::AtomicRmwOp::Add => pos.ins().iadd(old, x),
+        ir::AtomicRmwOp::Sub => pos.ins().isub(old, x),
+        ir::AtomicRmwOp::And => pos.ins().band(old, x),
+        ir::AtomicRmwOp::Or => pos.ins().bor(old, x),
+        ir::AtomicRmwOp::Xor => pos.ins().bxor(old, x),
+        ir::AtomicRmwOp::Xchg => x,
+    };
+    pos.ins()
+        .with_result(result)
+        .atomic_cas(flags, ordering, p, old, new);
+    pos.ins().br_icmp(
+        ir::condcodes::IntCC::NotEqual,
+        result,
+        old,
+        loop_ebb,
+        &[result],
+    );
+    pos.ins().jump(done_ebb, &[]);
+    pos.insert_ebb(done_ebb);
+
+    cfg.recompute_ebb(pos.func, old_ebb);
+    cfg.recompute_ebb(pos.func, loop_ebb);
+    cfg.recompute_ebb(pos.func, done_ebb);
+}
+
 fn expand_br_icmp(
     inst: ir::Inst,
     func: &mut ir::Function,
@@ -490,3 +594,169 @@ fn expand_stack_store(
     mflags.set_aligned();
     pos.func.dfg.replace(inst).store(mflags, val, addr, 0);
 }
+
+/// Narrow an `iconst` instruction by materializing the two halves separately.
+fn narrow_iconst(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let imm: i64 = match func.dfg[inst] {
+        ir::InstructionData::UnaryImm {
+            opcode: ir::Opcode::Iconst,
+            imm,
+        } => imm.into(),
+        _ => panic!("Expected iconst: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+    let half_ty = ty.half_width().expect("Can't narrow iconst");
+    let half_bits = u32::from(half_ty.lane_bits());
+
+    // The immediate is sign-extended to the full width of the type.
+    let (low, high) = if half_bits >= 64 {
+        (imm, imm >> 63)
+    } else {
+        let shift = 64 - half_bits;
+        ((imm << shift) >> shift, imm >> half_bits)
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let low = pos.ins().iconst(half_ty, low);
+    let high = pos.ins().iconst(half_ty, high);
+    pos.func.dfg.replace(inst).iconcat(low, high);
+}
+
+/// Narrow an `icmp` instruction by comparing the two halves separately.
+fn narrow_icmp(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    use crate::ir::condcodes::IntCC;
+
+    let (cond, x, y) = match func.dfg[inst] {
+        ir::InstructionData::IntCompare {
+            opcode: ir::Opcode::Icmp,
+            cond,
+            args,
+        } => (cond, args[0], args[1]),
+        _ => panic!("Expected icmp: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (xl, xh) = split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), x);
+    let (yl, yh) = split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), y);
+
+    // The high halves decide the ordering, unless they are equal. Only the high halves carry a
+    // sign, so the low halves are always compared as unsigned numbers.
+    let (cond_high, cond_low) = match cond {
+        IntCC::Equal => {
+            let low = pos.ins().icmp(IntCC::Equal, xl, yl);
+            let high = pos.ins().icmp(IntCC::Equal, xh, yh);
+            pos.func.dfg.replace(inst).band(low, high);
+            return;
+        }
+        IntCC::NotEqual => {
+            let low = pos.ins().icmp(IntCC::NotEqual, xl, yl);
+            let high = pos.ins().icmp(IntCC::NotEqual, xh, yh);
+            pos.func.dfg.replace(inst).bor(low, high);
+            return;
+        }
+        IntCC::SignedLessThan => (IntCC::SignedLessThan, IntCC::UnsignedLessThan),
+        IntCC::SignedLessThanOrEqual => (IntCC::SignedLessThan, IntCC::UnsignedLessThanOrEqual),
+        IntCC::SignedGreaterThan => (IntCC::SignedGreaterThan, IntCC::UnsignedGreaterThan),
+        IntCC::SignedGreaterThanOrEqual => {
+            (IntCC::SignedGreaterThan, IntCC::UnsignedGreaterThanOrEqual)
+        }
+        IntCC::UnsignedLessThan => (IntCC::UnsignedLessThan, IntCC::UnsignedLessThan),
+        IntCC::UnsignedLessThanOrEqual => (IntCC::UnsignedLessThan, IntCC::UnsignedLessThanOrEqual),
+        IntCC::UnsignedGreaterThan => (IntCC::UnsignedGreaterThan, IntCC::UnsignedGreaterThan),
+        IntCC::UnsignedGreaterThanOrEqual => (
+            IntCC::UnsignedGreaterThan,
+            IntCC::UnsignedGreaterThanOrEqual,
+        ),
+    };
+    let high = pos.ins().icmp(cond_high, xh, yh);
+    let high_eq = pos.ins().icmp(IntCC::Equal, xh, yh);
+    let low = pos.ins().icmp(cond_low, xl, yl);
+    let low = pos.ins().band(high_eq, low);
+    pos.func.dfg.replace(inst).bor(high, low);
+}
+
+/// Narrow the `ishl`, `ushr`, and `sshr` instructions by shifting the two halves separately.
+///
+/// The shift amount is taken modulo the width of the type. Shifting by a half width or more moves
+/// one half into the other, which is handled with `select` instead of a branch.
+fn narrow_shift(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (opcode, x, amt) = match func.dfg[inst] {
+        ir::InstructionData::Binary { opcode, args } => (opcode, args[0], args[1]),
+        _ => panic!("Expected shift: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+    let half_ty = ty.half_width().expect("Can't narrow shift");
+    let half_bits = i64::from(half_ty.lane_bits());
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (xl, xh) = split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), x);
+    // Only the low bits of the shift amount matter.
+    let amt = if pos.func.dfg.value_type(amt) == ty {
+        split::isplit(pos.func, cfg, pos.position(), pos.srcloc(), amt).0
+    } else {
+        amt
+    };
+    let amt = pos.ins().band_imm(amt, 2 * half_bits - 1);
+    let is_large = pos.ins().band_imm(amt, half_bits);
+    // Shifting by `!amt` shifts by `half_bits - 1 - amt` modulo `half_bits`. Together with a shift
+    // by one, this moves the bits crossing between the halves without shifting by `half_bits`,
+    // which would be taken modulo `half_bits` as well.
+    let inv_amt = pos.ins().bnot(amt);
+
+    let (low, high) = match opcode {
+        ir::Opcode::Ishl => {
+            let low = pos.ins().ishl(xl, amt);
+            let carry = pos.ins().ushr_imm(xl, 1);
+            let carry = pos.ins().ushr(carry, inv_amt);
+            let high = pos.ins().ishl(xh, amt);
+            let high = pos.ins().bor(high, carry);
+            let zero = pos.ins().iconst(half_ty, 0);
+            (
+                pos.ins().select(is_large, zero, low),
+                pos.ins().select(is_large, low, high),
+            )
+        }
+        ir::Opcode::Ushr | ir::Opcode::Sshr => {
+            let high = if opcode == ir::Opcode::Ushr {
+                pos.ins().ushr(xh, amt)
+            } else {
+                pos.ins().sshr(xh, amt)
+            };
+            let carry = pos.ins().ishl_imm(xh, 1);
+            let carry = pos.ins().ishl(carry, inv_amt);
+            let low = pos.ins().ushr(xl, amt);
+            let low = pos.ins().bor(low, carry);
+            let fill = if opcode == ir::Opcode::Ushr {
+                pos.ins().iconst(half_ty, 0)
+            } else {
+                pos.ins().sshr_imm(xh, half_bits - 1)
+            };
+            (
+                pos.ins().select(is_large, high, low),
+                pos.ins().select(is_large, fill, high),
+            )
+        }
+        _ => panic!("Expected shift: {}", pos.func.dfg.display_inst(inst, None)),
+    };
+    pos.func.dfg.replace(inst).iconcat(low, high);
+}
@@ -55,7 +55,8 @@ use hashmap_core::{map as hash_map, HashMap, HashSet};
 #[cfg(feature = "std")]
 use std::collections::{hash_map, HashMap, HashSet};
 
-pub use crate::context::Context;
+pub use crate::context::{CompileStats, Context};
+pub use crate::ebb_layout::EdgeFrequencies;
 pub use crate::legalizer::legalize_function;
 pub use crate::value_label::{ValueLabelsRanges, ValueLocRange};
 pub use crate::verifier::verify_function;
@@ -65,6 +66,7 @@ pub use cranelift_bforest as bforest;
 pub use cranelift_entity as entity;
 
 pub mod binemit;
+pub mod cache;
 pub mod cfg_printer;
 pub mod cursor;
 pub mod dbg;
@@ -87,6 +89,7 @@ mod constant_hash;
 mod context;
 mod dce;
 mod divconst_magic_numbers;
+mod ebb_layout;
 mod fx;
 mod iterators;
 mod legalizer;
@@ -98,6 +101,7 @@ mod predicates;
 mod ref_slice;
 mod regalloc;
 mod result;
+mod scheduler;
 mod scoped_hash_map;
 mod simple_gvn;
 mod simple_preopt;
@@ -60,6 +60,17 @@ pub fn is_colocated_data(global_value: ir::GlobalValue, func: &ir::Function) ->
     }
 }
 
+#[allow(dead_code)]
+pub fn is_zero_symbol_offset(global_value: ir::GlobalValue, func: &ir::Function) -> bool {
+    match func.global_values[global_value] {
+        ir::GlobalValueData::Symbol { offset, .. } => {
+            let offset: i64 = offset.into();
+            offset == 0
+        }
+        _ => panic!("is_zero_symbol_offset only makes sense for data with symbolic addresses"),
+    }
+}
+
 #[allow(dead_code)]
 pub fn has_length_of(value_list: &ir::ValueList, num: usize, func: &ir::Function) -> bool {
     value_list.len(&func.dfg.value_lists) == num
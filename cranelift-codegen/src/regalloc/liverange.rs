@@ -175,7 +175,7 @@ pub struct GenLiveRange<PO: ProgramOrder> {
     /// defined.
     liveins: bforest::Map<Ebb, Inst>,
 
-    po: PhantomData<*const PO>,
+    po: PhantomData<PO>,
 }
 
 /// Context information needed to query a `LiveRange`.
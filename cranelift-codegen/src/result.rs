@@ -30,6 +30,13 @@ pub enum CodegenError {
     /// is exceeded, compilation fails.
     #[fail(display = "Code for function is too large")]
     CodeTooLarge,
+
+    /// The function uses a feature that the target ISA doesn't support.
+    ///
+    /// Some features, such as stack limit checks, are only implemented by some ISAs or calling
+    /// conventions, and compilation fails rather than silently ignoring them.
+    #[fail(display = "Unsupported feature: {}", _0)]
+    Unsupported(&'static str),
 }
 
 /// A convenient alias for a `Result` that uses `CodegenError` as the error type.
@@ -0,0 +1,355 @@
+//! Post-regalloc instruction scheduling.
+//!
+//! In-order cores stall whenever an instruction needs a result that isn't available yet. This
+//! pass hides some of those stalls by reordering the instructions of each EBB, using the latency
+//! model provided by the target ISA.
+//!
+//! Since it runs after register allocation, the scheduler must preserve the dependencies through
+//! registers and stack slots as well as the dependencies through values. Every instruction is
+//! described by the resources it reads and writes: register units, stack slots, the CPU flags,
+//! and memory. An EBB is split into regions at instructions that can't be moved, such as
+//! branches, calls, and register diversions, and each region is list-scheduled separately.
+
+use crate::ir::{Ebb, Function, Inst, StackSlot, Value, ValueLoc};
+use crate::isa::{EncInfo, LatencyModel, OperandConstraint, RegUnit, TargetIsa};
+use crate::regalloc::RegDiversions;
+use crate::timing;
+use crate::HashMap;
+use std::vec::Vec;
+
+/// Reorder the instructions in each EBB of `func` to reduce pipeline stalls.
+///
+/// The function must have been register allocated.
+pub fn do_scheduling(func: &mut Function, isa: &dyn TargetIsa) {
+    let _tt = timing::scheduling();
+    let mut sched = Scheduler::new(isa);
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        sched.schedule_ebb(func, ebb);
+    }
+}
+
+/// A machine resource that instructions can read or write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Resource {
+    Reg(RegUnit),
+    Stack(StackSlot),
+    Flags,
+    Memory,
+}
+
+/// A node in the dependency graph of a region.
+struct Node {
+    inst: Inst,
+    /// Cycles until the results of this instruction are available.
+    latency: u32,
+    /// Successors and the number of cycles they must be issued after this instruction.
+    succs: Vec<(usize, u32)>,
+    /// Number of predecessors not scheduled yet.
+    preds: usize,
+    /// Length of the longest latency path from this instruction to the end of the region.
+    height: u32,
+    /// Earliest cycle this instruction can be issued without stalling.
+    ready: u32,
+}
+
+/// Last writer and readers since then of a resource.
+#[derive(Default)]
+struct Accesses {
+    writer: Option<usize>,
+    readers: Vec<usize>,
+}
+
+struct Scheduler {
+    encinfo: EncInfo,
+    uses_cpu_flags: bool,
+    latencies: LatencyModel,
+    divert: RegDiversions,
+    region: Vec<Inst>,
+    nodes: Vec<Node>,
+    accesses: HashMap<Resource, Accesses>,
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+}
+
+impl Scheduler {
+    fn new(isa: &dyn TargetIsa) -> Self {
+        Self {
+            encinfo: isa.encoding_info(),
+            uses_cpu_flags: isa.uses_cpu_flags(),
+            latencies: isa.latency_model(),
+            divert: RegDiversions::new(),
+            region: Vec::new(),
+            nodes: Vec::new(),
+            accesses: HashMap::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    fn schedule_ebb(&mut self, func: &mut Function, ebb: Ebb) {
+        self.divert.clear();
+        self.region.clear();
+
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            if self.is_barrier(func, inst) {
+                self.schedule_region(func, ebb, Some(inst));
+                self.divert.apply(&func.dfg[inst]);
+            } else {
+                self.region.push(inst);
+            }
+        }
+        self.schedule_region(func, ebb, None);
+    }
+
+    /// Is `inst` an instruction that can't be moved, and that other instructions can't be moved
+    /// across?
+    fn is_barrier(&self, func: &Function, inst: Inst) -> bool {
+        let enc = func.encodings[inst];
+        if !enc.is_legal() || self.encinfo.operand_constraints(enc).is_none() {
+            return true;
+        }
+        let opcode = func.dfg[inst].opcode();
+        opcode.is_branch()
+            || opcode.is_terminator()
+            || opcode.is_call()
+            || opcode.is_return()
+            || opcode.other_side_effects()
+    }
+
+    /// Schedule the instructions collected in `self.region`, and place them before `barrier`, or
+    /// at the end of `ebb`.
+    fn schedule_region(&mut self, func: &mut Function, ebb: Ebb, barrier: Option<Inst>) {
+        if self.region.len() > 1 {
+            self.build_graph(func);
+            let order = self.list_schedule();
+            if order.iter().zip(&self.region).any(|(a, b)| a != b) {
+                for &inst in &order {
+                    func.layout.remove_inst(inst);
+                    match barrier {
+                        Some(before) => func.layout.insert_inst(inst, before),
+                        None => func.layout.append_inst(inst, ebb),
+                    }
+                }
+            }
+        }
+        self.region.clear();
+    }
+
+    /// Build the dependency graph of the instructions in `self.region`.
+    fn build_graph(&mut self, func: &Function) {
+        self.nodes.clear();
+        self.accesses.clear();
+
+        for idx in 0..self.region.len() {
+            let inst = self.region[idx];
+            let opcode = func.dfg[inst].opcode();
+            self.nodes.push(Node {
+                inst,
+                latency: self.latencies.latency(opcode),
+                succs: Vec::new(),
+                preds: 0,
+                height: 0,
+                ready: 0,
+            });
+
+            self.collect_resources(func, inst);
+
+            // Read-after-write dependencies wait for the writer's latency.
+            for i in 0..self.reads.len() {
+                let res = self.reads[i];
+                let acc = self.accesses.entry(res).or_default();
+                if let Some(writer) = acc.writer {
+                    let latency = self.nodes[writer].latency;
+                    add_edge(&mut self.nodes, writer, idx, latency);
+                }
+                acc.readers.push(idx);
+            }
+
+            // Write-after-read and write-after-write dependencies only constrain the order.
+            for i in 0..self.writes.len() {
+                let res = self.writes[i];
+                let acc = self.accesses.entry(res).or_default();
+                for &reader in &acc.readers {
+                    if reader != idx {
+                        add_edge(&mut self.nodes, reader, idx, 0);
+                    }
+                }
+                match acc.writer {
+                    Some(writer) if writer != idx => add_edge(&mut self.nodes, writer, idx, 1),
+                    _ => {}
+                }
+                acc.writer = Some(idx);
+                acc.readers.clear();
+            }
+        }
+
+        // The edges always go forward, so the heights can be computed in a single backwards pass.
+        for idx in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[idx];
+            let height = node
+                .succs
+                .iter()
+                .map(|&(succ, latency)| latency + self.nodes[succ].height)
+                .max()
+                .unwrap_or(0)
+                .max(node.latency);
+            self.nodes[idx].height = height;
+        }
+    }
+
+    /// Compute the resources read and written by `inst` into `self.reads` and `self.writes`.
+    fn collect_resources(&mut self, func: &Function, inst: Inst) {
+        self.reads.clear();
+        self.writes.clear();
+
+        let opcode = func.dfg[inst].opcode();
+        let constraints = self
+            .encinfo
+            .operand_constraints(func.encodings[inst])
+            .expect("only barriers lack constraints");
+
+        for (i, &arg) in func.dfg.inst_args(inst).iter().enumerate() {
+            let loc = self.divert.get(arg, &func.locations);
+            add_value(&mut self.reads, func, arg, loc, constraints.ins.get(i));
+        }
+        for (i, &res) in func.dfg.inst_results(inst).iter().enumerate() {
+            let loc = func.locations[res];
+            add_value(&mut self.writes, func, res, loc, constraints.outs.get(i));
+        }
+
+        if constraints.clobbers_flags && self.uses_cpu_flags {
+            self.writes.push(Resource::Flags);
+        }
+        if opcode.can_load() {
+            self.reads.push(Resource::Memory);
+        }
+        // Trapping instructions are kept in order with all memory accesses, so a load can't be
+        // hoisted above the bounds check that guards it.
+        if opcode.can_store() || opcode.can_trap() {
+            self.writes.push(Resource::Memory);
+        }
+    }
+
+    /// Pick an order for the nodes, returning the instructions in that order.
+    ///
+    /// This is a greedy list scheduler which simulates a single-issue pipeline. Each cycle, it
+    /// issues the ready instruction with the longest path to the end of the region, preferring
+    /// the original order between equals.
+    fn list_schedule(&mut self) -> Vec<Inst> {
+        let mut ready: Vec<usize> = (0..self.nodes.len())
+            .filter(|&idx| self.nodes[idx].preds == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut cycle = 0;
+
+        while !ready.is_empty() {
+            let nodes = &self.nodes;
+            let pos = (0..ready.len())
+                .min_by_key(|&pos| {
+                    let node = &nodes[ready[pos]];
+                    (
+                        node.ready.max(cycle),
+                        core::cmp::Reverse(node.height),
+                        ready[pos],
+                    )
+                })
+                .expect("ready list is not empty");
+            let idx = ready.swap_remove(pos);
+            let issue = self.nodes[idx].ready.max(cycle);
+            order.push(self.nodes[idx].inst);
+
+            for i in 0..self.nodes[idx].succs.len() {
+                let (succ, latency) = self.nodes[idx].succs[i];
+                let node = &mut self.nodes[succ];
+                node.ready = node.ready.max(issue + latency);
+                node.preds -= 1;
+                if node.preds == 0 {
+                    ready.push(succ);
+                }
+            }
+            cycle = issue + 1;
+        }
+
+        debug_assert_eq!(order.len(), self.nodes.len(), "cycle in dependency graph");
+        order
+    }
+}
+
+/// Add a dependency edge from `from` to `to`.
+fn add_edge(nodes: &mut [Node], from: usize, to: usize, latency: u32) {
+    nodes[from].succs.push((to, latency));
+    nodes[to].preds += 1;
+}
+
+/// Add the resources holding `value` at `loc` to `resources`.
+fn add_value(
+    resources: &mut Vec<Resource>,
+    func: &Function,
+    value: Value,
+    loc: ValueLoc,
+    constraint: Option<&OperandConstraint>,
+) {
+    if func.dfg.value_type(value).is_flags() {
+        resources.push(Resource::Flags);
+    }
+    match loc {
+        ValueLoc::Reg(unit) => {
+            let width = constraint.map_or(1, |c| c.regclass.width);
+            for offset in 0..RegUnit::from(width) {
+                resources.push(Resource::Reg(unit + offset));
+            }
+        }
+        ValueLoc::Stack(ss) => resources.push(Resource::Stack(ss)),
+        ValueLoc::Unassigned => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::InstBuilder;
+    use crate::isa;
+    use crate::settings::{self, Configurable};
+    use core::str::FromStr;
+    use target_lexicon::triple;
+
+    #[test]
+    fn hide_multiply_latency() {
+        let mut isa_builder = isa::lookup(triple!("riscv32")).unwrap();
+        isa_builder.enable("supports_m").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let (mul, add1, add2) = {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, I32);
+            let v1 = pos.func.dfg.append_ebb_param(ebb0, I32);
+            let v2 = pos.func.dfg.append_ebb_param(ebb0, I32);
+            let v3 = pos.ins().imul(v0, v1);
+            let v4 = pos.ins().iadd(v3, v2);
+            let v5 = pos.ins().iadd(v0, v1);
+            pos.ins().return_(&[v4, v5]);
+
+            for &(v, r) in &[(v0, 10), (v1, 11), (v2, 12), (v3, 13), (v4, 14), (v5, 15)] {
+                pos.func.locations[v] = ValueLoc::Reg(r);
+            }
+            let def = |v| pos.func.dfg.value_def(v).unwrap_inst();
+            (def(v3), def(v4), def(v5))
+        };
+        for inst in func.layout.ebb_insts(ebb0).collect::<Vec<_>>() {
+            let ctrl_type = func.dfg.ctrl_typevar(inst);
+            let enc = isa.encode(&func, &func.dfg[inst], ctrl_type).unwrap();
+            func.encodings[inst] = enc;
+        }
+
+        do_scheduling(&mut func, &*isa);
+        let order: Vec<Inst> = func.layout.ebb_insts(ebb0).take(3).collect();
+        assert_eq!(order, [mul, add2, add1]);
+    }
+}
@@ -379,12 +379,14 @@ mod tests {
             f.to_string(),
             "[shared]\n\
              opt_level = \"default\"\n\
+             tls_model = \"none\"\n\
              baldrdash_prologue_words = 0\n\
              probestack_size_log2 = 12\n\
              enable_verifier = true\n\
              is_pic = false\n\
              colocated_libcalls = false\n\
              avoid_div_traps = false\n\
+             inline_wide_divrem = false\n\
              enable_float = true\n\
              enable_nan_canonicalization = false\n\
              enable_simd = false\n\
@@ -392,7 +394,9 @@ mod tests {
              allones_funcaddrs = false\n\
              probestack_enabled = true\n\
              probestack_func_adjusts_sp = false\n\
-             jump_tables_enabled = true\n"
+             jump_tables_enabled = true\n\
+             enable_heap_access_spectre_mitigation = false\n\
+             enable_post_ra_scheduling = false\n"
         );
         assert_eq!(f.opt_level(), super::OptLevel::Default);
         assert_eq!(f.enable_simd(), false);
@@ -424,7 +428,9 @@ mod tests {
         );
         assert_eq!(
             b.set("opt_level", "true"),
-            Err(BadValue("any among default, best, fastest".to_string()))
+            Err(BadValue(
+                "any among default, best, fastest, size".to_string()
+            ))
         );
         assert_eq!(b.set("opt_level", "best"), Ok(()));
         assert_eq!(b.set("enable_simd", "0"), Ok(()));
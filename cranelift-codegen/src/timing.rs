@@ -4,7 +4,7 @@
 
 use core::fmt;
 
-pub use self::details::{add_to_current, take_current, PassTimes, TimingToken};
+pub use self::details::{add_to_current, take_current, PassTime, PassTimes, TimingToken};
 
 // Each pass that can be timed is predefined with the `define_passes!` macro. Each pass has a
 // snake_case name and a plain text description used when printing out the timing report.
@@ -13,10 +13,11 @@ pub use self::details::{add_to_current, take_current, PassTimes, TimingToken};
 //
 // - A C-style enum containing all the pass names and a `None` variant.
 // - A usize constant with the number of defined passes.
+// - A const array of pass names.
 // - A const array of pass descriptions.
 // - A public function per pass used to start the timing of that pass.
 macro_rules! define_passes {
-    { $enum:ident, $num_passes:ident, $descriptions:ident;
+    { $enum:ident, $num_passes:ident, $names:ident, $descriptions:ident;
       $($pass:ident: $desc:expr,)+
     } => {
         #[allow(non_camel_case_types)]
@@ -25,6 +26,8 @@ macro_rules! define_passes {
 
         const $num_passes: usize = $enum::None as usize;
 
+        const $names: [&str; $num_passes] = [ $(stringify!($pass)),+ ];
+
         const $descriptions: [&str; $num_passes] = [ $($desc),+ ];
 
         $(
@@ -38,7 +41,7 @@ macro_rules! define_passes {
 
 // Pass definitions.
 define_passes! {
-    Pass, NUM_PASSES, DESCRIPTIONS;
+    Pass, NUM_PASSES, NAMES, DESCRIPTIONS;
 
     process_file: "Processing test file",
     parse_text: "Parsing textual Cranelift IR",
@@ -62,6 +65,7 @@ define_passes! {
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
     unreachable_code: "Remove unreachable blocks",
+    ebb_layout: "EBB layout",
 
     regalloc: "Register allocation",
     ra_liveness: "RA liveness analysis",
@@ -70,6 +74,7 @@ define_passes! {
     ra_reload: "RA reloading",
     ra_coloring: "RA coloring",
 
+    scheduling: "Post-regalloc instruction scheduling",
     prologue_epilogue: "Prologue/epilogue insertion",
     shrink_instructions: "Instruction encoding shrinking",
     relax_branches: "Branch relaxation",
@@ -101,7 +106,7 @@ impl fmt::Display for Pass {
 /// `TimingToken` and `PassTimes` types and `take_current`, `add_to_current`, and `start_pass` funcs
 #[cfg(feature = "std")]
 mod details {
-    use super::{Pass, DESCRIPTIONS, NUM_PASSES};
+    use super::{Pass, DESCRIPTIONS, NAMES, NUM_PASSES};
     use log::debug;
     use std::cell::{Cell, RefCell};
     use std::fmt;
@@ -125,20 +130,51 @@ mod details {
     }
 
     /// Accumulated timing information for a single pass.
-    #[derive(Default, Copy, Clone)]
-    struct PassTime {
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct PassTime {
         /// Total time spent running this pass including children.
-        total: Duration,
+        pub total: Duration,
 
         /// Time spent running in child passes.
-        child: Duration,
+        pub child: Duration,
+    }
+
+    impl PassTime {
+        /// Time spent running this pass, excluding its child passes.
+        pub fn self_time(&self) -> Duration {
+            self.total.checked_sub(self.child).unwrap_or_default()
+        }
     }
 
     /// Accumulated timing for all passes.
+    #[derive(Clone, Debug)]
     pub struct PassTimes {
         pass: [PassTime; NUM_PASSES],
     }
 
+    impl PassTimes {
+        /// Iterate over the passes that have run, in the order they are defined.
+        ///
+        /// Each pass is identified by the name of the function starting its timing, such as
+        /// `"regalloc"`, and a plain text description.
+        pub fn iter(&self) -> impl Iterator<Item = (&'static str, &'static str, PassTime)> + '_ {
+            NAMES
+                .iter()
+                .zip(&DESCRIPTIONS[..])
+                .zip(&self.pass[..])
+                .filter(|(_, time)| time.total != Duration::default())
+                .map(|((&name, &desc), &time)| (name, desc, time))
+        }
+
+        /// Get the accumulated timing of the pass named `name`, if it exists.
+        pub fn get(&self, name: &str) -> Option<PassTime> {
+            NAMES
+                .iter()
+                .position(|&n| n == name)
+                .map(|idx| self.pass[idx])
+        }
+    }
+
     impl Default for PassTimes {
         fn default() -> Self {
             PassTimes {
@@ -152,12 +188,7 @@ mod details {
             writeln!(f, "======== ========  ==================================")?;
             writeln!(f, "   Total     Self  Pass")?;
             writeln!(f, "-------- --------  ----------------------------------")?;
-            for (time, desc) in self.pass.iter().zip(&DESCRIPTIONS[..]) {
-                // Omit passes that haven't run.
-                if time.total == Duration::default() {
-                    continue;
-                }
-
+            for (_, desc, time) in self.iter() {
                 // Write a duration as secs.millis, trailing space.
                 fn fmtdur(mut dur: Duration, f: &mut fmt::Formatter) -> fmt::Result {
                     // Round to nearest ms by adding 500us.
@@ -167,9 +198,7 @@ mod details {
                 }
 
                 fmtdur(time.total, f)?;
-                if let Some(s) = time.total.checked_sub(time.child) {
-                    fmtdur(s, f)?;
-                }
+                fmtdur(time.self_time(), f)?;
                 writeln!(f, " {}", desc)?;
             }
             writeln!(f, "======== ========  ==================================")
@@ -232,10 +261,36 @@ mod details {
 #[cfg(not(feature = "std"))]
 mod details {
     use super::Pass;
+    use core::time::Duration;
     /// Dummy `TimingToken`
     pub struct TimingToken;
+    /// Dummy `PassTime`
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct PassTime {
+        /// Always zero
+        pub total: Duration,
+        /// Always zero
+        pub child: Duration,
+    }
+    impl PassTime {
+        /// Always zero
+        pub fn self_time(&self) -> Duration {
+            Duration::default()
+        }
+    }
     /// Dummy `PassTimes`
+    #[derive(Default, Clone, Debug)]
     pub struct PassTimes;
+    impl PassTimes {
+        /// Returns no passes
+        pub fn iter(&self) -> impl Iterator<Item = (&'static str, &'static str, PassTime)> {
+            core::iter::empty()
+        }
+        /// Returns `None`
+        pub fn get(&self, _name: &str) -> Option<PassTime> {
+            None
+        }
+    }
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
@@ -253,10 +308,32 @@ mod details {
 mod tests {
     use super::*;
     use std::string::ToString;
+    use std::vec::Vec;
 
     #[test]
     fn display() {
         assert_eq!(Pass::None.to_string(), "<no pass>");
         assert_eq!(Pass::regalloc.to_string(), "Register allocation");
     }
+
+    #[test]
+    fn pass_times() {
+        let saved = take_current();
+        {
+            let _tt = regalloc();
+            let _tt = ra_liveness();
+        }
+        let times = take_current();
+        add_to_current(&saved);
+
+        let passes = times.iter().map(|(name, _, _)| name).collect::<Vec<_>>();
+        assert_eq!(passes, ["regalloc", "ra_liveness"]);
+        let (_, desc, time) = times.iter().next().unwrap();
+        assert_eq!(desc, "Register allocation");
+        assert_eq!(Some(time), times.get("regalloc"));
+        assert_eq!(time.child, times.get("ra_liveness").unwrap().total);
+        assert_eq!(time.self_time(), time.total - time.child);
+        assert_eq!(times.get("ra_reload"), Some(PassTime::default()));
+        assert_eq!(times.get("no_such_pass"), None);
+    }
 }
@@ -57,6 +57,7 @@
 //!   of arguments must match the destination type, and the lane indexes must be in range.
 
 use self::flags::verify_flags;
+use crate::abi::stack_arg_size;
 use crate::dbg::DisplayList;
 use crate::dominator_tree::DominatorTree;
 use crate::entity::SparseSet;
@@ -417,6 +418,71 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    fn verify_stack_limit(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        let limit = match self.func.stack_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        if !self.func.global_values.is_valid(limit) {
+            return nonfatal!(
+                errors,
+                AnyEntity::Function,
+                "invalid stack limit global value {}",
+                limit
+            );
+        }
+
+        if self
+            .func
+            .signature
+            .special_param_index(ir::ArgumentPurpose::StackLimit)
+            .is_some()
+        {
+            report!(
+                errors,
+                AnyEntity::Function,
+                "function has both a stack_limit parameter and a stack limit global value"
+            );
+        }
+
+        // The prologue computes the stack limit before any register is saved, so it can only
+        // use the `vmctx` parameter.
+        let mut seen = SparseSet::new();
+        let mut cur = limit;
+        while seen.insert(cur).is_none() {
+            match self.func.global_values[cur] {
+                ir::GlobalValueData::VMContext => break,
+                ir::GlobalValueData::Load { base, .. }
+                | ir::GlobalValueData::IAddImm { base, .. } => cur = base,
+                ir::GlobalValueData::Symbol { .. } => {
+                    return nonfatal!(
+                        errors,
+                        limit,
+                        "stack limit {} can't be computed from symbol {}",
+                        limit,
+                        cur
+                    );
+                }
+            }
+        }
+
+        if let Some(isa) = self.isa {
+            let limit_type = self.func.global_values[limit].global_type(isa);
+            let pointer_type = isa.pointer_type();
+            if limit_type != pointer_type {
+                report!(
+                    errors,
+                    limit,
+                    "stack limit has type {}, which is not the pointer type {}",
+                    limit_type,
+                    pointer_type
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn verify_tables(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
         if let Some(isa) = self.isa {
             for (table, table_data) in &self.func.tables {
@@ -675,6 +741,13 @@ impl<'a> Verifier<'a> {
             StoreComplex { ref args, .. } => {
                 self.verify_value_list(inst, args, errors)?;
             }
+            AtomicLoad { ordering, .. }
+            | AtomicStore { ordering, .. }
+            | AtomicRmw { ordering, .. }
+            | AtomicCas { ordering, .. }
+            | Fence { ordering, .. } => {
+                self.verify_atomic_ordering(inst, ordering, errors)?;
+            }
 
             // Exhaustive list so we can't forget to add new formats
             Unary { .. }
@@ -776,6 +849,39 @@ impl<'a> Verifier<'a> {
         }
     }
 
+    /// Check that `ordering` makes sense for the atomic instruction `inst`.
+    ///
+    /// Loads can't release and stores can't acquire, since there is no store or load to attach
+    /// the ordering to. A relaxed fence doesn't order anything.
+    fn verify_atomic_ordering(
+        &self,
+        inst: Inst,
+        ordering: ir::AtomicOrdering,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let valid = match self.func.dfg[inst].opcode() {
+            Opcode::AtomicLoad => {
+                ordering != ir::AtomicOrdering::Release && ordering != ir::AtomicOrdering::AcqRel
+            }
+            Opcode::AtomicStore => {
+                ordering != ir::AtomicOrdering::Acquire && ordering != ir::AtomicOrdering::AcqRel
+            }
+            Opcode::Fence => ordering != ir::AtomicOrdering::Relaxed,
+            _ => true,
+        };
+        if !valid {
+            nonfatal!(
+                errors,
+                inst,
+                "invalid ordering {} for {}",
+                ordering,
+                self.func.dfg[inst].opcode()
+            )
+        } else {
+            Ok(())
+        }
+    }
+
     fn verify_heap(
         &self,
         inst: Inst,
@@ -1326,7 +1432,7 @@ impl<'a> Verifier<'a> {
                             slot
                         );
                     }
-                    if slot.size != abi.value_type.bytes() {
+                    if slot.size != stack_arg_size(&abi) {
                         return fatal!(
                             errors,
                             inst,
@@ -1743,6 +1849,7 @@ impl<'a> Verifier<'a> {
         self.verify_global_values(errors)?;
         self.verify_heaps(errors)?;
         self.verify_tables(errors)?;
+        self.verify_stack_limit(errors)?;
         self.verify_jump_tables(errors)?;
         self.typecheck_entry_block_params(errors)?;
 
@@ -102,6 +102,11 @@ pub trait FuncWriter {
             self.write_entity_definition(w, func, jt.into(), jt_data)?;
         }
 
+        if let Some(limit) = func.stack_limit {
+            any = true;
+            writeln!(w, "    stack_limit = {}", limit)?;
+        }
+
         Ok(any)
     }
 
@@ -253,19 +258,20 @@ pub fn write_ebb_header(
     let regs = regs.as_ref();
 
     let mut args = func.dfg.ebb_params(ebb).iter().cloned();
-    match args.next() {
-        None => return writeln!(w, ":"),
-        Some(arg) => {
-            write!(w, "(")?;
+    if let Some(arg) = args.next() {
+        write!(w, "(")?;
+        write_arg(w, func, regs, arg)?;
+        // Remaining arguments.
+        for arg in args {
+            write!(w, ", ")?;
             write_arg(w, func, regs, arg)?;
         }
+        write!(w, ")")?;
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, regs, arg)?;
+    if func.layout.is_cold(ebb) {
+        write!(w, " cold")?;
     }
-    writeln!(w, "):")
+    writeln!(w, ":")
 }
 
 fn write_valueloc(w: &mut dyn Write, loc: &ValueLoc, regs: &RegInfo) -> fmt::Result {
@@ -637,6 +643,36 @@ pub fn write_operands(
                 offset
             )
         }
+        AtomicLoad {
+            flags,
+            ordering,
+            arg,
+            ..
+        } => write!(w, "{} {} {}", flags, ordering, arg),
+        AtomicStore {
+            flags,
+            ordering,
+            args,
+            ..
+        } => write!(w, "{} {} {}, {}", flags, ordering, args[0], args[1]),
+        AtomicRmw {
+            flags,
+            op,
+            ordering,
+            args,
+            ..
+        } => write!(w, "{} {} {} {}, {}", flags, op, ordering, args[0], args[1]),
+        AtomicCas {
+            flags,
+            ordering,
+            args,
+            ..
+        } => write!(
+            w,
+            "{} {} {}, {}, {}",
+            flags, ordering, args[0], args[1], args[2]
+        ),
+        Fence { ordering, .. } => write!(w, " {}", ordering),
         RegMove { arg, src, dst, .. } => {
             if let Some(isa) = isa {
                 let regs = isa.register_info();
@@ -780,6 +816,12 @@ mod tests {
             f.to_string(),
             "function %foo() fast {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4):\n    return\n}\n"
         );
+
+        f.layout.set_cold(ebb);
+        assert_eq!(
+            f.to_string(),
+            "function %foo() fast {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4) cold:\n    return\n}\n"
+        );
     }
 
     #[test]
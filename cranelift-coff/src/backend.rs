@@ -0,0 +1,583 @@
+//! Defines `CoffBackend`.
+
+use crate::object::{
+    Object, Relocation, SectionId, Symbol, SymbolId, IMAGE_FILE_MACHINE_AMD64,
+    IMAGE_REL_AMD64_ADDR32, IMAGE_REL_AMD64_ADDR64, IMAGE_REL_AMD64_REL32, IMAGE_SCN_CNT_CODE,
+    IMAGE_SCN_CNT_INITIALIZED_DATA, IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE,
+    IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE,
+};
+use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::settings::OptLevel;
+use cranelift_codegen::{self, binemit, ir};
+use cranelift_module::{
+    Backend, DataContext, DataDescription, DataStorage, Init, Linkage, ModuleError,
+    ModuleNamespace, ModuleResult,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use target_lexicon::{Architecture, PointerWidth};
+
+/// The alignment of functions in the `.text` section.
+const FUNCTION_ALIGN: u64 = 16;
+
+/// A builder for `CoffBackend`.
+pub struct CoffBuilder {
+    isa: Box<dyn TargetIsa>,
+    name: String,
+    libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+}
+
+impl CoffBuilder {
+    /// Create a new `CoffBuilder` using the given Cranelift target, that
+    /// can be passed to
+    /// [`Module::new`](cranelift_module/struct.Module.html#method.new].
+    ///
+    /// Only x86-64 targets are supported. Code may be compiled with or without PIC; since COFF
+    /// has no GOT, the backend emits its own table of addresses for GOT-relative references.
+    ///
+    /// The `libcall_names` function provides a way to translate `cranelift_codegen`'s `ir::LibCall`
+    /// enum to symbols. LibCalls are inserted in the IR as part of the legalization for certain
+    /// floating point instructions, and for stack probes. If you don't know what to use for this
+    /// argument, use `cranelift_module::default_libcall_names()`.
+    pub fn new(
+        isa: Box<dyn TargetIsa>,
+        name: String,
+        libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    ) -> ModuleResult<Self> {
+        if isa.triple().architecture != Architecture::X86_64 {
+            return Err(ModuleError::Backend(format!(
+                "COFF output doesn't support {}",
+                isa.triple().architecture
+            )));
+        }
+        Ok(Self {
+            isa,
+            name,
+            libcall_names,
+        })
+    }
+}
+
+/// A `CoffBackend` implements `Backend` and emits ".obj" files in the COFF format used by
+/// Windows linkers.
+///
+/// Functions are placed in `.text`, writable data objects in `.data`, zero-initialized ones in
+/// `.bss`, and read-only ones in `.rdata`, unless they request a custom section. The Windows
+/// loader applies relocations to read-only sections, so relro data is placed in `.rdata` too.
+/// `Preemptible` definitions are exported like `Export` ones, as Windows doesn't interpose
+/// symbols.
+///
+/// See the `CoffBuilder` for a convenient way to construct `CoffBackend` instances.
+pub struct CoffBackend {
+    isa: Box<dyn TargetIsa>,
+    name: String,
+    object: Object,
+    text: SectionId,
+    data: SectionId,
+    rdata: SectionId,
+    bss: SectionId,
+    /// The custom sections, by name.
+    custom_sections: HashMap<String, SectionId>,
+    /// The custom sections of functions that haven't been defined yet.
+    function_sections: HashMap<String, String>,
+    /// The table of addresses used for GOT-relative references, created on first use.
+    got: Option<SectionId>,
+    got_entries: HashMap<SymbolId, u32>,
+    symbols: HashMap<String, SymbolId>,
+    libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+}
+
+pub struct CoffCompiledFunction {
+    code_length: u32,
+}
+
+impl CoffCompiledFunction {
+    pub fn code_length(&self) -> u32 {
+        self.code_length
+    }
+}
+
+pub struct CoffCompiledData {}
+
+impl Backend for CoffBackend {
+    type Builder = CoffBuilder;
+
+    type CompiledFunction = CoffCompiledFunction;
+    type CompiledData = CoffCompiledData;
+
+    // There's no need to return individual artifacts; we're writing them into
+    // the output file instead.
+    type FinalizedFunction = ();
+    type FinalizedData = ();
+
+    /// The returned value here provides functions for emitting object files
+    /// to memory and files.
+    type Product = CoffProduct;
+
+    /// Create a new `CoffBackend` using the given Cranelift target.
+    fn new(builder: CoffBuilder) -> Self {
+        let mut object = Object::new(IMAGE_FILE_MACHINE_AMD64);
+        let text = object.add_section(
+            ".text",
+            IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        );
+        let data = object.add_section(
+            ".data",
+            IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        );
+        let rdata = object.add_section(
+            ".rdata",
+            IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        );
+        let bss = object.add_section(
+            ".bss",
+            IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        );
+        Self {
+            isa: builder.isa,
+            name: builder.name,
+            object,
+            text,
+            data,
+            rdata,
+            bss,
+            custom_sections: HashMap::new(),
+            function_sections: HashMap::new(),
+            got: None,
+            got_entries: HashMap::new(),
+            symbols: HashMap::new(),
+            libcall_names: builder.libcall_names,
+        }
+    }
+
+    fn isa(&self) -> &dyn TargetIsa {
+        &*self.isa
+    }
+
+    fn declare_function(&mut self, name: &str, linkage: Linkage) {
+        self.declare_symbol(name, linkage, true);
+    }
+
+    fn declare_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        _writable: bool,
+        _storage: DataStorage,
+        _align: Option<u8>,
+    ) {
+        self.declare_symbol(name, linkage, false);
+    }
+
+    fn set_function_section(&mut self, name: &str, section: &str) -> ModuleResult<()> {
+        self.function_sections
+            .insert(name.to_owned(), section.to_owned());
+        Ok(())
+    }
+
+    fn define_function(
+        &mut self,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        namespace: &ModuleNamespace<Self>,
+        code_size: u32,
+    ) -> ModuleResult<CoffCompiledFunction> {
+        let mut code: Vec<u8> = vec![0; code_size as usize];
+        let mut reloc_sink = CoffRelocSink {
+            relocs: Vec::new(),
+            namespace,
+            libcall_names: &*self.libcall_names,
+        };
+        let mut trap_sink = NullTrapSink {};
+        unsafe {
+            ctx.emit_to_memory(
+                &*self.isa,
+                code.as_mut_ptr(),
+                &mut reloc_sink,
+                &mut trap_sink,
+            )
+        };
+        let relocs = reloc_sink.relocs;
+
+        let section = match self.function_sections.remove(name) {
+            Some(section) => self.custom_section(
+                &section,
+                IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+            ),
+            None => self.text,
+        };
+        // Functions are only padded to `FUNCTION_ALIGN` when not optimizing for size.
+        let align = if self.isa.flags().opt_level() == OptLevel::Size {
+            1
+        } else {
+            FUNCTION_ALIGN
+        };
+        let offset = self.object.append(section, &code, align);
+        self.define_symbol(name, section, offset);
+        for (at, reloc, to, addend) in relocs {
+            self.relocate_code(section, offset + at, reloc, &to, addend)?;
+        }
+
+        Ok(CoffCompiledFunction {
+            code_length: code_size,
+        })
+    }
+
+    fn define_data(
+        &mut self,
+        name: &str,
+        writable: bool,
+        storage: DataStorage,
+        align: Option<u8>,
+        data_ctx: &DataContext,
+        namespace: &ModuleNamespace<Self>,
+    ) -> ModuleResult<CoffCompiledData> {
+        if storage == DataStorage::ThreadLocal {
+            return Err(ModuleError::Backend(
+                "COFF output doesn't support TLS data objects".to_owned(),
+            ));
+        }
+
+        let &DataDescription {
+            ref init,
+            ref function_decls,
+            ref data_decls,
+            ref function_relocs,
+            ref data_relocs,
+            align: requested_align,
+            ref section,
+            ..
+        } = data_ctx.description();
+
+        // Zero-initialized data only takes no space in the file if it needs no relocations.
+        let uninitialized = match *init {
+            Init::Zeros { .. } => function_relocs.is_empty() && data_relocs.is_empty(),
+            _ => false,
+        };
+        let section = match *section {
+            Some(ref section) => {
+                let mut characteristics = IMAGE_SCN_MEM_READ;
+                if writable {
+                    characteristics |= IMAGE_SCN_MEM_WRITE;
+                }
+                characteristics |= if uninitialized {
+                    IMAGE_SCN_CNT_UNINITIALIZED_DATA
+                } else {
+                    IMAGE_SCN_CNT_INITIALIZED_DATA
+                };
+                self.custom_section(section, characteristics)
+            }
+            None if uninitialized && writable => self.bss,
+            None if writable => self.data,
+            None => self.rdata,
+        };
+        let align = align.map(u64::from).max(requested_align).unwrap_or(1);
+
+        let size = init.size();
+        let offset = match *init {
+            Init::Uninitialized => {
+                panic!("data is not initialized yet");
+            }
+            Init::Zeros { .. } if self.object.is_uninitialized(section) => {
+                self.object.reserve(section, size as u32, align)
+            }
+            Init::Zeros { .. } => self.object.append(section, &vec![0; size], align),
+            Init::Bytes { ref contents } => self.object.append(section, contents, align),
+        };
+        self.define_symbol(name, section, offset);
+
+        for &(at, id) in function_relocs {
+            let to = self.symbols[&namespace.get_function_decl(&function_decls[id]).name];
+            self.relocate_pointer(section, offset + at, to, 0);
+        }
+        for &(at, id, addend) in data_relocs {
+            let to = self.symbols[&namespace.get_data_decl(&data_decls[id]).name];
+            self.relocate_pointer(section, offset + at, to, addend);
+        }
+
+        Ok(CoffCompiledData {})
+    }
+
+    fn write_data_funcaddr(
+        &mut self,
+        _data: &mut CoffCompiledData,
+        _offset: usize,
+        _what: ir::FuncRef,
+    ) {
+        unimplemented!()
+    }
+
+    fn write_data_dataaddr(
+        &mut self,
+        _data: &mut CoffCompiledData,
+        _offset: usize,
+        _what: ir::GlobalValue,
+        _usize: binemit::Addend,
+    ) {
+        unimplemented!()
+    }
+
+    fn finalize_function(
+        &mut self,
+        _func: &CoffCompiledFunction,
+        _namespace: &ModuleNamespace<Self>,
+    ) {
+        // Nothing to do.
+    }
+
+    fn get_finalized_function(&self, _func: &CoffCompiledFunction) {
+        // Nothing to do.
+    }
+
+    fn finalize_data(&mut self, _data: &CoffCompiledData, _namespace: &ModuleNamespace<Self>) {
+        // Nothing to do.
+    }
+
+    fn get_finalized_data(&self, _data: &CoffCompiledData) {
+        // Nothing to do.
+    }
+
+    fn publish(&mut self) {
+        // Nothing to do.
+    }
+
+    fn finish(self) -> CoffProduct {
+        CoffProduct {
+            name: self.name,
+            object: self.object,
+        }
+    }
+}
+
+impl CoffBackend {
+    fn declare_symbol(&mut self, name: &str, linkage: Linkage, function: bool) {
+        let external = linkage != Linkage::Local;
+        if let Some(&symbol) = self.symbols.get(name) {
+            // Declarations are merged by `Module`, which only ever widens the linkage.
+            self.object.symbol_mut(symbol).external = external;
+            return;
+        }
+        let symbol = self.object.add_symbol(Symbol {
+            name: name.to_owned(),
+            section: None,
+            value: 0,
+            function,
+            external,
+        });
+        self.symbols.insert(name.to_owned(), symbol);
+    }
+
+    fn define_symbol(&mut self, name: &str, section: SectionId, offset: u32) {
+        let symbol = self.object.symbol_mut(self.symbols[name]);
+        debug_assert!(symbol.section.is_none(), "inconsistent declaration");
+        symbol.section = Some(section);
+        symbol.value = offset;
+    }
+
+    /// Get the symbol for a libcall, declaring it as an import on first use.
+    fn libcall_symbol(&mut self, name: String) -> SymbolId {
+        if !self.symbols.contains_key(&name) {
+            self.declare_symbol(&name, Linkage::Import, true);
+        }
+        self.symbols[&name]
+    }
+
+    /// Get the custom section `name`, adding it with `characteristics` on first use.
+    fn custom_section(&mut self, name: &str, characteristics: u32) -> SectionId {
+        if let Some(&section) = self.custom_sections.get(name) {
+            return section;
+        }
+        let section = self.object.add_section(name, characteristics);
+        self.custom_sections.insert(name.to_owned(), section);
+        section
+    }
+
+    /// Get the offset of the entry holding the address of `symbol` in the GOT, adding it on
+    /// first use.
+    fn got_entry(&mut self, symbol: SymbolId) -> (SectionId, u32) {
+        let got = match self.got {
+            Some(got) => got,
+            None => {
+                // The `$` suffix makes linkers merge the table into `.rdata`.
+                let got = self.object.add_section(
+                    ".rdata$got",
+                    IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+                );
+                self.got = Some(got);
+                got
+            }
+        };
+        if let Some(&offset) = self.got_entries.get(&symbol) {
+            return (got, offset);
+        }
+        let offset = self.object.append(got, &[0; 8], 8);
+        self.object.add_relocation(
+            got,
+            Relocation {
+                offset,
+                symbol,
+                kind: IMAGE_REL_AMD64_ADDR64,
+            },
+        );
+        self.got_entries.insert(symbol, offset);
+        (got, offset)
+    }
+
+    fn relocate_code(
+        &mut self,
+        section: SectionId,
+        offset: CodeOffset,
+        reloc: Reloc,
+        to: &RelocTarget,
+        addend: Addend,
+    ) -> ModuleResult<()> {
+        let symbol = match *to {
+            RelocTarget::Symbol(ref name) => self.symbols[name],
+            RelocTarget::LibCall(ref name) => self.libcall_symbol(name.clone()),
+        };
+        let (kind, symbol, addend) = match reloc {
+            Reloc::Abs4 => (IMAGE_REL_AMD64_ADDR32, symbol, addend),
+            Reloc::Abs8 => (IMAGE_REL_AMD64_ADDR64, symbol, addend),
+            // COFF relocations are relative to the end of the field, where Cranelift's are
+            // relative to its start, so the addend includes its size.
+            Reloc::X86PCRel4 | Reloc::X86CallPCRel4 | Reloc::X86CallPLTRel4 => {
+                (IMAGE_REL_AMD64_REL32, symbol, addend + 4)
+            }
+            Reloc::X86GOTPCRel4 => {
+                let (got, entry) = self.got_entry(symbol);
+                (
+                    IMAGE_REL_AMD64_REL32,
+                    self.object.section_symbol(got),
+                    i64::from(entry) + addend + 4,
+                )
+            }
+            _ => {
+                return Err(ModuleError::Backend(format!(
+                    "COFF output doesn't support {} relocations",
+                    reloc
+                )))
+            }
+        };
+        let size = if kind == IMAGE_REL_AMD64_ADDR64 { 8 } else { 4 };
+        write_addend(self.object.data_mut(section), offset, size, addend);
+        self.object.add_relocation(
+            section,
+            Relocation {
+                offset,
+                symbol,
+                kind,
+            },
+        );
+        Ok(())
+    }
+
+    /// Relocate a pointer to `symbol` plus `addend` in a data object.
+    fn relocate_pointer(&mut self, section: SectionId, offset: u32, symbol: SymbolId, addend: i64) {
+        let (kind, size) = match self.isa.triple().pointer_width().unwrap() {
+            PointerWidth::U64 => (IMAGE_REL_AMD64_ADDR64, 8),
+            _ => (IMAGE_REL_AMD64_ADDR32, 4),
+        };
+        write_addend(self.object.data_mut(section), offset, size, addend);
+        self.object.add_relocation(
+            section,
+            Relocation {
+                offset,
+                symbol,
+                kind,
+            },
+        );
+    }
+}
+
+/// Store the implicit `addend` of a `size`-byte relocation at `offset` in `data`.
+fn write_addend(data: &mut [u8], offset: u32, size: usize, addend: i64) {
+    let offset = offset as usize;
+    if size == 8 {
+        data[offset..offset + 8].copy_from_slice(&addend.to_le_bytes());
+    } else {
+        let addend_i32 = addend as i32;
+        debug_assert!(i64::from(addend_i32) == addend);
+        data[offset..offset + 4].copy_from_slice(&addend_i32.to_le_bytes());
+    }
+}
+
+/// This is the output of `Module`'s
+/// [`finish`](../cranelift_module/struct.Module.html#method.finish) function.
+/// It provides functions for writing out the object file to memory or a file.
+pub struct CoffProduct {
+    name: String,
+    object: Object,
+}
+
+impl CoffProduct {
+    /// Return the name of the output file. This is the name passed into `new`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Lay out the object file, producing bytes in memory.
+    pub fn emit(&self) -> Vec<u8> {
+        self.object.emit()
+    }
+
+    /// Write the object file to a file.
+    pub fn write(&self, mut sink: File) -> io::Result<()> {
+        sink.write_all(&self.object.emit())
+    }
+}
+
+/// The symbol a relocation in a function refers to.
+enum RelocTarget {
+    Symbol(String),
+    LibCall(String),
+}
+
+/// Collects the relocations of a function, which are applied once its code is placed in `.text`.
+struct CoffRelocSink<'a> {
+    relocs: Vec<(CodeOffset, Reloc, RelocTarget, Addend)>,
+    namespace: &'a ModuleNamespace<'a, CoffBackend>,
+    libcall_names: &'a dyn Fn(ir::LibCall) -> String,
+}
+
+impl<'a> RelocSink for CoffRelocSink<'a> {
+    fn reloc_ebb(&mut self, _offset: CodeOffset, _reloc: Reloc, _ebb_offset: CodeOffset) {
+        unimplemented!();
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ir::ExternalName,
+        addend: Addend,
+    ) {
+        let target = match *name {
+            ir::ExternalName::User { .. } => {
+                if self.namespace.is_function(name) {
+                    RelocTarget::Symbol(self.namespace.get_function_decl(name).name.clone())
+                } else {
+                    RelocTarget::Symbol(self.namespace.get_data_decl(name).name.clone())
+                }
+            }
+            ir::ExternalName::LibCall(ref libcall) => {
+                RelocTarget::LibCall((self.libcall_names)(*libcall))
+            }
+            _ => panic!("invalid ExternalName {}", name),
+        };
+        self.relocs.push((offset, reloc, target, addend));
+    }
+
+    fn reloc_jt(&mut self, _offset: CodeOffset, reloc: Reloc, _jt: ir::JumpTable) {
+        match reloc {
+            Reloc::X86PCRelRodata4 => {
+                // Not necessary to record this unless we are going to split apart code and its
+                // jumptbl/rodata.
+            }
+            _ => {
+                panic!("Unhandled reloc");
+            }
+        }
+    }
+}
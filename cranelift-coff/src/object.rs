@@ -0,0 +1,458 @@
+//! A writer for COFF object files, as described in the "PE Format" chapter of the Microsoft
+//! documentation.
+
+use std::cmp;
+
+/// The machine type of x86-64 object files.
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+/// A 64-bit absolute address.
+pub const IMAGE_REL_AMD64_ADDR64: u16 = 0x0001;
+/// A 32-bit absolute address.
+pub const IMAGE_REL_AMD64_ADDR32: u16 = 0x0002;
+/// A 32-bit address relative to the byte following the relocation.
+pub const IMAGE_REL_AMD64_REL32: u16 = 0x0004;
+
+/// The section contains executable code.
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+/// The section contains initialized data.
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+/// The section contains uninitialized data, and takes no space in the file.
+pub const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x0000_0080;
+/// The section holds more relocations than fit in its header.
+const IMAGE_SCN_LNK_NRELOC_OVFL: u32 = 0x0100_0000;
+/// The section can be executed as code.
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+/// The section can be read.
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+/// The section can be written to.
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_DTYPE_FUNCTION: u16 = 0x20;
+
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const RELOCATION_SIZE: usize = 10;
+const SYMBOL_SIZE: usize = 18;
+
+/// The largest alignment a section can request.
+const MAX_ALIGN: u64 = 8192;
+
+/// An index into the sections of an `Object`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectionId(usize);
+
+/// An index into the symbols of an `Object`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolId(usize);
+
+/// A relocation, which is applied to the bytes at `offset` in its section.
+///
+/// COFF relocations have implicit addends: the addend is the value already stored at `offset`.
+pub struct Relocation {
+    pub offset: u32,
+    pub symbol: SymbolId,
+    pub kind: u16,
+}
+
+struct Section {
+    name: String,
+    characteristics: u32,
+    align: u64,
+    data: Vec<u8>,
+    /// The size of an uninitialized section, which has no `data`.
+    size: u32,
+    relocs: Vec<Relocation>,
+    symbol: SymbolId,
+}
+
+impl Section {
+    fn is_uninitialized(&self) -> bool {
+        self.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0
+    }
+
+    fn size(&self) -> u32 {
+        if self.is_uninitialized() {
+            self.size
+        } else {
+            self.data.len() as u32
+        }
+    }
+}
+
+/// A symbol, either defined at `value` in `section`, or undefined if it has no section.
+pub struct Symbol {
+    pub name: String,
+    pub section: Option<SectionId>,
+    pub value: u32,
+    pub function: bool,
+    pub external: bool,
+}
+
+/// A COFF object file being built.
+pub struct Object {
+    machine: u16,
+    sections: Vec<Section>,
+    symbols: Vec<Symbol>,
+}
+
+impl Object {
+    /// Create an empty object file for `machine`.
+    pub fn new(machine: u16) -> Self {
+        Self {
+            machine,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Add a section, along with the static symbol naming it.
+    pub fn add_section(&mut self, name: &str, characteristics: u32) -> SectionId {
+        let id = SectionId(self.sections.len());
+        let symbol = self.add_symbol(Symbol {
+            name: name.to_owned(),
+            section: Some(id),
+            value: 0,
+            function: false,
+            external: false,
+        });
+        self.sections.push(Section {
+            name: name.to_owned(),
+            characteristics,
+            align: 1,
+            data: Vec::new(),
+            size: 0,
+            relocs: Vec::new(),
+            symbol,
+        });
+        id
+    }
+
+    /// Does `section` hold uninitialized data?
+    pub fn is_uninitialized(&self, section: SectionId) -> bool {
+        self.sections[section.0].is_uninitialized()
+    }
+
+    /// Get the static symbol naming `section`, at its start.
+    pub fn section_symbol(&self, section: SectionId) -> SymbolId {
+        self.sections[section.0].symbol
+    }
+
+    /// Append `bytes` to `section`, aligned to `align`, and return their offset.
+    pub fn append(&mut self, section: SectionId, bytes: &[u8], align: u64) -> u32 {
+        let section = &mut self.sections[section.0];
+        debug_assert!(!section.is_uninitialized());
+        section.align = cmp::max(section.align, align);
+        let offset = align_to(section.data.len(), align);
+        section.data.resize(offset, 0);
+        section.data.extend_from_slice(bytes);
+        offset as u32
+    }
+
+    /// Reserve `size` bytes of uninitialized `section`, aligned to `align`, and return their
+    /// offset.
+    pub fn reserve(&mut self, section: SectionId, size: u32, align: u64) -> u32 {
+        let section = &mut self.sections[section.0];
+        debug_assert!(section.is_uninitialized());
+        section.align = cmp::max(section.align, align);
+        let offset = align_to(section.size as usize, align) as u32;
+        section.size = offset + size;
+        offset
+    }
+
+    /// Get the contents of `section`, to write implicit addends into.
+    pub fn data_mut(&mut self, section: SectionId) -> &mut [u8] {
+        &mut self.sections[section.0].data
+    }
+
+    /// Add a symbol to the symbol table.
+    pub fn add_symbol(&mut self, symbol: Symbol) -> SymbolId {
+        self.symbols.push(symbol);
+        SymbolId(self.symbols.len() - 1)
+    }
+
+    /// Get a symbol, to define it.
+    pub fn symbol_mut(&mut self, symbol: SymbolId) -> &mut Symbol {
+        &mut self.symbols[symbol.0]
+    }
+
+    /// Add a relocation to `section`.
+    pub fn add_relocation(&mut self, section: SectionId, reloc: Relocation) {
+        self.sections[section.0].relocs.push(reloc);
+    }
+
+    /// Lay out and write the object file.
+    pub fn emit(&self) -> Vec<u8> {
+        let mut strtab = Vec::new();
+
+        // The index of each symbol in the symbol table, where section symbols are followed by an
+        // auxiliary record.
+        let mut symbol_indices = Vec::with_capacity(self.symbols.len());
+        let mut symbol_count = 0;
+        for id in 0..self.symbols.len() {
+            symbol_indices.push(symbol_count as u32);
+            symbol_count += if self.is_section_symbol(SymbolId(id)) {
+                2
+            } else {
+                1
+            };
+        }
+
+        // The offsets of the contents and the relocations of each section.
+        let mut offset = FILE_HEADER_SIZE + self.sections.len() * SECTION_HEADER_SIZE;
+        let mut section_offsets = Vec::with_capacity(self.sections.len());
+        for section in &self.sections {
+            let data_offset = offset;
+            offset += section.data.len();
+            let relocs_offset = offset;
+            let reloc_count = section.relocs.len() + section_overflows(section) as usize;
+            offset += reloc_count * RELOCATION_SIZE;
+            section_offsets.push((data_offset, relocs_offset));
+        }
+        let symtab_offset = offset;
+
+        let mut out = Vec::new();
+
+        // The file header.
+        out.extend_from_slice(&self.machine.to_le_bytes());
+        out.extend_from_slice(&(self.sections.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&(symtab_offset as u32).to_le_bytes());
+        out.extend_from_slice(&(symbol_count as u32).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+
+        // The section headers.
+        for (section, &(data_offset, relocs_offset)) in self.sections.iter().zip(&section_offsets) {
+            if section.name.len() <= 8 {
+                let mut name = [0; 8];
+                name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+                out.extend_from_slice(&name);
+            } else {
+                let mut name = [0; 8];
+                let long_name = format!("/{}", add_string(&mut strtab, &section.name));
+                name[..long_name.len()].copy_from_slice(long_name.as_bytes());
+                out.extend_from_slice(&name);
+            }
+            let (reloc_count, relocs_offset) = if section.relocs.is_empty() {
+                (0, 0)
+            } else {
+                (cmp::min(section.relocs.len(), 0xffff), relocs_offset as u32)
+            };
+            let mut characteristics = section.characteristics | align_flags(section.align);
+            if section_overflows(section) {
+                characteristics |= IMAGE_SCN_LNK_NRELOC_OVFL;
+            }
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            let data_offset = if section.is_uninitialized() {
+                0
+            } else {
+                data_offset as u32
+            };
+            out.extend_from_slice(&section.size().to_le_bytes());
+            out.extend_from_slice(&data_offset.to_le_bytes());
+            out.extend_from_slice(&relocs_offset.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&(reloc_count as u16).to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&characteristics.to_le_bytes());
+        }
+
+        // The contents and relocations of each section.
+        for section in &self.sections {
+            out.extend_from_slice(&section.data);
+            if section_overflows(section) {
+                // The real count is in the first relocation, and includes it.
+                out.extend_from_slice(&(section.relocs.len() as u32 + 1).to_le_bytes());
+                out.extend_from_slice(&0u32.to_le_bytes());
+                out.extend_from_slice(&0u16.to_le_bytes());
+            }
+            for reloc in &section.relocs {
+                out.extend_from_slice(&reloc.offset.to_le_bytes());
+                out.extend_from_slice(&symbol_indices[reloc.symbol.0].to_le_bytes());
+                out.extend_from_slice(&reloc.kind.to_le_bytes());
+            }
+        }
+        debug_assert_eq!(out.len(), symtab_offset);
+
+        // The symbol table.
+        for (id, symbol) in self.symbols.iter().enumerate() {
+            if symbol.name.len() <= 8 {
+                let mut name = [0; 8];
+                name[..symbol.name.len()].copy_from_slice(symbol.name.as_bytes());
+                out.extend_from_slice(&name);
+            } else {
+                out.extend_from_slice(&0u32.to_le_bytes());
+                out.extend_from_slice(&add_string(&mut strtab, &symbol.name).to_le_bytes());
+            }
+            out.extend_from_slice(&symbol.value.to_le_bytes());
+            let section_number = symbol.section.map_or(0, |section| section.0 as u16 + 1);
+            out.extend_from_slice(&section_number.to_le_bytes());
+            let ty = if symbol.function {
+                IMAGE_SYM_DTYPE_FUNCTION
+            } else {
+                0
+            };
+            out.extend_from_slice(&ty.to_le_bytes());
+            out.push(if symbol.external {
+                IMAGE_SYM_CLASS_EXTERNAL
+            } else {
+                IMAGE_SYM_CLASS_STATIC
+            });
+            if self.is_section_symbol(SymbolId(id)) {
+                // The auxiliary record describing the section.
+                let section = &self.sections[symbol.section.unwrap().0];
+                out.push(1);
+                out.extend_from_slice(&section.size().to_le_bytes());
+                out.extend_from_slice(
+                    &(cmp::min(section.relocs.len(), 0xffff) as u16).to_le_bytes(),
+                );
+                out.extend_from_slice(&[0; SYMBOL_SIZE - 6]);
+            } else {
+                out.push(0);
+            }
+        }
+
+        // The string table, whose size includes its own.
+        out.extend_from_slice(&(strtab.len() as u32 + 4).to_le_bytes());
+        out.extend_from_slice(&strtab);
+        out
+    }
+
+    fn is_section_symbol(&self, id: SymbolId) -> bool {
+        match self.symbols[id.0].section {
+            Some(section) => self.sections[section.0].symbol == id,
+            None => false,
+        }
+    }
+}
+
+/// Does `section` have too many relocations to count in its header?
+fn section_overflows(section: &Section) -> bool {
+    section.relocs.len() >= 0xffff
+}
+
+/// Get the `IMAGE_SCN_ALIGN_*` flag for `align`.
+fn align_flags(align: u64) -> u32 {
+    let align = cmp::min(align.next_power_of_two(), MAX_ALIGN);
+    (align.trailing_zeros() + 1) << 20
+}
+
+fn align_to(offset: usize, align: u64) -> usize {
+    let align = align as usize;
+    (offset + align - 1) & !(align - 1)
+}
+
+/// Add `s` to the string table, and return its offset, which counts the size of the table.
+fn add_string(strtab: &mut Vec<u8>, s: &str) -> u32 {
+    let offset = strtab.len() as u32 + 4;
+    strtab.extend_from_slice(s.as_bytes());
+    strtab.push(0);
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn layout() {
+        let mut obj = Object::new(IMAGE_FILE_MACHINE_AMD64);
+        let text = obj.add_section(
+            ".text",
+            IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        );
+        let offset = obj.append(text, &[0xe8, 0, 0, 0, 0, 0xc3], 16);
+        assert_eq!(offset, 0);
+        obj.add_symbol(Symbol {
+            name: "a_long_function_name".to_owned(),
+            section: Some(text),
+            value: offset,
+            function: true,
+            external: true,
+        });
+        let callee = obj.add_symbol(Symbol {
+            name: "callee".to_owned(),
+            section: None,
+            value: 0,
+            function: true,
+            external: true,
+        });
+        obj.add_relocation(
+            text,
+            Relocation {
+                offset: 1,
+                symbol: callee,
+                kind: IMAGE_REL_AMD64_REL32,
+            },
+        );
+        let bytes = obj.emit();
+
+        assert_eq!(u16_at(&bytes, 0), IMAGE_FILE_MACHINE_AMD64);
+        assert_eq!(u16_at(&bytes, 2), 1);
+        // The section symbol and its auxiliary record, then two symbols.
+        assert_eq!(u32_at(&bytes, 12), 4);
+
+        // The section header.
+        let shdr = FILE_HEADER_SIZE;
+        assert_eq!(&bytes[shdr..shdr + 8], b".text\0\0\0");
+        assert_eq!(u32_at(&bytes, shdr + 16), 6);
+        let data_offset = u32_at(&bytes, shdr + 20) as usize;
+        assert_eq!(
+            &bytes[data_offset..data_offset + 6],
+            &[0xe8, 0, 0, 0, 0, 0xc3]
+        );
+        assert_eq!(u16_at(&bytes, shdr + 32), 1);
+        assert_eq!(u32_at(&bytes, shdr + 36) & 0x00f0_0000, 0x0050_0000);
+
+        // The relocation refers to the undefined symbol, after the auxiliary record.
+        let reloc = u32_at(&bytes, shdr + 24) as usize;
+        assert_eq!(u32_at(&bytes, reloc), 1);
+        assert_eq!(u32_at(&bytes, reloc + 4), 3);
+        assert_eq!(u16_at(&bytes, reloc + 8), IMAGE_REL_AMD64_REL32);
+
+        // The long name is in the string table.
+        let symtab = u32_at(&bytes, 8) as usize;
+        let sym = symtab + 2 * SYMBOL_SIZE;
+        assert_eq!(u32_at(&bytes, sym), 0);
+        let strtab = symtab + 4 * SYMBOL_SIZE;
+        let name = strtab + u32_at(&bytes, sym + 4) as usize;
+        assert_eq!(&bytes[name..name + 21], b"a_long_function_name\0");
+        assert_eq!(u32_at(&bytes, strtab) as usize, bytes.len() - strtab);
+    }
+
+    #[test]
+    fn uninitialized() {
+        let mut obj = Object::new(IMAGE_FILE_MACHINE_AMD64);
+        let bss = obj.add_section(
+            ".bss",
+            IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        );
+        assert_eq!(obj.reserve(bss, 3, 1), 0);
+        assert_eq!(obj.reserve(bss, 0x1000, 0x1000), 0x1000);
+        let bytes = obj.emit();
+
+        // The section has a size, but no contents.
+        let shdr = FILE_HEADER_SIZE;
+        assert_eq!(u32_at(&bytes, shdr + 16), 0x2000);
+        assert_eq!(u32_at(&bytes, shdr + 20), 0);
+        assert_eq!(u32_at(&bytes, shdr + 36) & 0x00f0_0000, 0x00d0_0000);
+        assert_eq!(
+            u32_at(&bytes, 8) as usize,
+            FILE_HEADER_SIZE + SECTION_HEADER_SIZE
+        );
+    }
+}
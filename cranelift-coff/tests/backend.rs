@@ -0,0 +1,520 @@
+use cranelift_codegen::ir::*;
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_coff::*;
+use cranelift_frontend::*;
+use cranelift_module::*;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+const IMAGE_REL_AMD64_ADDR64: u16 = 0x0001;
+const IMAGE_REL_AMD64_REL32: u16 = 0x0004;
+
+const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x0000_0080;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_DTYPE_FUNCTION: u16 = 0x20;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// A section of a parsed COFF object file.
+struct Section {
+    name: String,
+    characteristics: u32,
+    size: u32,
+    data: Vec<u8>,
+    /// The relocations, as `(offset, symbol index, kind)`.
+    relocs: Vec<(u32, usize, u16)>,
+}
+
+/// A symbol of a parsed COFF object file. The section number counts from 1, and is 0 for
+/// undefined symbols.
+struct Symbol {
+    name: String,
+    value: u32,
+    section: usize,
+    ty: u16,
+    class: u8,
+}
+
+/// A COFF object file, parsed independently of the writer in `cranelift_coff`.
+struct Coff {
+    sections: Vec<Section>,
+    /// The symbols by their index in the symbol table, which is `None` for auxiliary records.
+    symbols: Vec<Option<Symbol>>,
+}
+
+impl Coff {
+    fn parse(bytes: &[u8]) -> Self {
+        assert_eq!(u16_at(bytes, 0), 0x8664);
+        let section_count = usize::from(u16_at(bytes, 2));
+        let symtab = u32_at(bytes, 8) as usize;
+        let symbol_count = u32_at(bytes, 12) as usize;
+        let strtab = symtab + symbol_count * 18;
+        let string = |offset: usize| {
+            let start = strtab + offset;
+            let end = start + bytes[start..].iter().position(|&b| b == 0).unwrap();
+            String::from_utf8(bytes[start..end].to_vec()).unwrap()
+        };
+        let short_name = |name: &[u8]| {
+            let end = name.iter().position(|&b| b == 0).unwrap_or(8);
+            String::from_utf8(name[..end].to_vec()).unwrap()
+        };
+
+        let mut sections = Vec::new();
+        for index in 0..section_count {
+            let header = 20 + index * 40;
+            let raw_name = &bytes[header..header + 8];
+            let name = if raw_name[0] == b'/' {
+                string(short_name(&raw_name[1..]).parse().unwrap())
+            } else {
+                short_name(raw_name)
+            };
+            let size = u32_at(bytes, header + 16);
+            let data_offset = u32_at(bytes, header + 20) as usize;
+            let data = if data_offset == 0 {
+                Vec::new()
+            } else {
+                bytes[data_offset..data_offset + size as usize].to_vec()
+            };
+            let relocs_offset = u32_at(bytes, header + 24) as usize;
+            let relocs = (0..usize::from(u16_at(bytes, header + 32)))
+                .map(|i| {
+                    let reloc = relocs_offset + i * 10;
+                    (
+                        u32_at(bytes, reloc),
+                        u32_at(bytes, reloc + 4) as usize,
+                        u16_at(bytes, reloc + 8),
+                    )
+                })
+                .collect();
+            sections.push(Section {
+                name,
+                characteristics: u32_at(bytes, header + 36),
+                size,
+                data,
+                relocs,
+            });
+        }
+
+        let mut symbols = Vec::new();
+        while symbols.len() < symbol_count {
+            let sym = symtab + symbols.len() * 18;
+            let name = if u32_at(bytes, sym) == 0 {
+                string(u32_at(bytes, sym + 4) as usize)
+            } else {
+                short_name(&bytes[sym..sym + 8])
+            };
+            symbols.push(Some(Symbol {
+                name,
+                value: u32_at(bytes, sym + 8),
+                section: usize::from(u16_at(bytes, sym + 12)),
+                ty: u16_at(bytes, sym + 14),
+                class: bytes[sym + 16],
+            }));
+            for _ in 0..bytes[sym + 17] {
+                symbols.push(None);
+            }
+        }
+
+        Self { sections, symbols }
+    }
+
+    fn section(&self, name: &str) -> &Section {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .unwrap_or_else(|| panic!("missing section {}", name))
+    }
+
+    fn symbol(&self, name: &str) -> &Symbol {
+        self.symbols
+            .iter()
+            .filter_map(Option::as_ref)
+            .find(|symbol| symbol.name == name)
+            .unwrap_or_else(|| panic!("missing symbol {}", name))
+    }
+
+    /// Get the name of the section a defined symbol is in.
+    fn section_name(&self, symbol: &Symbol) -> &str {
+        &self.sections[symbol.section - 1].name
+    }
+
+    /// Get the relocations of section `name`, as `(kind, symbol name, implicit addend)`.
+    fn relocs(&self, name: &str) -> Vec<(u16, String, i64)> {
+        let section = self.section(name);
+        section
+            .relocs
+            .iter()
+            .map(|&(offset, symbol, kind)| {
+                let offset = offset as usize;
+                let addend = if kind == IMAGE_REL_AMD64_ADDR64 {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(&section.data[offset..offset + 8]);
+                    i64::from_le_bytes(bytes)
+                } else {
+                    i64::from(u32_at(&section.data, offset) as i32)
+                };
+                let symbol = self.symbols[symbol].as_ref().unwrap();
+                (kind, symbol.name.clone(), addend)
+            })
+            .collect()
+    }
+}
+
+fn make_module(pic: bool) -> Module<CoffBackend> {
+    let mut flag_builder = settings::builder();
+    if pic {
+        flag_builder.enable("is_pic").unwrap();
+    }
+    let isa = isa::lookup(Triple::from_str("x86_64-pc-windows-msvc").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    Module::new(CoffBuilder::new(isa, "test.obj".to_owned(), default_libcall_names()).unwrap())
+}
+
+/// Declare a function taking no arguments and returning nothing.
+fn declare(module: &mut Module<CoffBackend>, name: &str, linkage: Linkage) -> FuncId {
+    let sig = module.make_signature();
+    module.declare_function(name, linkage, &sig).unwrap()
+}
+
+/// Define `func`, calling the functions in `calls` and taking the addresses of the data objects
+/// in `addresses`.
+fn define(module: &mut Module<CoffBackend>, func: FuncId, calls: &[FuncId], addresses: &[DataId]) {
+    let mut ctx = Context::new();
+    ctx.func.signature = module.make_signature();
+    ctx.func.name = ExternalName::user(0, func.as_u32());
+    let callees = calls
+        .iter()
+        .map(|&callee| module.declare_func_in_func(callee, &mut ctx.func))
+        .collect::<Vec<_>>();
+    let globals = addresses
+        .iter()
+        .map(|&data| module.declare_data_in_func(data, &mut ctx.func))
+        .collect::<Vec<_>>();
+    let pointer_type = module.target_config().pointer_type();
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.seal_block(ebb);
+        for callee in callees {
+            bcx.ins().call(callee, &[]);
+        }
+        for gv in globals {
+            let addr = bcx.ins().global_value(pointer_type, gv);
+            bcx.ins().store(MemFlags::new(), addr, addr, 0);
+        }
+        bcx.ins().return_(&[]);
+    }
+    module.define_function(func, &mut ctx).unwrap();
+}
+
+fn define_bytes(module: &mut Module<CoffBackend>, data: DataId, bytes: &[u8]) {
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(bytes.to_vec().into_boxed_slice());
+    module.define_data(data, &data_ctx).unwrap();
+}
+
+#[test]
+fn call_relocations() {
+    for &pic in &[false, true] {
+        let mut module = make_module(pic);
+        let callee = declare(&mut module, "callee", Linkage::Local);
+        let imported = declare(&mut module, "imported", Linkage::Import);
+        let caller = declare(&mut module, "caller", Linkage::Export);
+        define(&mut module, callee, &[], &[]);
+        define(&mut module, caller, &[callee, imported], &[]);
+        let coff = Coff::parse(&module.finish().emit());
+
+        // Cranelift's PC-relative addends are relative to the start of the field, so a call has
+        // an addend of -4, while COFF's are relative to its end, so the stored addend is 0.
+        let relocs = coff.relocs(".text");
+        let imported_reloc = if pic {
+            // A call through the PLT, which COFF doesn't have, is a direct call.
+            (IMAGE_REL_AMD64_REL32, "imported".to_owned(), 0)
+        } else {
+            // The address of a function that may be far away is loaded as an absolute address.
+            (IMAGE_REL_AMD64_ADDR64, "imported".to_owned(), 0)
+        };
+        assert_eq!(
+            relocs,
+            [
+                (IMAGE_REL_AMD64_REL32, "callee".to_owned(), 0),
+                imported_reloc
+            ]
+        );
+        assert!(coff.section(".text").relocs[0].0 >= coff.symbol("caller").value);
+    }
+}
+
+#[test]
+fn got_entries() {
+    let mut module = make_module(true);
+    let a = module
+        .declare_data("a", Linkage::Import, false, DataStorage::Static, None)
+        .unwrap();
+    let b = module
+        .declare_data("b", Linkage::Import, false, DataStorage::Static, None)
+        .unwrap();
+    let f = declare(&mut module, "f", Linkage::Export);
+    let g = declare(&mut module, "g", Linkage::Export);
+    define(&mut module, f, &[], &[a, b]);
+    define(&mut module, g, &[], &[a]);
+    let coff = Coff::parse(&module.finish().emit());
+
+    // Each imported symbol has one entry in the table, holding its address.
+    let got = coff.section(".rdata$got");
+    assert_eq!(got.size, 16);
+    assert_eq!(
+        got.characteristics & 0xff00_ffff,
+        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ
+    );
+    // Aligned to 8 bytes.
+    assert_eq!(got.characteristics & 0x00f0_0000, 0x0040_0000);
+    assert_eq!(
+        coff.relocs(".rdata$got"),
+        [
+            (IMAGE_REL_AMD64_ADDR64, "a".to_owned(), 0),
+            (IMAGE_REL_AMD64_ADDR64, "b".to_owned(), 0)
+        ]
+    );
+
+    // The code refers to the entries relative to the section symbol of the table. The addend is
+    // the offset of the entry, plus 4 for the difference in where the relocations are relative
+    // to, minus 4 for the size of the field.
+    assert_eq!(
+        coff.relocs(".text"),
+        [
+            (IMAGE_REL_AMD64_REL32, ".rdata$got".to_owned(), 0),
+            (IMAGE_REL_AMD64_REL32, ".rdata$got".to_owned(), 8),
+            (IMAGE_REL_AMD64_REL32, ".rdata$got".to_owned(), 0)
+        ]
+    );
+    let section_symbol = coff.symbol(".rdata$got");
+    assert_eq!(section_symbol.class, IMAGE_SYM_CLASS_STATIC);
+    assert_eq!(section_symbol.value, 0);
+    assert_eq!(coff.section_name(section_symbol), ".rdata$got");
+
+    // Without PIC, there is no table.
+    let mut module = make_module(false);
+    let a = module
+        .declare_data("a", Linkage::Import, false, DataStorage::Static, None)
+        .unwrap();
+    let f = declare(&mut module, "f", Linkage::Export);
+    define(&mut module, f, &[], &[a]);
+    let coff = Coff::parse(&module.finish().emit());
+    assert!(coff.sections.iter().all(|s| s.name != ".rdata$got"));
+    assert_eq!(
+        coff.relocs(".text"),
+        [(IMAGE_REL_AMD64_ADDR64, "a".to_owned(), 0)]
+    );
+}
+
+#[test]
+fn linkage() {
+    let mut module = make_module(false);
+    let local = declare(&mut module, "local", Linkage::Local);
+    let exported = declare(&mut module, "exported", Linkage::Export);
+    let preemptible = declare(&mut module, "preemptible", Linkage::Preemptible);
+    declare(&mut module, "imported", Linkage::Import);
+    // Declarations are merged, widening the linkage.
+    let widened = declare(&mut module, "widened", Linkage::Local);
+    declare(&mut module, "widened", Linkage::Export);
+    let local_data = module
+        .declare_data(
+            "local_data",
+            Linkage::Local,
+            true,
+            DataStorage::Static,
+            None,
+        )
+        .unwrap();
+    let exported_data = module
+        .declare_data(
+            "exported_data",
+            Linkage::Export,
+            true,
+            DataStorage::Static,
+            None,
+        )
+        .unwrap();
+    for &func in &[local, exported, preemptible, widened] {
+        define(&mut module, func, &[], &[]);
+    }
+    define_bytes(&mut module, local_data, &[1]);
+    define_bytes(&mut module, exported_data, &[2]);
+    let coff = Coff::parse(&module.finish().emit());
+
+    for &(name, class, section, ty) in &[
+        (
+            "local",
+            IMAGE_SYM_CLASS_STATIC,
+            ".text",
+            IMAGE_SYM_DTYPE_FUNCTION,
+        ),
+        (
+            "exported",
+            IMAGE_SYM_CLASS_EXTERNAL,
+            ".text",
+            IMAGE_SYM_DTYPE_FUNCTION,
+        ),
+        (
+            "preemptible",
+            IMAGE_SYM_CLASS_EXTERNAL,
+            ".text",
+            IMAGE_SYM_DTYPE_FUNCTION,
+        ),
+        (
+            "widened",
+            IMAGE_SYM_CLASS_EXTERNAL,
+            ".text",
+            IMAGE_SYM_DTYPE_FUNCTION,
+        ),
+        ("local_data", IMAGE_SYM_CLASS_STATIC, ".data", 0),
+        ("exported_data", IMAGE_SYM_CLASS_EXTERNAL, ".data", 0),
+    ] {
+        let symbol = coff.symbol(name);
+        assert_eq!(symbol.class, class, "{}", name);
+        assert_eq!(coff.section_name(symbol), section, "{}", name);
+        assert_eq!(symbol.ty, ty, "{}", name);
+    }
+
+    let imported = coff.symbol("imported");
+    assert_eq!(imported.class, IMAGE_SYM_CLASS_EXTERNAL);
+    assert_eq!(imported.section, 0);
+    assert_eq!(imported.value, 0);
+
+    // Functions are aligned to 16 bytes.
+    for &name in &["local", "exported", "preemptible", "widened"] {
+        assert_eq!(coff.symbol(name).value % 16, 0, "{}", name);
+    }
+}
+
+#[test]
+fn sections() {
+    let mut module = make_module(false);
+    let func = declare(&mut module, "func", Linkage::Export);
+    let hot = declare(&mut module, "hot", Linkage::Export);
+    module.set_function_section(hot, ".text$hot").unwrap();
+    define(&mut module, func, &[], &[]);
+    define(&mut module, hot, &[], &[]);
+
+    let declare_data = |module: &mut Module<CoffBackend>, name: &str, writable: bool| {
+        module
+            .declare_data(name, Linkage::Export, writable, DataStorage::Static, None)
+            .unwrap()
+    };
+    let writable = declare_data(&mut module, "writable", true);
+    let readonly = declare_data(&mut module, "readonly", false);
+    let zeros = declare_data(&mut module, "zeros", true);
+    let custom = declare_data(&mut module, "custom", true);
+    let pointers = declare_data(&mut module, "pointers", true);
+    define_bytes(&mut module, writable, &[1, 2, 3]);
+    define_bytes(&mut module, readonly, &[4, 5]);
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(0x100);
+    data_ctx.set_align(64);
+    module.define_data(zeros, &data_ctx).unwrap();
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(vec![6; 4].into_boxed_slice());
+    data_ctx.set_section(".mydata");
+    module.define_data(custom, &data_ctx).unwrap();
+
+    // A zero-initialized object with relocations has to be stored in the file.
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(16);
+    let func_ref = module.declare_func_in_data(func, &mut data_ctx);
+    data_ctx.write_function_addr(0, func_ref);
+    let gv = module.declare_data_in_data(writable, &mut data_ctx);
+    data_ctx.write_data_addr(8, gv, 2);
+    module.define_data(pointers, &data_ctx).unwrap();
+
+    let coff = Coff::parse(&module.finish().emit());
+
+    let text = coff.section(".text");
+    assert_eq!(
+        text.characteristics & 0xff00_ffff,
+        IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ
+    );
+    assert_eq!(coff.section_name(coff.symbol("func")), ".text");
+    let hot = coff.section(".text$hot");
+    assert_eq!(hot.characteristics, text.characteristics);
+    assert_eq!(coff.section_name(coff.symbol("hot")), ".text$hot");
+
+    let data = coff.section(".data");
+    assert_eq!(
+        data.characteristics & 0xff00_ffff,
+        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+    );
+    let symbol = coff.symbol("writable");
+    assert_eq!(coff.section_name(symbol), ".data");
+    let offset = symbol.value as usize;
+    assert_eq!(data.data[offset..offset + 3], [1, 2, 3]);
+
+    let rdata = coff.section(".rdata");
+    assert_eq!(
+        rdata.characteristics & 0xff00_ffff,
+        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ
+    );
+    let symbol = coff.symbol("readonly");
+    assert_eq!(coff.section_name(symbol), ".rdata");
+    let offset = symbol.value as usize;
+    assert_eq!(rdata.data[offset..offset + 2], [4, 5]);
+
+    // Zero-initialized data takes no space in the file.
+    let bss = coff.section(".bss");
+    assert_eq!(
+        bss.characteristics & 0xff00_ffff,
+        IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+    );
+    assert_eq!(bss.characteristics & 0x00f0_0000, 0x0070_0000);
+    assert!(bss.data.is_empty());
+    assert_eq!(bss.size, 0x100);
+    assert_eq!(coff.section_name(coff.symbol("zeros")), ".bss");
+
+    let mydata = coff.section(".mydata");
+    assert_eq!(
+        mydata.characteristics & 0xff00_ffff,
+        data.characteristics & 0xff00_ffff
+    );
+    assert_eq!(mydata.data, [6; 4]);
+    assert_eq!(coff.section_name(coff.symbol("custom")), ".mydata");
+
+    let symbol = coff.symbol("pointers");
+    assert_eq!(coff.section_name(symbol), ".data");
+    let offset = i64::from(symbol.value);
+    let relocs = coff.relocs(".data");
+    let reloc_offsets = data
+        .relocs
+        .iter()
+        .map(|&(at, _, _)| i64::from(at) - offset)
+        .collect::<Vec<_>>();
+    assert_eq!(reloc_offsets, [0, 8]);
+    assert_eq!(
+        relocs,
+        [
+            (IMAGE_REL_AMD64_ADDR64, "func".to_owned(), 0),
+            (IMAGE_REL_AMD64_ADDR64, "writable".to_owned(), 2)
+        ]
+    );
+}
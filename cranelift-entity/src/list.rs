@@ -3,6 +3,8 @@ use crate::packed_option::ReservedValue;
 use crate::EntityRef;
 use core::marker::PhantomData;
 use core::mem;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// A small list of entity references allocated from a pool.
@@ -60,6 +62,7 @@ use std::vec::Vec;
 /// The index stored in an `EntityList` points to part 2, the list elements. The value 0 is
 /// reserved for the empty list which isn't allocated in the vector.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct EntityList<T: EntityRef + ReservedValue> {
     index: u32,
     unused: PhantomData<T>,
@@ -77,6 +80,7 @@ impl<T: EntityRef + ReservedValue> Default for EntityList<T> {
 
 /// A memory pool for storing lists of `T`.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct ListPool<T: EntityRef + ReservedValue> {
     // The main array containing the lists.
     data: Vec<T>,
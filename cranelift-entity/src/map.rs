@@ -6,6 +6,8 @@ use crate::EntityRef;
 use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
 use core::slice;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// A mapping `K -> V` for densely indexed entity references.
@@ -17,6 +19,7 @@ use std::vec::Vec;
 /// The map does not track if an entry for a key has been inserted or not. Instead it behaves as if
 /// all keys have a default entry from the beginning.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct SecondaryMap<K, V>
 where
     K: EntityRef,
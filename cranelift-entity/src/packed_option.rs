@@ -9,6 +9,8 @@
 
 use core::fmt;
 use core::mem;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
 
 /// Types that have a reserved value which can't be created any other way.
 pub trait ReservedValue: Eq {
@@ -18,6 +20,7 @@ pub trait ReservedValue: Eq {
 
 /// Packed representation of `Option<T>`.
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct PackedOption<T: ReservedValue>(T);
 
 impl<T: ReservedValue> PackedOption<T> {
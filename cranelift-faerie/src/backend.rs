@@ -4,13 +4,15 @@ use crate::container;
 use crate::traps::{FaerieTrapManifest, FaerieTrapSink};
 use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
 use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::settings::OptLevel;
 use cranelift_codegen::{self, binemit, ir};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleError, ModuleNamespace,
-    ModuleResult,
+    Backend, DataContext, DataDescription, DataStorage, DwarfBuilder, DwarfRelocTarget, Init,
+    Linkage, ModuleError, ModuleNamespace, ModuleResult,
 };
 use faerie;
 use failure::Error;
+use std::collections::HashMap;
 use std::fs::File;
 use target_lexicon::Triple;
 
@@ -31,6 +33,7 @@ pub struct FaerieBuilder {
     name: String,
     collect_traps: FaerieTrapCollection,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    debug_info: Option<String>,
 }
 
 impl FaerieBuilder {
@@ -63,18 +66,38 @@ impl FaerieBuilder {
             name,
             collect_traps,
             libcall_names,
+            debug_info: None,
         })
     }
+
+    /// Emit DWARF debug information for the functions of the module, whose source file is
+    /// `file`.
+    ///
+    /// The debug information maps code to source locations, and describes the variables of
+    /// functions compiled with value labels. See `cranelift_module::DwarfBuilder` for details.
+    pub fn debug_info(&mut self, file: String) -> &Self {
+        self.debug_info = Some(file);
+        self
+    }
 }
 
 /// A `FaerieBackend` implements `Backend` and emits ".o" files using the `faerie` library.
 ///
+/// Faerie places each definition in its own section, named after it, and can't emit
+/// uninitialized or relro sections. So this backend places data in its default section even when
+/// a custom section is requested, emits relro data as writable data, and writes out
+/// zero-initialized data in full rather than placing it in `.bss`. Functions can't be placed in
+/// custom sections.
+///
 /// See the `FaerieBuilder` for a convenient way to construct `FaerieBackend` instances.
 pub struct FaerieBackend {
     isa: Box<dyn TargetIsa>,
     artifact: faerie::Artifact,
     trap_manifest: Option<FaerieTrapManifest>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    dwarf: Option<DwarfBuilder>,
+    /// The linkage of each data object that hasn't been defined yet.
+    data_linkages: HashMap<String, Linkage>,
 }
 
 pub struct FaerieCompiledFunction {
@@ -106,6 +129,10 @@ impl Backend for FaerieBackend {
 
     /// Create a new `FaerieBackend` using the given Cranelift target.
     fn new(builder: FaerieBuilder) -> Self {
+        let dwarf = builder
+            .debug_info
+            .as_ref()
+            .map(|file| DwarfBuilder::new(&*builder.isa, file));
         Self {
             artifact: faerie::Artifact::new(builder.isa.triple().clone(), builder.name),
             isa: builder.isa,
@@ -114,6 +141,8 @@ impl Backend for FaerieBackend {
                 FaerieTrapCollection::Disabled => None,
             },
             libcall_names: builder.libcall_names,
+            dwarf,
+            data_linkages: HashMap::new(),
         }
     }
 
@@ -122,17 +151,45 @@ impl Backend for FaerieBackend {
     }
 
     fn declare_function(&mut self, name: &str, linkage: Linkage) {
+        // Functions use faerie's default alignment, unless optimizing for size.
+        let align = if self.isa.flags().opt_level() == OptLevel::Size {
+            Some(1)
+        } else {
+            None
+        };
         self.artifact
-            .declare(name, translate_function_linkage(linkage))
+            .declare(name, translate_function_linkage(linkage, align))
             .expect("inconsistent declarations");
     }
 
-    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, align: Option<u8>) {
+    fn declare_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        _writable: bool,
+        _storage: DataStorage,
+        _align: Option<u8>,
+    ) {
+        // TLS data objects are declared like regular ones. They can be imported, and the TLS
+        // relocations are emitted by the code that accesses them.
+        //
+        // Data objects are declared as imports until they are defined, as their alignment and
+        // kind also depend on their `DataContext`.
+        if linkage.is_definable() {
+            self.data_linkages.insert(name.to_owned(), linkage);
+        }
         self.artifact
-            .declare(name, translate_data_linkage(linkage, writable, align))
+            .declare(name, faerie::Decl::data_import())
             .expect("inconsistent declarations");
     }
 
+    fn set_function_section(&mut self, name: &str, section: &str) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "faerie can't place function {} in section {}",
+            name, section
+        )))
+    }
+
     fn define_function(
         &mut self,
         name: &str,
@@ -179,6 +236,10 @@ impl Backend for FaerieBackend {
         // because `define` will take ownership of code, this is our last chance
         let code_length = code.len() as u32;
 
+        if let Some(ref mut dwarf) = self.dwarf {
+            dwarf.add_function(name, ctx, &*self.isa, code_length);
+        }
+
         self.artifact
             .define(name, code)
             .expect("inconsistent declaration");
@@ -189,19 +250,41 @@ impl Backend for FaerieBackend {
     fn define_data(
         &mut self,
         name: &str,
-        _writable: bool,
-        _align: Option<u8>,
+        writable: bool,
+        storage: DataStorage,
+        align: Option<u8>,
         data_ctx: &DataContext,
         namespace: &ModuleNamespace<Self>,
     ) -> ModuleResult<FaerieCompiledData> {
+        if storage == DataStorage::ThreadLocal {
+            return Err(ModuleError::Backend(
+                "faerie can't define TLS data objects".to_owned(),
+            ));
+        }
+
         let &DataDescription {
             ref init,
             ref function_decls,
             ref data_decls,
             ref function_relocs,
             ref data_relocs,
+            align: requested_align,
+            section: _,
+            relro,
         } = data_ctx.description();
 
+        let linkage = self
+            .data_linkages
+            .remove(name)
+            .expect("data object must be declared before being defined");
+        let align = align.map(u64::from).max(requested_align);
+        self.artifact
+            .declare(
+                name,
+                translate_data_linkage(linkage, writable || relro, align),
+            )
+            .expect("inconsistent declarations");
+
         let size = init.size();
         let mut bytes = Vec::with_capacity(size);
         match *init {
@@ -290,7 +373,41 @@ impl Backend for FaerieBackend {
         // Nothing to do.
     }
 
-    fn finish(self) -> FaerieProduct {
+    fn finish(mut self) -> FaerieProduct {
+        if let Some(ref dwarf) = self.dwarf {
+            let sections = dwarf.emit();
+            for section in &sections {
+                self.artifact
+                    .declare_with(
+                        section.section.name(),
+                        faerie::Decl::section(faerie::SectionKind::Debug),
+                        section.data.clone(),
+                    )
+                    .expect("inconsistent declaration");
+            }
+            for section in &sections {
+                for reloc in &section.relocs {
+                    let to = match reloc.target {
+                        DwarfRelocTarget::Function(ref name) => name.as_str(),
+                        DwarfRelocTarget::Section(target) => target.name(),
+                    };
+                    self.artifact
+                        .link_with(
+                            faerie::Link {
+                                from: section.section.name(),
+                                to,
+                                at: u64::from(reloc.offset),
+                            },
+                            faerie::Reloc::Debug {
+                                size: reloc.size,
+                                addend: reloc.addend as i32,
+                            },
+                        )
+                        .expect("faerie relocation error");
+                }
+            }
+        }
+
         FaerieProduct {
             artifact: self.artifact,
             trap_manifest: self.trap_manifest,
@@ -326,17 +443,17 @@ impl FaerieProduct {
     }
 }
 
-fn translate_function_linkage(linkage: Linkage) -> faerie::Decl {
+fn translate_function_linkage(linkage: Linkage, align: Option<usize>) -> faerie::Decl {
     match linkage {
         Linkage::Import => faerie::Decl::function_import().into(),
-        Linkage::Local => faerie::Decl::function().into(),
-        Linkage::Export => faerie::Decl::function().global().into(),
-        Linkage::Preemptible => faerie::Decl::function().weak().into(),
+        Linkage::Local => faerie::Decl::function().with_align(align).into(),
+        Linkage::Export => faerie::Decl::function().global().with_align(align).into(),
+        Linkage::Preemptible => faerie::Decl::function().weak().with_align(align).into(),
     }
 }
 
-fn translate_data_linkage(linkage: Linkage, writable: bool, align: Option<u8>) -> faerie::Decl {
-    let align = align.map(|align| usize::from(align));
+fn translate_data_linkage(linkage: Linkage, writable: bool, align: Option<u64>) -> faerie::Decl {
+    let align = align.map(|align| align as usize);
     match linkage {
         Linkage::Import => faerie::Decl::data_import().into(),
         Linkage::Local => faerie::Decl::data()
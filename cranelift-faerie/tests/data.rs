@@ -0,0 +1,61 @@
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_faerie::*;
+use cranelift_module::*;
+use goblin::elf::{section_header, Elf};
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+#[test]
+fn relro_and_custom_section_data() {
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_pic").unwrap();
+    let isa = isa::lookup(Triple::from_str("x86_64-unknown-linux-gnu").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    let builder = FaerieBuilder::new(
+        isa,
+        "test.o".to_owned(),
+        FaerieTrapCollection::Disabled,
+        default_libcall_names(),
+    )
+    .unwrap();
+    let mut module: Module<FaerieBackend> = Module::new(builder);
+
+    let mut data_ctx = DataContext::new();
+    for &(name, relro, section) in &[("relro", true, None), ("custom", false, Some(".custom"))] {
+        let data_id = module
+            .declare_data(name, Linkage::Export, false, DataStorage::Static, None)
+            .unwrap();
+        data_ctx.clear();
+        data_ctx.define(vec![1, 2, 3, 4].into_boxed_slice());
+        if relro {
+            data_ctx.set_relro();
+        }
+        if let Some(section) = section {
+            data_ctx.set_section(section);
+        }
+        module.define_data(data_id, &data_ctx).unwrap();
+    }
+
+    let bytes = module.finish().emit().unwrap();
+    let elf = Elf::parse(&bytes).unwrap();
+    let flags = |name: &str| {
+        elf.section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get(header.sh_name).unwrap().unwrap() == name)
+            .unwrap_or_else(|| panic!("missing section {}", name))
+            .sh_flags
+    };
+
+    // Relro data is emitted as writable data, and data in a custom section is placed in its
+    // default section.
+    assert_ne!(
+        flags(".data.relro") & u64::from(section_header::SHF_WRITE),
+        0
+    );
+    assert_eq!(
+        flags(".rodata.custom") & u64::from(section_header::SHF_WRITE),
+        0
+    );
+}
@@ -0,0 +1,190 @@
+use cranelift_codegen::ir::*;
+use cranelift_codegen::isa::{self, CallConv};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_faerie::*;
+use cranelift_frontend::*;
+use cranelift_module::*;
+use goblin::elf::{reloc, section_header, Elf};
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+fn module() -> Module<FaerieBackend> {
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_pic").unwrap();
+    let isa = isa::lookup(Triple::from_str("x86_64-unknown-linux-gnu").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    let mut builder = FaerieBuilder::new(
+        isa,
+        "test.o".to_owned(),
+        FaerieTrapCollection::Disabled,
+        default_libcall_names(),
+    )
+    .unwrap();
+    builder.debug_info("test.wasm".to_owned());
+    Module::new(builder)
+}
+
+fn signature() -> Signature {
+    Signature {
+        params: vec![AbiParam::new(types::I32)],
+        returns: vec![AbiParam::new(types::I32)],
+        call_conv: CallConv::SystemV,
+    }
+}
+
+fn define_function(module: &mut Module<FaerieBackend>, name: &str) {
+    let func_id = module
+        .declare_function(name, Linkage::Export, &signature())
+        .unwrap();
+    let mut ctx = Context::new();
+    translate(func_id, &mut ctx);
+    module.define_function(func_id, &mut ctx).unwrap();
+}
+
+/// Translate a function with a labelled parameter into `ctx`.
+fn translate(func_id: FuncId, ctx: &mut Context) {
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), signature());
+    ctx.func.collect_debug_info();
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.append_ebb_params_for_function_params(ebb);
+        bcx.switch_to_block(ebb);
+        bcx.seal_block(ebb);
+        let x = bcx.ebb_params(ebb)[0];
+        bcx.set_srcloc(SourceLoc::new(3));
+        bcx.set_val_label(x, ValueLabel::from_u32(0));
+        let y = bcx.ins().iadd_imm(x, 1);
+        bcx.set_srcloc(SourceLoc::new(5));
+        bcx.ins().return_(&[y]);
+    }
+}
+
+/// Get the relocations applied to the section `name`, as `(type, target)`, where the target is
+/// the name of a symbol or of the section a section symbol refers to. Faerie places each function
+/// in its own `.text.<name>` section, and refers to it by the section symbol, so these targets are
+/// reported as the name of the function.
+fn relocations(elf: &Elf, name: &str) -> Vec<(u32, String)> {
+    let section_name = |index: usize| {
+        elf.shdr_strtab
+            .get(elf.section_headers[index].sh_name)
+            .unwrap()
+            .unwrap()
+            .to_owned()
+    };
+    let index = (0..elf.section_headers.len())
+        .find(|&index| section_name(index) == name)
+        .unwrap_or_else(|| panic!("missing section {}", name));
+
+    let mut result = Vec::new();
+    for &(reloc_index, ref relocs) in &elf.shdr_relocs {
+        if elf.section_headers[reloc_index].sh_info as usize != index {
+            continue;
+        }
+        for reloc in relocs.iter() {
+            let sym = elf.syms.get(reloc.r_sym).unwrap();
+            let target = if sym.st_type() == goblin::elf::sym::STT_SECTION {
+                let name = section_name(sym.st_shndx);
+                match name.get(..6) {
+                    Some(".text.") => name[6..].to_owned(),
+                    _ => name,
+                }
+            } else {
+                elf.strtab.get(sym.st_name).unwrap().unwrap().to_owned()
+            };
+            result.push((reloc.r_type, target));
+        }
+    }
+    result
+}
+
+#[test]
+fn debug_sections_are_linked() {
+    let mut module = module();
+    define_function(&mut module, "first");
+    define_function(&mut module, "second");
+    let product = module.finish();
+    let bytes = product.emit().unwrap();
+    let elf = Elf::parse(&bytes).unwrap();
+
+    for name in &[".debug_abbrev", ".debug_info", ".debug_line", ".debug_loc"] {
+        let header = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get(header.sh_name).unwrap().unwrap() == *name)
+            .unwrap_or_else(|| panic!("missing section {}", name));
+        assert_eq!(header.sh_type, section_header::SHT_PROGBITS);
+        assert_eq!(header.sh_flags & u64::from(section_header::SHF_ALLOC), 0);
+        assert_ne!(header.sh_size, 0);
+    }
+
+    // `.debug_info` refers to the other sections, and to the address of each function.
+    let info = relocations(&elf, ".debug_info");
+    for &section in &[".debug_abbrev", ".debug_line", ".debug_loc"] {
+        assert!(
+            info.contains(&(reloc::R_X86_64_32, section.to_owned())),
+            "{:?}",
+            info
+        );
+    }
+    for &function in &["first", "second"] {
+        assert!(
+            info.contains(&(reloc::R_X86_64_64, function.to_owned())),
+            "{:?}",
+            info
+        );
+    }
+
+    // Each function has a line number sequence starting at its address.
+    let line = relocations(&elf, ".debug_line");
+    assert_eq!(
+        line,
+        [
+            (reloc::R_X86_64_64, "first".to_owned()),
+            (reloc::R_X86_64_64, "second".to_owned())
+        ]
+    );
+
+    // The location lists hold the code ranges of the labelled parameter in each function.
+    let loc = relocations(&elf, ".debug_loc");
+    assert!(!loc.is_empty());
+    assert!(loc
+        .iter()
+        .all(|&(ty, ref target)| ty == reloc::R_X86_64_64
+            && (target == "first" || target == "second")));
+}
+
+#[test]
+fn parallel_functions_have_variables() {
+    let mut module = module();
+    let func_ids = ["first", "second"]
+        .iter()
+        .map(|name| {
+            module
+                .declare_function(name, Linkage::Export, &signature())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    module
+        .define_functions_parallel(&func_ids, 2, |func_id, ctx| {
+            translate(func_id, ctx);
+            Ok(())
+        })
+        .unwrap();
+    let product = module.finish();
+    let bytes = product.emit().unwrap();
+    let elf = Elf::parse(&bytes).unwrap();
+
+    // The location lists are built from the compiled functions, so each function has one.
+    let loc = relocations(&elf, ".debug_loc");
+    for &function in &["first", "second"] {
+        assert!(
+            loc.iter().any(|(_, target)| target == function),
+            "{:?}",
+            loc
+        );
+    }
+}
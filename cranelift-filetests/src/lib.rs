@@ -31,6 +31,7 @@ use std::time;
 
 mod concurrent;
 mod match_directive;
+mod run_directive;
 mod runner;
 mod runone;
 mod subtest;
@@ -40,12 +41,16 @@ mod test_cat;
 mod test_compile;
 mod test_dce;
 mod test_domtree;
+mod test_ebb_layout;
+mod test_interpret;
 mod test_legalizer;
 mod test_licm;
 mod test_postopt;
 mod test_preopt;
 mod test_print_cfg;
 mod test_regalloc;
+mod test_run;
+mod test_schedule;
 mod test_shrink;
 mod test_simple_gvn;
 mod test_simple_preopt;
@@ -117,12 +122,16 @@ fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<dyn subtest::
         "compile" => test_compile::subtest(parsed),
         "dce" => test_dce::subtest(parsed),
         "domtree" => test_domtree::subtest(parsed),
+        "ebb_layout" => test_ebb_layout::subtest(parsed),
+        "interpret" => test_interpret::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
         "postopt" => test_postopt::subtest(parsed),
         "simple_preopt" => test_simple_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
         "regalloc" => test_regalloc::subtest(parsed),
+        "run" => test_run::subtest(parsed),
+        "schedule" => test_schedule::subtest(parsed),
         "shrink" => test_shrink::subtest(parsed),
         "simple-gvn" => test_simple_gvn::subtest(parsed),
         "verifier" => test_verifier::subtest(parsed),
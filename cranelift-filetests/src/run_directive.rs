@@ -0,0 +1,161 @@
+//! Parsing of the `run:` directives used by test commands that execute functions.
+//!
+//! A run directive names the function to call, the arguments to pass, and the expected outcome:
+//!
+//! ```clif
+//!     ; run: %add(1, 2) == 3
+//!     ; run: %split(0x1.8p0) == 1, 0x1.0p-1
+//!     ; run: %div(1, 0) == trap int_divz
+//!     ; run: %is_zero(0)
+//! ```
+//!
+//! Without an expected outcome, the function must return a single boolean which is expected to
+//! be true. Values use the syntax of the immediates in the text format, and their types are
+//! given by the signature of the function.
+
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Imm64};
+use cranelift_codegen::ir::{types, AbiParam, Function, TrapCode, Type};
+use cranelift_interpreter::DataValue;
+
+/// The expected outcome of a call.
+#[derive(Debug, PartialEq)]
+pub enum Expected {
+    /// The call returns these values.
+    Values(Vec<DataValue>),
+    /// The call traps with this code.
+    Trap(TrapCode),
+}
+
+/// A parsed `run:` directive.
+#[derive(Debug, PartialEq)]
+pub struct RunDirective {
+    /// The arguments to pass to the function.
+    pub args: Vec<DataValue>,
+    /// The expected outcome.
+    pub expected: Expected,
+}
+
+impl RunDirective {
+    /// Parse the `text` following a `run:` directive for `func`.
+    pub fn parse(text: &str, func: &Function) -> Result<Self, String> {
+        let (call, expected) = match text.find("==") {
+            Some(idx) => (text[..idx].trim(), Some(text[idx + 2..].trim())),
+            None => (text.trim(), None),
+        };
+
+        let open = call
+            .find('(')
+            .ok_or("expected '(' after the function name")?;
+        if !call.ends_with(')') {
+            return Err(format!("expected ')' at the end of '{}'", call));
+        }
+        let name = &call[..open];
+        if name != func.name.to_string() {
+            return Err(format!("can't call {} from {}", name, func.name));
+        }
+        let args = parse_values(&call[open + 1..call.len() - 1], &func.signature.params)?;
+
+        let expected = match expected {
+            None => {
+                if func.signature.returns.len() != 1
+                    || !func.signature.returns[0].value_type.is_bool()
+                {
+                    return Err(format!("{} must return a boolean", func.name));
+                }
+                Expected::Values(vec![DataValue::B(true)])
+            }
+            Some(text) if text.starts_with("trap") => {
+                let code = text["trap".len()..].trim();
+                Expected::Trap(
+                    code.parse()
+                        .map_err(|_| format!("invalid trap code '{}'", code))?,
+                )
+            }
+            Some(text) => Expected::Values(parse_values(text, &func.signature.returns)?),
+        };
+
+        Ok(Self { args, expected })
+    }
+}
+
+/// Parse a comma-separated list of values with the types in `abi`.
+fn parse_values(text: &str, abi: &[AbiParam]) -> Result<Vec<DataValue>, String> {
+    let text = text.trim();
+    let parts: Vec<&str> = if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(str::trim).collect()
+    };
+    if parts.len() != abi.len() {
+        return Err(format!(
+            "expected {} values, got {} in '{}'",
+            abi.len(),
+            parts.len(),
+            text
+        ));
+    }
+    parts
+        .iter()
+        .zip(abi)
+        .map(|(part, param)| parse_value(part, param.value_type))
+        .collect()
+}
+
+/// Parse a single value of type `ty`.
+fn parse_value(text: &str, ty: Type) -> Result<DataValue, String> {
+    let err = || format!("invalid {} value '{}'", ty, text);
+    if ty.is_bool() && !ty.is_vector() {
+        return match text {
+            "true" => Ok(DataValue::B(true)),
+            "false" => Ok(DataValue::B(false)),
+            _ => Err(err()),
+        };
+    }
+    match ty {
+        types::F32 => {
+            let bits = match text.parse::<Ieee32>() {
+                Ok(x) => x.bits(),
+                Err(_) => text.parse::<f32>().map_err(|_| err())?.to_bits(),
+            };
+            Ok(DataValue::F32(f32::from_bits(bits)))
+        }
+        types::F64 => {
+            let bits = match text.parse::<Ieee64>() {
+                Ok(x) => x.bits(),
+                Err(_) => text.parse::<f64>().map_err(|_| err())?.to_bits(),
+            };
+            Ok(DataValue::F64(f64::from_bits(bits)))
+        }
+        _ => {
+            let imm: i64 = text.parse::<Imm64>().map_err(|_| err())?.into();
+            DataValue::from_int(ty, imm as u128).ok_or_else(err)
+        }
+    }
+}
+
+#[test]
+fn test_run_directive() {
+    let func = cranelift_reader::parse_functions(
+        "function %f(i32, f64) -> i8, b1 {
+         ebb0(v0: i32, v1: f64):
+             trap user0
+         }",
+    )
+    .unwrap()
+    .remove(0);
+
+    assert_eq!(
+        RunDirective::parse("%f(-1, 0x1.0p1) == 0xff, true", &func),
+        Ok(RunDirective {
+            args: vec![DataValue::I32(-1), DataValue::F64(2.0)],
+            expected: Expected::Values(vec![DataValue::I8(-1), DataValue::B(true)]),
+        })
+    );
+    assert_eq!(
+        RunDirective::parse("%f(0, 1.5) == trap user0", &func).map(|d| d.expected),
+        Ok(Expected::Trap(TrapCode::User(0)))
+    );
+    assert!(RunDirective::parse("%f(0) == 0, true", &func).is_err());
+    assert!(RunDirective::parse("%g(0, 1.5) == 0, true", &func).is_err());
+    assert!(RunDirective::parse("%f(0, 1.5)", &func).is_err());
+}
@@ -0,0 +1,86 @@
+//! Test command for executing functions with the interpreter.
+//!
+//! The `interpret` test command runs each function with the interpreter once for every `run:`
+//! directive attached to it, and checks that the function returns the expected values or traps
+//! with the expected code. See the `run_directive` module for the syntax of the directives.
+//!
+//! Functions run without any accessible memory other than their own stack slots, and they can
+//! only call themselves.
+
+use crate::match_directive::match_directive;
+use crate::run_directive::{Expected, RunDirective};
+use crate::subtest::{Context, SubTest, SubtestResult};
+use cranelift_codegen::ir::Function;
+use cranelift_interpreter::{DataValue, Interpreter, InterpreterError, NoMemory};
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+
+/// Stop functions that don't terminate after this many instructions.
+const STEP_LIMIT: u64 = 10_000_000;
+
+struct TestInterpret;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<dyn SubTest>> {
+    assert_eq!(parsed.command, "interpret");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestInterpret))
+    }
+}
+
+impl SubTest for TestInterpret {
+    fn name(&self) -> &'static str {
+        "interpret"
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let func = func.as_ref();
+        let mut memory = NoMemory;
+        let mut interp = Interpreter::new(&mut memory);
+        interp.add_function(func);
+        interp.set_step_limit(Some(STEP_LIMIT));
+
+        for comment in &context.details.comments {
+            let text = match match_directive(comment.text, "run:") {
+                Some(text) => text,
+                None => continue,
+            };
+            let directive = RunDirective::parse(text, func)?;
+            let outcome = interp.call(func, &directive.args);
+            let passed = match (&outcome, &directive.expected) {
+                (Ok(values), Expected::Values(expected)) => same_values(values, expected),
+                (Err(InterpreterError::Trap(code)), Expected::Trap(expected)) => code == expected,
+                _ => false,
+            };
+            if !passed {
+                return Err(format!(
+                    "run: {}\ngot {}",
+                    text,
+                    match outcome {
+                        Ok(values) => display_values(&values),
+                        Err(e) => e.to_string(),
+                    }
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare values bit for bit, so that NaNs can be expected.
+fn same_values(values: &[DataValue], expected: &[DataValue]) -> bool {
+    values.len() == expected.len()
+        && values
+            .iter()
+            .zip(expected)
+            .all(|(&x, &y)| x.ty() == y.ty() && x.to_bits(x.ty()) == y.to_bits(y.ty()))
+}
+
+fn display_values(values: &[DataValue]) -> String {
+    values
+        .iter()
+        .map(DataValue::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,290 @@
+//! Test command for executing compiled functions.
+//!
+//! The `run` test command compiles each function for the target ISA, loads the code into
+//! executable memory and calls it once for every `run:` directive attached to it, checking that
+//! the function returns the expected values. See the `run_directive` module for the syntax of the
+//! directives.
+//!
+//! Functions are only executed when the target ISA matches the architecture of the host; the test
+//! is skipped otherwise. The compiled code is not linked, so functions can't call other functions,
+//! and they can't access memory other than their own stack slots. A trap would bring down the
+//! whole test runner, so directives expecting a trap are rejected; use `test interpret` for them.
+
+use crate::match_directive::match_directive;
+use crate::run_directive::{Expected, RunDirective};
+use crate::subtest::{Context, SubTest, SubtestResult};
+use cranelift_codegen::binemit::{self, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::types::{B1, I8};
+use cranelift_codegen::ir::{self, Function, InstBuilder};
+use cranelift_codegen::isa::{CallConv, TargetIsa};
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_interpreter::DataValue;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+use std::mem;
+use target_lexicon::Triple;
+
+/// The size of the slot holding each argument or result passed through the trampoline.
+const SLOT_SIZE: usize = 16;
+
+struct TestRun;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<dyn SubTest>> {
+    assert_eq!(parsed.command, "run");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRun))
+    }
+}
+
+impl SubTest for TestRun {
+    fn name(&self) -> &'static str {
+        "run"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let isa = context.isa.expect("run needs an ISA");
+        let func = func.as_ref();
+
+        // Check the directives even when they aren't executed, so that a file is accepted or
+        // rejected the same way on every host.
+        let mut directives = Vec::new();
+        for comment in &context.details.comments {
+            let text = match match_directive(comment.text, "run:") {
+                Some(text) => text,
+                None => continue,
+            };
+            let directive = RunDirective::parse(text, func)?;
+            let expected = match directive.expected {
+                Expected::Values(values) => values,
+                Expected::Trap(code) => {
+                    return Err(format!(
+                        "run: {}\nexpecting trap {} is not supported by test run, \
+                         use test interpret",
+                        text, code
+                    ));
+                }
+            };
+            directives.push((text, directive.args, expected));
+        }
+
+        if isa.triple().architecture != Triple::host().architecture {
+            return Ok(());
+        }
+
+        let signature = &func.signature;
+        let code = compile(func.clone(), isa)?;
+        let trampoline = compile(make_trampoline(signature, isa)?, isa)?;
+
+        for (text, args, expected) in directives {
+            let slots = signature.params.len().max(signature.returns.len());
+            let mut values = vec![0u8; slots * SLOT_SIZE];
+            for (slot, arg) in values.chunks_mut(SLOT_SIZE).zip(&args) {
+                slot.copy_from_slice(&arg.to_bits(arg.ty()).to_le_bytes());
+            }
+
+            unsafe {
+                let trampoline: extern "C" fn(*const u8, *mut u8) =
+                    mem::transmute(trampoline.as_ptr());
+                trampoline(code.as_ptr(), values.as_mut_ptr());
+            }
+
+            let results = values
+                .chunks(SLOT_SIZE)
+                .zip(&signature.returns)
+                .map(|(slot, ret)| {
+                    let mut bytes = [0; SLOT_SIZE];
+                    bytes.copy_from_slice(slot);
+                    DataValue::from_bits(ret.value_type, u128::from_le_bytes(bytes))
+                        .expect("checked result type")
+                })
+                .collect::<Vec<_>>();
+            if !same_values(&results, &expected) {
+                return Err(format!("run: {}\ngot {}", text, display_values(&results)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compile `func` for `isa` and load its code into executable memory.
+fn compile(func: Function, isa: &dyn TargetIsa) -> SubtestResult<ExecutableMemory> {
+    let mut comp_ctx = cranelift_codegen::Context::for_function(func);
+    let code_info = comp_ctx
+        .compile(isa)
+        .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+
+    let mut code = vec![0; code_info.total_size as usize];
+    let mut relocs = UnsupportedRelocs(None);
+    unsafe {
+        comp_ctx.emit_to_memory(isa, code.as_mut_ptr(), &mut relocs, &mut NullTrapSink {});
+    }
+    if let Some(reloc) = relocs.0 {
+        return Err(format!(
+            "{} can't be run: unsupported relocation {}",
+            comp_ctx.func.name, reloc
+        ));
+    }
+
+    ExecutableMemory::new(&code)
+}
+
+/// Build a function calling a function with the signature `signature`, with the arguments read
+/// from an array of slots and the results written back to the same array:
+///
+/// ```text
+/// function %trampoline(callee: iN, values: iN) system_v
+/// ```
+fn make_trampoline(signature: &ir::Signature, isa: &dyn TargetIsa) -> SubtestResult<Function> {
+    let pointer_type = isa.pointer_type();
+    let mut sig = ir::Signature::new(CallConv::triple_default(&Triple::host()));
+    sig.params.push(ir::AbiParam::new(pointer_type));
+    sig.params.push(ir::AbiParam::new(pointer_type));
+
+    let mut func = Function::with_name_signature(ir::ExternalName::testcase("trampoline"), sig);
+    let callee_sig = func.import_signature(signature.clone());
+    let ebb = func.dfg.make_ebb();
+    let callee = func.dfg.append_ebb_param(ebb, pointer_type);
+    let values = func.dfg.append_ebb_param(ebb, pointer_type);
+
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+    let flags = ir::MemFlags::trusted();
+
+    let mut args = Vec::with_capacity(signature.params.len());
+    for (i, param) in signature.params.iter().enumerate() {
+        let ty = check_type(param.value_type)?;
+        let offset = (i * SLOT_SIZE) as i32;
+        let arg = if ty == B1 {
+            let byte = pos.ins().load(I8, flags, values, offset);
+            pos.ins().icmp_imm(IntCC::NotEqual, byte, 0)
+        } else {
+            pos.ins().load(ty, flags, values, offset)
+        };
+        args.push(arg);
+    }
+
+    let call = pos.ins().call_indirect(callee_sig, callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, &result) in results.iter().enumerate() {
+        let ty = check_type(pos.func.dfg.value_type(result))?;
+        let offset = (i * SLOT_SIZE) as i32;
+        let result = if ty == B1 {
+            pos.ins().bint(I8, result)
+        } else {
+            result
+        };
+        pos.ins().store(flags, result, values, offset);
+    }
+    pos.ins().return_(&[]);
+
+    Ok(func)
+}
+
+/// Check that values of type `ty` can be passed through the trampoline.
+fn check_type(ty: ir::Type) -> SubtestResult<ir::Type> {
+    if ty == B1 || ((ty.is_int() || ty.is_float()) && ty.bits() <= 64) {
+        Ok(ty)
+    } else {
+        Err(format!(
+            "values of type {} can't be passed to a run test",
+            ty
+        ))
+    }
+}
+
+/// Relocation sink recording the first relocation, since there is nothing to link with.
+struct UnsupportedRelocs(Option<Reloc>);
+
+impl RelocSink for UnsupportedRelocs {
+    fn reloc_ebb(&mut self, _offset: CodeOffset, reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.0.get_or_insert(reloc);
+    }
+
+    fn reloc_external(
+        &mut self,
+        _offset: CodeOffset,
+        reloc: Reloc,
+        _name: &ir::ExternalName,
+        _addend: binemit::Addend,
+    ) {
+        self.0.get_or_insert(reloc);
+    }
+
+    fn reloc_jt(&mut self, _offset: CodeOffset, reloc: Reloc, _jt: ir::JumpTable) {
+        // Jump tables are addressed relative to the code itself.
+        if reloc != Reloc::X86PCRelRodata4 {
+            self.0.get_or_insert(reloc);
+        }
+    }
+}
+
+/// Page-aligned memory holding executable code.
+struct ExecutableMemory {
+    buffer: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl ExecutableMemory {
+    /// Copy `code` into new executable memory.
+    fn new(code: &[u8]) -> SubtestResult<Self> {
+        let page_size = region::page::size();
+        let len = (code.len().max(1) + (page_size - 1)) & !(page_size - 1);
+        let mut buffer = vec![0; len + page_size];
+        let offset = buffer.as_ptr().align_offset(page_size);
+        buffer[offset..offset + code.len()].copy_from_slice(code);
+        unsafe {
+            region::protect(
+                buffer[offset..].as_ptr(),
+                len,
+                region::Protection::READ_EXECUTE,
+            )
+        }
+        .map_err(|e| format!("can't make memory executable: {}", e))?;
+        Ok(Self {
+            buffer,
+            offset,
+            len,
+        })
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.buffer[self.offset..].as_ptr()
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        unsafe { region::protect(self.as_ptr(), self.len, region::Protection::READ_WRITE) }
+            .expect("can't make memory writable");
+    }
+}
+
+/// Compare values bit for bit, so that NaNs can be expected.
+fn same_values(values: &[DataValue], expected: &[DataValue]) -> bool {
+    values.len() == expected.len()
+        && values
+            .iter()
+            .zip(expected)
+            .all(|(&x, &y)| x.ty() == y.ty() && x.to_bits(x.ty()) == y.to_bits(y.ty()))
+}
+
+fn display_values(values: &[DataValue]) -> String {
+    values
+        .iter()
+        .map(DataValue::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -246,6 +246,13 @@ impl<'a> FunctionBuilder<'a> {
         ebb
     }
 
+    /// Marks `ebb` as cold, meaning that it is expected to be executed rarely.
+    ///
+    /// The code generator places cold EBBs after the hot code of the function.
+    pub fn set_cold_ebb(&mut self, ebb: Ebb) {
+        self.func.layout.set_cold(ebb);
+    }
+
     /// After the call to this function, new instructions will be inserted into the designated
     /// block, in the order they are declared. You must declare the types of the Ebb arguments
     /// you will use here.
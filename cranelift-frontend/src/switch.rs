@@ -10,6 +10,11 @@ type EntryIndex = u64;
 /// Unlike with `br_table`, `Switch` cases may be sparse or non-0-based.
 /// They emit efficient code using branches, jump tables, or a combination of both.
 ///
+/// The cases are clustered into ranges which are dense enough to be dispatched with a jump table,
+/// and the remaining cases are compared one by one. A balanced binary search tree of comparisons
+/// selects the right cluster or case. How dense and how large a cluster must be to become a jump
+/// table can be tuned with `set_min_jump_table_density` and `set_min_jump_table_cases`.
+///
 /// # Example
 ///
 /// ```rust
@@ -39,9 +44,11 @@ type EntryIndex = u64;
 /// switch.set_entry(7, block2);
 /// switch.emit(&mut builder, val, fallback);
 /// ```
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Switch {
     cases: HashMap<EntryIndex, Ebb>,
+    min_jump_table_cases: usize,
+    min_jump_table_density: u8,
 }
 
 impl Switch {
@@ -49,9 +56,29 @@ impl Switch {
     pub fn new() -> Self {
         Self {
             cases: HashMap::new(),
+            min_jump_table_cases: 4,
+            min_jump_table_density: 40,
         }
     }
 
+    /// Set the minimum number of cases dispatched by a jump table. Smaller clusters of cases are
+    /// compared one by one instead. The default is 4.
+    pub fn set_min_jump_table_cases(&mut self, cases: usize) {
+        self.min_jump_table_cases = cases;
+    }
+
+    /// Set the minimum percentage of the entries of a jump table which must be cases, rather than
+    /// gaps jumping to the default ebb. It must be between 1 and 100. The default is 40; 100 only
+    /// allows jump tables for contiguous cases.
+    pub fn set_min_jump_table_density(&mut self, percent: u8) {
+        assert!(
+            percent > 0 && percent <= 100,
+            "Invalid jump table density {}%",
+            percent
+        );
+        self.min_jump_table_density = percent;
+    }
+
     /// Set a switch entry
     pub fn set_entry(&mut self, index: EntryIndex, ebb: Ebb) {
         let prev = self.cases.insert(index, ebb);
@@ -64,30 +91,58 @@ impl Switch {
 
     /// Turn the `cases` `HashMap` into a list of `ContiguousCaseRange`s.
     ///
+    /// Clusters of cases are grown greedily from the smallest entry index, until the next case
+    /// would make them too sparse. The gaps of a cluster with enough cases to become a jump table jump to
+    /// `otherwise`, and the cases of smaller clusters get a `ContiguousCaseRange` each.
+    ///
     /// # Postconditions
     ///
     /// * Every entry will be represented.
     /// * The `ContiguousCaseRange`s will not overlap.
-    /// * Between two `ContiguousCaseRange`s there will be at least one entry index.
     /// * No `ContiguousCaseRange`s will be empty.
-    fn collect_contiguous_case_ranges(self) -> Vec<ContiguousCaseRange> {
+    /// * Every `ContiguousCaseRange` of more than one entry has at least `min_jump_table_cases`
+    ///   entries which aren't `otherwise`.
+    fn collect_contiguous_case_ranges(self, otherwise: Ebb) -> Vec<ContiguousCaseRange> {
         debug!("build_contiguous_case_ranges before: {:#?}", self.cases);
         let mut cases = self.cases.into_iter().collect::<Vec<(_, _)>>();
         cases.sort_by_key(|&(index, _)| index);
 
+        let min_cases = self.min_jump_table_cases.max(2);
+        let min_density = u128::from(self.min_jump_table_density);
         let mut contiguous_case_ranges: Vec<ContiguousCaseRange> = vec![];
-        let mut last_index = None;
-        for (index, ebb) in cases {
-            match last_index {
-                None => contiguous_case_ranges.push(ContiguousCaseRange::new(index)),
-                Some(last_index) => {
-                    if index > last_index + 1 {
-                        contiguous_case_ranges.push(ContiguousCaseRange::new(index));
-                    }
+        let mut start = 0;
+        while start < cases.len() {
+            // Extend the cluster starting with this case for as long as it stays dense enough.
+            let first_index = cases[start].0;
+            let mut end = start + 1;
+            while end < cases.len() {
+                let span = u128::from(cases[end].0 - first_index) + 1;
+                let count = (end - start + 1) as u128;
+                if count * 100 < span * min_density {
+                    break;
                 }
+                end += 1;
             }
-            contiguous_case_ranges.last_mut().unwrap().ebbs.push(ebb);
-            last_index = Some(index);
+
+            if end - start < min_cases {
+                contiguous_case_ranges.push(ContiguousCaseRange::new(first_index));
+                contiguous_case_ranges
+                    .last_mut()
+                    .unwrap()
+                    .ebbs
+                    .push(cases[start].1);
+                start += 1;
+                continue;
+            }
+
+            let mut range = ContiguousCaseRange::new(first_index);
+            for &(index, ebb) in &cases[start..end] {
+                let gap = (index - first_index) as usize - range.ebbs.len();
+                range.ebbs.extend((0..gap).map(|_| otherwise));
+                range.ebbs.push(ebb);
+            }
+            contiguous_case_ranges.push(range);
+            start = end;
         }
 
         debug!(
@@ -240,20 +295,26 @@ impl Switch {
             _ => val,
         };
 
-        let contiguous_case_ranges = self.collect_contiguous_case_ranges();
+        let contiguous_case_ranges = self.collect_contiguous_case_ranges(otherwise);
         let cases_and_jt_ebbs = Self::build_search_tree(bx, val, otherwise, contiguous_case_ranges);
         Self::build_jump_tables(bx, val, otherwise, cases_and_jt_ebbs);
     }
 }
 
+impl Default for Switch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// This represents a contiguous range of cases to switch on.
 ///
-/// For example 10 => ebb1, 11 => ebb2, 12 => ebb7 will be represented as:
+/// For example 10 => ebb1, 11 => ebb2, 13 => ebb7 with ebb0 as the default will be represented as:
 ///
 /// ```plain
 /// ContiguousCaseRange {
 ///     first_index: 10,
-///     ebbs: vec![Ebb::from_u32(1), Ebb::from_u32(2), Ebb::from_u32(7)]
+///     ebbs: vec![Ebb::from_u32(1), Ebb::from_u32(2), Ebb::from_u32(0), Ebb::from_u32(7)]
 /// }
 /// ```
 #[derive(Debug)]
@@ -282,7 +343,10 @@ mod tests {
     use std::string::ToString;
 
     macro_rules! setup {
-        ($default:expr, [$($index:expr,)*]) => {{
+        ($default:expr, [$($index:expr,)*]) => {
+            setup!($default, [$($index,)*], |_: &mut Switch| {})
+        };
+        ($default:expr, [$($index:expr,)*], $configure:expr) => {{
             let mut func = Function::new();
             let mut func_ctx = FunctionBuilderContext::new();
             {
@@ -291,6 +355,7 @@ mod tests {
                 bx.switch_to_block(ebb);
                 let val = bx.ins().iconst(types::I8, 0);
                 let mut switch = Switch::new();
+                $configure(&mut switch);
                 $(
                     let ebb = bx.create_ebb();
                     switch.set_entry($index, ebb);
@@ -332,9 +397,30 @@ mod tests {
         );
     }
 
+    /// Only allow jump tables for contiguous cases, with at least two cases.
+    fn contiguous(switch: &mut Switch) {
+        switch.set_min_jump_table_cases(2);
+        switch.set_min_jump_table_density(100);
+    }
+
     #[test]
     fn switch_bool() {
         let func = setup!(0, [0, 1,]);
+        assert_eq!(
+            func,
+            "ebb0:
+    v0 = iconst.i8 0
+    v1 = uextend.i32 v0
+    v2 = icmp_imm eq v1, 1
+    brnz v2, ebb2
+    brz v1, ebb1
+    jump ebb0"
+        );
+    }
+
+    #[test]
+    fn switch_bool_jump_table() {
+        let func = setup!(0, [0, 1,], contiguous);
         assert_eq!(
             func,
             "    jt0 = jump_table [ebb1, ebb2]
@@ -367,6 +453,95 @@ ebb3:
     #[test]
     fn switch_many() {
         let func = setup!(0, [0, 1, 5, 7, 10, 11, 12,]);
+        assert_eq!(
+            func,
+            "    jt0 = jump_table [ebb1, ebb2, ebb0, ebb0, ebb0, ebb3, ebb0, ebb4, ebb0, ebb0, ebb5, ebb6, ebb7]
+
+ebb0:
+    v0 = iconst.i8 0
+    v1 = uextend.i32 v0
+    jump ebb8
+
+ebb8:
+    br_table.i32 v1, ebb0, jt0"
+        );
+    }
+
+    #[test]
+    fn switch_sparse() {
+        // A binary search tree of comparisons.
+        let func = setup!(0, [0, 100, 200, 300, 400, 500,]);
+        assert_eq!(
+            func,
+            "ebb0:
+    v0 = iconst.i8 0
+    v1 = uextend.i32 v0
+    v2 = icmp_imm uge v1, 300
+    brnz v2, ebb8
+    jump ebb7
+
+ebb8:
+    v3 = icmp_imm.i32 eq v1, 500
+    brnz v3, ebb6
+    v4 = icmp_imm.i32 eq v1, 400
+    brnz v4, ebb5
+    v5 = icmp_imm.i32 eq v1, 300
+    brnz v5, ebb4
+    jump ebb0
+
+ebb7:
+    v6 = icmp_imm.i32 eq v1, 200
+    brnz v6, ebb3
+    v7 = icmp_imm.i32 eq v1, 100
+    brnz v7, ebb2
+    brz.i32 v1, ebb1
+    jump ebb0"
+        );
+    }
+
+    #[test]
+    fn switch_clusters() {
+        // A contiguous cluster, a cluster with gaps and isolated cases.
+        let func = setup!(0, [1, 2, 3, 4, 100, 200, 201, 203, 205, 207, 1000,]);
+        assert_eq!(
+            func,
+            "    jt0 = jump_table [ebb1, ebb2, ebb3, ebb4]
+    jt1 = jump_table [ebb6, ebb7, ebb0, ebb8, ebb0, ebb9, ebb0, ebb10]
+
+ebb0:
+    v0 = iconst.i8 0
+    v1 = uextend.i32 v0
+    v2 = icmp_imm uge v1, 200
+    brnz v2, ebb13
+    jump ebb12
+
+ebb13:
+    v3 = icmp_imm.i32 eq v1, 1000
+    brnz v3, ebb11
+    v4 = icmp_imm.i32 uge v1, 200
+    brnz v4, ebb14
+    jump ebb0
+
+ebb12:
+    v5 = icmp_imm.i32 eq v1, 100
+    brnz v5, ebb5
+    v6 = icmp_imm.i32 uge v1, 1
+    brnz v6, ebb15
+    jump ebb0
+
+ebb15:
+    v7 = iadd_imm.i32 v1, -1
+    br_table v7, ebb0, jt0
+
+ebb14:
+    v8 = iadd_imm.i32 v1, -200
+    br_table v8, ebb0, jt1"
+        );
+    }
+
+    #[test]
+    fn switch_many_contiguous() {
+        let func = setup!(0, [0, 1, 5, 7, 10, 11, 12,], contiguous);
         assert_eq!(
             func,
             "    jt0 = jump_table [ebb1, ebb2]
@@ -434,7 +609,7 @@ ebb10:
 
     #[test]
     fn switch_optimal_codegen() {
-        let func = setup!(0, [-1i64 as u64, 0, 1,]);
+        let func = setup!(0, [-1i64 as u64, 0, 1,], contiguous);
         assert_eq!(
             func,
             "    jt0 = jump_table [ebb2, ebb3]
@@ -0,0 +1,1195 @@
+//! Direct execution of Cranelift IR functions.
+//!
+//! The interpreter executes the instructions of a function one at a time, keeping the value of
+//! every SSA value in a map. It implements the target-independent instruction set with the
+//! semantics documented in the language reference, so it can be used as a reference when testing
+//! optimizations and code generation.
+
+use crate::memory::Memory;
+use crate::value::{mask, DataValue};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{
+    self, types, ArgumentPurpose, AtomicRmwOp, Ebb, ExternalName, Function, GlobalValue,
+    GlobalValueData, Inst, InstructionData, Opcode, TrapCode, Type, Value,
+};
+use cranelift_entity::EntityRef;
+use failure_derive::Fail;
+use std::collections::HashMap;
+
+/// Apply a binary float operation to the two first arguments, returning the error made by `$err`
+/// from the enclosing function if they aren't floats of the same type.
+macro_rules! float_binary {
+    ($args:expr, $err:expr, |$x:ident, $y:ident| $e:expr) => {
+        match ($args[0], $args[1]) {
+            (DataValue::F32($x), DataValue::F32($y)) => DataValue::F32($e),
+            (DataValue::F64($x), DataValue::F64($y)) => DataValue::F64($e),
+            _ => return Err($err()),
+        }
+    };
+}
+
+/// Apply a unary float operation to the first argument, returning the error made by `$err` from
+/// the enclosing function if it isn't a float.
+macro_rules! float_unary {
+    ($args:expr, $err:expr, |$x:ident| $e:expr) => {
+        match $args[0] {
+            DataValue::F32($x) => DataValue::F32($e),
+            DataValue::F64($x) => DataValue::F64($e),
+            _ => return Err($err()),
+        }
+    };
+}
+
+/// The maximum depth of nested calls before reporting a stack overflow.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// An error preventing an interpreted function from returning normally.
+#[derive(Fail, Debug, PartialEq)]
+pub enum InterpreterError {
+    /// The function trapped.
+    #[fail(display = "trap: {}", _0)]
+    Trap(TrapCode),
+
+    /// The function executed an instruction that the interpreter can't handle, such as an
+    /// ISA-specific instruction or a reference to a symbol.
+    #[fail(display = "unsupported instruction: {}", _0)]
+    Unsupported(String),
+
+    /// The function called a function that wasn't added to the interpreter.
+    #[fail(display = "unknown function: {}", _0)]
+    UnknownFunction(String),
+
+    /// The arguments don't match the signature of the called function.
+    #[fail(display = "bad arguments: {}", _0)]
+    BadArguments(String),
+
+    /// The function executed more instructions than the step limit allows.
+    #[fail(display = "step limit exceeded")]
+    StepLimitExceeded,
+
+    /// The function used a value before defining it, which the verifier would have rejected.
+    #[fail(display = "{} used before it was defined", _0)]
+    UndefinedValue(Value),
+}
+
+/// A convenient alias for a `Result` that uses `InterpreterError` as the error type.
+pub type InterpreterResult<T> = Result<T, InterpreterError>;
+
+/// What to do after executing an instruction.
+enum ControlFlow {
+    /// Continue with the next instruction.
+    Continue,
+    /// Jump to the start of an EBB with the given arguments.
+    Jump(Ebb, Vec<DataValue>),
+    /// Return from the function with the given values.
+    Return(Vec<DataValue>),
+}
+
+/// The state of a function activation.
+struct Frame<'f> {
+    func: &'f Function,
+    values: HashMap<Value, DataValue>,
+    stack_slots: Vec<Vec<u8>>,
+}
+
+impl<'f> Frame<'f> {
+    fn new(func: &'f Function) -> Self {
+        Self {
+            func,
+            values: HashMap::new(),
+            stack_slots: func
+                .stack_slots
+                .values()
+                .map(|ss| vec![0; ss.size as usize])
+                .collect(),
+        }
+    }
+
+    fn get(&self, value: Value) -> InterpreterResult<DataValue> {
+        let value = self.func.dfg.resolve_aliases(value);
+        self.values
+            .get(&value)
+            .cloned()
+            .ok_or(InterpreterError::UndefinedValue(value))
+    }
+
+    fn get_all(&self, values: &[Value]) -> InterpreterResult<Vec<DataValue>> {
+        values.iter().map(|&v| self.get(v)).collect()
+    }
+
+    fn set(&mut self, value: Value, data: DataValue) {
+        self.values.insert(value, data);
+    }
+}
+
+/// An interpreter for Cranelift IR functions.
+///
+/// Functions that can be called by the interpreted code must be registered with `add_function`
+/// first. Memory accesses through addresses go to the `Memory` provided when creating the
+/// interpreter, while stack slots are private to each function activation and have no address.
+pub struct Interpreter<'a> {
+    functions: Vec<&'a Function>,
+    memory: &'a mut dyn Memory,
+    step_limit: Option<u64>,
+    steps: u64,
+    depth: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Create an interpreter that accesses `memory`.
+    pub fn new(memory: &'a mut dyn Memory) -> Self {
+        Self {
+            functions: Vec::new(),
+            memory,
+            step_limit: None,
+            steps: 0,
+            depth: 0,
+        }
+    }
+
+    /// Make `func` callable by its name from interpreted functions.
+    pub fn add_function(&mut self, func: &'a Function) {
+        self.functions.push(func);
+    }
+
+    /// Limit the number of instructions executed by each call to `call`.
+    ///
+    /// This guarantees that interpreting a function terminates, which is useful when the function
+    /// was generated by a fuzzer.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Call the function named `name`, which must have been added with `add_function`.
+    pub fn call_by_name(
+        &mut self,
+        name: &ExternalName,
+        args: &[DataValue],
+    ) -> InterpreterResult<Vec<DataValue>> {
+        let func = self.lookup(name)?;
+        self.call(func, args)
+    }
+
+    /// Call `func` with `args`, returning the values it returns.
+    pub fn call(
+        &mut self,
+        func: &'a Function,
+        args: &[DataValue],
+    ) -> InterpreterResult<Vec<DataValue>> {
+        self.steps = 0;
+        self.depth = 0;
+        self.run(func, args)
+    }
+
+    fn lookup(&self, name: &ExternalName) -> InterpreterResult<&'a Function> {
+        self.functions
+            .iter()
+            .find(|f| f.name == *name)
+            .cloned()
+            .ok_or_else(|| InterpreterError::UnknownFunction(name.to_string()))
+    }
+
+    /// Execute `func`, without resetting the step counter.
+    fn run(&mut self, func: &'a Function, args: &[DataValue]) -> InterpreterResult<Vec<DataValue>> {
+        check_values(&func.signature.params, args)?;
+        let entry = func
+            .layout
+            .entry_block()
+            .ok_or_else(|| InterpreterError::BadArguments(format!("{} has no body", func.name)))?;
+
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(InterpreterError::Trap(TrapCode::StackOverflow));
+        }
+        self.depth += 1;
+
+        let mut frame = Frame::new(func);
+        let mut ebb = entry;
+        let mut params = args.to_vec();
+        let result = 'ebbs: loop {
+            for (&param, &value) in func.dfg.ebb_params(ebb).iter().zip(&params) {
+                frame.set(param, value);
+            }
+
+            for inst in func.layout.ebb_insts(ebb) {
+                self.steps += 1;
+                if self.step_limit.map_or(false, |limit| self.steps > limit) {
+                    break 'ebbs Err(InterpreterError::StepLimitExceeded);
+                }
+                match self.step(&mut frame, inst) {
+                    Ok(ControlFlow::Continue) => {}
+                    Ok(ControlFlow::Jump(dest, args)) => {
+                        ebb = dest;
+                        params = args;
+                        continue 'ebbs;
+                    }
+                    Ok(ControlFlow::Return(values)) => break 'ebbs Ok(values),
+                    Err(e) => break 'ebbs Err(e),
+                }
+            }
+            break Err(InterpreterError::Unsupported(format!(
+                "fell off the end of {}",
+                ebb
+            )));
+        };
+
+        self.depth -= 1;
+        result
+    }
+
+    /// Execute a single instruction.
+    fn step(&mut self, frame: &mut Frame<'a>, inst: Inst) -> InterpreterResult<ControlFlow> {
+        let func = frame.func;
+        let dfg = &func.dfg;
+        let data = &dfg[inst];
+        let opcode = data.opcode();
+
+        if let Some(flow) = self.step_control(frame, inst)? {
+            return Ok(flow);
+        }
+
+        let args = frame.get_all(dfg.inst_args(inst))?;
+        let ctrl_ty = dfg.ctrl_typevar(inst);
+        let result_ty = dfg
+            .inst_results(inst)
+            .first()
+            .map_or(types::INVALID, |&v| dfg.value_type(v));
+        let unsupported =
+            || InterpreterError::Unsupported(dfg.display_inst(inst, None).to_string());
+        if ctrl_ty.is_vector() || ctrl_ty.is_flags() || result_ty.is_vector() {
+            return Err(unsupported());
+        }
+
+        // Make an integer of the controlling type from the low bits of `x`.
+        let int = |x: u128| DataValue::from_int(ctrl_ty, x).ok_or_else(unsupported);
+        let bits = u32::from(ctrl_ty.bits());
+        let imm = || -> i64 {
+            match *data {
+                InstructionData::UnaryImm { imm, .. }
+                | InstructionData::BinaryImm { imm, .. }
+                | InstructionData::IntCompareImm { imm, .. } => imm.into(),
+                _ => panic!("no immediate in {}", opcode),
+            }
+        };
+        // The second operand of a binary instruction, or its immediate.
+        let rhs = || -> DataValue {
+            match *data {
+                InstructionData::BinaryImm { .. } | InstructionData::IntCompareImm { .. } => {
+                    DataValue::from_int(ctrl_ty, imm() as u128).unwrap_or(DataValue::B(false))
+                }
+                _ => args[1],
+            }
+        };
+
+        let results = match opcode {
+            Opcode::Nop | Opcode::Fence => vec![],
+            Opcode::Copy | Opcode::Spill | Opcode::Fill | Opcode::Bextend | Opcode::Breduce => {
+                vec![args[0]]
+            }
+
+            Opcode::Iconst => vec![int(imm() as u128)?],
+            Opcode::F32const => match *data {
+                InstructionData::UnaryIeee32 { imm, .. } => {
+                    vec![DataValue::F32(f32::from_bits(imm.bits()))]
+                }
+                _ => return Err(unsupported()),
+            },
+            Opcode::F64const => match *data {
+                InstructionData::UnaryIeee64 { imm, .. } => {
+                    vec![DataValue::F64(f64::from_bits(imm.bits()))]
+                }
+                _ => return Err(unsupported()),
+            },
+            Opcode::Bconst => match *data {
+                InstructionData::UnaryBool { imm, .. } => vec![DataValue::B(imm)],
+                _ => return Err(unsupported()),
+            },
+
+            // Integer arithmetic.
+            Opcode::Iadd | Opcode::IaddImm => {
+                vec![int(args[0].as_u128().wrapping_add(rhs().as_u128()))?]
+            }
+            Opcode::Isub => vec![int(args[0].as_u128().wrapping_sub(args[1].as_u128()))?],
+            Opcode::IrsubImm => vec![int((imm() as u128).wrapping_sub(args[0].as_u128()))?],
+            Opcode::Imul | Opcode::ImulImm => {
+                vec![int(args[0].as_u128().wrapping_mul(rhs().as_u128()))?]
+            }
+            Opcode::Umulhi | Opcode::Smulhi if bits == 128 => return Err(unsupported()),
+            Opcode::Umulhi => vec![int((args[0].as_u128() * args[1].as_u128()) >> bits)?],
+            Opcode::Smulhi => vec![int(
+                ((args[0].as_i128() * args[1].as_i128()) >> bits) as u128
+            )?],
+            Opcode::Udiv | Opcode::UdivImm | Opcode::Urem | Opcode::UremImm => {
+                let (x, y) = (args[0].as_u128(), rhs().as_u128());
+                if y == 0 {
+                    return Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero));
+                }
+                match opcode {
+                    Opcode::Udiv | Opcode::UdivImm => vec![int(x / y)?],
+                    _ => vec![int(x % y)?],
+                }
+            }
+            Opcode::Sdiv | Opcode::SdivImm | Opcode::Srem | Opcode::SremImm => {
+                let (x, y) = (args[0].as_i128(), rhs().as_i128());
+                if y == 0 {
+                    return Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero));
+                }
+                let min = (1u128 << (bits - 1)).wrapping_neg() as i128;
+                let overflow = x == min && y == -1;
+                match opcode {
+                    Opcode::Sdiv | Opcode::SdivImm if overflow => {
+                        return Err(InterpreterError::Trap(TrapCode::IntegerOverflow))
+                    }
+                    Opcode::Sdiv | Opcode::SdivImm => vec![int(x.wrapping_div(y) as u128)?],
+                    _ if overflow => vec![int(0)?],
+                    _ => vec![int(x.wrapping_rem(y) as u128)?],
+                }
+            }
+
+            // Bitwise operations.
+            Opcode::Band | Opcode::BandImm => vec![bitwise(args[0], rhs(), ctrl_ty, |x, y| x & y)?],
+            Opcode::Bor | Opcode::BorImm => vec![bitwise(args[0], rhs(), ctrl_ty, |x, y| x | y)?],
+            Opcode::Bxor | Opcode::BxorImm => vec![bitwise(args[0], rhs(), ctrl_ty, |x, y| x ^ y)?],
+            Opcode::BandNot => vec![bitwise(args[0], args[1], ctrl_ty, |x, y| x & !y)?],
+            Opcode::BorNot => vec![bitwise(args[0], args[1], ctrl_ty, |x, y| x | !y)?],
+            Opcode::BxorNot => vec![bitwise(args[0], args[1], ctrl_ty, |x, y| x ^ !y)?],
+            Opcode::Bnot => vec![bitwise(args[0], args[0], ctrl_ty, |x, _| !x)?],
+
+            // Shifts and rotates. The shift amount is taken modulo the number of bits.
+            Opcode::Ishl
+            | Opcode::IshlImm
+            | Opcode::Ushr
+            | Opcode::UshrImm
+            | Opcode::Sshr
+            | Opcode::SshrImm
+            | Opcode::Rotl
+            | Opcode::RotlImm
+            | Opcode::Rotr
+            | Opcode::RotrImm => {
+                let amt = (rhs().as_u128() % u128::from(bits)) as u32;
+                let x = args[0].as_u128();
+                let rotl = |amt: u32| {
+                    if amt == 0 {
+                        x
+                    } else {
+                        (x << amt) | (x >> (bits - amt))
+                    }
+                };
+                vec![int(match opcode {
+                    Opcode::Ishl | Opcode::IshlImm => x << amt,
+                    Opcode::Ushr | Opcode::UshrImm => x >> amt,
+                    Opcode::Sshr | Opcode::SshrImm => (args[0].as_i128() >> amt) as u128,
+                    Opcode::Rotl | Opcode::RotlImm => rotl(amt),
+                    _ => rotl((bits - amt) % bits),
+                })?]
+            }
+
+            // Bit counting.
+            Opcode::Clz => vec![int(u128::from(
+                args[0].as_u128().leading_zeros() - (128 - bits),
+            ))?],
+            Opcode::Cls => {
+                let x = args[0].as_u128();
+                let x = if args[0].as_i128() < 0 {
+                    !x & mask(ctrl_ty.bits())
+                } else {
+                    x
+                };
+                vec![int(u128::from(x.leading_zeros() - (128 - bits) - 1))?]
+            }
+            Opcode::Ctz => {
+                let x = args[0].as_u128();
+                vec![int(u128::from(if x == 0 {
+                    bits
+                } else {
+                    x.trailing_zeros()
+                }))?]
+            }
+            Opcode::Popcnt => vec![int(u128::from(args[0].as_u128().count_ones()))?],
+            Opcode::Bitrev => vec![int(args[0].as_u128().reverse_bits() >> (128 - bits))?],
+
+            // Comparisons and selects.
+            Opcode::Icmp | Opcode::IcmpImm => {
+                let cond = match *data {
+                    InstructionData::IntCompare { cond, .. }
+                    | InstructionData::IntCompareImm { cond, .. } => cond,
+                    _ => return Err(unsupported()),
+                };
+                vec![DataValue::B(icmp(cond, args[0], rhs()))]
+            }
+            Opcode::Fcmp => {
+                let cond = match *data {
+                    InstructionData::FloatCompare { cond, .. } => cond,
+                    _ => return Err(unsupported()),
+                };
+                vec![DataValue::B(
+                    fcmp(cond, args[0], args[1]).ok_or_else(unsupported)?,
+                )]
+            }
+            Opcode::Select => vec![if args[0].is_true() { args[1] } else { args[2] }],
+
+            // Conversions.
+            Opcode::Bint => vec![
+                DataValue::from_int(result_ty, u128::from(args[0].is_true()))
+                    .ok_or_else(unsupported)?,
+            ],
+            Opcode::Bmask => {
+                let bits = if args[0].is_true() { !0 } else { 0 };
+                vec![DataValue::from_bits(result_ty, bits).ok_or_else(unsupported)?]
+            }
+            Opcode::Uextend | Opcode::Ireduce => {
+                vec![DataValue::from_int(result_ty, args[0].as_u128()).ok_or_else(unsupported)?]
+            }
+            Opcode::Sextend => {
+                vec![DataValue::from_int(result_ty, args[0].as_i128() as u128)
+                    .ok_or_else(unsupported)?]
+            }
+            Opcode::Iconcat => {
+                let half = u32::from(ctrl_ty.bits());
+                let x = args[0].as_u128() | (args[1].as_u128() << half);
+                vec![DataValue::from_int(result_ty, x).ok_or_else(unsupported)?]
+            }
+            Opcode::Isplit => {
+                let x = args[0].as_u128();
+                vec![
+                    DataValue::from_int(result_ty, x).ok_or_else(unsupported)?,
+                    DataValue::from_int(result_ty, x >> result_ty.bits())
+                        .ok_or_else(unsupported)?,
+                ]
+            }
+            Opcode::Bitcast | Opcode::RawBitcast => {
+                let arg_ty = dfg.value_type(dfg.inst_args(inst)[0]);
+                vec![DataValue::from_bits(result_ty, args[0].to_bits(arg_ty))
+                    .ok_or_else(unsupported)?]
+            }
+
+            // Floating point arithmetic.
+            Opcode::Fadd => vec![float_binary!(args, unsupported, |x, y| x + y)],
+            Opcode::Fsub => vec![float_binary!(args, unsupported, |x, y| x - y)],
+            Opcode::Fmul => vec![float_binary!(args, unsupported, |x, y| x * y)],
+            Opcode::Fdiv => vec![float_binary!(args, unsupported, |x, y| x / y)],
+            Opcode::Fcopysign => vec![float_binary!(args, unsupported, |x, y| x.copysign(y))],
+            Opcode::Fmin => vec![float_binary!(
+                args,
+                unsupported,
+                |x, y| if x.is_nan() || y.is_nan() {
+                    x + y
+                } else if x == y {
+                    if x.is_sign_negative() {
+                        x
+                    } else {
+                        y
+                    }
+                } else {
+                    x.min(y)
+                }
+            )],
+            Opcode::Fmax => vec![float_binary!(
+                args,
+                unsupported,
+                |x, y| if x.is_nan() || y.is_nan() {
+                    x + y
+                } else if x == y {
+                    if x.is_sign_positive() {
+                        x
+                    } else {
+                        y
+                    }
+                } else {
+                    x.max(y)
+                }
+            )],
+            Opcode::Fma => match (args[0], args[1], args[2]) {
+                (DataValue::F32(x), DataValue::F32(y), DataValue::F32(z)) => {
+                    vec![DataValue::F32(x.mul_add(y, z))]
+                }
+                (DataValue::F64(x), DataValue::F64(y), DataValue::F64(z)) => {
+                    vec![DataValue::F64(x.mul_add(y, z))]
+                }
+                _ => return Err(unsupported()),
+            },
+            Opcode::Sqrt => vec![float_unary!(args, unsupported, |x| x.sqrt())],
+            Opcode::Fneg => vec![float_unary!(args, unsupported, |x| -x)],
+            Opcode::Fabs => vec![float_unary!(args, unsupported, |x| x.abs())],
+            Opcode::Ceil => vec![float_unary!(args, unsupported, |x| x.ceil())],
+            Opcode::Floor => vec![float_unary!(args, unsupported, |x| x.floor())],
+            Opcode::Trunc => vec![float_unary!(args, unsupported, |x| x.trunc())],
+            Opcode::Nearest => vec![float_unary!(args, unsupported, |x| {
+                // Round to nearest, ties to even.
+                let r = x.round();
+                if (r - x).abs() == 0.5 {
+                    2.0 * (x / 2.0).round()
+                } else {
+                    r
+                }
+            })],
+
+            // Floating point conversions.
+            Opcode::Fpromote => match args[0] {
+                DataValue::F32(x) => vec![DataValue::F64(f64::from(x))],
+                _ => return Err(unsupported()),
+            },
+            Opcode::Fdemote => match args[0] {
+                DataValue::F64(x) => vec![DataValue::F32(x as f32)],
+                _ => return Err(unsupported()),
+            },
+            Opcode::FcvtFromSint | Opcode::FcvtFromUint => {
+                let signed = opcode == Opcode::FcvtFromSint;
+                vec![match result_ty {
+                    types::F32 if signed => DataValue::F32(args[0].as_i128() as f32),
+                    types::F32 => DataValue::F32(args[0].as_u128() as f32),
+                    types::F64 if signed => DataValue::F64(args[0].as_i128() as f64),
+                    types::F64 => DataValue::F64(args[0].as_u128() as f64),
+                    _ => return Err(unsupported()),
+                }]
+            }
+            Opcode::FcvtToSint
+            | Opcode::FcvtToUint
+            | Opcode::FcvtToSintSat
+            | Opcode::FcvtToUintSat => {
+                let x = match args[0] {
+                    DataValue::F32(x) => f64::from(x),
+                    DataValue::F64(x) => x,
+                    _ => return Err(unsupported()),
+                };
+                vec![fcvt_to_int(opcode, x, result_ty).ok_or_else(unsupported)??]
+            }
+
+            // Memory accesses.
+            Opcode::Load
+            | Opcode::LoadComplex
+            | Opcode::Uload8
+            | Opcode::Uload8Complex
+            | Opcode::Sload8
+            | Opcode::Sload8Complex
+            | Opcode::Uload16
+            | Opcode::Uload16Complex
+            | Opcode::Sload16
+            | Opcode::Sload16Complex
+            | Opcode::Uload32
+            | Opcode::Uload32Complex
+            | Opcode::Sload32
+            | Opcode::Sload32Complex
+            | Opcode::AtomicLoad => {
+                let addr = address(data, &args);
+                let size = match opcode {
+                    Opcode::Uload8 | Opcode::Uload8Complex => 1,
+                    Opcode::Sload8 | Opcode::Sload8Complex => 1,
+                    Opcode::Uload16 | Opcode::Uload16Complex => 2,
+                    Opcode::Sload16 | Opcode::Sload16Complex => 2,
+                    Opcode::Uload32 | Opcode::Uload32Complex => 4,
+                    Opcode::Sload32 | Opcode::Sload32Complex => 4,
+                    _ => result_ty.bytes() as usize,
+                };
+                let x = self.load(addr, size)?;
+                vec![match opcode {
+                    Opcode::Sload8
+                    | Opcode::Sload8Complex
+                    | Opcode::Sload16
+                    | Opcode::Sload16Complex
+                    | Opcode::Sload32
+                    | Opcode::Sload32Complex => {
+                        let shift = 128 - 8 * size as u32;
+                        DataValue::from_int(result_ty, (((x << shift) as i128) >> shift) as u128)
+                    }
+                    _ => DataValue::from_bits(result_ty, x),
+                }
+                .ok_or_else(unsupported)?]
+            }
+            Opcode::Store
+            | Opcode::StoreComplex
+            | Opcode::Istore8
+            | Opcode::Istore8Complex
+            | Opcode::Istore16
+            | Opcode::Istore16Complex
+            | Opcode::Istore32
+            | Opcode::Istore32Complex
+            | Opcode::AtomicStore => {
+                let addr = address(data, &args[1..]);
+                let size = match opcode {
+                    Opcode::Istore8 | Opcode::Istore8Complex => 1,
+                    Opcode::Istore16 | Opcode::Istore16Complex => 2,
+                    Opcode::Istore32 | Opcode::Istore32Complex => 4,
+                    _ => ctrl_ty.bytes() as usize,
+                };
+                self.store(addr, size, args[0].to_bits(ctrl_ty))?;
+                vec![]
+            }
+            Opcode::AtomicRmw => {
+                let op = match *data {
+                    InstructionData::AtomicRmw { op, .. } => op,
+                    _ => return Err(unsupported()),
+                };
+                let (addr, size) = (args[0].as_u128() as u64, ctrl_ty.bytes() as usize);
+                let old = self.load(addr, size)?;
+                let x = args[1].as_u128();
+                let new = match op {
+                    AtomicRmwOp::Add => old.wrapping_add(x),
+                    AtomicRmwOp::Sub => old.wrapping_sub(x),
+                    AtomicRmwOp::And => old & x,
+                    AtomicRmwOp::Or => old | x,
+                    AtomicRmwOp::Xor => old ^ x,
+                    AtomicRmwOp::Xchg => x,
+                };
+                self.store(addr, size, new)?;
+                vec![int(old)?]
+            }
+            Opcode::AtomicCas => {
+                let (addr, size) = (args[0].as_u128() as u64, ctrl_ty.bytes() as usize);
+                let old = self.load(addr, size)?;
+                if old == args[1].as_u128() {
+                    self.store(addr, size, args[2].as_u128())?;
+                }
+                vec![int(old)?]
+            }
+            Opcode::StackLoad | Opcode::StackStore => {
+                let (slot, offset) = match *data {
+                    InstructionData::StackLoad {
+                        stack_slot, offset, ..
+                    }
+                    | InstructionData::StackStore {
+                        stack_slot, offset, ..
+                    } => (stack_slot, offset),
+                    _ => return Err(unsupported()),
+                };
+                let bytes = &mut frame.stack_slots[slot.index()];
+                let offset: i64 = offset.into();
+                let ty = if opcode == Opcode::StackLoad {
+                    result_ty
+                } else {
+                    ctrl_ty
+                };
+                let size = ty.bytes() as usize;
+                if offset < 0 || offset as usize + size > bytes.len() {
+                    return Err(InterpreterError::Trap(TrapCode::HeapOutOfBounds));
+                }
+                let bytes = &mut bytes[offset as usize..offset as usize + size];
+                if opcode == Opcode::StackLoad {
+                    vec![DataValue::from_bits(ty, from_le_bytes(bytes)).ok_or_else(unsupported)?]
+                } else {
+                    bytes.copy_from_slice(&args[0].to_bits(ty).to_le_bytes()[..size]);
+                    vec![]
+                }
+            }
+            Opcode::GlobalValue => match *data {
+                InstructionData::UnaryGlobalValue { global_value, .. } => {
+                    vec![self.global_value(frame, global_value)?]
+                }
+                _ => return Err(unsupported()),
+            },
+            Opcode::HeapAddr => {
+                let (heap, size) = match *data {
+                    InstructionData::HeapAddr { heap, imm, .. } => {
+                        let size: u32 = imm.into();
+                        (heap, u128::from(size))
+                    }
+                    _ => return Err(unsupported()),
+                };
+                let heap = &func.heaps[heap];
+                let base = self.global_value(frame, heap.base)?.as_u128();
+                let bound = match heap.style {
+                    ir::HeapStyle::Static { bound } => u128::from(Into::<u64>::into(bound)),
+                    ir::HeapStyle::Dynamic { bound_gv } => {
+                        self.global_value(frame, bound_gv)?.as_u128()
+                    }
+                };
+                let index = args[0].as_u128();
+                if index + size > bound {
+                    return Err(InterpreterError::Trap(TrapCode::HeapOutOfBounds));
+                }
+                vec![DataValue::from_int(result_ty, base + index).ok_or_else(unsupported)?]
+            }
+
+            Opcode::Call => {
+                let func_ref = match *data {
+                    InstructionData::Call { func_ref, .. } => func_ref,
+                    _ => return Err(unsupported()),
+                };
+                let callee = self.lookup(&dfg.ext_funcs[func_ref].name)?;
+                let results = self.run(callee, &args)?;
+                check_values(&callee.signature.returns, &results)?;
+                results
+            }
+
+            _ => return Err(unsupported()),
+        };
+
+        let result_values = dfg.inst_results(inst);
+        debug_assert_eq!(results.len(), result_values.len());
+        for (&value, &data) in result_values.iter().zip(&results) {
+            frame.set(value, data);
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Execute `inst` if it is a branch, a terminator, or a trap.
+    fn step_control(
+        &mut self,
+        frame: &Frame,
+        inst: Inst,
+    ) -> InterpreterResult<Option<ControlFlow>> {
+        let func = frame.func;
+        let dfg = &func.dfg;
+        let data = &dfg[inst];
+        let args = dfg.inst_args(inst);
+        let jump = |dest: Ebb, args: &[Value]| -> InterpreterResult<Option<ControlFlow>> {
+            Ok(Some(ControlFlow::Jump(dest, frame.get_all(args)?)))
+        };
+
+        Ok(match *data {
+            InstructionData::Jump {
+                destination,
+                ref args,
+                ..
+            } => jump(destination, args.as_slice(&dfg.value_lists))?,
+            InstructionData::Branch {
+                opcode,
+                destination,
+                ..
+            } => {
+                let cond = frame.get(args[0])?.is_true();
+                if cond == (opcode == Opcode::Brnz) {
+                    jump(destination, &args[1..])?
+                } else {
+                    Some(ControlFlow::Continue)
+                }
+            }
+            InstructionData::BranchIcmp {
+                cond, destination, ..
+            } => {
+                if icmp(cond, frame.get(args[0])?, frame.get(args[1])?) {
+                    jump(destination, &args[2..])?
+                } else {
+                    Some(ControlFlow::Continue)
+                }
+            }
+            InstructionData::BranchTable {
+                arg,
+                destination,
+                table,
+                ..
+            } => {
+                let index = frame.get(arg)?.as_u128();
+                let entries = func.jump_tables[table].as_slice();
+                match entries.get(index as usize) {
+                    Some(&dest) if index < entries.len() as u128 => jump(dest, &[])?,
+                    _ => jump(destination, &[])?,
+                }
+            }
+            InstructionData::MultiAry { opcode, .. }
+                if opcode == Opcode::Return || opcode == Opcode::FallthroughReturn =>
+            {
+                let values = frame.get_all(args)?;
+                check_values(&func.signature.returns, &values)?;
+                Some(ControlFlow::Return(values))
+            }
+            InstructionData::Trap { code, .. } => {
+                return Err(InterpreterError::Trap(code));
+            }
+            InstructionData::CondTrap { opcode, arg, code } => {
+                if frame.get(arg)?.is_true() == (opcode == Opcode::Trapnz) {
+                    return Err(InterpreterError::Trap(code));
+                }
+                Some(ControlFlow::Continue)
+            }
+            _ => None,
+        })
+    }
+
+    /// Compute the value of a global value.
+    fn global_value(&mut self, frame: &Frame, gv: GlobalValue) -> InterpreterResult<DataValue> {
+        let func = frame.func;
+        match func.global_values[gv] {
+            GlobalValueData::VMContext => func
+                .special_param(ArgumentPurpose::VMContext)
+                .ok_or_else(|| InterpreterError::BadArguments("missing vmctx".to_string()))
+                .and_then(|v| frame.get(v)),
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => {
+                let base = self.global_value(frame, base)?.as_u128();
+                let offset: i64 = offset.into();
+                Ok(
+                    DataValue::from_int(global_type, base.wrapping_add(offset as u128))
+                        .expect("integer global value"),
+                )
+            }
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                ..
+            } => {
+                let base = self.global_value(frame, base)?.as_u128() as u64;
+                let offset: i64 = offset.into();
+                let x = self.load(
+                    base.wrapping_add(offset as u64),
+                    global_type.bytes() as usize,
+                )?;
+                Ok(DataValue::from_int(global_type, x).expect("integer global value"))
+            }
+            GlobalValueData::Symbol { ref name, .. } => {
+                Err(InterpreterError::Unsupported(format!("symbol {}", name)))
+            }
+        }
+    }
+
+    /// Load `size` bytes at `addr` from memory.
+    fn load(&mut self, addr: u64, size: usize) -> InterpreterResult<u128> {
+        let mut buf = [0; 16];
+        self.memory
+            .load(addr, &mut buf[..size])
+            .map_err(InterpreterError::Trap)?;
+        Ok(from_le_bytes(&buf[..size]))
+    }
+
+    /// Store the `size` low bytes of `bits` at `addr` in memory.
+    fn store(&mut self, addr: u64, size: usize, bits: u128) -> InterpreterResult<()> {
+        self.memory
+            .store(addr, &bits.to_le_bytes()[..size])
+            .map_err(InterpreterError::Trap)
+    }
+}
+
+/// Check that `values` match the types of the parameters or returns in `abi`.
+fn check_values(abi: &[ir::AbiParam], values: &[DataValue]) -> InterpreterResult<()> {
+    if abi.len() != values.len() {
+        return Err(InterpreterError::BadArguments(format!(
+            "expected {} values, got {}",
+            abi.len(),
+            values.len()
+        )));
+    }
+    for (param, value) in abi.iter().zip(values) {
+        if !value.is_compatible(param.value_type) {
+            return Err(InterpreterError::BadArguments(format!(
+                "expected {}, got {}",
+                param.value_type, value
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Compute the effective address of a load or store from its address arguments and offset.
+fn address(data: &InstructionData, args: &[DataValue]) -> u64 {
+    let offset: i64 = match *data {
+        InstructionData::Load { offset, .. }
+        | InstructionData::LoadComplex { offset, .. }
+        | InstructionData::Store { offset, .. }
+        | InstructionData::StoreComplex { offset, .. } => offset.into(),
+        _ => 0,
+    };
+    args.iter().fold(offset as u64, |addr, arg| {
+        addr.wrapping_add(arg.as_u128() as u64)
+    })
+}
+
+/// Read a little-endian integer from up to 16 bytes.
+fn from_le_bytes(bytes: &[u8]) -> u128 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |x, &byte| (x << 8) | u128::from(byte))
+}
+
+/// Apply a bitwise operation to integers or booleans.
+fn bitwise(
+    x: DataValue,
+    y: DataValue,
+    ty: Type,
+    op: fn(u128, u128) -> u128,
+) -> InterpreterResult<DataValue> {
+    let bits = op(x.to_bits(ty), y.to_bits(ty)) & mask(ty.bits());
+    DataValue::from_bits(ty, bits)
+        .ok_or_else(|| InterpreterError::Unsupported(format!("bitwise operation on {}", ty)))
+}
+
+/// Compare two integers.
+fn icmp(cond: IntCC, x: DataValue, y: DataValue) -> bool {
+    let (ux, uy) = (x.as_u128(), y.as_u128());
+    let (sx, sy) = (x.as_i128(), y.as_i128());
+    match cond {
+        IntCC::Equal => ux == uy,
+        IntCC::NotEqual => ux != uy,
+        IntCC::SignedLessThan => sx < sy,
+        IntCC::SignedGreaterThanOrEqual => sx >= sy,
+        IntCC::SignedGreaterThan => sx > sy,
+        IntCC::SignedLessThanOrEqual => sx <= sy,
+        IntCC::UnsignedLessThan => ux < uy,
+        IntCC::UnsignedGreaterThanOrEqual => ux >= uy,
+        IntCC::UnsignedGreaterThan => ux > uy,
+        IntCC::UnsignedLessThanOrEqual => ux <= uy,
+    }
+}
+
+/// Compare two floats.
+fn fcmp(cond: FloatCC, x: DataValue, y: DataValue) -> Option<bool> {
+    let (x, y) = match (x, y) {
+        (DataValue::F32(x), DataValue::F32(y)) => (f64::from(x), f64::from(y)),
+        (DataValue::F64(x), DataValue::F64(y)) => (x, y),
+        _ => return None,
+    };
+    let unordered = x.is_nan() || y.is_nan();
+    Some(match cond {
+        FloatCC::Ordered => !unordered,
+        FloatCC::Unordered => unordered,
+        FloatCC::Equal => x == y,
+        FloatCC::NotEqual => x != y,
+        FloatCC::OrderedNotEqual => !unordered && x != y,
+        FloatCC::UnorderedOrEqual => unordered || x == y,
+        FloatCC::LessThan => x < y,
+        FloatCC::LessThanOrEqual => x <= y,
+        FloatCC::GreaterThan => x > y,
+        FloatCC::GreaterThanOrEqual => x >= y,
+        FloatCC::UnorderedOrLessThan => unordered || x < y,
+        FloatCC::UnorderedOrLessThanOrEqual => unordered || x <= y,
+        FloatCC::UnorderedOrGreaterThan => unordered || x > y,
+        FloatCC::UnorderedOrGreaterThanOrEqual => unordered || x >= y,
+    })
+}
+
+/// Convert `x` to an integer of type `ty` as the `fcvt_to_*` instruction `opcode` does.
+///
+/// Returns `None` if `ty` isn't a supported integer type, and a trap if the conversion traps.
+fn fcvt_to_int(opcode: Opcode, x: f64, ty: Type) -> Option<InterpreterResult<DataValue>> {
+    let bits = u32::from(ty.bits());
+    if !ty.is_int() || bits > 64 {
+        return None;
+    }
+    let signed = opcode == Opcode::FcvtToSint || opcode == Opcode::FcvtToSintSat;
+    let saturating = opcode == Opcode::FcvtToSintSat || opcode == Opcode::FcvtToUintSat;
+
+    // The integers in range are `min <= x < max`.
+    let (min, max) = if signed {
+        let half = (1u64 << (bits - 1)) as f64;
+        (-half, half)
+    } else {
+        (0.0, 2.0 * (1u64 << (bits - 1)) as f64)
+    };
+    let x = x.trunc();
+    let value = if x.is_nan() {
+        if !saturating {
+            return Some(Err(InterpreterError::Trap(
+                TrapCode::BadConversionToInteger,
+            )));
+        }
+        0
+    } else if x < min || x >= max {
+        if !saturating {
+            return Some(Err(InterpreterError::Trap(TrapCode::IntegerOverflow)));
+        }
+        match (signed, x < min) {
+            (true, true) => (-(1i128 << (bits - 1))) as u128,
+            (true, false) => (1u128 << (bits - 1)) - 1,
+            (false, true) => 0,
+            (false, false) => mask(ty.bits()),
+        }
+    } else if signed {
+        x as i64 as u128
+    } else {
+        x as u64 as u128
+    };
+    DataValue::from_int(ty, value).map(Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{LinearMemory, NoMemory};
+    use cranelift_reader::parse_functions;
+
+    fn parse(text: &str) -> Function {
+        parse_functions(text).unwrap().into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let func = parse(
+            "function %f(i32, i32) -> i32, i8 {
+             ebb0(v0: i32, v1: i32):
+                 v2 = imul v0, v1
+                 v3 = iadd_imm v2, -1
+                 v4 = ireduce.i8 v3
+                 v5 = sshr_imm v4, 1
+                 return v3, v5
+             }",
+        );
+        let mut memory = NoMemory;
+        let mut interp = Interpreter::new(&mut memory);
+        assert_eq!(
+            interp.call(&func, &[DataValue::I32(7), DataValue::I32(-3)]),
+            Ok(vec![DataValue::I32(-22), DataValue::I8(-11)])
+        );
+        assert_eq!(
+            interp.call(&func, &[DataValue::I32(7)]),
+            Err(InterpreterError::BadArguments(
+                "expected 2 values, got 1".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn loops_and_calls() {
+        let funcs = parse_functions(
+            "function %fib(i64) -> i64 {
+                 fn0 = %add(i64, i64) -> i64
+             ebb0(v0: i64):
+                 v1 = iconst.i64 0
+                 v2 = iconst.i64 1
+                 jump ebb1(v0, v1, v2)
+             ebb1(v3: i64, v4: i64, v5: i64):
+                 brz v3, ebb2
+                 v6 = call fn0(v4, v5)
+                 v7 = iadd_imm v3, -1
+                 jump ebb1(v7, v5, v6)
+             ebb2:
+                 return v4
+             }
+
+             function %add(i64, i64) -> i64 {
+             ebb0(v0: i64, v1: i64):
+                 v2 = iadd v0, v1
+                 return v2
+             }",
+        )
+        .unwrap();
+        let mut memory = NoMemory;
+        let mut interp = Interpreter::new(&mut memory);
+        interp.add_function(&funcs[1]);
+        assert_eq!(
+            interp.call(&funcs[0], &[DataValue::I64(50)]),
+            Ok(vec![DataValue::I64(12_586_269_025)])
+        );
+
+        interp.set_step_limit(Some(100));
+        assert_eq!(
+            interp.call(&funcs[0], &[DataValue::I64(50)]),
+            Err(InterpreterError::StepLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn traps() {
+        let func = parse(
+            "function %f(i32, i32) -> i32 {
+             ebb0(v0: i32, v1: i32):
+                 v2 = sdiv v0, v1
+                 return v2
+             }",
+        );
+        let mut memory = NoMemory;
+        let mut interp = Interpreter::new(&mut memory);
+        assert_eq!(
+            interp.call(&func, &[DataValue::I32(1), DataValue::I32(0)]),
+            Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero))
+        );
+        assert_eq!(
+            interp.call(
+                &func,
+                &[DataValue::I32(i32::min_value()), DataValue::I32(-1)]
+            ),
+            Err(InterpreterError::Trap(TrapCode::IntegerOverflow))
+        );
+    }
+
+    #[test]
+    fn invalid_functions() {
+        // `v1` doesn't dominate its use, so it is undefined when coming from `ebb0`.
+        let func = parse(
+            "function %f(i32) -> i32 {
+             ebb0(v0: i32):
+                 brz v0, ebb1
+                 jump ebb2
+             ebb2:
+                 v1 = iconst.i32 1
+                 jump ebb1
+             ebb1:
+                 return v1
+             }",
+        );
+        let mut memory = NoMemory;
+        let mut interp = Interpreter::new(&mut memory);
+        assert_eq!(
+            interp.call(&func, &[DataValue::I32(1)]),
+            Ok(vec![DataValue::I32(1)])
+        );
+        assert_eq!(
+            interp.call(&func, &[DataValue::I32(0)]),
+            Err(InterpreterError::UndefinedValue(Value::new(1)))
+        );
+
+        // A float operation on values that aren't floats of the same type.
+        let func = parse(
+            "function %f(f32, f64) -> f32 {
+             ebb0(v0: f32, v1: f64):
+                 v2 = fadd v0, v1
+                 return v2
+             }",
+        );
+        assert_eq!(
+            interp.call(&func, &[DataValue::F32(1.0), DataValue::F64(2.0)]),
+            Err(InterpreterError::Unsupported(
+                "v2 = fadd.f32 v0, v1".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn heap() {
+        let func = parse(
+            "function %f(i32, i64 vmctx) -> i32 {
+                 gv0 = vmctx
+                 heap0 = static gv0, min 0x10, bound 0x10, offset_guard 0, index_type i32
+             ebb0(v0: i32, v1: i64):
+                 v2 = heap_addr.i64 heap0, v0, 4
+                 v3 = load.i32 v2
+                 v4 = iadd_imm v3, 1
+                 store v4, v2
+                 return v3
+             }",
+        );
+        let mut memory = LinearMemory::new(0x1000, 0x10);
+        memory.bytes_mut()[8] = 41;
+        {
+            let mut interp = Interpreter::new(&mut memory);
+            let vmctx = DataValue::I64(0x1000);
+            assert_eq!(
+                interp.call(&func, &[DataValue::I32(8), vmctx]),
+                Ok(vec![DataValue::I32(41)])
+            );
+            assert_eq!(
+                interp.call(&func, &[DataValue::I32(13), vmctx]),
+                Err(InterpreterError::Trap(TrapCode::HeapOutOfBounds))
+            );
+        }
+        assert_eq!(memory.bytes()[8], 42);
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(
+            fcvt_to_int(Opcode::FcvtToSint, -2.5, types::I32),
+            Some(Ok(DataValue::I32(-2)))
+        );
+        assert_eq!(
+            fcvt_to_int(Opcode::FcvtToUint, 4294967296.0, types::I32),
+            Some(Err(InterpreterError::Trap(TrapCode::IntegerOverflow)))
+        );
+        assert_eq!(
+            fcvt_to_int(Opcode::FcvtToUintSat, 4294967296.0, types::I32),
+            Some(Ok(DataValue::I32(-1)))
+        );
+        assert_eq!(
+            fcvt_to_int(Opcode::FcvtToSintSat, core::f64::NAN, types::I64),
+            Some(Ok(DataValue::I64(0)))
+        );
+        assert_eq!(
+            fcvt_to_int(Opcode::FcvtToSint, core::f64::NAN, types::I64),
+            Some(Err(InterpreterError::Trap(
+                TrapCode::BadConversionToInteger
+            )))
+        );
+    }
+}
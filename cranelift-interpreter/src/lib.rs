@@ -0,0 +1,38 @@
+//! Interpreter for Cranelift IR.
+//!
+//! The interpreter executes `ir::Function`s directly, which provides a reference for the semantics
+//! of the IR. File tests use it to check the results of functions without a native backend, and
+//! fuzzers use it to compare the behavior of a function before and after optimization.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::new_without_default))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        clippy::mut_mut,
+        clippy::nonminimal_bool,
+        clippy::option_map_unwrap_or,
+        clippy::option_map_unwrap_or_else,
+        clippy::print_stdout,
+        clippy::unicode_not_nfc,
+        clippy::use_self
+    )
+)]
+
+mod interpreter;
+mod memory;
+mod value;
+
+pub use crate::interpreter::{Interpreter, InterpreterError, InterpreterResult};
+pub use crate::memory::{LinearMemory, Memory, NoMemory};
+pub use crate::value::DataValue;
+
+/// Version number of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,120 @@
+//! Memory accessed by interpreted functions.
+//!
+//! The interpreter doesn't know how the memory of the program it runs is laid out. All loads and
+//! stores through addresses are forwarded to an object implementing the `Memory` trait, which is
+//! provided by the embedder.
+
+use cranelift_codegen::ir::TrapCode;
+
+/// The memory that interpreted functions load from and store to.
+///
+/// Multi-byte values are passed in little-endian byte order.
+pub trait Memory {
+    /// Read `buf.len()` bytes from the memory at `addr`.
+    ///
+    /// Returns the trap code to report if the memory is not accessible.
+    fn load(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), TrapCode>;
+
+    /// Write `bytes` to the memory at `addr`.
+    ///
+    /// Returns the trap code to report if the memory is not accessible.
+    fn store(&mut self, addr: u64, bytes: &[u8]) -> Result<(), TrapCode>;
+}
+
+/// A memory where no address is accessible.
+///
+/// This is enough for functions that don't access memory, or only access their own stack slots.
+pub struct NoMemory;
+
+impl Memory for NoMemory {
+    fn load(&mut self, _addr: u64, _buf: &mut [u8]) -> Result<(), TrapCode> {
+        Err(TrapCode::HeapOutOfBounds)
+    }
+
+    fn store(&mut self, _addr: u64, _bytes: &[u8]) -> Result<(), TrapCode> {
+        Err(TrapCode::HeapOutOfBounds)
+    }
+}
+
+/// A single contiguous range of accessible memory, starting at a base address.
+pub struct LinearMemory {
+    base: u64,
+    bytes: Vec<u8>,
+}
+
+impl LinearMemory {
+    /// Create a zero-filled memory of `size` bytes mapped at `base`.
+    pub fn new(base: u64, size: usize) -> Self {
+        Self {
+            base,
+            bytes: vec![0; size],
+        }
+    }
+
+    /// Get the address of the first byte of the memory.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Get the contents of the memory.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Get the mutable contents of the memory.
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    /// Get the offset range of `len` bytes at `addr`, if they are all inside the memory.
+    fn range(&self, addr: u64, len: usize) -> Option<(usize, usize)> {
+        let start = addr.checked_sub(self.base)?;
+        let end = start.checked_add(len as u64)?;
+        if end > self.bytes.len() as u64 {
+            return None;
+        }
+        Some((start as usize, end as usize))
+    }
+}
+
+impl Memory for LinearMemory {
+    fn load(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), TrapCode> {
+        let (start, end) = self
+            .range(addr, buf.len())
+            .ok_or(TrapCode::HeapOutOfBounds)?;
+        buf.copy_from_slice(&self.bytes[start..end]);
+        Ok(())
+    }
+
+    fn store(&mut self, addr: u64, bytes: &[u8]) -> Result<(), TrapCode> {
+        let (start, end) = self
+            .range(addr, bytes.len())
+            .ok_or(TrapCode::HeapOutOfBounds)?;
+        self.bytes[start..end].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_bounds() {
+        let mut mem = LinearMemory::new(0x1000, 16);
+        assert_eq!(mem.store(0x100c, &[1, 2, 3, 4]), Ok(()));
+        assert_eq!(
+            mem.store(0x100d, &[1, 2, 3, 4]),
+            Err(TrapCode::HeapOutOfBounds)
+        );
+        assert_eq!(mem.store(0xfff, &[1]), Err(TrapCode::HeapOutOfBounds));
+        assert_eq!(
+            mem.store(u64::max_value(), &[1, 2]),
+            Err(TrapCode::HeapOutOfBounds)
+        );
+
+        let mut buf = [0; 2];
+        assert_eq!(mem.load(0x100d, &mut buf), Ok(()));
+        assert_eq!(buf, [2, 3]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Values manipulated by the interpreter.
+
+use core::fmt::{self, Display, Formatter};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{types, Type};
+
+/// The value of an SSA value at run time.
+///
+/// Boolean values of any width are represented by `B`. Integers are stored in a variant matching
+/// their type, and the arithmetic on them is performed by the interpreter with wrapping semantics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataValue {
+    /// A boolean value.
+    B(bool),
+    /// An `i8` value.
+    I8(i8),
+    /// An `i16` value.
+    I16(i16),
+    /// An `i32` value.
+    I32(i32),
+    /// An `i64` value.
+    I64(i64),
+    /// An `i128` value.
+    I128(i128),
+    /// An `f32` value.
+    F32(f32),
+    /// An `f64` value.
+    F64(f64),
+}
+
+impl DataValue {
+    /// Create an integer value of type `ty` from the low bits of `bits`.
+    ///
+    /// Returns `None` if `ty` is not a scalar integer type.
+    pub fn from_int(ty: Type, bits: u128) -> Option<Self> {
+        Some(match ty {
+            types::I8 => DataValue::I8(bits as i8),
+            types::I16 => DataValue::I16(bits as i16),
+            types::I32 => DataValue::I32(bits as i32),
+            types::I64 => DataValue::I64(bits as i64),
+            types::I128 => DataValue::I128(bits as i128),
+            _ => return None,
+        })
+    }
+
+    /// Create a value of type `ty` from its in-memory bit pattern.
+    ///
+    /// Booleans are true when any bit is set. Returns `None` for vector and special types.
+    pub fn from_bits(ty: Type, bits: u128) -> Option<Self> {
+        if ty.is_bool() && !ty.is_vector() {
+            return Some(DataValue::B(bits != 0));
+        }
+        match ty {
+            types::F32 => Some(DataValue::F32(f32::from_bits(bits as u32))),
+            types::F64 => Some(DataValue::F64(f64::from_bits(bits as u64))),
+            _ => Self::from_int(ty, bits),
+        }
+    }
+
+    /// Get the bit pattern of this value, zero-extended to 128 bits.
+    ///
+    /// A true boolean of type `ty` has all of its bits set, as when it is stored in memory.
+    pub fn to_bits(self, ty: Type) -> u128 {
+        match self {
+            DataValue::B(b) => {
+                if b {
+                    mask(ty.bits())
+                } else {
+                    0
+                }
+            }
+            DataValue::F32(x) => u128::from(x.to_bits()),
+            DataValue::F64(x) => u128::from(x.to_bits()),
+            _ => self.as_u128(),
+        }
+    }
+
+    /// Get an integer value zero-extended to 128 bits.
+    ///
+    /// Booleans are 0 or 1, and floating point values are reinterpreted as their bit pattern.
+    pub fn as_u128(self) -> u128 {
+        match self {
+            DataValue::B(b) => u128::from(b),
+            DataValue::I8(x) => u128::from(x as u8),
+            DataValue::I16(x) => u128::from(x as u16),
+            DataValue::I32(x) => u128::from(x as u32),
+            DataValue::I64(x) => u128::from(x as u64),
+            DataValue::I128(x) => x as u128,
+            DataValue::F32(x) => u128::from(x.to_bits()),
+            DataValue::F64(x) => u128::from(x.to_bits()),
+        }
+    }
+
+    /// Get an integer value sign-extended to 128 bits.
+    pub fn as_i128(self) -> i128 {
+        match self {
+            DataValue::B(b) => i128::from(b),
+            DataValue::I8(x) => i128::from(x),
+            DataValue::I16(x) => i128::from(x),
+            DataValue::I32(x) => i128::from(x),
+            DataValue::I64(x) => i128::from(x),
+            DataValue::I128(x) => x,
+            DataValue::F32(x) => i128::from(x.to_bits() as i32),
+            DataValue::F64(x) => i128::from(x.to_bits() as i64),
+        }
+    }
+
+    /// Is this a true boolean, or a non-zero integer?
+    pub fn is_true(self) -> bool {
+        match self {
+            DataValue::B(b) => b,
+            _ => self.as_u128() != 0,
+        }
+    }
+
+    /// Get the type of this value, using `B1` for booleans.
+    pub fn ty(self) -> Type {
+        match self {
+            DataValue::B(_) => types::B1,
+            DataValue::I8(_) => types::I8,
+            DataValue::I16(_) => types::I16,
+            DataValue::I32(_) => types::I32,
+            DataValue::I64(_) => types::I64,
+            DataValue::I128(_) => types::I128,
+            DataValue::F32(_) => types::F32,
+            DataValue::F64(_) => types::F64,
+        }
+    }
+
+    /// Can this value be assigned to an SSA value of type `ty`?
+    pub fn is_compatible(self, ty: Type) -> bool {
+        match self {
+            DataValue::B(_) => ty.is_bool() && !ty.is_vector(),
+            _ => self.ty() == ty,
+        }
+    }
+}
+
+/// Get a mask of the `bits` low bits.
+pub(crate) fn mask(bits: u16) -> u128 {
+    if bits >= 128 {
+        !0
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+impl Display for DataValue {
+    /// Format the value with the same syntax as the immediates of the text format.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            DataValue::B(b) => write!(f, "{}", b),
+            DataValue::I8(x) => write!(f, "{}", x),
+            DataValue::I16(x) => write!(f, "{}", x),
+            DataValue::I32(x) => write!(f, "{}", x),
+            DataValue::I64(x) => write!(f, "{}", x),
+            DataValue::I128(x) => write!(f, "{}", x),
+            DataValue::F32(x) => write!(f, "{}", Ieee32::with_bits(x.to_bits())),
+            DataValue::F64(x) => write!(f, "{}", Ieee64::with_bits(x.to_bits())),
+        }
+    }
+}
+
+impl From<bool> for DataValue {
+    fn from(b: bool) -> Self {
+        DataValue::B(b)
+    }
+}
+
+impl From<i8> for DataValue {
+    fn from(x: i8) -> Self {
+        DataValue::I8(x)
+    }
+}
+
+impl From<i16> for DataValue {
+    fn from(x: i16) -> Self {
+        DataValue::I16(x)
+    }
+}
+
+impl From<i32> for DataValue {
+    fn from(x: i32) -> Self {
+        DataValue::I32(x)
+    }
+}
+
+impl From<i64> for DataValue {
+    fn from(x: i64) -> Self {
+        DataValue::I64(x)
+    }
+}
+
+impl From<i128> for DataValue {
+    fn from(x: i128) -> Self {
+        DataValue::I128(x)
+    }
+}
+
+impl From<f32> for DataValue {
+    fn from(x: f32) -> Self {
+        DataValue::F32(x)
+    }
+}
+
+impl From<f64> for DataValue {
+    fn from(x: f64) -> Self {
+        DataValue::F64(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(
+            DataValue::from_int(types::I8, 0x1ff),
+            Some(DataValue::I8(-1))
+        );
+        assert_eq!(DataValue::I8(-1).as_u128(), 0xff);
+        assert_eq!(DataValue::I8(-1).as_i128(), -1);
+        assert_eq!(DataValue::B(true).to_bits(types::B32), 0xffff_ffff);
+        assert_eq!(
+            DataValue::from_bits(types::F32, 0x3f80_0000),
+            Some(DataValue::F32(1.0))
+        );
+        assert_eq!(DataValue::from_bits(types::IFLAGS, 0), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(DataValue::I32(-7).to_string(), "-7");
+        assert_eq!(DataValue::F64(1.5).to_string(), "0x1.8000000000000p0");
+        assert_eq!(DataValue::B(false).to_string(), "false");
+    }
+}
@@ -1,7 +1,12 @@
 //! Defines the `Backend` trait.
 
 use crate::DataContext;
+use crate::DataStorage;
+use crate::FuncId;
+#[cfg(feature = "std")]
+use crate::LazyHandle;
 use crate::Linkage;
+use crate::ModuleError;
 use crate::ModuleNamespace;
 use crate::ModuleResult;
 use core::marker;
@@ -59,7 +64,21 @@ where
     fn declare_function(&mut self, name: &str, linkage: Linkage);
 
     /// Declare a data object.
-    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, align: Option<u8>);
+    fn declare_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+        storage: DataStorage,
+        align: Option<u8>,
+    );
+
+    /// Place the function `name`, which is about to be defined, in the custom section `section`.
+    ///
+    /// Backends which have no sections ignore this, which is the default.
+    fn set_function_section(&mut self, _name: &str, _section: &str) -> ModuleResult<()> {
+        Ok(())
+    }
 
     /// Define a function, producing the function body from the given `Context`.
     ///
@@ -72,6 +91,52 @@ where
         code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction>;
 
+    /// Define a new body for a function that has already been defined as `old`.
+    ///
+    /// Once the new body is finalized and published, calls to the function, including through
+    /// addresses finalized before, must reach the new body. Backends that can't replace code
+    /// return an error, which is the default.
+    fn redefine_function(
+        &mut self,
+        name: &str,
+        _ctx: &Context,
+        _namespace: &ModuleNamespace<Self>,
+        _code_size: u32,
+        _old: &Self::CompiledFunction,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        Err(ModuleError::Backend(format!(
+            "redefining function {} is not supported",
+            name
+        )))
+    }
+
+    /// Free the memory of the function bodies replaced by `redefine_function` which have been
+    /// published.
+    ///
+    /// The caller guarantees that no thread is still executing the replaced bodies. Backends
+    /// which don't replace code have nothing to free, which is the default.
+    unsafe fn free_replaced_functions(&mut self) {}
+
+    /// Define a stub for the function `id`, whose body is compiled when it is first called.
+    ///
+    /// When the stub is called, it calls `handle.callback()` with `handle.data()` and `id`, which
+    /// redefines the function with `redefine_function` and finalizes it, and then continues into
+    /// the new body with the original arguments. The backend must keep a clone of `handle` alive
+    /// for as long as the stub may be called. Backends that don't support lazy compilation
+    /// return an error, which is the default.
+    #[cfg(feature = "std")]
+    fn define_function_stub(
+        &mut self,
+        name: &str,
+        _id: FuncId,
+        _handle: &LazyHandle,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        Err(ModuleError::Backend(format!(
+            "lazily compiling function {} is not supported",
+            name
+        )))
+    }
+
     /// Define a zero-initialized data object of the given size.
     ///
     /// Data objects must be declared before being defined.
@@ -79,6 +144,7 @@ where
         &mut self,
         name: &str,
         writable: bool,
+        storage: DataStorage,
         align: Option<u8>,
         data_ctx: &DataContext,
         namespace: &ModuleNamespace<Self>,
@@ -146,8 +212,13 @@ pub fn default_libcall_names() -> Box<dyn Fn(ir::LibCall) -> String> {
         ir::LibCall::TruncF64 => "trunc".to_owned(),
         ir::LibCall::NearestF32 => "nearbyintf".to_owned(),
         ir::LibCall::NearestF64 => "nearbyint".to_owned(),
+        ir::LibCall::UdivI64 => "__udivdi3".to_owned(),
+        ir::LibCall::SdivI64 => "__divdi3".to_owned(),
+        ir::LibCall::UremI64 => "__umoddi3".to_owned(),
+        ir::LibCall::SremI64 => "__moddi3".to_owned(),
         ir::LibCall::Memcpy => "memcpy".to_owned(),
         ir::LibCall::Memset => "memset".to_owned(),
         ir::LibCall::Memmove => "memmove".to_owned(),
+        ir::LibCall::ElfTlsGetAddr => "__tls_get_addr".to_owned(),
     })
 }
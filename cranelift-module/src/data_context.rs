@@ -3,7 +3,9 @@
 use cranelift_codegen::binemit::{Addend, CodeOffset};
 use cranelift_codegen::entity::PrimaryMap;
 use cranelift_codegen::ir;
+use std::borrow::ToOwned;
 use std::boxed::Box;
+use std::string::String;
 use std::vec::Vec;
 
 /// This specifies how data is to be initialized.
@@ -46,6 +48,12 @@ pub struct DataDescription {
     pub function_relocs: Vec<(CodeOffset, ir::FuncRef)>,
     /// Data addresses to write at specified offsets.
     pub data_relocs: Vec<(CodeOffset, ir::GlobalValue, Addend)>,
+    /// The alignment requested for the data, in bytes, in addition to that of its declaration.
+    pub align: Option<u64>,
+    /// The custom section to place the data in, instead of the default one for its kind.
+    pub section: Option<String>,
+    /// Whether read-only data is only read-only once its relocations are applied.
+    pub relro: bool,
 }
 
 /// This is to data objects what cranelift_codegen::Context is to functions.
@@ -63,6 +71,9 @@ impl DataContext {
                 data_decls: PrimaryMap::new(),
                 function_relocs: vec![],
                 data_relocs: vec![],
+                align: None,
+                section: None,
+                relro: false,
             },
         }
     }
@@ -74,9 +85,15 @@ impl DataContext {
         self.description.data_decls.clear();
         self.description.function_relocs.clear();
         self.description.data_relocs.clear();
+        self.description.align = None;
+        self.description.section = None;
+        self.description.relro = false;
     }
 
     /// Define a zero-initialized object with the given size.
+    ///
+    /// The COFF backend places zero-initialized objects in `.bss`, which takes no space in the
+    /// file. Faerie has no uninitialized sections, so the faerie backend writes them out in full.
     pub fn define_zeroinit(&mut self, size: usize) {
         debug_assert_eq!(self.description.init, Init::Uninitialized);
         self.description.init = Init::Zeros { size };
@@ -90,6 +107,35 @@ impl DataContext {
         self.description.init = Init::Bytes { contents };
     }
 
+    /// Request that the data be aligned to `align` bytes, which must be a power of two.
+    ///
+    /// The data is aligned to the larger of this and the alignment of its declaration.
+    pub fn set_align(&mut self, align: u64) {
+        debug_assert!(align.is_power_of_two());
+        self.description.align = Some(align);
+    }
+
+    /// Place the data in the custom section `section`.
+    ///
+    /// The COFF backend supports custom sections, and backends which have no sections, like
+    /// SimpleJIT, ignore this. The faerie backend can't emit custom data sections, so it places
+    /// the data in its default section.
+    pub fn set_section(&mut self, section: &str) {
+        self.description.section = Some(section.to_owned());
+    }
+
+    /// Mark read-only data as only read-only once its relocations are applied.
+    ///
+    /// This lets position-independent objects hold addresses in read-only data, by placing it in
+    /// a section like ELF's `.data.rel.ro`, which the dynamic linker writes to before protecting
+    /// it. It has no effect on writable data.
+    ///
+    /// The COFF backend places relro data in `.rdata`, and SimpleJIT ignores this. The faerie
+    /// backend can't emit relro sections, so it emits relro data as writable data.
+    pub fn set_relro(&mut self) {
+        self.description.relro = true;
+    }
+
     /// Declare an external function import.
     ///
     /// Users of the `Module` API generally should call
@@ -145,9 +191,15 @@ mod tests {
             assert!(description.data_decls.is_empty());
             assert!(description.function_relocs.is_empty());
             assert!(description.data_relocs.is_empty());
+            assert_eq!(description.align, None);
+            assert_eq!(description.section, None);
+            assert!(!description.relro);
         }
 
         data_ctx.define_zeroinit(256);
+        data_ctx.set_align(4096);
+        data_ctx.set_section(".data.custom");
+        data_ctx.set_relro();
 
         let _func_a = data_ctx.import_function(ir::ExternalName::user(0, 0));
         let func_b = data_ctx.import_function(ir::ExternalName::user(0, 1));
@@ -166,6 +218,9 @@ mod tests {
             assert_eq!(description.data_decls.len(), 2);
             assert_eq!(description.function_relocs.len(), 2);
             assert_eq!(description.data_relocs.len(), 1);
+            assert_eq!(description.align, Some(4096));
+            assert_eq!(description.section.as_ref().unwrap(), ".data.custom");
+            assert!(description.relro);
         }
 
         data_ctx.clear();
@@ -176,6 +231,9 @@ mod tests {
             assert!(description.data_decls.is_empty());
             assert!(description.function_relocs.is_empty());
             assert!(description.data_relocs.is_empty());
+            assert_eq!(description.align, None);
+            assert_eq!(description.section, None);
+            assert!(!description.relro);
         }
 
         let contents = vec![33, 34, 35, 36];
@@ -0,0 +1,930 @@
+//! Generation of DWARF debug information for the functions of a module.
+//!
+//! A `DwarfBuilder` collects the debug information of compiled functions, and produces the
+//! contents of the DWARF sections describing them, along with the relocations that object file
+//! backends must apply to them:
+//!
+//! - `.debug_line` maps code addresses to source locations. Cranelift source locations are
+//!   opaque, so each one is reported as the line with the same number in the source file of the
+//!   module. For WebAssembly, this is the offset of the instruction in the module.
+//! - `.debug_info` describes each function, and a variable for each of its value labels.
+//! - `.debug_loc` holds the locations of the variables, from the value locations computed by the
+//!   register allocator.
+//!
+//! Value labels are only recorded when `Function::collect_debug_info` was called before
+//! compiling. Variable locations are currently only described for x86-64.
+
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::ir::{self, SourceLoc, Type, ValueLabel, ValueLabelAssignments, ValueLoc};
+use cranelift_codegen::isa::{RegUnit, TargetIsa};
+use cranelift_codegen::{Context, ValueLocRange};
+use std::borrow::ToOwned;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// A DWARF section produced by `DwarfBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DwarfSection {
+    /// `.debug_abbrev`.
+    Abbrev,
+    /// `.debug_info`.
+    Info,
+    /// `.debug_line`.
+    Line,
+    /// `.debug_loc`.
+    Loc,
+}
+
+impl DwarfSection {
+    /// Get the name of the section in ELF object files.
+    pub fn name(self) -> &'static str {
+        match self {
+            DwarfSection::Abbrev => ".debug_abbrev",
+            DwarfSection::Info => ".debug_info",
+            DwarfSection::Line => ".debug_line",
+            DwarfSection::Loc => ".debug_loc",
+        }
+    }
+}
+
+/// The target of a `DwarfReloc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DwarfRelocTarget {
+    /// The address of the function with this name.
+    Function(String),
+    /// The offset of a location in a DWARF section.
+    Section(DwarfSection),
+}
+
+/// A relocation in a DWARF section.
+///
+/// The field at `offset` holds `size` bytes, and must be set to the address or section offset of
+/// `target`, plus `addend`. The field initially holds `addend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DwarfReloc {
+    /// The offset of the field in the section.
+    pub offset: u32,
+    /// The size of the field, in bytes.
+    pub size: u8,
+    /// The target of the relocation.
+    pub target: DwarfRelocTarget,
+    /// The value added to the address of the target.
+    pub addend: i64,
+}
+
+/// The contents of a DWARF section produced by `DwarfBuilder`.
+#[derive(Debug, Clone)]
+pub struct DwarfSectionData {
+    /// The section.
+    pub section: DwarfSection,
+    /// The bytes of the section.
+    pub data: Vec<u8>,
+    /// The relocations to apply to `data`.
+    pub relocs: Vec<DwarfReloc>,
+}
+
+/// A variable of a function, for a value label.
+struct DebugVariable {
+    label: ValueLabel,
+    ty: Type,
+    /// The code ranges where the variable is live, with the DWARF expression of its location.
+    locations: Vec<(CodeOffset, CodeOffset, Vec<u8>)>,
+}
+
+/// The debug information of a compiled function.
+struct DebugFunction {
+    name: String,
+    size: CodeOffset,
+    /// The code offsets at which the source location changes.
+    srclocs: Vec<(CodeOffset, SourceLoc)>,
+    /// The DWARF expression of the frame base, if variables on the stack can be located.
+    frame_base: Option<Vec<u8>>,
+    variables: Vec<DebugVariable>,
+}
+
+/// Collects the debug information of compiled functions, and emits it as DWARF.
+pub struct DwarfBuilder {
+    file: String,
+    address_size: u8,
+    functions: Vec<DebugFunction>,
+}
+
+impl DwarfBuilder {
+    /// Create a new `DwarfBuilder` for code compiled for `isa`, from the source file `file`.
+    pub fn new(isa: &dyn TargetIsa, file: &str) -> Self {
+        Self {
+            file: file.to_owned(),
+            address_size: isa.pointer_bytes(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Add the function `name`, which has been compiled in `ctx` into `size` bytes of code.
+    pub fn add_function(&mut self, name: &str, ctx: &Context, isa: &dyn TargetIsa, size: u32) {
+        let srclocs = ctx
+            .address_map(isa)
+            .entries()
+            .iter()
+            .filter(|&&(_, srcloc)| !srcloc.is_default())
+            .cloned()
+            .collect();
+
+        let is_x86_64 = isa.name() == "x86" && isa.pointer_bits() == 64;
+        let frame_base = if is_x86_64 {
+            // Cranelift always sets up `%rbp` as the frame pointer on x86-64, and stack slot
+            // offsets are relative to the stack pointer before the call, 16 bytes above it.
+            let mut expr = vec![DW_OP_BREG0 + 6];
+            write_sleb128(&mut expr, 16);
+            Some(expr)
+        } else {
+            None
+        };
+
+        let variables = if is_x86_64 {
+            function_variables(isa, ctx)
+        } else {
+            Vec::new()
+        };
+
+        self.functions.push(DebugFunction {
+            name: name.to_owned(),
+            size,
+            srclocs,
+            frame_base,
+            variables,
+        });
+    }
+
+    /// Emit the DWARF sections describing the functions added so far.
+    pub fn emit(&self) -> Vec<DwarfSectionData> {
+        let mut line = Writer::new(DwarfSection::Line, self.address_size);
+        self.emit_line(&mut line);
+        let mut loc = Writer::new(DwarfSection::Loc, self.address_size);
+        let mut info = Writer::new(DwarfSection::Info, self.address_size);
+        self.emit_info(&mut info, &mut loc);
+        let mut abbrev = Writer::new(DwarfSection::Abbrev, self.address_size);
+        abbrev.data.extend_from_slice(ABBREVIATIONS);
+        vec![abbrev.finish(), info.finish(), line.finish(), loc.finish()]
+    }
+
+    fn emit_line(&self, w: &mut Writer) {
+        let unit_length = w.reserve_u32();
+        w.u16(4);
+        let header_length = w.reserve_u32();
+        w.u8(1); // minimum_instruction_length
+        w.u8(1); // maximum_operations_per_instruction
+        w.u8(1); // default_is_stmt
+        w.u8(LINE_BASE as u8);
+        w.u8(LINE_RANGE);
+        w.u8(OPCODE_BASE);
+        w.data
+            .extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        w.u8(0); // No include directories.
+        w.string(&self.file);
+        w.uleb128(0); // Directory.
+        w.uleb128(0); // Modification time.
+        w.uleb128(0); // Length.
+        w.u8(0);
+        w.patch_length(header_length);
+
+        for func in &self.functions {
+            w.u8(0);
+            w.uleb128(1 + u64::from(self.address_size));
+            w.u8(DW_LNE_SET_ADDRESS);
+            w.address(&func.name, 0);
+
+            let mut address = 0;
+            let mut line = 1;
+            for &(offset, srcloc) in &func.srclocs {
+                w.u8(DW_LNS_ADVANCE_PC);
+                w.uleb128(u64::from(offset - address));
+                w.u8(DW_LNS_ADVANCE_LINE);
+                w.sleb128(i64::from(srcloc.bits()) - line);
+                w.u8(DW_LNS_COPY);
+                address = offset;
+                line = i64::from(srcloc.bits());
+            }
+            w.u8(DW_LNS_ADVANCE_PC);
+            w.uleb128(u64::from(func.size - address));
+            w.u8(0);
+            w.uleb128(1);
+            w.u8(DW_LNE_END_SEQUENCE);
+        }
+        w.patch_length(unit_length);
+    }
+
+    fn emit_info(&self, w: &mut Writer, loc: &mut Writer) {
+        let unit_length = w.reserve_u32();
+        w.u16(4);
+        w.section_offset(DwarfSection::Abbrev, 0);
+        w.u8(self.address_size);
+
+        w.uleb128(ABBREV_COMPILE_UNIT);
+        w.string(concat!("Cranelift ", env!("CARGO_PKG_VERSION")));
+        w.string(&self.file);
+        w.section_offset(DwarfSection::Line, 0);
+        // Addresses are absolute, and relocated.
+        w.address_value(0);
+
+        // Describe the base types used by variables first, to know their offsets.
+        let mut types: Vec<(Type, u32)> = Vec::new();
+        for var in self.functions.iter().flat_map(|func| &func.variables) {
+            if !types.iter().any(|&(ty, _)| ty == var.ty) {
+                types.push((var.ty, w.data.len() as u32));
+                w.uleb128(ABBREV_BASE_TYPE);
+                w.string(&var.ty.to_string());
+                w.u8(base_type_encoding(var.ty));
+                w.u8(var.ty.bytes() as u8);
+            }
+        }
+
+        for func in &self.functions {
+            match func.frame_base {
+                Some(ref expr) => {
+                    w.uleb128(ABBREV_SUBPROGRAM_FRAME_BASE);
+                    w.string(&func.name);
+                    w.address(&func.name, 0);
+                    w.u32(func.size);
+                    w.uleb128(expr.len() as u64);
+                    w.data.extend_from_slice(expr);
+                }
+                None => {
+                    w.uleb128(ABBREV_SUBPROGRAM);
+                    w.string(&func.name);
+                    w.address(&func.name, 0);
+                    w.u32(func.size);
+                }
+            }
+
+            for var in &func.variables {
+                w.uleb128(ABBREV_VARIABLE);
+                w.string(&var.label.to_string());
+                w.section_offset(DwarfSection::Loc, loc.data.len() as u32);
+                let &(_, type_offset) = types.iter().find(|&&(ty, _)| ty == var.ty).unwrap();
+                w.u32(type_offset);
+
+                for &(start, end, ref expr) in &var.locations {
+                    loc.address(&func.name, start);
+                    loc.address(&func.name, end);
+                    loc.u16(expr.len() as u16);
+                    loc.data.extend_from_slice(expr);
+                }
+                loc.address_value(0);
+                loc.address_value(0);
+            }
+            w.u8(0);
+        }
+        w.u8(0);
+        w.patch_length(unit_length);
+    }
+}
+
+/// Collect the variables for the value labels of the function compiled in `ctx`.
+fn function_variables(isa: &dyn TargetIsa, ctx: &Context) -> Vec<DebugVariable> {
+    let func = &ctx.func;
+    let values_labels = match func.dfg.values_labels {
+        Some(ref values_labels) => values_labels,
+        None => return Vec::new(),
+    };
+    let ranges = match ctx.build_value_labels_ranges(isa) {
+        Ok(ranges) => ranges,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut labels: Vec<(ValueLabel, Type)> = Vec::new();
+    for (&value, assignments) in values_labels {
+        if let ValueLabelAssignments::Starts(ref starts) = *assignments {
+            for start in starts {
+                if !labels.iter().any(|&(label, _)| label == start.label) {
+                    labels.push((start.label, func.dfg.value_type(value)));
+                }
+            }
+        }
+    }
+    labels.sort_by_key(|&(label, _)| label.as_u32());
+
+    labels
+        .into_iter()
+        .map(|(label, ty)| {
+            let locations = ranges
+                .get(&label)
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .filter_map(|range| {
+                    location_expr(isa, func, range).map(|expr| (range.start, range.end, expr))
+                })
+                .collect();
+            DebugVariable {
+                label,
+                ty,
+                locations,
+            }
+        })
+        .collect()
+}
+
+/// Get the DWARF expression of the location of a value in `range`, on x86-64.
+fn location_expr(
+    isa: &dyn TargetIsa,
+    func: &ir::Function,
+    range: &ValueLocRange,
+) -> Option<Vec<u8>> {
+    let mut expr = Vec::new();
+    match range.loc {
+        ValueLoc::Reg(unit) => {
+            let reg = x86_64_dwarf_register(isa, unit)?;
+            if reg < 32 {
+                expr.push(DW_OP_REG0 + reg as u8);
+            } else {
+                expr.push(DW_OP_REGX);
+                write_uleb128(&mut expr, u64::from(reg));
+            }
+        }
+        ValueLoc::Stack(ss) => {
+            let offset = func.stack_slots[ss].offset?;
+            expr.push(DW_OP_FBREG);
+            write_sleb128(&mut expr, i64::from(offset));
+        }
+        ValueLoc::Unassigned => return None,
+    }
+    Some(expr)
+}
+
+/// Get the DWARF number of an x86-64 register.
+fn x86_64_dwarf_register(isa: &dyn TargetIsa, unit: RegUnit) -> Option<u16> {
+    let reginfo = isa.register_info();
+    let bank = reginfo.bank_containing_regunit(unit)?;
+    let index = unit - bank.first_unit;
+    match bank.name {
+        // Cranelift numbers the registers by their encoding.
+        "IntRegs" => Some(match index {
+            0 => 0,
+            1 => 2,
+            2 => 1,
+            3 => 3,
+            4 => 7,
+            5 => 6,
+            6 => 4,
+            7 => 5,
+            _ => index,
+        }),
+        "FloatRegs" => Some(17 + index),
+        _ => None,
+    }
+}
+
+/// Get the DWARF encoding of a base type.
+fn base_type_encoding(ty: Type) -> u8 {
+    if ty.is_bool() {
+        DW_ATE_BOOLEAN
+    } else if ty.is_float() {
+        DW_ATE_FLOAT
+    } else if ty.is_int() {
+        DW_ATE_SIGNED
+    } else {
+        DW_ATE_UNSIGNED
+    }
+}
+
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_TAG_VARIABLE: u8 = 0x34;
+
+const DW_AT_BYTE_SIZE: u8 = 0x0b;
+const DW_AT_ENCODING: u8 = 0x3e;
+const DW_AT_FRAME_BASE: u8 = 0x40;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_TYPE: u8 = 0x49;
+
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA1: u8 = 0x0b;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_STRING: u8 = 0x08;
+
+const DW_ATE_BOOLEAN: u8 = 0x02;
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_ATE_UNSIGNED: u8 = 0x08;
+
+const DW_OP_BREG0: u8 = 0x70;
+const DW_OP_FBREG: u8 = 0x91;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REGX: u8 = 0x90;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+const LINE_BASE: i8 = -5;
+const LINE_RANGE: u8 = 14;
+const OPCODE_BASE: u8 = 13;
+
+const ABBREV_COMPILE_UNIT: u64 = 1;
+const ABBREV_SUBPROGRAM: u64 = 2;
+const ABBREV_SUBPROGRAM_FRAME_BASE: u64 = 3;
+const ABBREV_VARIABLE: u64 = 4;
+const ABBREV_BASE_TYPE: u64 = 5;
+
+/// The contents of `.debug_abbrev`, which describes the layout of the entries in `.debug_info`.
+#[rustfmt::skip]
+const ABBREVIATIONS: &[u8] = &[
+    ABBREV_COMPILE_UNIT as u8, DW_TAG_COMPILE_UNIT, 1,
+    DW_AT_PRODUCER, DW_FORM_STRING,
+    DW_AT_NAME, DW_FORM_STRING,
+    DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET,
+    DW_AT_LOW_PC, DW_FORM_ADDR,
+    0, 0,
+    ABBREV_SUBPROGRAM as u8, DW_TAG_SUBPROGRAM, 1,
+    DW_AT_NAME, DW_FORM_STRING,
+    DW_AT_LOW_PC, DW_FORM_ADDR,
+    DW_AT_HIGH_PC, DW_FORM_DATA4,
+    0, 0,
+    ABBREV_SUBPROGRAM_FRAME_BASE as u8, DW_TAG_SUBPROGRAM, 1,
+    DW_AT_NAME, DW_FORM_STRING,
+    DW_AT_LOW_PC, DW_FORM_ADDR,
+    DW_AT_HIGH_PC, DW_FORM_DATA4,
+    DW_AT_FRAME_BASE, DW_FORM_EXPRLOC,
+    0, 0,
+    ABBREV_VARIABLE as u8, DW_TAG_VARIABLE, 0,
+    DW_AT_NAME, DW_FORM_STRING,
+    DW_AT_LOCATION, DW_FORM_SEC_OFFSET,
+    DW_AT_TYPE, DW_FORM_REF4,
+    0, 0,
+    ABBREV_BASE_TYPE as u8, DW_TAG_BASE_TYPE, 0,
+    DW_AT_NAME, DW_FORM_STRING,
+    DW_AT_ENCODING, DW_FORM_DATA1,
+    DW_AT_BYTE_SIZE, DW_FORM_DATA1,
+    0, 0,
+    0,
+];
+
+fn write_uleb128(data: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(data: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+/// Writes the contents of a DWARF section, in little-endian order.
+struct Writer {
+    section: DwarfSection,
+    address_size: u8,
+    data: Vec<u8>,
+    relocs: Vec<DwarfReloc>,
+}
+
+impl Writer {
+    fn new(section: DwarfSection, address_size: u8) -> Self {
+        Self {
+            section,
+            address_size,
+            data: Vec::new(),
+            relocs: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> DwarfSectionData {
+        DwarfSectionData {
+            section: self.section,
+            data: self.data,
+            relocs: self.relocs,
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn uleb128(&mut self, value: u64) {
+        write_uleb128(&mut self.data, value);
+    }
+
+    fn sleb128(&mut self, value: i64) {
+        write_sleb128(&mut self.data, value);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    /// Write an address which isn't relocated.
+    fn address_value(&mut self, value: u64) {
+        let bytes = value.to_le_bytes();
+        self.data
+            .extend_from_slice(&bytes[..usize::from(self.address_size)]);
+    }
+
+    /// Write the address of the code at `offset` in the function `name`.
+    fn address(&mut self, name: &str, offset: CodeOffset) {
+        self.relocs.push(DwarfReloc {
+            offset: self.data.len() as u32,
+            size: self.address_size,
+            target: DwarfRelocTarget::Function(name.to_owned()),
+            addend: i64::from(offset),
+        });
+        self.address_value(u64::from(offset));
+    }
+
+    /// Write a reference to `offset` in `section`.
+    fn section_offset(&mut self, section: DwarfSection, offset: u32) {
+        self.relocs.push(DwarfReloc {
+            offset: self.data.len() as u32,
+            size: 4,
+            target: DwarfRelocTarget::Section(section),
+            addend: i64::from(offset),
+        });
+        self.u32(offset);
+    }
+
+    /// Reserve a 32-bit length field, to be set by `patch_length`.
+    fn reserve_u32(&mut self) -> usize {
+        let at = self.data.len();
+        self.u32(0);
+        at
+    }
+
+    /// Set the length field at `at` to the size of the data that follows it.
+    fn patch_length(&mut self, at: usize) {
+        let length = (self.data.len() - at - 4) as u32;
+        self.data[at..at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::{types, AbiParam, ExternalName, Function, InstBuilder, Signature};
+    use cranelift_codegen::isa::{self, CallConv};
+    use cranelift_codegen::settings;
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+    use gimli::{constants, AttributeValue, EndianSlice, LittleEndian};
+    use std::boxed::Box;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+
+    fn isa(triple: &str) -> Box<dyn TargetIsa> {
+        let flags = settings::Flags::new(settings::builder());
+        isa::lookup(Triple::from_str(triple).unwrap())
+            .unwrap()
+            .finish(flags)
+    }
+
+    /// Compile a function adding its two parameters, at the source locations `first` and
+    /// `first + 2`. The first parameter has the label 0 from `first`, and the sum has the label 1
+    /// from `first + 2`. Labels must start at distinct source locations to be tracked.
+    fn compile(isa: &dyn TargetIsa, first: u32) -> (Context, u32) {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+
+        let mut ctx = Context::new();
+        ctx.func = Function::with_name_signature(ExternalName::testcase("add"), sig);
+        ctx.func.collect_debug_info();
+        let mut func_ctx = FunctionBuilderContext::new();
+        {
+            let mut bcx = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+            let ebb = bcx.create_ebb();
+            bcx.append_ebb_params_for_function_params(ebb);
+            bcx.switch_to_block(ebb);
+            bcx.seal_block(ebb);
+            let (x, y) = (bcx.ebb_params(ebb)[0], bcx.ebb_params(ebb)[1]);
+            bcx.set_srcloc(SourceLoc::new(first));
+            bcx.set_val_label(x, ValueLabel::from_u32(0));
+            let sum = bcx.ins().iadd(x, y);
+            bcx.set_srcloc(SourceLoc::new(first + 2));
+            bcx.set_val_label(sum, ValueLabel::from_u32(1));
+            bcx.ins().return_(&[sum]);
+        }
+        let size = ctx.compile(isa).unwrap().total_size;
+        (ctx, size)
+    }
+
+    /// Apply the relocations of the emitted sections, with the functions placed at the given
+    /// addresses and each section at offset 0.
+    fn relocate(
+        sections: &[DwarfSectionData],
+        addresses: &[(&str, u64)],
+    ) -> Vec<(DwarfSection, Vec<u8>)> {
+        sections
+            .iter()
+            .map(|section| {
+                let mut data = section.data.clone();
+                for reloc in &section.relocs {
+                    let value = match reloc.target {
+                        DwarfRelocTarget::Function(ref name) => {
+                            let &(_, address) =
+                                addresses.iter().find(|&&(n, _)| n == name).unwrap();
+                            address as i64 + reloc.addend
+                        }
+                        DwarfRelocTarget::Section(_) => reloc.addend,
+                    };
+                    let at = reloc.offset as usize;
+                    let size = usize::from(reloc.size);
+                    data[at..at + size].copy_from_slice(&value.to_le_bytes()[..size]);
+                }
+                (section.section, data)
+            })
+            .collect()
+    }
+
+    fn load(sections: &[(DwarfSection, Vec<u8>)]) -> gimli::Dwarf<EndianSlice<'_, LittleEndian>> {
+        gimli::Dwarf::load(|id| -> gimli::Result<_> {
+            let data = sections
+                .iter()
+                .find(|&&(section, _)| section.name() == id.name())
+                .map_or(&[][..], |(_, data)| data.as_slice());
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .unwrap()
+    }
+
+    type Unit<'a> = gimli::Unit<EndianSlice<'a, LittleEndian>>;
+    type Entry<'a, 'u> = gimli::DebuggingInformationEntry<'a, 'u, EndianSlice<'a, LittleEndian>>;
+
+    fn string<'a>(
+        dwarf: &gimli::Dwarf<EndianSlice<'a, LittleEndian>>,
+        unit: &Unit<'a>,
+        entry: &Entry<'a, '_>,
+        name: constants::DwAt,
+    ) -> String {
+        let value = entry.attr_value(name).unwrap().unwrap();
+        let string = dwarf.attr_string(unit, value).unwrap();
+        string.to_string().unwrap().to_owned()
+    }
+
+    /// Get the rows of the line program of `unit`, as `(address, line, end_sequence)`.
+    fn line_rows(unit: &Unit) -> Vec<(u64, u64, bool)> {
+        let mut rows = unit.line_program.clone().unwrap().rows();
+        let mut result = Vec::new();
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            let line = row.line().map_or(0, |line| line.get());
+            result.push((row.address(), line, row.end_sequence()));
+        }
+        result
+    }
+
+    #[test]
+    fn x86_64_sections() {
+        let isa = isa("x86_64");
+        let (ctx, size) = compile(&*isa, 10);
+        let mut builder = DwarfBuilder::new(&*isa, "test.wasm");
+        builder.add_function("add", &ctx, &*isa, size);
+        let sections = builder.emit();
+
+        let names = sections
+            .iter()
+            .map(|s| s.section.name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [".debug_abbrev", ".debug_info", ".debug_line", ".debug_loc"]
+        );
+        let info = &sections[1];
+        for &section in &[DwarfSection::Abbrev, DwarfSection::Line, DwarfSection::Loc] {
+            assert!(info
+                .relocs
+                .iter()
+                .any(|r| r.size == 4 && r.target == DwarfRelocTarget::Section(section)));
+        }
+        assert!(info
+            .relocs
+            .iter()
+            .any(|r| r.size == 8 && r.target == DwarfRelocTarget::Function("add".to_string())));
+
+        let base = 0x1000;
+        let relocated = relocate(&sections, &[("add", base)]);
+        let dwarf = load(&relocated);
+        let mut units = dwarf.units();
+        let header = units.next().unwrap().unwrap();
+        assert!(units.next().unwrap().is_none());
+        assert_eq!(header.version(), 4);
+        assert_eq!(header.address_size(), 8);
+        let unit = dwarf.unit(header).unwrap();
+
+        // `.debug_info`.
+        let mut entries = unit.entries();
+        let (_, cu) = entries.next_dfs().unwrap().unwrap();
+        assert_eq!(cu.tag(), constants::DW_TAG_compile_unit);
+        assert_eq!(
+            string(&dwarf, &unit, cu, constants::DW_AT_name),
+            "test.wasm"
+        );
+
+        let mut base_types = 0;
+        let mut variables = Vec::new();
+        let mut subprograms = 0;
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            match entry.tag() {
+                constants::DW_TAG_base_type => {
+                    assert_eq!(string(&dwarf, &unit, entry, constants::DW_AT_name), "i32");
+                    assert_eq!(
+                        entry.attr_value(constants::DW_AT_encoding).unwrap(),
+                        Some(AttributeValue::Encoding(constants::DW_ATE_signed))
+                    );
+                    assert_eq!(
+                        entry
+                            .attr_value(constants::DW_AT_byte_size)
+                            .unwrap()
+                            .and_then(|v| v.udata_value()),
+                        Some(4)
+                    );
+                    base_types += 1;
+                }
+                constants::DW_TAG_subprogram => {
+                    assert_eq!(string(&dwarf, &unit, entry, constants::DW_AT_name), "add");
+                    assert_eq!(
+                        entry.attr_value(constants::DW_AT_low_pc).unwrap(),
+                        Some(AttributeValue::Addr(base))
+                    );
+                    assert_eq!(
+                        entry
+                            .attr_value(constants::DW_AT_high_pc)
+                            .unwrap()
+                            .and_then(|v| v.udata_value()),
+                        Some(u64::from(size))
+                    );
+                    // `DW_OP_breg6 16`: 16 bytes above `%rbp`.
+                    match entry.attr_value(constants::DW_AT_frame_base).unwrap() {
+                        Some(AttributeValue::Exprloc(expr)) => {
+                            assert_eq!(expr.0.slice(), [0x76, 16])
+                        }
+                        value => panic!("unexpected frame base {:?}", value),
+                    }
+                    subprograms += 1;
+                }
+                constants::DW_TAG_variable => {
+                    let name = string(&dwarf, &unit, entry, constants::DW_AT_name);
+                    let ty = match entry.attr_value(constants::DW_AT_type).unwrap() {
+                        Some(AttributeValue::UnitRef(offset)) => offset,
+                        value => panic!("unexpected type {:?}", value),
+                    };
+                    assert_eq!(unit.entry(ty).unwrap().tag(), constants::DW_TAG_base_type);
+
+                    // `.debug_loc`.
+                    let value = entry
+                        .attr_value(constants::DW_AT_location)
+                        .unwrap()
+                        .unwrap();
+                    let mut locations = dwarf.attr_locations(&unit, value).unwrap().unwrap();
+                    let mut exprs = Vec::new();
+                    while let Some(location) = locations.next().unwrap() {
+                        assert!(base <= location.range.begin);
+                        assert!(location.range.begin < location.range.end);
+                        assert!(location.range.end <= base + u64::from(size));
+                        let expr = location.data.0.slice();
+                        // A register, or an offset from the frame base.
+                        assert!(
+                            (0x50..0x70).contains(&expr[0]) || expr[0] == 0x90 || expr[0] == 0x91,
+                            "unexpected location {:?}",
+                            expr
+                        );
+                        exprs.push(expr.to_vec());
+                    }
+                    // The first parameter is passed in `%rdi`, which is `DW_OP_reg5`.
+                    if name == "val0" {
+                        assert_eq!(exprs, [[0x55]]);
+                    }
+                    variables.push(name);
+                }
+                tag => panic!("unexpected tag {}", tag),
+            }
+        }
+        assert_eq!(base_types, 1);
+        assert_eq!(subprograms, 1);
+        assert_eq!(variables, ["val0", "val1"]);
+
+        // `.debug_line`.
+        let program = unit.line_program.clone().unwrap();
+        let file = program.header().file(1).unwrap();
+        let file_name = dwarf.attr_string(&unit, file.path_name()).unwrap();
+        assert_eq!(file_name.slice(), b"test.wasm");
+
+        let rows = line_rows(&unit);
+        let lines = rows
+            .iter()
+            .filter(|&&(_, _, end)| !end)
+            .map(|&(_, line, _)| line)
+            .collect::<Vec<_>>();
+        assert!(lines.contains(&10) && lines.contains(&12), "{:?}", rows);
+        assert!(rows.windows(2).all(|w| w[0].0 <= w[1].0), "{:?}", rows);
+        assert!(rows[0].0 >= base);
+        assert_eq!(rows.last().unwrap(), &(base + u64::from(size), 12, true));
+    }
+
+    #[test]
+    fn x86_32_sections() {
+        // There are no variables outside of x86-64, but the functions and lines are described.
+        let isa = isa("i686");
+        let (ctx1, size1) = compile(&*isa, 1);
+        let (ctx2, size2) = compile(&*isa, 20);
+        let mut builder = DwarfBuilder::new(&*isa, "test.wasm");
+        builder.add_function("f1", &ctx1, &*isa, size1);
+        builder.add_function("f2", &ctx2, &*isa, size2);
+        let sections = builder.emit();
+
+        let (base1, base2) = (0x1000, 0x2000);
+        let relocated = relocate(&sections, &[("f1", base1), ("f2", base2)]);
+        let dwarf = load(&relocated);
+        let header = dwarf.units().next().unwrap().unwrap();
+        assert_eq!(header.address_size(), 4);
+        let unit = dwarf.unit(header).unwrap();
+
+        let mut entries = unit.entries();
+        let mut functions = Vec::new();
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            match entry.tag() {
+                constants::DW_TAG_compile_unit => {}
+                constants::DW_TAG_subprogram => {
+                    assert!(entry
+                        .attr_value(constants::DW_AT_frame_base)
+                        .unwrap()
+                        .is_none());
+                    let low_pc = match entry.attr_value(constants::DW_AT_low_pc).unwrap() {
+                        Some(AttributeValue::Addr(address)) => address,
+                        value => panic!("unexpected low_pc {:?}", value),
+                    };
+                    functions.push((string(&dwarf, &unit, entry, constants::DW_AT_name), low_pc));
+                }
+                tag => panic!("unexpected tag {}", tag),
+            }
+        }
+        assert_eq!(
+            functions,
+            [("f1".to_string(), base1), ("f2".to_string(), base2)]
+        );
+
+        // Each function has its own sequence, ending after its code.
+        let rows = line_rows(&unit);
+        let ends = rows
+            .iter()
+            .filter(|&&(_, _, end)| end)
+            .map(|&(address, line, _)| (address, line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ends,
+            [
+                (base1 + u64::from(size1), 3),
+                (base2 + u64::from(size2), 22)
+            ]
+        );
+        for &(address, line, _) in &rows {
+            if line == 1 || line == 3 {
+                assert!(base1 <= address && address <= base1 + u64::from(size1));
+            } else {
+                assert!(line == 20 || line == 22, "{:?}", rows);
+                assert!(base2 <= address && address <= base2 + u64::from(size2));
+            }
+        }
+    }
+
+    #[test]
+    fn leb128() {
+        let mut data = Vec::new();
+        write_uleb128(&mut data, 624_485);
+        assert_eq!(data, [0xe5, 0x8e, 0x26]);
+        data.clear();
+        write_sleb128(&mut data, -123_456);
+        assert_eq!(data, [0xc0, 0xbb, 0x78]);
+        data.clear();
+        write_sleb128(&mut data, 64);
+        assert_eq!(data, [0xc0, 0x00]);
+    }
+}
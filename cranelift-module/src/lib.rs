@@ -35,13 +35,19 @@ use std::collections::{hash_map, HashMap};
 
 mod backend;
 mod data_context;
+mod dwarf;
 mod module;
 
 pub use crate::backend::{default_libcall_names, Backend};
 pub use crate::data_context::{DataContext, DataDescription, Init};
+pub use crate::dwarf::{
+    DwarfBuilder, DwarfReloc, DwarfRelocTarget, DwarfSection, DwarfSectionData,
+};
+#[cfg(feature = "std")]
+pub use crate::module::LazyHandle;
 pub use crate::module::{
-    DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleFunction, ModuleNamespace,
-    ModuleResult,
+    DataId, DataStorage, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleFunction,
+    ModuleNamespace, ModuleResult,
 };
 
 /// Version number of this crate.
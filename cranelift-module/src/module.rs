@@ -14,8 +14,15 @@ use cranelift_codegen::{ir, isa, CodegenError, Context};
 use failure::Fail;
 use log::info;
 use std::borrow::ToOwned;
+use std::boxed::Box;
 use std::string::String;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, PoisonError};
 use std::vec::Vec;
+#[cfg(feature = "std")]
+use std::{eprintln, panic, process, ptr, thread};
 
 /// A function identifier for use in the `Module` interface.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -60,6 +67,18 @@ pub enum Linkage {
     Export,
 }
 
+/// Where the storage of a data object lives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataStorage {
+    /// A single instance shared by all threads.
+    Static,
+    /// A separate instance in each thread.
+    ///
+    /// Only SimpleJIT can define thread-local data objects, with the `elf_gd` TLS model on
+    /// x86-64. Functions accessing them must be compiled with a `tls_model` setting.
+    ThreadLocal,
+}
+
 impl Linkage {
     fn merge(a: Self, b: Self) -> Self {
         match a {
@@ -186,6 +205,7 @@ pub struct DataDeclaration {
     pub name: String,
     pub linkage: Linkage,
     pub writable: bool,
+    pub storage: DataStorage,
     pub align: Option<u8>,
 }
 
@@ -204,10 +224,20 @@ impl<B> ModuleData<B>
 where
     B: Backend,
 {
-    fn merge(&mut self, linkage: Linkage, writable: bool, align: Option<u8>) {
+    fn merge(
+        &mut self,
+        linkage: Linkage,
+        writable: bool,
+        storage: DataStorage,
+        align: Option<u8>,
+    ) -> Result<(), ModuleError> {
+        if self.decl.storage != storage {
+            return Err(ModuleError::IncompatibleDeclaration(self.decl.name.clone()));
+        }
         self.decl.linkage = Linkage::merge(self.decl.linkage, linkage);
         self.decl.writable = self.decl.writable || writable;
         self.decl.align = self.decl.align.max(align);
+        Ok(())
     }
 }
 
@@ -317,6 +347,75 @@ where
     }
 }
 
+/// A function which translates the body of a lazily compiled function into a `Context`.
+#[cfg(feature = "std")]
+type LazyCompiler<B> = Box<dyn FnMut(&Module<B>, FuncId, &mut Context) -> ModuleResult<()>>;
+
+/// The connection between the stubs of lazily compiled functions and their `Module`.
+///
+/// Backends keep a clone of the handle for as long as the stubs they define with it may be
+/// called. The module itself is only reachable through the handle while it runs code with
+/// `Module::run`; a stub called at any other time aborts the process.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct LazyHandle(Arc<LazyState>);
+
+#[cfg(feature = "std")]
+struct LazyState {
+    /// The address of the module inside `Module::run`, or null.
+    module: Mutex<*mut u8>,
+    /// Compiles and finalizes a function of the module at the given address.
+    compile: unsafe fn(*mut u8, FuncId) -> ModuleResult<()>,
+}
+
+// The module address is only dereferenced with the mutex held, while the `Module::run` that
+// set it keeps the module exclusively borrowed.
+#[cfg(feature = "std")]
+unsafe impl Send for LazyState {}
+#[cfg(feature = "std")]
+unsafe impl Sync for LazyState {}
+
+#[cfg(feature = "std")]
+impl LazyHandle {
+    fn new<B: Backend>() -> Self {
+        LazyHandle(Arc::new(LazyState {
+            module: Mutex::new(ptr::null_mut()),
+            compile: compile_lazy_function::<B>,
+        }))
+    }
+
+    /// The function stubs call with `data()` and the index of their function.
+    pub fn callback(&self) -> extern "C" fn(*const u8, u32) {
+        lazy_callback
+    }
+
+    /// The address stubs pass to `callback()`, which stays valid while a clone of this handle
+    /// is alive.
+    pub fn data(&self) -> *const u8 {
+        &*self.0 as *const LazyState as *const u8
+    }
+
+    /// Make `module` reachable from the stubs, returning the previous address.
+    fn set_module(&self, module: *mut u8) -> *mut u8 {
+        let mut current = self.0.module.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, module)
+    }
+}
+
+/// Restores the module address of a `LazyHandle` when `Module::run` returns or unwinds.
+#[cfg(feature = "std")]
+struct LazyScope<'a> {
+    handle: &'a LazyHandle,
+    previous: *mut u8,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Drop for LazyScope<'a> {
+    fn drop(&mut self) {
+        self.handle.set_module(self.previous);
+    }
+}
+
 /// A `Module` is a utility for collecting functions and data objects, and linking them together.
 pub struct Module<B>
 where
@@ -326,6 +425,11 @@ where
     contents: ModuleContents<B>,
     functions_to_finalize: Vec<FuncId>,
     data_objects_to_finalize: Vec<DataId>,
+    #[cfg(feature = "std")]
+    lazy_compiler: Option<LazyCompiler<B>>,
+    /// The handle shared with the stubs of lazily compiled functions, once there are any.
+    #[cfg(feature = "std")]
+    lazy_handle: Option<LazyHandle>,
     backend: B,
 }
 
@@ -343,6 +447,10 @@ where
             },
             functions_to_finalize: Vec::new(),
             data_objects_to_finalize: Vec::new(),
+            #[cfg(feature = "std")]
+            lazy_compiler: None,
+            #[cfg(feature = "std")]
+            lazy_handle: None,
             backend: B::new(backend_builder),
         }
     }
@@ -441,6 +549,7 @@ where
         name: &str,
         linkage: Linkage,
         writable: bool,
+        storage: DataStorage,
         align: Option<u8>, // An alignment bigger than 128 is unlikely
     ) -> ModuleResult<DataId> {
         // TODO: Can we avoid allocating names so often?
@@ -449,11 +558,12 @@ where
             Occupied(entry) => match *entry.get() {
                 FuncOrDataId::Data(id) => {
                     let existing = &mut self.contents.data_objects[id];
-                    existing.merge(linkage, writable, align);
+                    existing.merge(linkage, writable, storage, align)?;
                     self.backend.declare_data(
                         name,
                         existing.decl.linkage,
                         existing.decl.writable,
+                        existing.decl.storage,
                         existing.decl.align,
                     );
                     Ok(id)
@@ -469,12 +579,14 @@ where
                         name: name.to_owned(),
                         linkage,
                         writable,
+                        storage,
                         align,
                     },
                     compiled: None,
                 });
                 entry.insert(FuncOrDataId::Data(id));
-                self.backend.declare_data(name, linkage, writable, align);
+                self.backend
+                    .declare_data(name, linkage, writable, storage, align);
                 Ok(id)
             }
         }
@@ -505,6 +617,7 @@ where
             name: ir::ExternalName::user(1, data.as_u32()),
             offset: ir::immediates::Imm64::new(0),
             colocated,
+            tls: decl.storage == DataStorage::ThreadLocal,
         })
     }
 
@@ -518,6 +631,23 @@ where
         ctx.import_global_value(ir::ExternalName::user(1, data.as_u32()))
     }
 
+    /// Place the function `func` in the custom section `section`, instead of the default one for
+    /// code.
+    ///
+    /// This must be done before the function is defined. The COFF backend supports custom
+    /// sections, and backends which have no sections, like SimpleJIT, ignore it. The faerie
+    /// backend returns an error.
+    pub fn set_function_section(&mut self, func: FuncId, section: &str) -> ModuleResult<()> {
+        let info = &self.contents.functions[func];
+        if info.compiled.is_some() {
+            return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
+        }
+        if !info.decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+        }
+        self.backend.set_function_section(&info.decl.name, section)
+    }
+
     /// Define a function, producing the function body from the given `Context`.
     ///
     /// Returns the size of the function's code and constant data.
@@ -527,6 +657,113 @@ where
         &mut self,
         func: FuncId,
         ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let total_size = self.compile_function(func, ctx)?;
+        self.define_compiled_function(func, ctx, total_size)?;
+        Ok(total_size)
+    }
+
+    /// Define a new body for a function, replacing its current definition if it has one.
+    ///
+    /// Replacing a definition is only supported by backends which can redirect calls to the new
+    /// body, such as `SimpleJITBackend` with hotswap enabled. Once the new body has been
+    /// finalized, calls to the function, including calls through pointers obtained earlier from
+    /// `get_finalized_function`, reach the new body. The old body stays allocated until
+    /// `free_replaced_functions` is called.
+    ///
+    /// Returns the size of the function's code and constant data.
+    ///
+    /// Note: After calling this function the given `Context` will contain the compiled function.
+    pub fn redefine_function(
+        &mut self,
+        func: FuncId,
+        ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let info = &self.contents.functions[func];
+        if !info.decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+        }
+        if info.compiled.is_none() {
+            return self.define_function(func, ctx);
+        }
+        let total_size = self.compile_function(func, ctx)?;
+
+        let info = &self.contents.functions[func];
+        let compiled = Some(self.backend.redefine_function(
+            &info.decl.name,
+            ctx,
+            &ModuleNamespace::<B> {
+                contents: &self.contents,
+            },
+            total_size,
+            info.compiled.as_ref().unwrap(),
+        )?);
+
+        self.contents.functions[func].compiled = compiled;
+        if !self.functions_to_finalize.contains(&func) {
+            self.functions_to_finalize.push(func);
+        }
+        Ok(total_size)
+    }
+
+    /// Free the memory of the function bodies replaced by `redefine_function`.
+    ///
+    /// Only the bodies replaced before the last `finalize_definitions` are freed. Addresses
+    /// returned by `get_finalized_function` stay valid.
+    ///
+    /// This function is unsafe since it can't guarantee that no thread is still executing the
+    /// replaced bodies, for example in a call which started before the function was redefined.
+    pub unsafe fn free_replaced_functions(&mut self) {
+        self.backend.free_replaced_functions();
+    }
+
+    /// Set the function which translates the bodies of lazily compiled functions.
+    ///
+    /// See `define_function_lazily`.
+    #[cfg(feature = "std")]
+    pub fn set_lazy_compiler<F>(&mut self, compile: F)
+    where
+        F: FnMut(&Self, FuncId, &mut Context) -> ModuleResult<()> + 'static,
+    {
+        self.lazy_compiler = Some(Box::new(compile));
+    }
+
+    /// Define a function with a stub which compiles its body the first time it is called.
+    ///
+    /// When the stub is called, the function given to `set_lazy_compiler` translates the body of
+    /// the function into a `Context`, which is then compiled and finalized in place of the stub
+    /// before the call continues. Later calls reach the compiled body directly.
+    ///
+    /// Lazy compilation is only supported by backends which can redirect calls to the compiled
+    /// body, such as `SimpleJITBackend`. Since the stubs call back into this module, code which
+    /// may reach a stub must be run inside `Module::run`. Calling a stub outside of it, or failing
+    /// to compile the function, aborts the process.
+    #[cfg(feature = "std")]
+    pub fn define_function_lazily(&mut self, func: FuncId) -> ModuleResult<()> {
+        let info = &self.contents.functions[func];
+        if info.compiled.is_some() {
+            return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
+        }
+        if !info.decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+        }
+
+        let handle = self.lazy_handle.get_or_insert_with(LazyHandle::new::<B>);
+        let compiled = Some(
+            self.backend
+                .define_function_stub(&info.decl.name, func, handle)?,
+        );
+
+        self.contents.functions[func].compiled = compiled;
+        self.functions_to_finalize.push(func);
+        Ok(())
+    }
+
+    /// Compile the body of `func` in `ctx`.
+    fn compile_function(
+        &self,
+        func: FuncId,
+        ctx: &mut Context,
     ) -> ModuleResult<binemit::CodeOffset> {
         let CodeInfo { total_size, .. } = ctx.compile(self.backend.isa()).map_err(|e| {
             info!(
@@ -536,7 +773,105 @@ where
             );
             ModuleError::Compilation(e)
         })?;
+        Ok(total_size)
+    }
+
+    /// Define the functions in `funcs`, translating and compiling them on `threads` threads.
+    ///
+    /// For each function, `translate` is called on one of the threads with a new context, and
+    /// must fill in `ctx.func`. The threads share `translate`, so it must own the data it uses.
+    /// The compiled contexts are then defined in the order of `funcs` on the calling thread, so
+    /// the result doesn't depend on the number of threads or on the order in which the
+    /// compilations finish.
+    ///
+    /// If a function fails to translate or compile, the functions before it are still defined,
+    /// and its error is returned.
+    ///
+    /// Returns the size of the code and constant data of each function.
+    #[cfg(feature = "std")]
+    pub fn define_functions_parallel<F>(
+        &mut self,
+        funcs: &[FuncId],
+        threads: usize,
+        translate: F,
+    ) -> ModuleResult<Vec<binemit::CodeOffset>>
+    where
+        F: Fn(FuncId, &mut Context) -> ModuleResult<()> + Send + Sync + 'static,
+    {
+        // The workers own everything they share, so they don't borrow from this module.
+        let isa: Arc<dyn isa::TargetIsa> = Arc::from(self.backend.isa().boxed_clone());
+        let queue = Arc::new(funcs.to_vec());
+        let translate = Arc::new(translate);
+        let next = Arc::new(AtomicUsize::new(0));
+        let failed = Arc::new(AtomicBool::new(false));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let isa = isa.clone();
+                let queue = queue.clone();
+                let translate = translate.clone();
+                let next = next.clone();
+                let failed = failed.clone();
+                thread::spawn(move || {
+                    let mut done = Vec::new();
+                    while !failed.load(Ordering::Relaxed) {
+                        // Functions are claimed in order, so when one fails, all the functions
+                        // before it have been claimed and will be compiled.
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= queue.len() {
+                            break;
+                        }
+                        // The backend gets the whole context, including the compiled function and
+                        // the register allocation that debug information is built from.
+                        let mut ctx = Context::new();
+                        ctx.func.signature.call_conv = isa.default_call_conv();
+                        let result = translate(queue[index], &mut ctx)
+                            .and_then(|()| {
+                                ctx.compile(&*isa).map_err(|e| {
+                                    info!(
+                                        "defining function {}: {}",
+                                        queue[index],
+                                        ctx.func.display(&*isa)
+                                    );
+                                    ModuleError::Compilation(e)
+                                })
+                            })
+                            .map(|info| (info.total_size, ctx));
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        done.push((index, result));
+                    }
+                    done
+                })
+            })
+            .collect::<Vec<_>>();
 
+        let mut compiled = Vec::new();
+        compiled.resize_with(funcs.len(), || None);
+        for worker in workers {
+            let done = worker.join().unwrap_or_else(|e| panic::resume_unwind(e));
+            for (index, result) in done {
+                compiled[index] = Some(result);
+            }
+        }
+
+        let mut sizes = Vec::with_capacity(funcs.len());
+        for (&func, result) in funcs.iter().zip(compiled) {
+            // Only the functions after a failed one can be missing.
+            let (total_size, ctx) = result.expect("function wasn't compiled")?;
+            self.define_compiled_function(func, &ctx, total_size)?;
+            sizes.push(total_size);
+        }
+        Ok(sizes)
+    }
+
+    /// Define a function that has been compiled in `ctx`.
+    fn define_compiled_function(
+        &mut self,
+        func: FuncId,
+        ctx: &Context,
+        total_size: binemit::CodeOffset,
+    ) -> ModuleResult<()> {
         let info = &self.contents.functions[func];
         if info.compiled.is_some() {
             return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
@@ -556,7 +891,7 @@ where
 
         self.contents.functions[func].compiled = compiled;
         self.functions_to_finalize.push(func);
-        Ok(total_size)
+        Ok(())
     }
 
     /// Define a function, producing the data contents from the given `DataContext`.
@@ -572,6 +907,7 @@ where
             Some(self.backend.define_data(
                 &info.decl.name,
                 info.decl.writable,
+                info.decl.storage,
                 info.decl.align,
                 data_ctx,
                 &ModuleNamespace::<B> {
@@ -689,6 +1025,27 @@ where
         )
     }
 
+    /// Run `f`, which may call the stubs of lazily compiled functions.
+    ///
+    /// The module stays exclusively borrowed while `f` runs, so the stubs can compile functions
+    /// into it. See `define_function_lazily`.
+    #[cfg(feature = "std")]
+    pub fn run<F, T>(&mut self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let handle = match self.lazy_handle {
+            Some(ref handle) => handle.clone(),
+            None => return f(),
+        };
+        let previous = handle.set_module(self as *mut Self as *mut u8);
+        let _scope = LazyScope {
+            handle: &handle,
+            previous,
+        };
+        f()
+    }
+
     /// Return the target isa
     pub fn isa(&self) -> &dyn isa::TargetIsa {
         self.backend.isa()
@@ -701,3 +1058,60 @@ where
         self.backend.finish()
     }
 }
+
+/// Compile and finalize the body of a lazily compiled function, on behalf of its stub.
+///
+/// `data` points to the `LazyState` of a `LazyHandle`. Since this is called from generated code,
+/// failures abort the process rather than unwinding into it.
+#[cfg(feature = "std")]
+extern "C" fn lazy_callback(data: *const u8, func: u32) {
+    let state = unsafe { &*(data as *const LazyState) };
+    let func = FuncId::from_u32(func);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let module = state.module.lock().unwrap_or_else(PoisonError::into_inner);
+        if module.is_null() {
+            return Err(ModuleError::Backend(
+                "lazily compiled function called outside of Module::run".to_owned(),
+            ));
+        }
+        unsafe { (state.compile)(*module, func) }
+    }));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            eprintln!("lazy compilation of function {} failed: {}", func, e);
+            process::abort();
+        }
+        Err(_) => {
+            eprintln!("lazy compilation of function {} panicked", func);
+            process::abort();
+        }
+    }
+}
+
+/// Compile and finalize `func` in the `Module<B>` at `module`.
+///
+/// The caller must hold the lock of the `LazyState` which `Module::run` set `module` in.
+#[cfg(feature = "std")]
+unsafe fn compile_lazy_function<B>(module: *mut u8, func: FuncId) -> ModuleResult<()>
+where
+    B: Backend,
+{
+    let module = &mut *(module as *mut Module<B>);
+    let mut compile = match module.lazy_compiler.take() {
+        Some(compile) => compile,
+        None => {
+            return Err(ModuleError::Backend(
+                "lazily compiled function called without a lazy compiler".to_owned(),
+            ))
+        }
+    };
+
+    let mut ctx = module.make_context();
+    let result = compile(module, func, &mut ctx);
+    module.lazy_compiler = Some(compile);
+    result?;
+    module.redefine_function(func, &mut ctx)?;
+    module.finalize_definitions();
+    Ok(())
+}
@@ -13,10 +13,10 @@ use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32,
 use cranelift_codegen::ir::instructions::{InstructionData, InstructionFormat, VariableArgs};
 use cranelift_codegen::ir::types::INVALID;
 use cranelift_codegen::ir::{
-    AbiParam, ArgumentExtension, ArgumentLoc, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
-    GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, JumpTable, JumpTableData, MemFlags,
-    Opcode, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Table, TableData, Type,
-    Value, ValueLoc,
+    AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Ebb, ExtFuncData, ExternalName,
+    FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, JumpTable,
+    JumpTableData, MemFlags, Opcode, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind,
+    Table, TableData, Type, Value, ValueLoc,
 };
 use cranelift_codegen::isa::{self, CallConv, Encoding, RegUnit, TargetIsa};
 use cranelift_codegen::packed_option::ReservedValue;
@@ -168,6 +168,7 @@ impl<'a> Context<'a> {
                 name: ExternalName::testcase(""),
                 offset: Imm64::new(0),
                 colocated: false,
+                tls: false,
             });
         }
         self.function.global_values[gv] = data;
@@ -183,6 +184,16 @@ impl<'a> Context<'a> {
         }
     }
 
+    // Set the stack limit of the function.
+    fn set_stack_limit(&mut self, gv: GlobalValue, loc: Location) -> ParseResult<()> {
+        self.check_gv(gv, loc)?;
+        if self.function.stack_limit.is_some() {
+            return err!(loc, "stack limit defined more than once");
+        }
+        self.function.stack_limit = Some(gv);
+        Ok(())
+    }
+
     // Allocate a heap slot.
     fn add_heap(&mut self, heap: Heap, data: HeapData, loc: Location) -> ParseResult<()> {
         self.map.def_heap(heap, loc)?;
@@ -1059,6 +1070,23 @@ impl<'a> Parser<'a> {
             match s {
                 "uext" => arg.extension = ArgumentExtension::Uext,
                 "sext" => arg.extension = ArgumentExtension::Sext,
+                "sarg" => {
+                    // flag ::= "sarg" "(" uimm32 [ "," uimm32 ] ")"
+                    self.consume();
+                    self.match_token(Token::LPar, "expected '(' before struct size")?;
+                    let size = self.match_uimm32("expected struct size in bytes")?;
+                    let mut mask = 0;
+                    if self.optional(Token::Comma) {
+                        let bits: u32 = self.match_uimm32("expected struct byte mask")?.into();
+                        if bits > 0xffff {
+                            return err!(self.loc, "struct byte mask must fit in 16 bits");
+                        }
+                        mask = bits as u16;
+                    }
+                    self.match_token(Token::RPar, "expected ')' after struct size")?;
+                    arg.purpose = ArgumentPurpose::StructArgument(size.into(), mask);
+                    continue;
+                }
                 _ => {
                     if let Ok(purpose) = s.parse() {
                         arg.purpose = purpose;
@@ -1165,6 +1193,12 @@ impl<'a> Parser<'a> {
                     self.parse_jump_table_decl()
                         .and_then(|(jt, dat)| ctx.add_jt(jt, dat, self.loc))
                 }
+                Some(Token::Identifier("stack_limit")) => {
+                    self.start_gathering_comments();
+                    let loc = self.loc;
+                    self.parse_stack_limit_decl()
+                        .and_then(|gv| ctx.set_stack_limit(gv, loc))
+                }
                 // More to come..
                 _ => return Ok(()),
             }?;
@@ -1217,7 +1251,7 @@ impl<'a> Parser<'a> {
     // global-val-desc ::= "vmctx"
     //                   | "load" "." type "notrap" "aligned" GlobalValue(base) [offset]
     //                   | "iadd_imm" "(" GlobalValue(base) ")" imm64
-    //                   | "symbol" ["colocated"] name + imm64
+    //                   | "symbol" ["colocated"] ["tls"] name + imm64
     //
     fn parse_global_value_decl(&mut self) -> ParseResult<(GlobalValue, GlobalValueData)> {
         let gv = self.match_gv("expected global value number: gv«n»")?;
@@ -1266,12 +1300,14 @@ impl<'a> Parser<'a> {
             }
             "symbol" => {
                 let colocated = self.optional(Token::Identifier("colocated"));
+                let tls = self.optional(Token::Identifier("tls"));
                 let name = self.parse_external_name()?;
                 let offset = self.optional_offset_imm64()?;
                 GlobalValueData::Symbol {
                     name,
                     offset,
                     colocated,
+                    tls,
                 }
             }
             other => return err!(self.loc, "Unknown global value kind '{}'", other),
@@ -1547,6 +1583,21 @@ impl<'a> Parser<'a> {
         Ok((jt, data))
     }
 
+    // Parse a stack limit decl.
+    //
+    // stack-limit-decl ::= * "stack_limit" "=" GlobalValue(gv)
+    fn parse_stack_limit_decl(&mut self) -> ParseResult<GlobalValue> {
+        self.match_identifier("stack_limit", "expected 'stack_limit'")?;
+        self.match_token(Token::Equal, "expected '=' in stack_limit decl")?;
+        let gv = self.match_gv("expected global value: gv«n»")?;
+
+        // Collect any trailing comments.
+        self.token();
+        self.claim_gathered_comments(AnyEntity::Function);
+
+        Ok(gv)
+    }
+
     // Parse a function body, add contents to `ctx`.
     //
     // function-body ::= * { extended-basic-block }
@@ -1585,7 +1636,7 @@ impl<'a> Parser<'a> {
     // Parse an extended basic block, add contents to `ctx`.
     //
     // extended-basic-block ::= * ebb-header { instruction }
-    // ebb-header           ::= Ebb(ebb) [ebb-params] ":"
+    // ebb-header           ::= Ebb(ebb) [ebb-params] ["cold"] ":"
     //
     fn parse_extended_basic_block(&mut self, ctx: &mut Context) -> ParseResult<()> {
         // Collect comments for the next ebb.
@@ -1594,12 +1645,17 @@ impl<'a> Parser<'a> {
         let ebb_num = self.match_ebb("expected EBB header")?;
         let ebb = ctx.add_ebb(ebb_num, self.loc)?;
 
-        if !self.optional(Token::Colon) {
-            // ebb-header ::= Ebb(ebb) [ * ebb-params ] ":"
+        if self.token() == Some(Token::LPar) {
+            // ebb-header ::= Ebb(ebb) [ * ebb-params ] [ "cold" ] ":"
             self.parse_ebb_params(ctx, ebb)?;
-            self.match_token(Token::Colon, "expected ':' after EBB parameters")?;
         }
 
+        // ebb-header ::= Ebb(ebb) [ ebb-params ] [ * "cold" ] ":"
+        if self.optional(Token::Identifier("cold")) {
+            ctx.function.layout.set_cold(ebb);
+        }
+        self.match_token(Token::Colon, "expected ':' after EBB header")?;
+
         // Collect any trailing comments.
         self.token();
         self.claim_gathered_comments(ebb);
@@ -2379,6 +2435,64 @@ impl<'a> Parser<'a> {
                 ctx.check_fn(func_ref, self.loc)?;
                 InstructionData::FuncAddr { opcode, func_ref }
             }
+            InstructionFormat::AtomicLoad => {
+                let flags = self.optional_memflags();
+                let ordering = self.match_enum("expected memory ordering")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicLoad {
+                    opcode,
+                    flags,
+                    ordering,
+                    arg: addr,
+                }
+            }
+            InstructionFormat::AtomicStore => {
+                let flags = self.optional_memflags();
+                let ordering = self.match_enum("expected memory ordering")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicStore {
+                    opcode,
+                    flags,
+                    ordering,
+                    args: [arg, addr],
+                }
+            }
+            InstructionFormat::AtomicRmw => {
+                let flags = self.optional_memflags();
+                let op = self.match_enum("expected atomic operation")?;
+                let ordering = self.match_enum("expected memory ordering")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                InstructionData::AtomicRmw {
+                    opcode,
+                    flags,
+                    op,
+                    ordering,
+                    args: [addr, arg],
+                }
+            }
+            InstructionFormat::AtomicCas => {
+                let flags = self.optional_memflags();
+                let ordering = self.match_enum("expected memory ordering")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let expected = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                InstructionData::AtomicCas {
+                    opcode,
+                    flags,
+                    ordering,
+                    args: [addr, expected, arg],
+                }
+            }
+            InstructionFormat::Fence => {
+                let ordering = self.match_enum("expected memory ordering")?;
+                InstructionData::Fence { opcode, ordering }
+            }
             InstructionFormat::StackLoad => {
                 let ss = self.match_ss("expected stack slot number: ss«n»")?;
                 ctx.check_ss(ss, self.loc)?;
@@ -2595,6 +2709,25 @@ mod tests {
         assert_eq!(location.line_number, 1);
         assert_eq!(message, "expected parameter type");
         assert!(!is_warning);
+
+        let arg = Parser::new("i64 sarg(24)").parse_abi_param(None).unwrap();
+        assert_eq!(arg.purpose, ArgumentPurpose::StructArgument(24, 0));
+        assert_eq!(arg.to_string(), "i64 sarg(24)");
+        let arg = Parser::new("i64 sarg(12, 0xff0)")
+            .parse_abi_param(None)
+            .unwrap();
+        assert_eq!(arg.purpose, ArgumentPurpose::StructArgument(12, 0xff0));
+        assert_eq!(arg.to_string(), "i64 sarg(12, 0xff0)");
+        assert!(Parser::new("i64 sarg(12, 0x10000)")
+            .parse_abi_param(None)
+            .is_err());
+        assert_eq!(
+            Parser::new("i64 sarg 24")
+                .parse_abi_param(None)
+                .unwrap_err()
+                .to_string(),
+            "1: expected '(' before struct size"
+        );
     }
 
     #[test]
@@ -2721,6 +2854,8 @@ mod tests {
             "function %ebbs() system_v {
                                      ebb0:
                                      ebb4(v3: i32):
+                                     ebb5 cold:
+                                     ebb6(v4: i64) cold:
                                      }",
         )
         .parse_function(None)
@@ -2731,11 +2866,21 @@ mod tests {
 
         let ebb0 = ebbs.next().unwrap();
         assert_eq!(func.dfg.ebb_params(ebb0), &[]);
+        assert!(!func.layout.is_cold(ebb0));
 
         let ebb4 = ebbs.next().unwrap();
         let ebb4_args = func.dfg.ebb_params(ebb4);
         assert_eq!(ebb4_args.len(), 1);
         assert_eq!(func.dfg.value_type(ebb4_args[0]), types::I32);
+        assert!(!func.layout.is_cold(ebb4));
+
+        let ebb5 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_params(ebb5), &[]);
+        assert!(func.layout.is_cold(ebb5));
+
+        let ebb6 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_params(ebb6).len(), 1);
+        assert!(func.layout.is_cold(ebb6));
     }
 
     #[test]
@@ -2777,6 +2922,48 @@ mod tests {
         assert!(!is_warning);
     }
 
+    #[test]
+    fn stack_limit() {
+        let (func, _) = Parser::new(
+            "function %limit(i64 vmctx) system_v {
+                gv0 = vmctx
+                gv1 = load.i64 notrap aligned gv0+8
+                stack_limit = gv1
+            ebb0(v0: i64):
+                return
+            }",
+        )
+        .parse_function(None)
+        .unwrap();
+        assert_eq!(func.stack_limit, GlobalValue::with_number(1));
+        assert!(func.to_string().contains("\n    stack_limit = gv1\n"));
+
+        let ParseError {
+            location, message, ..
+        } = Parser::new(
+            "function %limit(i64 vmctx) system_v {
+                gv0 = vmctx
+                stack_limit = gv1",
+        )
+        .parse_function(None)
+        .unwrap_err();
+        assert_eq!(location.line_number, 3);
+        assert_eq!(message, "undefined global value gv1");
+
+        let ParseError {
+            location, message, ..
+        } = Parser::new(
+            "function %limit(i64 vmctx) system_v {
+                gv0 = vmctx
+                stack_limit = gv0
+                stack_limit = gv0",
+        )
+        .parse_function(None)
+        .unwrap_err();
+        assert_eq!(location.line_number, 4);
+        assert_eq!(message, "stack limit defined more than once");
+    }
+
     #[test]
     fn duplicate_ss() {
         let ParseError {
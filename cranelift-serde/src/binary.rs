@@ -0,0 +1,865 @@
+//! A compact binary format for the serde data model.
+//!
+//! This format is used to store Cranelift IR, settings, and compilation results in a form that is
+//! much smaller and faster to read than JSON, for example to persist functions between runs or to
+//! send them to other processes.
+//!
+//! The encoding is not self-describing:
+//!
+//! - Unsigned integers are written in LEB128, and signed integers are zigzag-encoded first.
+//!   Single bytes and booleans are written as is.
+//! - Floating point numbers are written as their little-endian bit pattern.
+//! - Strings, byte arrays, sequences, and maps are prefixed with their length.
+//! - Structs and tuples are written as the sequence of their fields, without names.
+//! - Enum variants are written as their index, followed by their contents.
+//! - `None` is written as a 0 byte, and `Some` as a 1 byte followed by the value.
+//!
+//! Data can only be read back as the type it was written from, by the same version of Cranelift.
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::ser::{self, Serialize};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::str;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// An error reading or writing the binary format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input ended in the middle of a value.
+    UnexpectedEof,
+    /// The input continues after the end of the value.
+    TrailingBytes,
+    /// A variable-length integer is too large for its type.
+    IntegerOverflow,
+    /// A boolean, option tag, or `char` has an invalid value.
+    InvalidValue,
+    /// A string isn't valid UTF-8.
+    InvalidUtf8,
+    /// A sequence or map with an unknown length was serialized.
+    UnknownLength,
+    /// The type being deserialized needs a self-describing format.
+    NotSelfDescribing,
+    /// An error reported by a `Serialize` or `Deserialize` implementation.
+    Custom(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::TrailingBytes => write!(f, "trailing bytes after the end of the value"),
+            Error::IntegerOverflow => write!(f, "integer too large"),
+            Error::InvalidValue => write!(f, "invalid boolean, option tag, or char"),
+            Error::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            Error::UnknownLength => write!(f, "sequences and maps must have a known length"),
+            Error::NotSelfDescribing => write!(f, "the binary format is not self-describing"),
+            Error::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// A result of reading or writing the binary format.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Serialize `value` into a new byte vector.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut ser = Serializer { output: Vec::new() };
+    value.serialize(&mut ser)?;
+    Ok(ser.output)
+}
+
+/// Deserialize a value of type `T` from `bytes`, which must contain exactly one value.
+pub fn from_slice<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    let mut de = Deserializer { input: bytes };
+    let value = T::deserialize(&mut de)?;
+    if de.input.is_empty() {
+        Ok(value)
+    } else {
+        Err(Error::TrailingBytes)
+    }
+}
+
+/// Map a signed integer to an unsigned one, so that small magnitudes have short encodings.
+fn zigzag(v: i128) -> u128 {
+    ((v << 1) ^ (v >> 127)) as u128
+}
+
+fn unzigzag(v: u128) -> i128 {
+    ((v >> 1) as i128) ^ -((v & 1) as i128)
+}
+
+struct Serializer {
+    output: Vec<u8>,
+}
+
+impl Serializer {
+    fn write_varint(&mut self, mut v: u128) {
+        while v >= 0x80 {
+            self.output.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.output.push(v as u8);
+    }
+
+    fn write_len(&mut self, len: Option<usize>) -> Result<()> {
+        let len = len.ok_or(Error::UnknownLength)?;
+        self.write_varint(len as u128);
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i128(i128::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i128(i128::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.serialize_i128(i128::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.write_varint(zigzag(v));
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_u128(u128::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_u128(u128::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.serialize_u128(u128::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.write_varint(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.output.extend_from_slice(&v.to_bits().to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.output.extend_from_slice(&v.to_bits().to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(u32::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_varint(v.len() as u128);
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self> {
+        self.write_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self> {
+        self.write_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<'a> ser::SerializeSeq for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        if len > self.input.len() {
+            return Err(Error::UnexpectedEof);
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_varint(&mut self) -> Result<u128> {
+        let mut v: u128 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_byte()?;
+            if shift >= 128 || (shift == 126 && byte > 3) {
+                return Err(Error::IntegerOverflow);
+            }
+            v |= u128::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_unsigned(&mut self, max: u128) -> Result<u128> {
+        let v = self.read_varint()?;
+        if v > max {
+            return Err(Error::IntegerOverflow);
+        }
+        Ok(v)
+    }
+
+    fn read_signed(&mut self, min: i128, max: i128) -> Result<i128> {
+        let v = unzigzag(self.read_varint()?);
+        if v < min || v > max {
+            return Err(Error::IntegerOverflow);
+        }
+        Ok(v)
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        Ok(self.read_unsigned(usize::max_value() as u128)? as usize)
+    }
+
+    fn read_str(&mut self) -> Result<&'de str> {
+        let len = self.read_len()?;
+        str::from_utf8(self.read_bytes(len)?).map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.read_byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.read_byte()? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_signed(i16::min_value().into(), i16::max_value().into())?;
+        visitor.visit_i16(v as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_signed(i32::min_value().into(), i32::max_value().into())?;
+        visitor.visit_i32(v as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_signed(i64::min_value().into(), i64::max_value().into())?;
+        visitor.visit_i64(v as i64)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_signed(i128::min_value(), i128::max_value())?;
+        visitor.visit_i128(v)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.read_byte()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_unsigned(u16::max_value().into())?;
+        visitor.visit_u16(v as u16)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_unsigned(u32::max_value().into())?;
+        visitor.visit_u32(v as u32)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_unsigned(u64::max_value().into())?;
+        visitor.visit_u64(v as u64)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u128(self.read_varint()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bits = [0; 4];
+        bits.copy_from_slice(self.read_bytes(4)?);
+        visitor.visit_f32(f32::from_bits(u32::from_le_bytes(bits)))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bits = [0; 8];
+        bits.copy_from_slice(self.read_bytes(8)?);
+        visitor.visit_f64(f64::from_bits(u64::from_le_bytes(bits)))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = self.read_unsigned(u32::max_value().into())?;
+        visitor.visit_char(std::char::from_u32(v as u32).ok_or(Error::InvalidValue)?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.read_str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.read_len()?;
+        visitor.visit_borrowed_bytes(self.read_bytes(len)?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.read_byte()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.read_len()?;
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.read_len()?;
+        visitor.visit_map(Fields { de: self, len })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields {
+            de: self,
+            len: fields.len(),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::NotSelfDescribing)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Access to the `len` elements of a sequence, or entries of a map.
+struct Fields<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    len: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Fields<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        // Don't let a corrupted length make the visitor preallocate a huge buffer.
+        Some(self.len.min(self.de.input.len()))
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Fields<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len.min(self.de.input.len()))
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index = self.read_unsigned(u32::max_value().into())? as u32;
+        let value = seed.deserialize(index.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields {
+            de: self,
+            len: fields.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::Function;
+    use cranelift_codegen::settings::{self, Configurable};
+    use cranelift_codegen::{isa, Context};
+    use cranelift_reader::parse_functions;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+
+    #[test]
+    fn primitives() {
+        assert_eq!(to_vec(&300u32).unwrap(), [0xac, 0x02]);
+        assert_eq!(to_vec(&-1i64).unwrap(), [0x01]);
+        assert_eq!(to_vec(&Some("ab")).unwrap(), [1, 2, b'a', b'b']);
+
+        let value = (
+            u64::max_value(),
+            i128::min_value(),
+            -1.5f64,
+            'λ',
+            vec![Some(true), None],
+        );
+        let bytes = to_vec(&value).unwrap();
+        assert_eq!(
+            from_slice::<(u64, i128, f64, char, Vec<Option<bool>>)>(&bytes),
+            Ok(value)
+        );
+
+        let mut map = HashMap::new();
+        map.insert(String::from("x"), (1u8, -2i16));
+        assert_eq!(from_slice(&to_vec(&map).unwrap()), Ok(map));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(from_slice::<u32>(&[0x80]), Err(Error::UnexpectedEof));
+        assert_eq!(from_slice::<u8>(&[1, 2]), Err(Error::TrailingBytes));
+        assert_eq!(
+            from_slice::<u16>(&to_vec(&0x10000u32).unwrap()),
+            Err(Error::IntegerOverflow)
+        );
+        assert_eq!(from_slice::<bool>(&[2]), Err(Error::InvalidValue));
+        assert_eq!(from_slice::<String>(&[1, 0xff]), Err(Error::InvalidUtf8));
+        assert_eq!(from_slice::<u128>(&[0xff; 20]), Err(Error::IntegerOverflow));
+    }
+
+    #[test]
+    fn function() {
+        let funcs = parse_functions(
+            "function %sum(i32, i64 vmctx) -> i32 system_v {
+                 ss0 = explicit_slot 8
+                 gv0 = vmctx
+                 gv1 = load.i64 notrap aligned gv0+16
+                 heap0 = static gv1, min 0x1000, bound 0x1_0000, offset_guard 0x8000, index_type i32
+                 jt0 = jump_table [ebb1, ebb2]
+                 sig0 = (f32, b1) -> f64 baldrdash
+                 fn0 = colocated u2:7 sig0
+
+             ebb0(v0: i32, v1: i64):
+                 v2 = heap_addr.i64 heap0, v0, 4
+                 v3 = load.i32 notrap v2+8
+                 stack_store v3, ss0
+                 br_table v0, ebb2, jt0
+
+             ebb1:
+                 v4 = f32const -0x1.8p1
+                 v5 = bconst.b1 true
+                 v6 = call fn0(v4, v5)
+                 v7 = fcvt_to_sint.i32 v6
+                 jump ebb2
+
+             ebb2:
+                 v8 = iadd_imm v0, -3
+                 v9 = icmp ult v8, v0
+                 trapz v9, user7
+                 return v8
+             }",
+        )
+        .unwrap();
+
+        let bytes = to_vec(&funcs[0]).unwrap();
+        let func: Function = from_slice(&bytes).unwrap();
+        assert_eq!(
+            func.display(None).to_string(),
+            funcs[0].display(None).to_string()
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let mut builder = settings::builder();
+        builder.set("opt_level", "best").unwrap();
+        builder.enable("enable_verifier").unwrap();
+        let flags = settings::Flags::new(builder);
+
+        let bytes = to_vec(&flags).unwrap();
+        let copy: settings::Flags = from_slice(&bytes).unwrap();
+        assert_eq!(copy.to_string(), flags.to_string());
+    }
+    #[test]
+    fn compiled_function() {
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut ctx = Context::for_function(
+            parse_functions(
+                "function %f(i64, i64) -> i64 {
+                 ebb0(v0: i64, v1: i64):
+                     v2 = imul v0, v1
+                     v3 = iadd v2, v0
+                     return v3
+                 }",
+            )
+            .unwrap()
+            .remove(0),
+        );
+        ctx.compile(&*isa).unwrap();
+
+        // The encodings, value locations, and code offsets chosen by the compiler are preserved.
+        let bytes = to_vec(&ctx.func).unwrap();
+        let func: Function = from_slice(&bytes).unwrap();
+        assert_eq!(
+            func.display(Some(&*isa)).to_string(),
+            ctx.func.display(Some(&*isa)).to_string()
+        );
+        for ebb in func.layout.ebbs() {
+            assert_eq!(func.offsets[ebb], ctx.func.offsets[ebb]);
+        }
+    }
+}
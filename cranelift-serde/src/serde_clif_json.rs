@@ -176,6 +176,35 @@ pub enum SerInstData {
         flags: String,
         offset: String,
     },
+    AtomicLoad {
+        opcode: String,
+        arg: String,
+        flags: String,
+        ordering: String,
+    },
+    AtomicStore {
+        opcode: String,
+        args: [String; 2],
+        flags: String,
+        ordering: String,
+    },
+    AtomicRmw {
+        opcode: String,
+        args: [String; 2],
+        flags: String,
+        op: String,
+        ordering: String,
+    },
+    AtomicCas {
+        opcode: String,
+        args: [String; 3],
+        flags: String,
+        ordering: String,
+    },
+    Fence {
+        opcode: String,
+        ordering: String,
+    },
     StackLoad {
         opcode: String,
         stack_slot: String,
@@ -593,6 +622,60 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
                 offset: offset.to_string(),
             }
         }
+        InstructionData::AtomicLoad {
+            opcode,
+            arg,
+            flags,
+            ordering,
+        } => SerInstData::AtomicLoad {
+            opcode: opcode.to_string(),
+            arg: arg.to_string(),
+            flags: flags.to_string(),
+            ordering: ordering.to_string(),
+        },
+        InstructionData::AtomicStore {
+            opcode,
+            args,
+            flags,
+            ordering,
+        } => SerInstData::AtomicStore {
+            opcode: opcode.to_string(),
+            args: [args[0].to_string(), args[1].to_string()],
+            flags: flags.to_string(),
+            ordering: ordering.to_string(),
+        },
+        InstructionData::AtomicRmw {
+            opcode,
+            args,
+            flags,
+            op,
+            ordering,
+        } => SerInstData::AtomicRmw {
+            opcode: opcode.to_string(),
+            args: [args[0].to_string(), args[1].to_string()],
+            flags: flags.to_string(),
+            op: op.to_string(),
+            ordering: ordering.to_string(),
+        },
+        InstructionData::AtomicCas {
+            opcode,
+            args,
+            flags,
+            ordering,
+        } => SerInstData::AtomicCas {
+            opcode: opcode.to_string(),
+            args: [
+                args[0].to_string(),
+                args[1].to_string(),
+                args[2].to_string(),
+            ],
+            flags: flags.to_string(),
+            ordering: ordering.to_string(),
+        },
+        InstructionData::Fence { opcode, ordering } => SerInstData::Fence {
+            opcode: opcode.to_string(),
+            ordering: ordering.to_string(),
+        },
         InstructionData::StackLoad {
             opcode,
             stack_slot,
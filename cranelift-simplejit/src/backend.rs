@@ -1,32 +1,45 @@
 //! Defines `SimpleJITBackend`.
 
+#[cfg(feature = "gdb-jit")]
+use crate::gdb_jit::GdbJitRegistration;
 use crate::memory::Memory;
+use crate::profiling::ProfilingAgent;
+use crate::tls::{self, TlsIndex};
 use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, ir, settings};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleNamespace, ModuleResult,
+    Backend, DataContext, DataDescription, DataStorage, FuncId, Init, LazyHandle, Linkage,
+    ModuleError, ModuleNamespace, ModuleResult,
 };
 use cranelift_native;
 #[cfg(not(windows))]
 use libc;
 use std::collections::HashMap;
 use std::ffi::CString;
-use std::io::Write;
+use std::mem;
 use std::ptr;
-use target_lexicon::PointerWidth;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use target_lexicon::{Architecture, OperatingSystem, PointerWidth};
 #[cfg(windows)]
 use winapi;
 
-const EXECUTABLE_DATA_ALIGNMENT: u8 = 0x10;
-const WRITABLE_DATA_ALIGNMENT: u8 = 0x8;
-const READONLY_DATA_ALIGNMENT: u8 = 0x1;
+const EXECUTABLE_DATA_ALIGNMENT: u64 = 0x10;
+const WRITABLE_DATA_ALIGNMENT: u64 = 0x8;
+const READONLY_DATA_ALIGNMENT: u64 = 0x1;
+
+/// The size of the jumps generated by `absolute_jump` and `indirect_jump`.
+const JUMP_SIZE: usize = 13;
 
 /// A builder for `SimpleJITBackend`.
 pub struct SimpleJITBuilder {
     isa: Box<dyn TargetIsa>,
     symbols: HashMap<String, *const u8>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    profiler: Option<Box<dyn ProfilingAgent>>,
+    #[cfg(feature = "gdb-jit")]
+    gdb_jit: bool,
+    hotswap: bool,
 }
 
 impl SimpleJITBuilder {
@@ -67,6 +80,10 @@ impl SimpleJITBuilder {
             isa,
             symbols,
             libcall_names,
+            profiler: None,
+            #[cfg(feature = "gdb-jit")]
+            gdb_jit: false,
+            hotswap: false,
         }
     }
 
@@ -105,6 +122,39 @@ impl SimpleJITBuilder {
         }
         self
     }
+
+    /// Describe the functions defined by the JIT to a profiler, through `agent`.
+    ///
+    /// Functions are described once they are finalized. No profiler is used by default.
+    pub fn profiler(&mut self, agent: Box<dyn ProfilingAgent>) -> &Self {
+        self.profiler = Some(agent);
+        self
+    }
+
+    /// Register the functions defined by the JIT with debuggers, so that they are named in
+    /// backtraces.
+    ///
+    /// This uses the GDB JIT interface, which is supported by gdb and lldb. It is disabled by
+    /// default, since debuggers process each registration while they are attached.
+    ///
+    /// The interface is made of symbols which can only be defined once in a process, so this is
+    /// only available with the `gdb-jit` feature, which conflicts with other JITs that define
+    /// them, like LLVM's.
+    #[cfg(feature = "gdb-jit")]
+    pub fn gdb_jit(&mut self, enable: bool) -> &Self {
+        self.gdb_jit = enable;
+        self
+    }
+
+    /// Allow functions to be redefined with `Module::redefine_function`.
+    ///
+    /// Calls to functions then go through a jump slot holding the address of their current body,
+    /// and each body is allocated in pages of its own, so that the replaced bodies can be freed
+    /// with `Module::free_replaced_functions`. This is currently only supported on x86-64.
+    pub fn hotswap(&mut self, enable: bool) -> &Self {
+        self.hotswap = enable;
+        self
+    }
 }
 
 /// A `SimpleJITBackend` implements `Backend` and emits code and data into memory where it can be
@@ -118,6 +168,32 @@ pub struct SimpleJITBackend {
     code_memory: Memory,
     readonly_memory: Memory,
     writable_memory: Memory,
+    profiler: Option<Box<dyn ProfilingAgent>>,
+    /// The registrations of functions with debuggers, if enabled.
+    #[cfg(feature = "gdb-jit")]
+    gdb_jit: Option<Vec<GdbJitRegistration>>,
+    /// Whether functions can be redefined, see `SimpleJITBuilder::hotswap`.
+    hotswap: bool,
+    /// The jump slots of the functions finalized since the last `publish`, and their bodies.
+    pending_slots: Vec<(*const AtomicPtr<u8>, *mut u8)>,
+    /// The bodies with pages of their own which were replaced since the last `publish`.
+    pending_replaced: Vec<*mut u8>,
+    /// The bodies with pages of their own which can't be reached from jump slots anymore.
+    replaced: Vec<*mut u8>,
+    /// The handle called by lazy compilation stubs, which is leaked along with the code memory
+    /// when the backend is dropped.
+    lazy_handle: Option<LazyHandle>,
+    /// The jump to `tls::tls_get_addr` which calls to `__tls_get_addr` are resolved to, once a
+    /// thread-local data object is defined.
+    tls_get_addr: Option<*const u8>,
+}
+
+impl Drop for SimpleJITBackend {
+    fn drop(&mut self) {
+        if let Some(handle) = self.lazy_handle.take() {
+            mem::forget(handle);
+        }
+    }
 }
 
 /// A record of a relocation to perform.
@@ -128,16 +204,44 @@ struct RelocRecord {
     addend: Addend,
 }
 
+/// An indirect jump to the current body of a function which can be redefined.
+#[derive(Copy, Clone)]
+struct JumpSlot {
+    /// The code which jumps to the address in `target`.
+    entry: *const u8,
+    target: *const AtomicPtr<u8>,
+}
+
 pub struct SimpleJITCompiledFunction {
     code: *mut u8,
     size: usize,
     relocs: Vec<RelocRecord>,
+    /// The jump slot which calls to the function go through, if it can be redefined.
+    slot: Option<JumpSlot>,
+    /// Whether `code` was allocated in pages of its own.
+    own_pages: bool,
+    /// The name and source locations to describe the function to the profiler with once it's
+    /// finalized, if there is a profiler.
+    profile: Option<(String, Vec<(CodeOffset, ir::SourceLoc)>)>,
+}
+
+impl SimpleJITCompiledFunction {
+    /// Return the address which calls to the function go to.
+    fn entry(&self) -> *const u8 {
+        match self.slot {
+            Some(slot) => slot.entry,
+            None => self.code,
+        }
+    }
 }
 
 pub struct SimpleJITCompiledData {
     storage: *mut u8,
     size: usize,
     relocs: Vec<RelocRecord>,
+    /// For thread-local data objects, the index describing them, whose `storage` is the initial
+    /// contents of each thread's instance.
+    tls_index: Option<*const TlsIndex>,
 }
 
 impl SimpleJITBackend {
@@ -158,7 +262,7 @@ impl SimpleJITBackend {
                 if namespace.is_function(name) {
                     let (def, name_str, _signature) = namespace.get_function_definition(&name);
                     match def {
-                        Some(compiled) => compiled.code,
+                        Some(compiled) => compiled.entry(),
                         None => self.lookup_symbol(name_str),
                     }
                 } else {
@@ -169,6 +273,10 @@ impl SimpleJITBackend {
                     }
                 }
             }
+            ir::ExternalName::LibCall(ir::LibCall::ElfTlsGetAddr) => match self.tls_get_addr {
+                Some(ptr) => ptr,
+                None => panic!("can't access thread-local data objects which aren't defined"),
+            },
             ir::ExternalName::LibCall(ref libcall) => {
                 let sym = (self.libcall_names)(*libcall);
                 self.lookup_symbol(&sym)
@@ -176,6 +284,158 @@ impl SimpleJITBackend {
             _ => panic!("invalid ExternalName {}", name),
         }
     }
+
+    /// Return the address of the `TlsIndex` of the thread-local data object `name`.
+    fn get_tls_index(
+        &self,
+        namespace: &ModuleNamespace<Self>,
+        name: &ir::ExternalName,
+    ) -> *const u8 {
+        let (def, name_str, _writable) = namespace.get_data_definition(name);
+        match def.and_then(|compiled| compiled.tls_index) {
+            Some(index) => index as *const u8,
+            None => panic!(
+                "can't access thread-local data object {} which isn't defined",
+                name_str
+            ),
+        }
+    }
+
+    /// Emit the code of the function in `ctx` into executable memory.
+    fn emit_function(
+        &mut self,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        code_size: u32,
+    ) -> SimpleJITCompiledFunction {
+        let size = code_size as usize;
+        let ptr = if self.hotswap {
+            self.code_memory.allocate_pages(size)
+        } else {
+            self.code_memory.allocate(size, EXECUTABLE_DATA_ALIGNMENT)
+        }
+        .expect("TODO: handle OOM etc.");
+
+        let mut reloc_sink = SimpleJITRelocSink::new();
+        // Ignore traps for now. For now, frontends should just avoid generating code
+        // that traps.
+        let mut trap_sink = NullTrapSink {};
+        unsafe { ctx.emit_to_memory(&*self.isa, ptr, &mut reloc_sink, &mut trap_sink) };
+
+        let profile = if self.profiler.is_some() {
+            let srclocs = ctx
+                .address_map(&*self.isa)
+                .entries()
+                .iter()
+                .filter(|&&(_, srcloc)| !srcloc.is_default())
+                .cloned()
+                .collect::<Vec<_>>();
+            Some((name.to_string(), srclocs))
+        } else {
+            None
+        };
+        #[cfg(feature = "gdb-jit")]
+        {
+            if let Some(ref mut registrations) = self.gdb_jit {
+                registrations.push(GdbJitRegistration::new(name, ptr, size));
+            }
+        }
+
+        SimpleJITCompiledFunction {
+            code: ptr,
+            size,
+            relocs: reloc_sink.relocs,
+            slot: None,
+            own_pages: self.hotswap,
+            profile,
+        }
+    }
+
+    /// Allocate a jump slot, which `publish` points at the body of its function.
+    fn allocate_slot(&mut self) -> JumpSlot {
+        let target = self
+            .writable_memory
+            .allocate(
+                mem::size_of::<AtomicPtr<u8>>(),
+                mem::align_of::<AtomicPtr<u8>>() as u64,
+            )
+            .expect("TODO: handle OOM etc.") as *mut AtomicPtr<u8>;
+        unsafe { ptr::write(target, AtomicPtr::new(ptr::null_mut())) };
+
+        let jump = indirect_jump(target);
+        let entry = self
+            .code_memory
+            .allocate(jump.len(), EXECUTABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.");
+        unsafe { ptr::copy_nonoverlapping(jump.as_ptr(), entry, jump.len()) };
+        JumpSlot { entry, target }
+    }
+}
+
+/// Generate the code of an absolute jump to `to`, which clobbers `%r11`.
+fn absolute_jump(to: *const u8) -> [u8; JUMP_SIZE] {
+    let mut jump = [0u8; JUMP_SIZE];
+    // movabs $to, %r11
+    jump[0] = 0x49;
+    jump[1] = 0xbb;
+    jump[2..10].copy_from_slice(&(to as u64).to_le_bytes());
+    // jmp *%r11
+    jump[10..].copy_from_slice(&[0x41, 0xff, 0xe3]);
+    jump
+}
+
+/// Generate the code of a jump to the address in `slot`, which clobbers `%r11`.
+fn indirect_jump(slot: *const AtomicPtr<u8>) -> [u8; JUMP_SIZE] {
+    let mut jump = [0u8; JUMP_SIZE];
+    // movabs $slot, %r11
+    jump[0] = 0x49;
+    jump[1] = 0xbb;
+    jump[2..10].copy_from_slice(&(slot as u64).to_le_bytes());
+    // jmp *(%r11)
+    jump[10..].copy_from_slice(&[0x41, 0xff, 0x23]);
+    jump
+}
+
+/// Generate the code of a stub which calls `callback(data, id)`, preserving the argument
+/// registers, and then jumps to the address in `slot`.
+///
+/// By the time `callback` returns, `slot` has been pointed at the compiled body.
+fn lazy_stub(
+    callback: extern "C" fn(*const u8, u32),
+    data: *const u8,
+    id: u32,
+    slot: *const AtomicPtr<u8>,
+) -> Vec<u8> {
+    let mut code = Vec::new();
+    // push %rdi, %rsi, %rdx, %rcx, %r8, %r9
+    code.extend_from_slice(&[0x57, 0x56, 0x52, 0x51, 0x41, 0x50, 0x41, 0x51]);
+    // sub $0x88, %rsp, leaving room for %xmm0-7 and realigning the stack
+    code.extend_from_slice(&[0x48, 0x81, 0xec, 0x88, 0x00, 0x00, 0x00]);
+    for i in 0..8 {
+        // movdqu %xmm<i>, <16 * i>(%rsp)
+        code.extend_from_slice(&[0xf3, 0x0f, 0x7f, 0x44 | (i << 3), 0x24, 16 * i]);
+    }
+    // movabs $data, %rdi
+    code.extend_from_slice(&[0x48, 0xbf]);
+    code.extend_from_slice(&(data as u64).to_le_bytes());
+    // mov $id, %esi
+    code.push(0xbe);
+    code.extend_from_slice(&id.to_le_bytes());
+    // movabs $callback, %rax
+    code.extend_from_slice(&[0x48, 0xb8]);
+    code.extend_from_slice(&(callback as usize as u64).to_le_bytes());
+    // call *%rax
+    code.extend_from_slice(&[0xff, 0xd0]);
+    for i in 0..8 {
+        // movdqu <16 * i>(%rsp), %xmm<i>
+        code.extend_from_slice(&[0xf3, 0x0f, 0x6f, 0x44 | (i << 3), 0x24, 16 * i]);
+    }
+    // add $0x88, %rsp
+    code.extend_from_slice(&[0x48, 0x81, 0xc4, 0x88, 0x00, 0x00, 0x00]);
+    // pop %r9, %r8, %rcx, %rdx, %rsi, %rdi
+    code.extend_from_slice(&[0x41, 0x59, 0x41, 0x58, 0x59, 0x5a, 0x5e, 0x5f]);
+    code.extend_from_slice(&indirect_jump(slot));
+    code
 }
 
 impl<'simple_jit_backend> Backend for SimpleJITBackend {
@@ -190,6 +450,8 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
 
     /// SimpleJIT emits code and data into memory, and provides raw pointers
     /// to them.
+    ///
+    /// The data of a thread-local data object is the instance of the thread which requested it.
     type FinalizedFunction = *const u8;
     type FinalizedData = (*mut u8, usize);
 
@@ -206,6 +468,19 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             code_memory: Memory::new(),
             readonly_memory: Memory::new(),
             writable_memory: Memory::new(),
+            profiler: builder.profiler,
+            #[cfg(feature = "gdb-jit")]
+            gdb_jit: if builder.gdb_jit {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            hotswap: builder.hotswap,
+            pending_slots: Vec::new(),
+            pending_replaced: Vec::new(),
+            replaced: Vec::new(),
+            lazy_handle: None,
+            tls_get_addr: None,
         }
     }
 
@@ -222,6 +497,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         _name: &str,
         _linkage: Linkage,
         _writable: bool,
+        _storage: DataStorage,
         _align: Option<u8>,
     ) {
         // Nothing to do.
@@ -234,32 +510,110 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         _namespace: &ModuleNamespace<Self>,
         code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction> {
-        let size = code_size as usize;
+        if self.hotswap && self.isa.triple().architecture != Architecture::X86_64 {
+            return Err(ModuleError::Backend(format!(
+                "SimpleJIT doesn't support hotswapping functions on {}",
+                self.isa.triple().architecture
+            )));
+        }
+
+        let mut compiled = self.emit_function(name, ctx, code_size);
+        if self.hotswap {
+            compiled.slot = Some(self.allocate_slot());
+        }
+        Ok(compiled)
+    }
+
+    /// SimpleJIT emits the new body into fresh memory. When it is published, the jump slot of the
+    /// function is atomically pointed at it, so that existing callers and function pointers reach
+    /// the new code. Only functions defined with `SimpleJITBuilder::hotswap` enabled, or defined
+    /// lazily, have a jump slot.
+    fn redefine_function(
+        &mut self,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        _namespace: &ModuleNamespace<Self>,
+        code_size: u32,
+        old: &Self::CompiledFunction,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        let slot = match old.slot {
+            Some(slot) => slot,
+            None => {
+                return Err(ModuleError::Backend(format!(
+                    "SimpleJIT can only redefine function {} with hotswap enabled",
+                    name
+                )))
+            }
+        };
+
+        let mut compiled = self.emit_function(name, ctx, code_size);
+        compiled.slot = Some(slot);
+        if old.own_pages {
+            self.pending_replaced.push(old.code);
+        }
+        Ok(compiled)
+    }
+
+    /// SimpleJIT frees the bodies replaced by `redefine_function`, if they were allocated in pages
+    /// of their own with `SimpleJITBuilder::hotswap` enabled.
+    unsafe fn free_replaced_functions(&mut self) {
+        for code in self.replaced.drain(..) {
+            self.code_memory.free_pages(code);
+        }
+    }
+
+    /// SimpleJIT stubs save the argument registers and call the callback of `handle`, which points
+    /// the jump slot of the function at the compiled body, before jumping through it.
+    ///
+    /// This is currently only supported on x86-64 with the System V calling convention.
+    fn define_function_stub(
+        &mut self,
+        name: &str,
+        id: FuncId,
+        handle: &LazyHandle,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        let triple = self.isa.triple();
+        if triple.architecture != Architecture::X86_64
+            || triple.operating_system == OperatingSystem::Windows
+        {
+            return Err(ModuleError::Backend(format!(
+                "SimpleJIT doesn't support lazily compiling functions on {}",
+                triple
+            )));
+        }
+
+        if self.lazy_handle.is_none() {
+            self.lazy_handle = Some(handle.clone());
+        }
+        let slot = self.allocate_slot();
+        let stub = lazy_stub(handle.callback(), handle.data(), id.as_u32(), slot.target);
         let ptr = self
             .code_memory
-            .allocate(size, EXECUTABLE_DATA_ALIGNMENT)
+            .allocate(stub.len(), EXECUTABLE_DATA_ALIGNMENT)
             .expect("TODO: handle OOM etc.");
+        unsafe { ptr::copy_nonoverlapping(stub.as_ptr(), ptr, stub.len()) };
 
-        if cfg!(target_os = "linux") && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
-            let mut map_file = ::std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(format!("/tmp/perf-{}.map", ::std::process::id()))
-                .unwrap();
-
-            let _ = writeln!(map_file, "{:x} {:x} {}", ptr as usize, code_size, name);
+        #[cfg(feature = "gdb-jit")]
+        {
+            if let Some(ref mut registrations) = self.gdb_jit {
+                registrations.push(GdbJitRegistration::new(
+                    &format!("{}_stub", name),
+                    ptr,
+                    stub.len(),
+                ));
+            }
         }
 
-        let mut reloc_sink = SimpleJITRelocSink::new();
-        // Ignore traps for now. For now, frontends should just avoid generating code
-        // that traps.
-        let mut trap_sink = NullTrapSink {};
-        unsafe { ctx.emit_to_memory(&*self.isa, ptr, &mut reloc_sink, &mut trap_sink) };
-
         Ok(Self::CompiledFunction {
             code: ptr,
-            size,
-            relocs: reloc_sink.relocs,
+            size: stub.len(),
+            relocs: Vec::new(),
+            slot: Some(slot),
+            own_pages: false,
+            profile: self
+                .profiler
+                .as_ref()
+                .map(|_| (format!("{}_stub", name), Vec::new())),
         })
     }
 
@@ -267,20 +621,47 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         &mut self,
         _name: &str,
         writable: bool,
+        storage: DataStorage,
         align: Option<u8>,
         data: &DataContext,
         _namespace: &ModuleNamespace<Self>,
     ) -> ModuleResult<Self::CompiledData> {
+        let tls = storage == DataStorage::ThreadLocal;
+        if tls {
+            let triple = self.isa.triple();
+            if triple.architecture != Architecture::X86_64
+                || triple.operating_system == OperatingSystem::Windows
+                || self.isa.flags().tls_model() != settings::TlsModel::ElfGd
+            {
+                return Err(ModuleError::Backend(format!(
+                    "SimpleJIT only supports thread-local data objects with the elf_gd TLS \
+                     model on x86-64, not {} with {}",
+                    triple,
+                    self.isa.flags().tls_model()
+                )));
+            }
+        }
+
         let &DataDescription {
             ref init,
             ref function_decls,
             ref data_decls,
             ref function_relocs,
             ref data_relocs,
+            align: requested_align,
+            ..
         } = data.description();
 
+        // Custom sections and relro have no meaning here: read-only data is only protected once
+        // its relocations are applied anyway.
         let size = init.size();
-        let storage = if writable {
+        let align = align.map(u64::from).max(requested_align);
+        let storage = if tls {
+            // The initial contents are only copied into each thread's instance.
+            self.readonly_memory
+                .allocate(size, READONLY_DATA_ALIGNMENT)
+                .expect("TODO: handle OOM etc.")
+        } else if writable {
             self.writable_memory
                 .allocate(size, align.unwrap_or(WRITABLE_DATA_ALIGNMENT))
                 .expect("TODO: handle OOM etc.")
@@ -326,10 +707,39 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             });
         }
 
+        let tls_index = if tls {
+            if self.tls_get_addr.is_none() {
+                let jump = absolute_jump(tls::tls_get_addr as *const u8);
+                let ptr = self
+                    .code_memory
+                    .allocate(jump.len(), EXECUTABLE_DATA_ALIGNMENT)
+                    .expect("TODO: handle OOM etc.");
+                unsafe { ptr::copy_nonoverlapping(jump.as_ptr(), ptr, jump.len()) };
+                self.tls_get_addr = Some(ptr);
+            }
+            let index = TlsIndex::new(
+                storage,
+                size,
+                align.unwrap_or(WRITABLE_DATA_ALIGNMENT) as usize,
+            );
+            let ptr = self
+                .readonly_memory
+                .allocate(
+                    mem::size_of::<TlsIndex>(),
+                    mem::align_of::<TlsIndex>() as u64,
+                )
+                .expect("TODO: handle OOM etc.") as *mut TlsIndex;
+            unsafe { ptr::write(ptr, index) };
+            Some(ptr as *const TlsIndex)
+        } else {
+            None
+        };
+
         Ok(Self::CompiledData {
             storage,
             size,
             relocs,
+            tls_index,
         })
     }
 
@@ -369,7 +779,11 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             let ptr = func.code;
             debug_assert!((offset as usize) < func.size);
             let at = unsafe { ptr.offset(offset as isize) };
-            let base = self.get_definition(namespace, name);
+            let base = if reloc == Reloc::ElfX86_64TlsGd {
+                self.get_tls_index(namespace, name)
+            } else {
+                self.get_definition(namespace, name)
+            };
             // TODO: Handle overflow.
             let what = unsafe { base.offset(addend as isize) };
             match reloc {
@@ -386,7 +800,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                         write_unaligned(at as *mut u64, what as u64)
                     };
                 }
-                Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
+                Reloc::X86PCRel4 | Reloc::X86CallPCRel4 | Reloc::ElfX86_64TlsGd => {
                     // TODO: Handle overflow.
                     let pcrel = ((what as isize) - (at as isize)) as i32;
                     #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
@@ -394,15 +808,32 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                         write_unaligned(at as *mut i32, pcrel)
                     };
                 }
+                // `__tls_get_addr` is resolved to a jump in the code memory, so it's in range.
+                Reloc::X86CallPLTRel4
+                    if *name == ir::ExternalName::LibCall(ir::LibCall::ElfTlsGetAddr) =>
+                {
+                    let pcrel = ((what as isize) - (at as isize)) as i32;
+                    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                    unsafe {
+                        write_unaligned(at as *mut i32, pcrel)
+                    };
+                }
                 Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
                 _ => unimplemented!(),
             }
         }
-        func.code
+        // Profilers such as jitdump copy the code, so it's only described once it's relocated.
+        if let (Some(profiler), Some((name, srclocs))) = (&mut self.profiler, &func.profile) {
+            profiler.define_function(name, func.code, func.size, srclocs);
+        }
+        if let Some(slot) = func.slot {
+            self.pending_slots.push((slot.target, func.code));
+        }
+        func.entry()
     }
 
     fn get_finalized_function(&self, func: &Self::CompiledFunction) -> Self::FinalizedFunction {
-        func.code
+        func.entry()
     }
 
     fn finalize_data(
@@ -446,17 +877,26 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                 _ => unimplemented!(),
             }
         }
-        (data.storage, data.size)
+        self.get_finalized_data(data)
     }
 
     fn get_finalized_data(&self, data: &Self::CompiledData) -> Self::FinalizedData {
-        (data.storage, data.size)
+        match data.tls_index {
+            Some(index) => (tls::tls_get_addr(unsafe { &*index }), data.size),
+            None => (data.storage, data.size),
+        }
     }
 
     fn publish(&mut self) {
         // Now that we're done patching, prepare the memory for execution!
         self.readonly_memory.set_readonly();
         self.code_memory.set_readable_and_executable();
+
+        // Point the jump slots at the new bodies, now that they can be executed.
+        for (slot, code) in self.pending_slots.drain(..) {
+            unsafe { (*slot).store(code, Ordering::Release) };
+        }
+        self.replaced.append(&mut self.pending_replaced);
     }
 
     /// SimpleJIT emits code and data into memory as it processes them, so it
@@ -0,0 +1,281 @@
+//! Registration of JIT-compiled code with debuggers, through the GDB JIT interface.
+//!
+//! Debuggers such as gdb and lldb set a breakpoint in `__jit_debug_register_code`, and read the
+//! linked list of in-memory object files from `__jit_debug_descriptor` when it is hit. Each
+//! registered function is described by a small ELF object file whose `.text` section is placed at
+//! the address of its code, with a symbol giving its name.
+//!
+//! There can only be one definition of these symbols in a process, so this module is only built
+//! with the `gdb-jit` feature, which must not be enabled when another JIT in the same process,
+//! like LLVM's, defines them too.
+
+use crate::profiling::elf_machine;
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// The action for the debugger to take.
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+pub struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// A `JitDescriptor` which is only accessed while holding a `DescriptorLock`.
+#[repr(transparent)]
+pub struct SharedDescriptor(UnsafeCell<JitDescriptor>);
+
+unsafe impl Sync for SharedDescriptor {}
+
+/// The descriptor the debugger reads the registered object files from.
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static __jit_debug_descriptor: SharedDescriptor =
+    SharedDescriptor(UnsafeCell::new(JitDescriptor {
+        version: 1,
+        action_flag: JIT_NOACTION,
+        relevant_entry: ptr::null_mut(),
+        first_entry: ptr::null_mut(),
+    }));
+
+/// The function the debugger sets a breakpoint in, to be told about changes to the descriptor.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn __jit_debug_register_code() {
+    // Make sure that calls to this function aren't optimized away.
+    unsafe { ptr::read_volatile(&(*__jit_debug_descriptor.0.get()).action_flag) };
+}
+
+/// Whether a thread is updating `__jit_debug_descriptor`.
+static DESCRIPTOR_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Exclusive access to `__jit_debug_descriptor`, until dropped.
+struct DescriptorLock;
+
+impl DescriptorLock {
+    fn acquire() -> Self {
+        while DESCRIPTOR_LOCKED
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            thread::yield_now();
+        }
+        DescriptorLock
+    }
+
+    fn descriptor(&self) -> *mut JitDescriptor {
+        __jit_debug_descriptor.0.get()
+    }
+}
+
+impl Drop for DescriptorLock {
+    fn drop(&mut self) {
+        DESCRIPTOR_LOCKED.store(false, Ordering::Release);
+    }
+}
+
+/// A function registered with the GDB JIT interface, which is unregistered when dropped.
+pub(crate) struct GdbJitRegistration {
+    entry: *mut JitCodeEntry,
+    /// The object file describing the function, which must outlive the registration.
+    _image: Vec<u8>,
+}
+
+impl GdbJitRegistration {
+    /// Register the function `name`, whose code is the `size` bytes at `code`.
+    pub fn new(name: &str, code: *const u8, size: usize) -> Self {
+        let image = elf_image(name, code, size);
+        let entry = Box::into_raw(Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: image.as_ptr(),
+            symfile_size: image.len() as u64,
+        }));
+
+        let lock = DescriptorLock::acquire();
+        unsafe {
+            let descriptor = lock.descriptor();
+            (*entry).next_entry = (*descriptor).first_entry;
+            if !(*entry).next_entry.is_null() {
+                (*(*entry).next_entry).prev_entry = entry;
+            }
+            (*descriptor).first_entry = entry;
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+            (*descriptor).action_flag = JIT_NOACTION;
+        }
+
+        Self {
+            entry,
+            _image: image,
+        }
+    }
+}
+
+impl Drop for GdbJitRegistration {
+    fn drop(&mut self) {
+        let lock = DescriptorLock::acquire();
+        unsafe {
+            let descriptor = lock.descriptor();
+            let entry = self.entry;
+            if (*entry).prev_entry.is_null() {
+                (*descriptor).first_entry = (*entry).next_entry;
+            } else {
+                (*(*entry).prev_entry).next_entry = (*entry).next_entry;
+            }
+            if !(*entry).next_entry.is_null() {
+                (*(*entry).next_entry).prev_entry = (*entry).prev_entry;
+            }
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+            (*descriptor).action_flag = JIT_NOACTION;
+            (*descriptor).relevant_entry = ptr::null_mut();
+            drop(Box::from_raw(entry));
+        }
+    }
+}
+
+/// Build a relocatable ELF object file describing the function `name`, whose code is the `size`
+/// bytes at `code`.
+///
+/// The `.text` section holds no data, but its address is that of the code, which is how
+/// debuggers place the symbols of relocatable files.
+fn elf_image(name: &str, code: *const u8, size: usize) -> Vec<u8> {
+    const EHDR_SIZE: usize = 64;
+    const SHDR_SIZE: usize = 64;
+    const SYM_SIZE: usize = 24;
+    const SHSTRTAB: &[u8] = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+
+    let mut strtab = vec![0];
+    strtab.extend_from_slice(name.as_bytes());
+    strtab.push(0);
+
+    let symtab_offset = EHDR_SIZE;
+    let symtab_size = 2 * SYM_SIZE;
+    let strtab_offset = symtab_offset + symtab_size;
+    let shstrtab_offset = strtab_offset + strtab.len();
+    let shdrs_offset = (shstrtab_offset + SHSTRTAB.len() + 7) & !7;
+
+    let mut image = Vec::with_capacity(shdrs_offset + 5 * SHDR_SIZE);
+
+    // The file header.
+    image.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2]);
+    image.push(if cfg!(target_endian = "little") { 1 } else { 2 });
+    image.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    image.extend_from_slice(&1u16.to_ne_bytes()); // ET_REL
+    image.extend_from_slice(&(elf_machine() as u16).to_ne_bytes());
+    image.extend_from_slice(&1u32.to_ne_bytes());
+    image.extend_from_slice(&0u64.to_ne_bytes());
+    image.extend_from_slice(&0u64.to_ne_bytes());
+    image.extend_from_slice(&(shdrs_offset as u64).to_ne_bytes());
+    image.extend_from_slice(&0u32.to_ne_bytes());
+    image.extend_from_slice(&(EHDR_SIZE as u16).to_ne_bytes());
+    image.extend_from_slice(&0u16.to_ne_bytes());
+    image.extend_from_slice(&0u16.to_ne_bytes());
+    image.extend_from_slice(&(SHDR_SIZE as u16).to_ne_bytes());
+    image.extend_from_slice(&5u16.to_ne_bytes());
+    image.extend_from_slice(&4u16.to_ne_bytes());
+    debug_assert_eq!(image.len(), EHDR_SIZE);
+
+    // The symbol table: the null symbol, then the function at the start of `.text`.
+    image.extend_from_slice(&[0; SYM_SIZE]);
+    image.extend_from_slice(&1u32.to_ne_bytes());
+    image.push(0x12); // STB_GLOBAL, STT_FUNC
+    image.push(0);
+    image.extend_from_slice(&1u16.to_ne_bytes());
+    image.extend_from_slice(&0u64.to_ne_bytes());
+    image.extend_from_slice(&(size as u64).to_ne_bytes());
+
+    image.extend_from_slice(&strtab);
+    image.extend_from_slice(SHSTRTAB);
+    image.resize(shdrs_offset, 0);
+
+    let mut section = |name: u32,
+                       ty: u32,
+                       flags: u64,
+                       addr: u64,
+                       offset: usize,
+                       size: usize,
+                       link: u32,
+                       info: u32,
+                       align: u64,
+                       entsize: u64| {
+        image.extend_from_slice(&name.to_ne_bytes());
+        image.extend_from_slice(&ty.to_ne_bytes());
+        image.extend_from_slice(&flags.to_ne_bytes());
+        image.extend_from_slice(&addr.to_ne_bytes());
+        image.extend_from_slice(&(offset as u64).to_ne_bytes());
+        image.extend_from_slice(&(size as u64).to_ne_bytes());
+        image.extend_from_slice(&link.to_ne_bytes());
+        image.extend_from_slice(&info.to_ne_bytes());
+        image.extend_from_slice(&align.to_ne_bytes());
+        image.extend_from_slice(&entsize.to_ne_bytes());
+    };
+    section(0, 0, 0, 0, 0, 0, 0, 0, 0, 0);
+    // .text: SHT_NOBITS, SHF_ALLOC | SHF_EXECINSTR
+    section(1, 8, 6, code as u64, EHDR_SIZE, size, 0, 0, 16, 0);
+    // .symtab: SHT_SYMTAB, linked to .strtab, with one local symbol
+    section(
+        7,
+        2,
+        0,
+        0,
+        symtab_offset,
+        symtab_size,
+        3,
+        1,
+        8,
+        SYM_SIZE as u64,
+    );
+    // .strtab and .shstrtab: SHT_STRTAB
+    section(15, 3, 0, 0, strtab_offset, strtab.len(), 0, 0, 1, 0);
+    section(23, 3, 0, 0, shstrtab_offset, SHSTRTAB.len(), 0, 0, 1, 0);
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image() {
+        let image = elf_image("f", 0x1000 as *const u8, 0x20);
+        assert_eq!(&image[..4], b"\x7fELF");
+        assert_eq!(image.len() % 8, 0);
+        let shdrs = image.len() - 5 * 64;
+        let text_addr = &image[shdrs + 64 + 16..shdrs + 64 + 24];
+        assert_eq!(text_addr, &0x1000u64.to_ne_bytes());
+        assert_eq!(&image[64 + 48..64 + 48 + 3], b"\0f\0");
+    }
+
+    #[test]
+    fn register() {
+        let code = [0xc3u8; 4];
+        let first = || unsafe { (*DescriptorLock::acquire().descriptor()).first_entry };
+        let f = GdbJitRegistration::new("f", code.as_ptr(), code.len());
+        let g = GdbJitRegistration::new("g", code.as_ptr(), code.len());
+        assert_eq!(first(), g.entry);
+        drop(g);
+        assert_eq!(first(), f.entry);
+        drop(f);
+        assert!(first().is_null());
+    }
+}
@@ -24,9 +24,16 @@
 )]
 
 mod backend;
+#[cfg(feature = "gdb-jit")]
+mod gdb_jit;
 mod memory;
+mod profiling;
+mod tls;
 
 pub use crate::backend::{SimpleJITBackend, SimpleJITBuilder};
+#[cfg(target_os = "linux")]
+pub use crate::profiling::JitDumpAgent;
+pub use crate::profiling::{PerfMapAgent, ProfilingAgent};
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -103,6 +103,30 @@ impl PtrLen {
             Err(errno::errno().to_string())
         }
     }
+
+    /// Free the memory allocated by `with_size`.
+    #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
+    unsafe fn free(self) {
+        // Unmapping the memory doesn't depend on its protection.
+        drop(self.map);
+    }
+
+    #[cfg(all(not(target_os = "windows"), not(feature = "selinux-fix")))]
+    unsafe fn free(self) {
+        region::protect(self.ptr, self.len, region::Protection::ReadWrite)
+            .expect("unable to make memory writable");
+        libc::free(self.ptr as *mut libc::c_void);
+    }
+
+    #[cfg(target_os = "windows")]
+    unsafe fn free(self) {
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_RELEASE;
+
+        if VirtualFree(self.ptr as *mut _, 0, MEM_RELEASE) == 0 {
+            panic!("unable to free memory");
+        }
+    }
 }
 
 /// JIT memory manager. This manages pages of suitably aligned and
@@ -131,13 +155,14 @@ impl Memory {
     }
 
     /// TODO: Use a proper error type.
-    pub fn allocate(&mut self, size: usize, align: u8) -> Result<*mut u8, String> {
-        if self.position % align as usize != 0 {
-            self.position += align as usize - self.position % align as usize;
-            debug_assert!(self.position % align as usize == 0);
+    pub fn allocate(&mut self, size: usize, align: u64) -> Result<*mut u8, String> {
+        let align = align as usize;
+        if self.position % align != 0 {
+            self.position += align - self.position % align;
+            debug_assert!(self.position % align == 0);
         }
 
-        if size <= self.current.len - self.position {
+        if self.position + size <= self.current.len {
             // TODO: Ensure overflow is not possible.
             let ptr = unsafe { self.current.ptr.add(self.position) };
             self.position += size;
@@ -146,10 +171,44 @@ impl Memory {
 
         self.finish_current();
 
+        // New allocations are page-aligned, so only larger alignments need padding.
+        let padding = if align > region::page::size() {
+            align
+        } else {
+            0
+        };
+
         // TODO: Allocate more at a time.
-        self.current = PtrLen::with_size(size)?;
-        self.position = size;
-        Ok(self.current.ptr)
+        self.current = PtrLen::with_size(size + padding)?;
+        let misalignment = self.current.ptr as usize % align;
+        self.position = if misalignment == 0 {
+            0
+        } else {
+            align - misalignment
+        };
+        let ptr = unsafe { self.current.ptr.add(self.position) };
+        self.position += size;
+        Ok(ptr)
+    }
+
+    /// Allocate `size` bytes in pages of their own, which can be freed with `free_pages`.
+    pub fn allocate_pages(&mut self, size: usize) -> Result<*mut u8, String> {
+        let allocation = PtrLen::with_size(size)?;
+        let ptr = allocation.ptr;
+        self.allocations.push(allocation);
+        Ok(ptr)
+    }
+
+    /// Free the pages allocated by `allocate_pages` at `ptr`.
+    ///
+    /// The memory must not be used anymore.
+    pub unsafe fn free_pages(&mut self, ptr: *mut u8) {
+        let index = self
+            .allocations
+            .iter()
+            .position(|allocation| allocation.ptr == ptr)
+            .expect("pages weren't allocated by allocate_pages");
+        self.allocations.swap_remove(index).free();
     }
 
     /// Set all memory allocated in this `Memory` up to now as readable and executable.
@@ -0,0 +1,271 @@
+//! Profiling agents, which describe the code emitted by `SimpleJITBackend` to profilers.
+
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::ir::SourceLoc;
+#[cfg(target_os = "linux")]
+use libc;
+#[cfg(target_os = "linux")]
+use region;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::process;
+#[cfg(target_os = "linux")]
+use std::{ptr, slice};
+
+/// Receives the functions defined by a `SimpleJITBackend`, to describe them to a profiler.
+pub trait ProfilingAgent {
+    /// Describe the function `name`, whose code is the `size` bytes at `code`.
+    ///
+    /// `srclocs` lists the code offsets at which the source location changes, in increasing
+    /// order, along with the new location.
+    fn define_function(
+        &mut self,
+        name: &str,
+        code: *const u8,
+        size: usize,
+        srclocs: &[(CodeOffset, SourceLoc)],
+    );
+}
+
+/// A `ProfilingAgent` which writes a perf map, `/tmp/perf-<pid>.map`.
+///
+/// `perf report` uses the map to name the JIT-compiled functions in a profile. The map doesn't
+/// hold source locations.
+pub struct PerfMapAgent {
+    file: File,
+}
+
+impl PerfMapAgent {
+    /// Create the perf map of this process, or append to it if it already exists.
+    pub fn new() -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("/tmp/perf-{}.map", process::id()))?;
+        Ok(Self { file })
+    }
+}
+
+impl ProfilingAgent for PerfMapAgent {
+    fn define_function(
+        &mut self,
+        name: &str,
+        code: *const u8,
+        size: usize,
+        _srclocs: &[(CodeOffset, SourceLoc)],
+    ) {
+        let _ = writeln!(self.file, "{:x} {:x} {}", code as usize, size, name);
+    }
+}
+
+/// The magic number at the start of a jitdump file, "JiTD".
+#[cfg(target_os = "linux")]
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+
+/// The jitdump record describing the code of a function.
+#[cfg(target_os = "linux")]
+const JIT_CODE_LOAD: u32 = 0;
+
+/// The jitdump record mapping the code of a function to source lines.
+#[cfg(target_os = "linux")]
+const JIT_CODE_DEBUG_INFO: u32 = 2;
+
+/// A `ProfilingAgent` which writes a jitdump file, `jit-<pid>.dump`.
+///
+/// Unlike a perf map, a jitdump file holds a copy of the code of each function, so that perf can
+/// annotate it, and its source locations. Record a profile with `perf record -k 1`, then merge the
+/// jitdump file into it with `perf inject --jit` before running `perf report`.
+///
+/// Cranelift source locations are opaque, so each one is reported as the line with the same
+/// number in a file named after the function.
+#[cfg(target_os = "linux")]
+pub struct JitDumpAgent {
+    file: File,
+    /// The mapping of the file which tells perf where to find it.
+    marker: *mut libc::c_void,
+    code_index: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl JitDumpAgent {
+    /// Create the jitdump file of this process in `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let path = dir.as_ref().join(format!("jit-{}.dump", process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        // perf finds the jitdump file through an executable mapping of it.
+        let marker = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                region::page::size(),
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&JITDUMP_MAGIC.to_ne_bytes());
+        header.extend_from_slice(&1u32.to_ne_bytes());
+        header.extend_from_slice(&40u32.to_ne_bytes());
+        header.extend_from_slice(&elf_machine().to_ne_bytes());
+        header.extend_from_slice(&0u32.to_ne_bytes());
+        header.extend_from_slice(&process::id().to_ne_bytes());
+        header.extend_from_slice(&timestamp().to_ne_bytes());
+        header.extend_from_slice(&0u64.to_ne_bytes());
+        file.write_all(&header)?;
+
+        Ok(Self {
+            file,
+            marker,
+            code_index: 0,
+        })
+    }
+
+    /// Write a record with the given `id` and contents.
+    fn write_record(&mut self, id: u32, contents: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(16 + contents.len());
+        record.extend_from_slice(&id.to_ne_bytes());
+        record.extend_from_slice(&(16 + contents.len() as u32).to_ne_bytes());
+        record.extend_from_slice(&timestamp().to_ne_bytes());
+        record.extend_from_slice(contents);
+        self.file.write_all(&record)
+    }
+
+    fn write_function(
+        &mut self,
+        name: &str,
+        code: *const u8,
+        size: usize,
+        srclocs: &[(CodeOffset, SourceLoc)],
+    ) -> io::Result<()> {
+        let addr = code as u64;
+
+        // The debug info must precede the code it describes.
+        if !srclocs.is_empty() {
+            let mut debug_info = Vec::new();
+            debug_info.extend_from_slice(&addr.to_ne_bytes());
+            debug_info.extend_from_slice(&(srclocs.len() as u64).to_ne_bytes());
+            for &(offset, srcloc) in srclocs {
+                debug_info.extend_from_slice(&(addr + u64::from(offset)).to_ne_bytes());
+                debug_info.extend_from_slice(&srcloc.bits().to_ne_bytes());
+                debug_info.extend_from_slice(&0u32.to_ne_bytes());
+                debug_info.extend_from_slice(name.as_bytes());
+                debug_info.push(0);
+            }
+            self.write_record(JIT_CODE_DEBUG_INFO, &debug_info)?;
+        }
+
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as u32;
+        let mut code_load = Vec::new();
+        code_load.extend_from_slice(&process::id().to_ne_bytes());
+        code_load.extend_from_slice(&tid.to_ne_bytes());
+        code_load.extend_from_slice(&addr.to_ne_bytes());
+        code_load.extend_from_slice(&addr.to_ne_bytes());
+        code_load.extend_from_slice(&(size as u64).to_ne_bytes());
+        code_load.extend_from_slice(&self.code_index.to_ne_bytes());
+        code_load.extend_from_slice(name.as_bytes());
+        code_load.push(0);
+        code_load.extend_from_slice(unsafe { slice::from_raw_parts(code, size) });
+        self.code_index += 1;
+        self.write_record(JIT_CODE_LOAD, &code_load)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ProfilingAgent for JitDumpAgent {
+    fn define_function(
+        &mut self,
+        name: &str,
+        code: *const u8,
+        size: usize,
+        srclocs: &[(CodeOffset, SourceLoc)],
+    ) {
+        let _ = self.write_function(name, code, size, srclocs);
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for JitDumpAgent {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.marker, region::page::size()) };
+    }
+}
+
+/// Get the `CLOCK_MONOTONIC` time in nanoseconds, which perf uses with `-k 1`.
+#[cfg(target_os = "linux")]
+fn timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// Get the ELF machine of the host.
+pub(crate) fn elf_machine() -> u32 {
+    if cfg!(target_arch = "x86_64") {
+        62
+    } else if cfg!(target_arch = "x86") {
+        3
+    } else if cfg!(target_arch = "aarch64") {
+        183
+    } else if cfg!(target_arch = "arm") {
+        40
+    } else {
+        0
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn jitdump() {
+        let dir = std::env::temp_dir().join(format!("cranelift-jitdump-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let code = [0xc3u8; 4];
+        {
+            let mut agent = JitDumpAgent::new(&dir).unwrap();
+            agent.define_function("f", code.as_ptr(), code.len(), &[(0, SourceLoc::new(7))]);
+        }
+        let dump = fs::read(dir.join(format!("jit-{}.dump", process::id()))).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let u32_at = |offset: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&dump[offset..offset + 4]);
+            u32::from_ne_bytes(bytes)
+        };
+        assert_eq!(u32_at(0), JITDUMP_MAGIC);
+
+        // The debug info comes first, with one entry.
+        assert_eq!(u32_at(40), JIT_CODE_DEBUG_INFO);
+        let debug_info_size = u32_at(44) as usize;
+        assert_eq!(debug_info_size, 16 + 16 + 16 + 2);
+        assert_eq!(u32_at(40 + 32 + 8), 7);
+
+        // Then the code, after the name.
+        let code_load = 40 + debug_info_size;
+        assert_eq!(u32_at(code_load), JIT_CODE_LOAD);
+        assert_eq!(u32_at(code_load + 4) as usize, 16 + 40 + 2 + code.len());
+        assert_eq!(&dump[code_load + 56..code_load + 58], b"f\0");
+        assert_eq!(&dump[code_load + 58..], &code);
+    }
+}
@@ -0,0 +1,81 @@
+//! Thread-local data objects.
+//!
+//! Code compiled with the `elf_gd` TLS model passes the address of a `TlsIndex` to
+//! `__tls_get_addr` to find the calling thread's instance of a data object. The JIT can't register
+//! its data objects with the dynamic linker, so it resolves `__tls_get_addr` to `tls_get_addr`
+//! instead, which allocates the instances of each thread on first use.
+
+use std::alloc::{self, Layout};
+use std::cell::RefCell;
+use std::cmp;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The next key to identify a thread-local data object with, shared by all backends.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// The description of a thread-local data object which the TLS access sequence refers to.
+#[repr(C)]
+pub struct TlsIndex {
+    key: usize,
+    init: *const u8,
+    size: usize,
+    align: usize,
+}
+
+impl TlsIndex {
+    /// Describe a new thread-local data object, whose instances are initialized with the `size`
+    /// bytes at `init`.
+    pub fn new(init: *const u8, size: usize, align: usize) -> Self {
+        Self {
+            key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+            init,
+            size,
+            align,
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::from_size_align(cmp::max(self.size, 1), self.align)
+            .expect("invalid thread-local data object alignment")
+    }
+}
+
+/// The instance of a thread-local data object belonging to a thread.
+struct Instance {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+thread_local! {
+    /// The instances of the thread-local data objects which this thread accessed, by key.
+    static INSTANCES: RefCell<Vec<Option<Instance>>> = RefCell::new(Vec::new());
+}
+
+/// Return the address of the calling thread's instance of the data object described by `index`.
+///
+/// The instance is freed when the thread exits.
+pub extern "C" fn tls_get_addr(index: &TlsIndex) -> *mut u8 {
+    INSTANCES.with(|instances| {
+        let mut instances = instances.borrow_mut();
+        while instances.len() <= index.key {
+            instances.push(None);
+        }
+        let instance = instances[index.key].get_or_insert_with(|| {
+            let layout = index.layout();
+            let ptr = unsafe { alloc::alloc(layout) };
+            if ptr.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            unsafe { ptr::copy_nonoverlapping(index.init, ptr, index.size) };
+            Instance { ptr, layout }
+        });
+        instance.ptr
+    })
+}
@@ -1,3 +1,4 @@
+use cranelift_codegen::binemit::CodeOffset;
 use cranelift_codegen::ir::*;
 use cranelift_codegen::isa::CallConv;
 use cranelift_codegen::{ir::types::I16, Context};
@@ -5,6 +6,9 @@ use cranelift_entity::EntityRef;
 use cranelift_frontend::*;
 use cranelift_module::*;
 use cranelift_simplejit::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 #[test]
 fn error_on_incompatible_sig_in_declare_function() {
@@ -195,3 +199,413 @@ fn libcall_function() {
 
     module.finalize_definitions();
 }
+
+#[test]
+fn define_functions_parallel() {
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::new(default_libcall_names()));
+
+    let sig = Signature {
+        params: vec![AbiParam::new(types::I32)],
+        returns: vec![AbiParam::new(types::I32)],
+        call_conv: CallConv::SystemV,
+    };
+    let func_ids = (0..16)
+        .map(|i| {
+            module
+                .declare_function(&format!("f{}", i), Linkage::Local, &sig)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // Each function calls the previous one and adds 1 to its result.
+    let sizes = module
+        .define_functions_parallel(&func_ids, 4, move |func_id, ctx| {
+            ctx.func =
+                Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig.clone());
+            let mut func_ctx = FunctionBuilderContext::new();
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            bcx.append_ebb_params_for_function_params(ebb);
+            let x = bcx.ebb_params(ebb)[0];
+            let result = match func_id.as_u32() {
+                0 => x,
+                n => {
+                    let signature = bcx.import_signature(sig.clone());
+                    let callee = bcx.import_function(ExtFuncData {
+                        name: ExternalName::user(0, n - 1),
+                        signature,
+                        colocated: true,
+                    });
+                    let call = bcx.ins().call(callee, &[x]);
+                    let y = bcx.inst_results(call)[0];
+                    bcx.ins().iadd_imm(y, 1)
+                }
+            };
+            bcx.ins().return_(&[result]);
+            bcx.seal_all_blocks();
+            bcx.finalize();
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(sizes.len(), func_ids.len());
+
+    module.finalize_definitions();
+    let code = module.get_finalized_function(func_ids[15]);
+    let f15 = unsafe { std::mem::transmute::<_, extern "C" fn(i32) -> i32>(code) };
+    assert_eq!(f15(100), 115);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn redefine_function() {
+    let mut builder = SimpleJITBuilder::new(default_libcall_names());
+    builder.hotswap(true);
+    let mut module: Module<SimpleJITBackend> = Module::new(builder);
+
+    let sig = Signature {
+        params: vec![],
+        returns: vec![AbiParam::new(types::I32)],
+        call_conv: CallConv::SystemV,
+    };
+    let f_id = module.declare_function("f", Linkage::Local, &sig).unwrap();
+    let g_id = module.declare_function("g", Linkage::Local, &sig).unwrap();
+
+    let mut ctx = Context::new();
+    let mut func_ctx = FunctionBuilderContext::new();
+
+    // `f` returns a constant.
+    let mut define_f = |module: &mut Module<SimpleJITBackend>, value: i64| {
+        ctx.clear();
+        ctx.func = Function::with_name_signature(ExternalName::user(0, f_id.as_u32()), sig.clone());
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let result = bcx.ins().iconst(types::I32, value);
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+        module.redefine_function(f_id, &mut ctx).unwrap();
+    };
+
+    define_f(&mut module, 1);
+
+    // `g` returns the result of calling `f`.
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, g_id.as_u32()), sig.clone());
+    {
+        let mut func_ctx = FunctionBuilderContext::new();
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let signature = bcx.import_signature(sig.clone());
+        let callee = bcx.import_function(ExtFuncData {
+            name: ExternalName::user(0, f_id.as_u32()),
+            signature,
+            colocated: true,
+        });
+        let call = bcx.ins().call(callee, &[]);
+        let result = bcx.inst_results(call)[0];
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(g_id, &mut ctx).unwrap();
+
+    module.finalize_definitions();
+    let old_f = unsafe {
+        std::mem::transmute::<_, extern "C" fn() -> i32>(module.get_finalized_function(f_id))
+    };
+    let g = unsafe {
+        std::mem::transmute::<_, extern "C" fn() -> i32>(module.get_finalized_function(g_id))
+    };
+    assert_eq!(old_f(), 1);
+    assert_eq!(g(), 1);
+
+    // Both the old body of `f` and its callers reach the new body.
+    define_f(&mut module, 2);
+    module.finalize_definitions();
+    let new_f = unsafe {
+        std::mem::transmute::<_, extern "C" fn() -> i32>(module.get_finalized_function(f_id))
+    };
+    assert_eq!(new_f(), 2);
+    assert_eq!(old_f(), 2);
+    assert_eq!(g(), 2);
+
+    // The replaced body isn't reachable anymore, so it can be freed.
+    unsafe { module.free_replaced_functions() };
+    assert_eq!(old_f(), 2);
+    assert_eq!(g(), 2);
+}
+
+#[test]
+fn redefine_function_errors() {
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::new(default_libcall_names()));
+
+    let sig = Signature {
+        params: vec![],
+        returns: vec![],
+        call_conv: CallConv::SystemV,
+    };
+    let func_id = define_simple_function(&mut module);
+    let import_id = module
+        .declare_function("import", Linkage::Import, &sig)
+        .unwrap();
+    module.finalize_definitions();
+
+    let mut ctx = Context::new();
+    for &id in &[func_id, import_id] {
+        ctx.clear();
+        ctx.func = Function::with_name_signature(ExternalName::user(0, id.as_u32()), sig.clone());
+        {
+            let mut func_ctx = FunctionBuilderContext::new();
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            bcx.ins().return_(&[]);
+            bcx.seal_all_blocks();
+            bcx.finalize();
+        }
+        // Redefining requires hotswap, and imports can't be defined at all.
+        module.redefine_function(id, &mut ctx).err().unwrap();
+    }
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn define_function_lazily() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::new(default_libcall_names()));
+
+    let sig = Signature {
+        params: vec![AbiParam::new(types::I64), AbiParam::new(types::F64)],
+        returns: vec![AbiParam::new(types::F64)],
+        call_conv: CallConv::SystemV,
+    };
+    let f_id = module.declare_function("f", Linkage::Local, &sig).unwrap();
+
+    // `f` adds its arguments, once it has been compiled.
+    let compiled = Rc::new(Cell::new(0));
+    let counter = compiled.clone();
+    let f_sig = sig.clone();
+    module.set_lazy_compiler(move |_module, func_id, ctx| {
+        counter.set(counter.get() + 1);
+        ctx.func =
+            Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), f_sig.clone());
+        let mut func_ctx = FunctionBuilderContext::new();
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.append_ebb_params_for_function_params(ebb);
+        let (x, y) = (bcx.ebb_params(ebb)[0], bcx.ebb_params(ebb)[1]);
+        let x = bcx.ins().fcvt_from_sint(types::F64, x);
+        let result = bcx.ins().fadd(x, y);
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+        Ok(())
+    });
+    module.define_function_lazily(f_id).unwrap();
+    module.finalize_definitions();
+    assert_eq!(compiled.get(), 0);
+
+    let f = unsafe {
+        std::mem::transmute::<_, extern "C" fn(i64, f64) -> f64>(
+            module.get_finalized_function(f_id),
+        )
+    };
+    module.run(|| {
+        assert_eq!(f(1, 0.5), 1.5);
+        assert_eq!(compiled.get(), 1);
+        assert_eq!(f(2, 0.25), 2.25);
+        assert_eq!(compiled.get(), 1);
+    });
+    assert_eq!(f(3, 0.5), 3.5);
+}
+
+#[test]
+fn define_aligned_data() {
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::new(default_libcall_names()));
+
+    let mut data_ctx = DataContext::new();
+    let mut ids = Vec::new();
+    for &(align, writable) in &[(64, true), (0x4000, true), (0x4000, false)] {
+        let name = format!("data_{:x}_{}", align, writable);
+        let id = module
+            .declare_data(&name, Linkage::Local, writable, DataStorage::Static, None)
+            .unwrap();
+        data_ctx.define_zeroinit(24);
+        data_ctx.set_align(align);
+        data_ctx.set_section(".custom");
+        module.define_data(id, &data_ctx).unwrap();
+        data_ctx.clear();
+        ids.push((id, align));
+    }
+    module.finalize_definitions();
+
+    for (id, align) in ids {
+        let (ptr, size) = module.get_finalized_data(id);
+        assert_eq!(size, 24);
+        assert_eq!(ptr as u64 % align, 0);
+        assert_eq!(unsafe { std::slice::from_raw_parts(ptr, size) }, &[0; 24]);
+    }
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+fn thread_local_data() {
+    use cranelift_codegen::settings::{self, Configurable};
+
+    let mut flag_builder = settings::builder();
+    flag_builder.set("tls_model", "elf_gd").unwrap();
+    let isa = cranelift_codegen::isa::lookup(target_lexicon::Triple::host())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::with_isa(isa, default_libcall_names()));
+
+    let data_id = module
+        .declare_data(
+            "counter",
+            Linkage::Local,
+            true,
+            DataStorage::ThreadLocal,
+            None,
+        )
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(Box::new(5u64.to_le_bytes()));
+    module.define_data(data_id, &data_ctx).unwrap();
+
+    let sig = Signature {
+        params: vec![],
+        returns: vec![AbiParam::new(types::I64)],
+        call_conv: CallConv::SystemV,
+    };
+    let func_id = module
+        .declare_function("increment", Linkage::Local, &sig)
+        .unwrap();
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig);
+    let gv = module.declare_data_in_func(data_id, &mut ctx.func);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let addr = bcx.ins().global_value(types::I64, gv);
+        let value = bcx.ins().load(types::I64, MemFlags::new(), addr, 0);
+        let value = bcx.ins().iadd_imm(value, 1);
+        bcx.ins().store(MemFlags::new(), value, addr, 0);
+        bcx.ins().return_(&[value]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let increment = unsafe {
+        std::mem::transmute::<_, extern "C" fn() -> i64>(module.get_finalized_function(func_id))
+    };
+    assert_eq!(increment(), 6);
+    assert_eq!(increment(), 7);
+    // Each thread starts from the initial contents.
+    let other = std::thread::spawn(move || (increment(), increment()));
+    assert_eq!(other.join().unwrap(), (6, 7));
+    assert_eq!(increment(), 8);
+
+    let (ptr, size) = module.get_finalized_data(data_id);
+    assert_eq!(size, 8);
+    assert_eq!(unsafe { *(ptr as *const u64) }, 8);
+}
+
+#[test]
+fn thread_local_data_without_tls_model() {
+    let isa = cranelift_codegen::isa::lookup(target_lexicon::Triple::host())
+        .unwrap()
+        .finish(cranelift_codegen::settings::Flags::new(
+            cranelift_codegen::settings::builder(),
+        ));
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::with_isa(isa, default_libcall_names()));
+
+    let data_id = module
+        .declare_data(
+            "counter",
+            Linkage::Local,
+            true,
+            DataStorage::ThreadLocal,
+            None,
+        )
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(8);
+    module.define_data(data_id, &data_ctx).err().unwrap();
+}
+
+/// A `ProfilingAgent` which copies the code of the functions it is told about.
+struct RecordingAgent(Rc<RefCell<HashMap<String, Vec<u8>>>>);
+
+impl ProfilingAgent for RecordingAgent {
+    fn define_function(
+        &mut self,
+        name: &str,
+        code: *const u8,
+        size: usize,
+        _srclocs: &[(CodeOffset, SourceLoc)],
+    ) {
+        let code = unsafe { std::slice::from_raw_parts(code, size) };
+        self.0.borrow_mut().insert(name.to_string(), code.to_vec());
+    }
+}
+
+#[test]
+fn profiler_sees_relocated_code() {
+    let functions = Rc::new(RefCell::new(HashMap::new()));
+    let mut builder = SimpleJITBuilder::new(default_libcall_names());
+    builder.profiler(Box::new(RecordingAgent(functions.clone())));
+    let mut module: Module<SimpleJITBackend> = Module::new(builder);
+
+    let sig = Signature {
+        params: vec![],
+        returns: vec![],
+        call_conv: CallConv::SystemV,
+    };
+    let callee_id = define_simple_function(&mut module);
+    let caller_id = module
+        .declare_function("caller", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = Context::new();
+    ctx.func =
+        Function::with_name_signature(ExternalName::user(0, caller_id.as_u32()), sig.clone());
+    {
+        let mut func_ctx = FunctionBuilderContext::new();
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let callee = module.declare_func_in_func(callee_id, bcx.func);
+        bcx.ins().call(callee, &[]);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(caller_id, &mut ctx).unwrap();
+    assert!(functions.borrow().is_empty());
+
+    // Functions are described once their calls are relocated.
+    module.finalize_definitions();
+    let functions = functions.borrow();
+    assert_eq!(functions.len(), 2);
+    let code = &functions["caller"];
+    let finalized = module.get_finalized_function(caller_id);
+    assert_eq!(&code[..], unsafe {
+        std::slice::from_raw_parts(finalized, code.len())
+    });
+}
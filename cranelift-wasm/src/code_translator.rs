@@ -30,6 +30,7 @@ use crate::translation_utils::{
 };
 use crate::translation_utils::{FuncIndex, MemoryIndex, SignatureIndex, TableIndex};
 use core::{i32, u32};
+use cranelift_codegen::cursor::Cursor;
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{self, InstBuilder, JumpTableData, MemFlags, ValueLabel};
@@ -41,8 +42,12 @@ use wasmparser::{MemoryImmediate, Operator};
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::unneeded_field_pattern))]
 /// Translates wasm operators into Cranelift IR instructions. Returns `true` if it inserted
 /// a return.
+///
+/// `memory` is the index of the linear memory accessed by a load or store operator, which
+/// wasmparser doesn't decode.
 pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
     op: &Operator,
+    memory: u32,
     builder: &mut FunctionBuilder,
     state: &mut TranslationState,
     environ: &mut FE,
@@ -116,7 +121,20 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             // We do nothing
         }
         Operator::Unreachable => {
-            builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
+            let code = ir::TrapCode::UnreachableCodeReached;
+            if environ.is_trap_cold(code) {
+                // The current EBB may have branched away before reaching the trap, so only the
+                // trap itself is moved to a cold EBB.
+                if !builder.is_pristine() {
+                    let trap_ebb = builder.create_ebb();
+                    builder.ins().jump(trap_ebb, &[]);
+                    builder.switch_to_block(trap_ebb);
+                    builder.seal_block(trap_ebb);
+                }
+                let ebb = builder.cursor().current_ebb().unwrap();
+                builder.set_cold_ebb(ebb);
+            }
+            builder.ins().trap(code);
             state.reachable = false;
         }
         /***************************** Control flow blocks **********************************
@@ -413,8 +431,8 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
          * special functions.
          ************************************************************************************/
         Operator::MemoryGrow { reserved } => {
-            // The WebAssembly MVP only supports one linear memory, but we expect the reserved
-            // argument to be a memory index.
+            // The WebAssembly MVP only supports one linear memory, but the reserved argument is
+            // the memory index in the multi-memory proposal.
             let heap_index = MemoryIndex::from_u32(*reserved);
             let heap = state.get_heap(builder.func, *reserved, environ)?;
             let val = state.pop1();
@@ -432,72 +450,184 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         Operator::I32Load8U {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Uload8, I32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Uload8,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32Load16U {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Uload16, I32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Uload16,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32Load8S {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Sload8, I32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Sload8,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32Load16S {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Sload16, I32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Sload16,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load8U {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Uload8, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Uload8,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load16U {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Uload16, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Uload16,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load8S {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Sload8, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Sload8,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load16S {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Sload16, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Sload16,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load32S {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Sload32, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Sload32,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load32U {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Uload32, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Uload32,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32Load {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Load, I32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Load,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::F32Load {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Load, F32, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Load,
+                F32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Load {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Load, I64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Load,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::F64Load {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_load(*offset, ir::Opcode::Load, F64, builder, state, environ)?;
+            translate_load(
+                memory,
+                *offset,
+                ir::Opcode::Load,
+                F64,
+                builder,
+                state,
+                environ,
+            )?;
         }
         /****************************** Store instructions ***********************************
          * Wasm specifies an integer alignment flag but we drop it in Cranelift.
@@ -515,7 +645,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         | Operator::F64Store {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_store(*offset, ir::Opcode::Store, builder, state, environ)?;
+            translate_store(memory, *offset, ir::Opcode::Store, builder, state, environ)?;
         }
         Operator::I32Store8 {
             memarg: MemoryImmediate { flags: _, offset },
@@ -523,7 +653,14 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         | Operator::I64Store8 {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_store(*offset, ir::Opcode::Istore8, builder, state, environ)?;
+            translate_store(
+                memory,
+                *offset,
+                ir::Opcode::Istore8,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32Store16 {
             memarg: MemoryImmediate { flags: _, offset },
@@ -531,12 +668,26 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         | Operator::I64Store16 {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_store(*offset, ir::Opcode::Istore16, builder, state, environ)?;
+            translate_store(
+                memory,
+                *offset,
+                ir::Opcode::Istore16,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64Store32 {
             memarg: MemoryImmediate { flags: _, offset },
         } => {
-            translate_store(*offset, ir::Opcode::Istore32, builder, state, environ)?;
+            translate_store(
+                memory,
+                *offset,
+                ir::Opcode::Istore32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         /****************************** Nullary Operators ************************************/
         Operator::I32Const { value } => state.push1(builder.ins().iconst(I32, i64::from(*value))),
@@ -1153,13 +1304,20 @@ fn translate_unreachable_operator(
 /// Get the address+offset to use for a heap access.
 fn get_heap_addr(
     heap: ir::Heap,
-    addr32: ir::Value,
+    mut addr: ir::Value,
     offset: u32,
     addr_ty: Type,
     builder: &mut FunctionBuilder,
 ) -> (ir::Value, i32) {
     use core::cmp::min;
 
+    // A 32-bit memory may be mapped to a heap with 64-bit indices, which is the only kind of
+    // heap a 64-bit memory can be mapped to.
+    let index_type = builder.func.heaps[heap].index_type;
+    if builder.func.dfg.value_type(addr) == I32 && index_type == I64 {
+        addr = builder.ins().uextend(I64, addr);
+    }
+
     let mut adjusted_offset = u64::from(offset);
     let offset_guard_size: u64 = builder.func.heaps[heap].offset_guard_size.into();
 
@@ -1174,7 +1332,7 @@ fn get_heap_addr(
     // even if the access goes beyond the offset-guard pages. This is because the first byte
     // pointed to is inside the offset-guard pages.
     let check_size = min(u64::from(u32::MAX), 1 + adjusted_offset) as u32;
-    let base = builder.ins().heap_addr(addr_ty, heap, addr32, check_size);
+    let base = builder.ins().heap_addr(addr_ty, heap, addr, check_size);
 
     // Native load/store instructions take a signed `Offset32` immediate, so adjust the base
     // pointer if necessary.
@@ -1189,6 +1347,7 @@ fn get_heap_addr(
 
 /// Translate a load instruction.
 fn translate_load<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
     offset: u32,
     opcode: ir::Opcode,
     result_ty: Type,
@@ -1196,10 +1355,9 @@ fn translate_load<FE: FuncEnvironment + ?Sized>(
     state: &mut TranslationState,
     environ: &mut FE,
 ) -> WasmResult<()> {
-    let addr32 = state.pop1();
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ)?;
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let addr = state.pop1();
+    let heap = state.get_heap(builder.func, memory, environ)?;
+    let (base, offset) = get_heap_addr(heap, addr, offset, environ.pointer_type(), builder);
     // Note that we don't set `is_aligned` here, even if the load instruction's
     // alignment immediate says it's aligned, because WebAssembly's immediate
     // field is just a hint, while Cranelift's aligned flag needs a guarantee.
@@ -1213,18 +1371,18 @@ fn translate_load<FE: FuncEnvironment + ?Sized>(
 
 /// Translate a store instruction.
 fn translate_store<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
     offset: u32,
     opcode: ir::Opcode,
     builder: &mut FunctionBuilder,
     state: &mut TranslationState,
     environ: &mut FE,
 ) -> WasmResult<()> {
-    let (addr32, val) = state.pop2();
+    let (addr, val) = state.pop2();
     let val_ty = builder.func.dfg.value_type(val);
 
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ)?;
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let heap = state.get_heap(builder.func, memory, environ)?;
+    let (base, offset) = get_heap_addr(heap, addr, offset, environ.pointer_type(), builder);
     // See the comments in `translate_load` about the flags.
     let flags = MemFlags::new();
     builder
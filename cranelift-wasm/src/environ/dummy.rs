@@ -11,6 +11,7 @@ use crate::translation_utils::{
     DefinedFuncIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex, Table,
     TableIndex,
 };
+use crate::HashMap;
 use core::convert::TryFrom;
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir::immediates::{Offset32, Uimm64};
@@ -22,10 +23,8 @@ use std::boxed::Box;
 use std::string::String;
 use std::vec::Vec;
 
-/// Compute a `ir::ExternalName` for a given wasm function index.
-fn get_func_name(func_index: FuncIndex) -> ir::ExternalName {
-    ir::ExternalName::user(0, func_index.as_u32())
-}
+/// The number of characters kept in a testcase `ExternalName`.
+const TESTCASE_NAME_LENGTH: usize = 16;
 
 /// A collection of names under which a given entity is exported.
 pub struct Exportable<T> {
@@ -84,6 +83,13 @@ pub struct DummyModuleInfo {
 
     /// The start function.
     pub start_func: Option<FuncIndex>,
+
+    /// Function names as provided by `declare_func_name`.
+    pub function_names: HashMap<FuncIndex, String>,
+
+    /// Local names as provided by `declare_local_name`, along with the `ValueLabel` of the
+    /// values of each local.
+    pub local_names: HashMap<FuncIndex, Vec<(ir::ValueLabel, String)>>,
 }
 
 impl DummyModuleInfo {
@@ -102,10 +108,47 @@ impl DummyModuleInfo {
             memories: PrimaryMap::new(),
             globals: PrimaryMap::new(),
             start_func: None,
+            function_names: HashMap::new(),
+            local_names: HashMap::new(),
+        }
+    }
+
+    /// Compute a `ir::ExternalName` for a given wasm function index.
+    ///
+    /// Functions named by the name section get a testcase name made of the first characters of
+    /// their name, with characters other than ASCII letters and digits replaced by `_`. Other
+    /// functions, and functions whose testcase name would be the same as another function's, are
+    /// named after their index.
+    pub fn get_func_name(&self, func_index: FuncIndex) -> ir::ExternalName {
+        if let Some(ascii) = self
+            .function_names
+            .get(&func_index)
+            .and_then(|n| testcase_name(n))
+        {
+            let collides = self.function_names.iter().any(|(&other, n)| {
+                other != func_index && testcase_name(n).as_ref() == Some(&ascii)
+            });
+            if !collides {
+                return ir::ExternalName::testcase(ascii);
+            }
         }
+        ir::ExternalName::user(0, func_index.as_u32())
     }
 }
 
+/// The characters of a testcase `ExternalName` for a function named `name` by the name section.
+fn testcase_name(name: &str) -> Option<Vec<u8>> {
+    if name.is_empty() {
+        return None;
+    }
+    Some(
+        name.bytes()
+            .take(TESTCASE_NAME_LENGTH)
+            .map(|b| if b.is_ascii_alphanumeric() { b } else { b'_' })
+            .collect(),
+    )
+}
+
 /// This `ModuleEnvironment` implementation is a "naïve" one, doing essentially nothing and
 /// emitting placeholders when forced to. Don't try to execute code translated for this
 /// environment, essentially here for translation debug purposes.
@@ -124,6 +167,15 @@ pub struct DummyEnvironment {
 
     /// Instructs to collect debug data during translation.
     debug_info: bool,
+
+    /// Instructs to instrument the translated functions with fuel metering.
+    fuel_metering: bool,
+
+    /// Instructs to check the translated functions for stack overflow.
+    stack_limit: bool,
+
+    /// Instructs to move traps out of line.
+    cold_traps: bool,
 }
 
 impl DummyEnvironment {
@@ -135,13 +187,38 @@ impl DummyEnvironment {
             func_bytecode_sizes: Vec::new(),
             return_mode,
             debug_info,
+            fuel_metering: false,
+            stack_limit: false,
+            cold_traps: false,
         }
     }
 
+    /// Instrument the translated functions with fuel metering, using a fuel counter stored at
+    /// `vmctx+0`.
+    pub fn enable_fuel_metering(&mut self) {
+        self.fuel_metering = true;
+    }
+
+    /// Check the translated functions for stack overflow, using a stack limit stored at
+    /// `vmctx-8`.
+    pub fn enable_stack_limit(&mut self) {
+        self.stack_limit = true;
+    }
+
+    /// Consider the traps of the translated functions to be cold.
+    pub fn enable_cold_traps(&mut self) {
+        self.cold_traps = true;
+    }
+
     /// Return a `DummyFuncEnvironment` for translating functions within this
     /// `DummyEnvironment`.
     pub fn func_env(&self) -> DummyFuncEnvironment {
-        DummyFuncEnvironment::new(&self.info, self.return_mode)
+        DummyFuncEnvironment {
+            fuel_metering: self.fuel_metering,
+            stack_limit: self.stack_limit,
+            cold_traps: self.cold_traps,
+            ..DummyFuncEnvironment::new(&self.info, self.return_mode)
+        }
     }
 
     fn get_func_type(&self, func_index: FuncIndex) -> SignatureIndex {
@@ -159,6 +236,12 @@ pub struct DummyFuncEnvironment<'dummy_environment> {
     pub mod_info: &'dummy_environment DummyModuleInfo,
 
     return_mode: ReturnMode,
+
+    fuel_metering: bool,
+
+    stack_limit: bool,
+
+    cold_traps: bool,
 }
 
 impl<'dummy_environment> DummyFuncEnvironment<'dummy_environment> {
@@ -166,6 +249,9 @@ impl<'dummy_environment> DummyFuncEnvironment<'dummy_environment> {
         Self {
             mod_info,
             return_mode,
+            fuel_metering: false,
+            stack_limit: false,
+            cold_traps: false,
         }
     }
 
@@ -190,6 +276,33 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         self.return_mode
     }
 
+    fn is_trap_cold(&self, _code: ir::TrapCode) -> bool {
+        self.cold_traps
+    }
+
+    fn fuel_metering(&self) -> bool {
+        self.fuel_metering
+    }
+
+    fn make_fuel_counter(&mut self, func: &mut ir::Function) -> WasmResult<ir::GlobalValue> {
+        // The fuel counter is stored at `vmctx+0`.
+        Ok(func.create_global_value(ir::GlobalValueData::VMContext))
+    }
+
+    fn make_stack_limit(&mut self, func: &mut ir::Function) -> WasmResult<Option<ir::GlobalValue>> {
+        if !self.stack_limit {
+            return Ok(None);
+        }
+        // The stack limit is stored at `vmctx-8`.
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        Ok(Some(func.create_global_value(ir::GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(-8),
+            global_type: self.pointer_type(),
+            readonly: true,
+        })))
+    }
+
     fn make_global(
         &mut self,
         func: &mut ir::Function,
@@ -205,12 +318,25 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         })
     }
 
-    fn make_heap(&mut self, func: &mut ir::Function, _index: MemoryIndex) -> WasmResult<ir::Heap> {
-        // Create a static heap whose base address is stored at `vmctx+0`.
+    fn is_memory64(&self, index: MemoryIndex) -> bool {
+        match self.mod_info.memories.get(index) {
+            Some(memory) => memory.entity.memory64,
+            None => false,
+        }
+    }
+
+    fn make_heap(
+        &mut self,
+        func: &mut ir::Function,
+        index: MemoryIndex,
+        index_type: ir::Type,
+    ) -> WasmResult<ir::Heap> {
+        // Create a static heap whose base address is stored at `vmctx+index*pointer_bytes`.
         let addr = func.create_global_value(ir::GlobalValueData::VMContext);
+        let offset = index.index() * usize::from(self.pointer_bytes());
         let gv = func.create_global_value(ir::GlobalValueData::Load {
             base: addr,
-            offset: Offset32::new(0),
+            offset: Offset32::new(offset as i32),
             global_type: self.pointer_type(),
             readonly: true,
         });
@@ -222,7 +348,7 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
             style: ir::HeapStyle::Static {
                 bound: 0x1_0000_0000.into(),
             },
-            index_type: I32,
+            index_type,
         }))
     }
 
@@ -270,7 +396,7 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         // A real implementation would probably add a `vmctx` argument.
         // And maybe attempt some signature de-duplication.
         let signature = func.import_signature(self.vmctx_sig(sigidx));
-        let name = get_func_name(index);
+        let name = self.mod_info.get_func_name(index);
         Ok(func.import_function(ir::ExtFuncData {
             name,
             signature,
@@ -346,19 +472,21 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         &mut self,
         mut pos: FuncCursor,
         _index: MemoryIndex,
-        _heap: ir::Heap,
+        heap: ir::Heap,
         _val: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Ok(pos.ins().iconst(I32, -1))
+        let pages_type = pos.func.heaps[heap].index_type;
+        Ok(pos.ins().iconst(pages_type, -1))
     }
 
     fn translate_memory_size(
         &mut self,
         mut pos: FuncCursor,
         _index: MemoryIndex,
-        _heap: ir::Heap,
+        heap: ir::Heap,
     ) -> WasmResult<ir::Value> {
-        Ok(pos.ins().iconst(I32, -1))
+        let pages_type = pos.func.heaps[heap].index_type;
+        Ok(pos.ins().iconst(pages_type, -1))
     }
 }
 
@@ -474,16 +602,35 @@ impl<'data> ModuleEnvironment<'data> for DummyEnvironment {
         self.info.start_func = Some(func_index);
     }
 
+    fn declare_func_name(&mut self, func_index: FuncIndex, name: &'data str) {
+        self.info
+            .function_names
+            .insert(func_index, String::from(name));
+    }
+
+    fn declare_local_name(&mut self, func_index: FuncIndex, local_index: u32, name: &'data str) {
+        self.info
+            .local_names
+            .entry(func_index)
+            .or_default()
+            .push((ir::ValueLabel::from_u32(local_index), String::from(name)));
+    }
+
     fn define_function_body(
         &mut self,
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
         let func = {
-            let mut func_environ = DummyFuncEnvironment::new(&self.info, self.return_mode);
+            let mut func_environ = DummyFuncEnvironment {
+                fuel_metering: self.fuel_metering,
+                stack_limit: self.stack_limit,
+                cold_traps: self.cold_traps,
+                ..DummyFuncEnvironment::new(&self.info, self.return_mode)
+            };
             let func_index =
                 FuncIndex::new(self.get_num_func_imports() + self.info.function_bodies.len());
-            let name = get_func_name(func_index);
+            let name = self.info.get_func_name(func_index);
             let sig = func_environ.vmctx_sig(self.get_func_type(func_index));
             let mut func = ir::Function::with_name_signature(name, sig);
             if self.debug_info {
@@ -124,6 +124,16 @@ pub trait FuncEnvironment {
         ReturnMode::NormalReturns
     }
 
+    /// Is the code path ending in a trap with the given `code` expected to be cold?
+    ///
+    /// When this returns `true`, the trap is placed in an EBB of its own, which is marked as cold
+    /// so the code generator can move it out of line. This is currently consulted for the
+    /// `unreachable` operator. By default, traps are not considered cold and the code layout
+    /// isn't changed.
+    fn is_trap_cold(&self, _code: ir::TrapCode) -> bool {
+        false
+    }
+
     /// Set up the necessary preamble definitions in `func` to access the global variable
     /// identified by `index`.
     ///
@@ -137,11 +147,27 @@ pub trait FuncEnvironment {
         index: GlobalIndex,
     ) -> WasmResult<GlobalVariable>;
 
+    /// Is the linear memory identified by `index` a 64-bit memory, as proposed by memory64?
+    ///
+    /// The index space covers both imported and locally declared memories.
+    fn is_memory64(&self, _index: MemoryIndex) -> bool {
+        false
+    }
+
     /// Set up the necessary preamble definitions in `func` to access the linear memory identified
     /// by `index`.
     ///
     /// The index space covers both imported and locally declared memories.
-    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> WasmResult<ir::Heap>;
+    ///
+    /// The `index_type` is the type of the addresses used to access the memory: `I64` for a 64-bit
+    /// memory and `I32` otherwise. The heap may be indexed by a wider type than `index_type`, in
+    /// which case the addresses are zero-extended, but not by a narrower one.
+    fn make_heap(
+        &mut self,
+        func: &mut ir::Function,
+        index: MemoryIndex,
+        index_type: ir::Type,
+    ) -> WasmResult<ir::Heap>;
 
     /// Set up the necessary preamble definitions in `func` to access the table identified
     /// by `index`.
@@ -226,7 +252,8 @@ pub trait FuncEnvironment {
     ///
     /// The `val` value is the requested memory size in pages.
     ///
-    /// Returns the old size (in pages) of the memory.
+    /// Returns the old size (in pages) of the memory. Page counts are `i64` values for a 64-bit
+    /// memory and `i32` values otherwise.
     fn translate_memory_grow(
         &mut self,
         pos: FuncCursor,
@@ -240,7 +267,8 @@ pub trait FuncEnvironment {
     /// The `index` provided identifies the linear memory to query, and `heap` is the heap reference
     /// returned by `make_heap` for the same index.
     ///
-    /// Returns the size in pages of the memory.
+    /// Returns the size in pages of the memory, as an `i64` value for a 64-bit memory and an `i32`
+    /// value otherwise.
     fn translate_memory_size(
         &mut self,
         pos: FuncCursor,
@@ -257,6 +285,64 @@ pub trait FuncEnvironment {
         Ok(())
     }
 
+    /// Should the translated code be instrumented to meter its execution with fuel?
+    ///
+    /// When this returns `true`, each operator consumes the fuel given by `fuel_cost()`. The fuel
+    /// consumed by straight-line code is subtracted from the counter created by
+    /// `make_fuel_counter()` before control leaves it, and `translate_out_of_fuel()` is called
+    /// to handle the counter dropping below zero.
+    fn fuel_metering(&self) -> bool {
+        false
+    }
+
+    /// Get the amount of fuel consumed by the WebAssembly operator `op`.
+    ///
+    /// By default, `nop` and the operators delimiting control structures are free and every other
+    /// operator costs one unit of fuel.
+    fn fuel_cost(&self, op: &Operator) -> u32 {
+        match *op {
+            Operator::Nop
+            | Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::Else
+            | Operator::End => 0,
+            _ => 1,
+        }
+    }
+
+    /// Set up the necessary preamble definitions in `func` to access the fuel counter.
+    ///
+    /// Return a global value holding the address of the fuel counter, an `i64` in memory that
+    /// holds the remaining fuel. This is only called if `fuel_metering()` returns `true`.
+    fn make_fuel_counter(&mut self, _func: &mut ir::Function) -> WasmResult<ir::GlobalValue> {
+        Err(WasmError::Unsupported("fuel metering"))
+    }
+
+    /// Emit code for the exhaustion of the fuel counter.
+    ///
+    /// The code is emitted in a cold EBB that is executed when the fuel counter drops below zero.
+    /// By default, it traps with `TrapCode::OutOfFuel`. If the EBB is left unterminated, for
+    /// example after calling into the runtime to refuel the counter, execution resumes where it
+    /// was interrupted.
+    fn translate_out_of_fuel(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        builder.ins().trap(ir::TrapCode::OutOfFuel);
+        Ok(())
+    }
+
+    /// Set up the necessary preamble definitions in `func` to check for stack overflow.
+    ///
+    /// Return a global value holding the lowest address the stack pointer may reach, which becomes
+    /// the `stack_limit` of `func`: its prologue traps with `TrapCode::StackOverflow` if its stack
+    /// frame doesn't fit above the limit. The global value must be computed from the `vmctx`
+    /// parameter with `load` and `iadd_imm` global values. By default, there is no stack limit,
+    /// and the runtime is expected to catch stack overflows with guard pages.
+    fn make_stack_limit(
+        &mut self,
+        _func: &mut ir::Function,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        Ok(None)
+    }
+
     /// Optional callback for the `FunctionEnvironment` performing this translation to maintain
     /// internal state or prepare custom state for the operator to translate
     fn before_translate_operator(
@@ -368,6 +454,21 @@ pub trait ModuleEnvironment<'data> {
     /// Declares the optional start function.
     fn declare_start_func(&mut self, index: FuncIndex);
 
+    /// Declares the name of a function, as found in the "name" custom section. By default this
+    /// does nothing, but implementations can use it to name the translated functions.
+    ///
+    /// The name section is read before any other section, so these names are known by the time
+    /// the function bodies are defined.
+    fn declare_func_name(&mut self, _func_index: FuncIndex, _name: &'data str) {}
+
+    /// Declares the name of a local of a function, as found in the "name" custom section. By
+    /// default this does nothing.
+    ///
+    /// Locals are numbered starting with the function parameters, the same way as the
+    /// `ValueLabel`s attached to their values during translation.
+    fn declare_local_name(&mut self, _func_index: FuncIndex, _local_index: u32, _name: &'data str) {
+    }
+
     /// Provides the number of element initializers up front. By default this does nothing, but
     /// implementations can use this to preallocate memory if desired.
     fn reserve_table_elements(&mut self, _num: u32) {}
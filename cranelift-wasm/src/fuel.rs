@@ -0,0 +1,113 @@
+//! Fuel metering of the translated WebAssembly code.
+//!
+//! When `FuncEnvironment::fuel_metering` is enabled, every operator consumes the amount of fuel
+//! given by `FuncEnvironment::fuel_cost`. Rather than updating the fuel counter after each
+//! operator, the costs of straight-line code are added up during translation and charged all at
+//! once before control can leave that code: before branches, calls, returns and the boundaries of
+//! control structures.
+//!
+//! Charging fuel decrements the counter provided by `FuncEnvironment::make_fuel_counter`. If the
+//! counter drops below zero, a cold EBB generated by `FuncEnvironment::translate_out_of_fuel` is
+//! executed. It traps by default, but it may also refuel the counter and resume execution.
+
+use crate::environ::{FuncEnvironment, WasmResult};
+use crate::state::TranslationState;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::types::I64;
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_frontend::FunctionBuilder;
+use wasmparser::Operator;
+
+/// Accounting of the fuel consumed by a function while it is translated.
+pub struct FuelMeter {
+    /// Fuel consumed by the operators translated since fuel was last charged.
+    consumed: i64,
+
+    /// The address of the fuel counter, created on first use.
+    counter: Option<ir::GlobalValue>,
+}
+
+impl FuelMeter {
+    /// Create a new fuel meter for a function.
+    pub fn new() -> Self {
+        Self {
+            consumed: 0,
+            counter: None,
+        }
+    }
+
+    /// Account for the operator `op` that is about to be translated, charging the fuel consumed
+    /// so far if `op` may transfer control elsewhere.
+    pub fn before_operator<FE: FuncEnvironment + ?Sized>(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &TranslationState,
+        environ: &mut FE,
+    ) -> WasmResult<()> {
+        // Unreachable code is not translated, so there is nothing to pay for.
+        if !state.reachable {
+            return Ok(());
+        }
+        self.consumed += i64::from(environ.fuel_cost(op));
+        match *op {
+            Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::Unreachable => self.charge(builder, environ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Subtract the fuel consumed so far from the fuel counter, and handle its exhaustion.
+    fn charge<FE: FuncEnvironment + ?Sized>(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        environ: &mut FE,
+    ) -> WasmResult<()> {
+        if self.consumed == 0 {
+            return Ok(());
+        }
+        let counter = match self.counter {
+            Some(gv) => gv,
+            None => {
+                let gv = environ.make_fuel_counter(builder.func)?;
+                self.counter = Some(gv);
+                gv
+            }
+        };
+
+        let addr = builder.ins().global_value(environ.pointer_type(), counter);
+        let flags = ir::MemFlags::trusted();
+        let fuel = builder.ins().load(I64, flags, addr, 0);
+        let fuel = builder.ins().iadd_imm(fuel, -self.consumed);
+        builder.ins().store(flags, fuel, addr, 0);
+        self.consumed = 0;
+
+        let out_of_fuel = builder.create_ebb();
+        let next = builder.create_ebb();
+        let exhausted = builder.ins().icmp_imm(IntCC::SignedLessThan, fuel, 0);
+        builder.ins().brnz(exhausted, out_of_fuel, &[]);
+        builder.ins().jump(next, &[]);
+
+        builder.switch_to_block(out_of_fuel);
+        builder.seal_block(out_of_fuel);
+        builder.set_cold_ebb(out_of_fuel);
+        environ.translate_out_of_fuel(builder)?;
+        if !builder.is_filled() {
+            builder.ins().jump(next, &[]);
+        }
+
+        builder.switch_to_block(next);
+        builder.seal_block(next);
+        Ok(())
+    }
+}
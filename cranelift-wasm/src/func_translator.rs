@@ -6,14 +6,16 @@
 
 use crate::code_translator::translate_operator;
 use crate::environ::{FuncEnvironment, ReturnMode, WasmError, WasmResult};
+use crate::fuel::FuelMeter;
 use crate::state::TranslationState;
-use crate::translation_utils::get_vmctx_value_label;
+use crate::translation_utils::{get_vmctx_value_label, read_var_u64, MemoryIndex};
+use core::convert::TryFrom;
 use cranelift_codegen::entity::EntityRef;
 use cranelift_codegen::ir::{self, Ebb, InstBuilder, ValueLabel};
 use cranelift_codegen::timing;
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
 use log::info;
-use wasmparser::{self, BinaryReader};
+use wasmparser::{self, BinaryReader, MemoryImmediate, Operator};
 
 /// WebAssembly to Cranelift IR function translator.
 ///
@@ -96,6 +98,7 @@ impl FuncTranslator {
         builder.ensure_inserted_ebb();
 
         let num_params = declare_wasm_parameters(&mut builder, entry_block);
+        builder.func.stack_limit = environ.make_stack_limit(builder.func)?;
 
         // Set up the translation state with a single pushed control block representing the whole
         // function and its return values.
@@ -203,12 +206,21 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
     // The control stack is initialized with a single block representing the whole function.
     debug_assert_eq!(state.control_stack.len(), 1, "State not initialized");
 
+    let mut fuel = if environ.fuel_metering() {
+        Some(FuelMeter::new())
+    } else {
+        None
+    };
+
     // Keep going until the final `End` operator which pops the outermost block.
     while !state.control_stack.is_empty() {
         builder.set_srcloc(cur_srcloc(&reader));
-        let op = reader.read_operator()?;
+        let (op, memory) = read_operator(&mut reader, environ)?;
         environ.before_translate_operator(&op, builder, state)?;
-        translate_operator(&op, builder, state, environ)?;
+        if let Some(ref mut fuel) = fuel {
+            fuel.before_operator(&op, builder, state, environ)?;
+        }
+        translate_operator(&op, memory, builder, state, environ)?;
         environ.after_translate_operator(&op, builder, state)?;
     }
 
@@ -236,6 +248,79 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
     Ok(())
 }
 
+/// Read the next operator in `reader`, along with the index of the linear memory it accesses.
+///
+/// wasmparser doesn't decode the memory indices of the multi-memory proposal: the `memarg` of a
+/// load or store has an index after its alignment when bit 6 of the alignment is set, and
+/// `memory.size` and `memory.grow` may refer to memories past the second one. Nor does it decode
+/// the offsets of accesses to 64-bit memories, which are `varuint64`s.
+fn read_operator<'a, FE: FuncEnvironment + ?Sized>(
+    reader: &mut BinaryReader<'a>,
+    environ: &FE,
+) -> WasmResult<(Operator<'a>, u32)> {
+    let mut peek = reader.clone();
+    let code = peek.read_u8()? as u8;
+    let (op, memory) = match code {
+        0x28..=0x3e => {
+            let flags = peek.read_var_u32()?;
+            let memory = if flags & 0x40 == 0 {
+                0
+            } else {
+                peek.read_var_u32()?
+            };
+            let offset = if environ.is_memory64(MemoryIndex::from_u32(memory)) {
+                u32::try_from(read_var_u64(&mut peek)?)
+                    .map_err(|_| WasmError::Unsupported("memory access offsets of 2^32 or more"))?
+            } else if flags & 0x40 == 0 {
+                return Ok((reader.read_operator()?, 0));
+            } else {
+                peek.read_var_u32()?
+            };
+            let memarg = MemoryImmediate {
+                flags: flags & !0x40,
+                offset,
+            };
+            let op = match code {
+                0x28 => Operator::I32Load { memarg },
+                0x29 => Operator::I64Load { memarg },
+                0x2a => Operator::F32Load { memarg },
+                0x2b => Operator::F64Load { memarg },
+                0x2c => Operator::I32Load8S { memarg },
+                0x2d => Operator::I32Load8U { memarg },
+                0x2e => Operator::I32Load16S { memarg },
+                0x2f => Operator::I32Load16U { memarg },
+                0x30 => Operator::I64Load8S { memarg },
+                0x31 => Operator::I64Load8U { memarg },
+                0x32 => Operator::I64Load16S { memarg },
+                0x33 => Operator::I64Load16U { memarg },
+                0x34 => Operator::I64Load32S { memarg },
+                0x35 => Operator::I64Load32U { memarg },
+                0x36 => Operator::I32Store { memarg },
+                0x37 => Operator::I64Store { memarg },
+                0x38 => Operator::F32Store { memarg },
+                0x39 => Operator::F64Store { memarg },
+                0x3a => Operator::I32Store8 { memarg },
+                0x3b => Operator::I32Store16 { memarg },
+                0x3c => Operator::I64Store8 { memarg },
+                0x3d => Operator::I64Store16 { memarg },
+                _ => Operator::I64Store32 { memarg },
+            };
+            (op, memory)
+        }
+        0x3f => {
+            let memory = peek.read_var_u32()?;
+            (Operator::MemorySize { reserved: memory }, memory)
+        }
+        0x40 => {
+            let memory = peek.read_var_u32()?;
+            (Operator::MemoryGrow { reserved: memory }, memory)
+        }
+        _ => return Ok((reader.read_operator()?, 0)),
+    };
+    *reader = peek;
+    Ok((op, memory))
+}
+
 /// Get the current source location from a reader.
 fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
     // We record source locations as byte code offsets relative to the beginning of the file.
@@ -246,10 +331,13 @@ fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
 #[cfg(test)]
 mod tests {
     use super::{FuncTranslator, ReturnMode};
-    use crate::environ::DummyEnvironment;
-    use cranelift_codegen::ir::types::I32;
+    use crate::environ::{DummyEnvironment, ModuleEnvironment, WasmError};
+    use crate::translation_utils::Memory;
+    use cranelift_codegen::ir::types::{I32, I64};
     use cranelift_codegen::{ir, isa, settings, Context};
     use log::debug;
+    use std::string::ToString;
+    use std::vec::Vec;
     use target_lexicon::PointerWidth;
 
     #[test]
@@ -376,4 +464,288 @@ mod tests {
         debug!("{}", ctx.func.display(None));
         ctx.verify(&flags).unwrap();
     }
+
+    #[test]
+    fn infloop_fuel() {
+        // Same as above, but metered with fuel.
+        const BODY: [u8; 16] = [
+            0x01, // 1 local decl.
+            0x01, 0x7f, // 1 i32 local.
+            0x03, 0x7f, // loop i32
+            0x20, 0x00, // get_local 0
+            0x41, 0x01, // i32.const 0
+            0x6a, // i32.add
+            0x21, 0x00, // set_local 0
+            0x0c, 0x00, // br 0
+            0x0b, // end
+            0x0b, // end
+        ];
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let mut runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+        runtime.enable_fuel_metering();
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("infloop_fuel");
+        ctx.func
+            .signature
+            .params
+            .push(ir::AbiParam::special(I64, ir::ArgumentPurpose::VMContext));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(&BODY, 0, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+
+        // The five operators of the loop body are charged on each iteration, before the `br`.
+        let text = ctx.func.display(None).to_string();
+        assert!(text.contains("iadd_imm v8, -5"), "{}", text);
+        assert!(text.contains("trap out_of_fuel"), "{}", text);
+    }
+
+    #[test]
+    fn cold_trap() {
+        // (func $cold_trap (param i32) (result i32)
+        //    (block
+        //      (br_if 0 (get_local 0))
+        //      (unreachable))
+        //    (i32.const 0))
+        const BODY: [u8; 12] = [
+            0x00, // 0 local decls.
+            0x02, 0x40, // block
+            0x20, 0x00, // get_local 0
+            0x0d, 0x00, // br_if 0
+            0x00, // unreachable
+            0x0b, // end
+            0x41, 0x00, // i32.const 0
+            0x0b, // end
+        ];
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let mut runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+        runtime.enable_cold_traps();
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("cold_trap");
+        ctx.func.signature.params.push(ir::AbiParam::new(I32));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(&BODY, 0, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+
+        // The `br_if` leaves the entry EBB before the trap, so only the trap is cold.
+        let layout = &ctx.func.layout;
+        let entry = layout.entry_block().unwrap();
+        assert!(!layout.is_cold(entry));
+        let cold: Vec<_> = layout.ebbs().filter(|&ebb| layout.is_cold(ebb)).collect();
+        assert_eq!(cold.len(), 1);
+        let insts: Vec<_> = layout.ebb_insts(cold[0]).collect();
+        assert_eq!(insts.len(), 1);
+        assert_eq!(ctx.func.dfg[insts[0]].opcode(), ir::Opcode::Trap);
+    }
+
+    #[test]
+    fn stack_limit() {
+        // (func $stack_limit (result i32)
+        //    (i32.const 1))
+        const BODY: [u8; 4] = [
+            0x00, // 0 local decls.
+            0x41, 0x01, // i32.const 1
+            0x0b, // end
+        ];
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let mut runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+        runtime.enable_stack_limit();
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("stack_limit");
+        ctx.func
+            .signature
+            .params
+            .push(ir::AbiParam::special(I64, ir::ArgumentPurpose::VMContext));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(&BODY, 0, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+
+        let limit = ctx.func.stack_limit.expect("missing stack limit");
+        let text = ctx.func.display(None).to_string();
+        assert!(
+            text.contains(&format!("stack_limit = {}", limit)),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("load.i64 notrap aligned readonly"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn multi_memory64() {
+        // Store the size of the third memory in it, load from the first, 64-bit memory, and
+        // query the size of the second memory.
+        //
+        // (func $multi_memory64 (param i64) (result i32)
+        //     (i32.store 2 (i32.const 0) (memory.size 2))
+        //     (i32.add (i32.load (get_local 0)) (memory.size 1))
+        // )
+        const BODY: [u8; 18] = [
+            0x00, // local decl count
+            0x41, 0x00, // i32.const 0
+            0x3f, 0x02, // memory.size 2
+            0x36, 0x42, 0x02, 0x00, // i32.store 2
+            0x20, 0x00, // get_local 0
+            0x28, 0x02, 0x00, // i32.load
+            0x3f, 0x01, // memory.size 1
+            0x6a, // i32.add
+            0x0b, // end
+        ];
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let mut runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+        for &memory64 in &[true, false, false] {
+            runtime.declare_memory(Memory {
+                minimum: 1,
+                maximum: None,
+                shared: false,
+                memory64,
+            });
+        }
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("multi_memory64");
+        ctx.func.signature.params.push(ir::AbiParam::new(I64));
+        ctx.func
+            .signature
+            .params
+            .push(ir::AbiParam::special(I64, ir::ArgumentPurpose::VMContext));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(&BODY, 0, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+
+        // The heaps are created in the order of the first access to their memory.
+        let heaps = ctx.func.heaps.values().collect::<Vec<_>>();
+        assert_eq!(heaps.len(), 3);
+        assert_eq!(heaps[0].index_type, I32);
+        assert_eq!(heaps[1].index_type, I64);
+        assert_eq!(heaps[2].index_type, I32);
+        let text = ctx.func.display(None).to_string();
+        assert!(text.contains("heap_addr.i64 heap0, v"), "{}", text);
+        assert!(text.contains("heap_addr.i64 heap1, v"), "{}", text);
+    }
+
+    #[test]
+    fn memory64_offsets() {
+        // The offsets of accesses to 64-bit memories are `varuint64`s, which may take more than
+        // the 5 bytes of a `varuint32`, here to encode 8.
+        //
+        // (func $memory64_offsets (param i64) (result i32)
+        //     (i32.add (i32.load offset=8 (get_local 0)) (i32.load 0 offset=8 (get_local 0)))
+        // )
+        const BODY: [u8; 24] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x28, 0x02, 0x88, 0x80, 0x80, 0x80, 0x80, 0x00, // i32.load offset=8
+            0x20, 0x00, // get_local 0
+            0x28, 0x42, 0x00, 0x88, 0x80, 0x80, 0x80, 0x80, 0x00, // i32.load 0 offset=8
+            0x6a, // i32.add
+            0x0b, // end
+        ];
+        // (func (param i64) (result i32) (i32.load offset=0x1_0000_0000 (get_local 0)))
+        const LARGE_OFFSET: [u8; 11] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x28, 0x02, 0x80, 0x80, 0x80, 0x80, 0x10, // i32.load offset=0x1_0000_0000
+            0x0b, // end
+        ];
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let mut runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+        runtime.declare_memory(Memory {
+            minimum: 1,
+            maximum: None,
+            shared: false,
+            memory64: true,
+        });
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("memory64_offsets");
+        ctx.func.signature.params.push(ir::AbiParam::new(I64));
+        ctx.func
+            .signature
+            .params
+            .push(ir::AbiParam::special(I64, ir::ArgumentPurpose::VMContext));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(&BODY, 0, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+        let text = ctx.func.display(None).to_string();
+        assert_eq!(text.matches("+8").count(), 2, "{}", text);
+
+        let signature = ctx.func.signature.clone();
+        ctx.clear();
+        ctx.func.signature = signature;
+        match trans.translate(&LARGE_OFFSET, 0, &mut ctx.func, &mut runtime.func_env()) {
+            Err(WasmError::Unsupported(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
 }
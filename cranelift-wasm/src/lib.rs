@@ -51,6 +51,7 @@ use std::collections::{
 
 mod code_translator;
 mod environ;
+mod fuel;
 mod func_translator;
 mod module_translator;
 mod sections_translator;
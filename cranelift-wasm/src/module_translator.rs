@@ -4,10 +4,10 @@ use crate::environ::{ModuleEnvironment, WasmError, WasmResult};
 use crate::sections_translator::{
     parse_code_section, parse_data_section, parse_element_section, parse_export_section,
     parse_function_section, parse_global_section, parse_import_section, parse_memory_section,
-    parse_start_section, parse_table_section, parse_type_section,
+    parse_name_section, parse_start_section, parse_table_section, parse_type_section,
 };
 use cranelift_codegen::timing;
-use wasmparser::{ModuleReader, SectionCode};
+use wasmparser::{CustomSectionKind, ModuleReader, SectionCode};
 
 /// Translate a sequence of bytes forming a valid Wasm binary into a list of valid Cranelift IR
 /// [`Function`](../codegen/ir/function/struct.Function.html).
@@ -16,6 +16,8 @@ pub fn translate_module<'data>(
     environ: &mut dyn ModuleEnvironment<'data>,
 ) -> WasmResult<()> {
     let _tt = timing::wasm_translate_module();
+    translate_names(data, environ);
+
     let mut reader = ModuleReader::new(data)?;
 
     reader.skip_custom_sections()?;
@@ -36,7 +38,7 @@ pub fn translate_module<'data>(
     }
 
     if let SectionCode::Import = section.code {
-        let imports = section.get_import_section_reader()?;
+        let imports = section.get_binary_reader();
         parse_import_section(imports, environ)?;
 
         reader.skip_custom_sections()?;
@@ -69,7 +71,7 @@ pub fn translate_module<'data>(
     }
 
     if let SectionCode::Memory = section.code {
-        let memories = section.get_memory_section_reader()?;
+        let memories = section.get_binary_reader();
         parse_memory_section(memories, environ)?;
 
         reader.skip_custom_sections()?;
@@ -149,3 +151,31 @@ pub fn translate_module<'data>(
 
     Ok(())
 }
+
+/// Declare the function and local names of the "name" custom section, if there is one.
+///
+/// The name section follows the code section, but the names are needed as soon as the function
+/// bodies are defined, so it is looked up ahead of the other sections. Custom sections don't
+/// affect the semantics of a module, so a malformed name section is ignored rather than reported.
+fn translate_names<'data>(data: &'data [u8], environ: &mut dyn ModuleEnvironment<'data>) {
+    let mut reader = match ModuleReader::new(data) {
+        Ok(reader) => reader,
+        Err(_) => return,
+    };
+    while !reader.eof() {
+        let section = match reader.read() {
+            Ok(section) => section,
+            Err(_) => return,
+        };
+        if let SectionCode::Custom {
+            kind: CustomSectionKind::Name,
+            ..
+        } = section.code
+        {
+            if let Ok(names) = section.get_name_section_reader() {
+                let _ = parse_name_section(names, environ);
+            }
+            return;
+        }
+    }
+}
@@ -9,19 +9,18 @@
 //! interpreted on the fly.
 use crate::environ::{ModuleEnvironment, WasmError, WasmResult};
 use crate::translation_utils::{
-    tabletype_to_type, type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit, Memory,
-    MemoryIndex, SignatureIndex, Table, TableElementType, TableIndex,
+    read_var_u64, tabletype_to_type, type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit,
+    Memory, MemoryIndex, SignatureIndex, Table, TableElementType, TableIndex,
 };
 use core::convert::TryFrom;
 use cranelift_codegen::ir::{self, AbiParam, Signature};
 use cranelift_entity::EntityRef;
 use std::vec::Vec;
 use wasmparser::{
-    self, CodeSectionReader, Data, DataKind, DataSectionReader, Element, ElementKind,
+    self, BinaryReader, CodeSectionReader, Data, DataKind, DataSectionReader, Element, ElementKind,
     ElementSectionReader, Export, ExportSectionReader, ExternalKind, FuncType,
-    FunctionSectionReader, GlobalSectionReader, GlobalType, ImportSectionEntryType,
-    ImportSectionReader, MemorySectionReader, MemoryType, Operator, TableSectionReader,
-    TypeSectionReader,
+    FunctionSectionReader, GlobalSectionReader, GlobalType, Name, NameSectionReader, Naming,
+    Operator, TableSectionReader, TypeSectionReader,
 };
 
 /// Parses the Type section of the wasm module.
@@ -58,62 +57,77 @@ pub fn parse_type_section(
 }
 
 /// Parses the Import section of the wasm module.
+///
+/// The section is read directly, since wasmparser rejects the memory64 flag of memory types.
 pub fn parse_import_section<'data>(
-    imports: ImportSectionReader<'data>,
+    mut imports: BinaryReader<'data>,
     environ: &mut dyn ModuleEnvironment<'data>,
 ) -> WasmResult<()> {
-    environ.reserve_imports(imports.get_count());
+    let count = imports.read_var_u32()?;
+    environ.reserve_imports(count);
 
-    for entry in imports {
-        let import = entry?;
-        let module_name = import.module;
-        let field_name = import.field;
+    for _ in 0..count {
+        let module_name = imports.read_string()?;
+        let field_name = imports.read_string()?;
 
-        match import.ty {
-            ImportSectionEntryType::Function(sig) => {
+        let offset = imports.original_position();
+        match imports.read_u8()? {
+            0 => {
+                let sig = imports.read_var_u32()?;
                 environ.declare_func_import(SignatureIndex::from_u32(sig), module_name, field_name);
             }
-            ImportSectionEntryType::Memory(MemoryType {
-                limits: ref memlimits,
-                shared,
-            }) => {
-                environ.declare_memory_import(
-                    Memory {
-                        minimum: memlimits.initial,
-                        maximum: memlimits.maximum,
-                        shared,
+            1 => {
+                let element_type = imports.read_type()?;
+                let (minimum, maximum) = read_table_limits(&mut imports)?;
+                environ.declare_table_import(
+                    Table {
+                        ty: match tabletype_to_type(element_type)? {
+                            Some(t) => TableElementType::Val(t),
+                            None => TableElementType::Func,
+                        },
+                        minimum,
+                        maximum,
                     },
                     module_name,
                     field_name,
                 );
             }
-            ImportSectionEntryType::Global(ref ty) => {
+            2 => {
+                let memory = read_memory_type(&mut imports)?;
+                environ.declare_memory_import(memory, module_name, field_name);
+            }
+            3 => {
+                let content_type = imports.read_type()?;
+                let offset = imports.original_position();
+                let mutability = match imports.read_u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => {
+                        return Err(WasmError::InvalidWebAssembly {
+                            message: "invalid global mutability",
+                            offset,
+                        })
+                    }
+                };
                 environ.declare_global_import(
                     Global {
-                        ty: type_to_type(ty.content_type).unwrap(),
-                        mutability: ty.mutable,
+                        ty: type_to_type(content_type).unwrap(),
+                        mutability,
                         initializer: GlobalInit::Import,
                     },
                     module_name,
                     field_name,
                 );
             }
-            ImportSectionEntryType::Table(ref tab) => {
-                environ.declare_table_import(
-                    Table {
-                        ty: match tabletype_to_type(tab.element_type)? {
-                            Some(t) => TableElementType::Val(t),
-                            None => TableElementType::Func,
-                        },
-                        minimum: tab.limits.initial,
-                        maximum: tab.limits.maximum,
-                    },
-                    module_name,
-                    field_name,
-                );
+            _ => {
+                return Err(WasmError::InvalidWebAssembly {
+                    message: "invalid external kind",
+                    offset,
+                })
             }
         }
     }
+    expect_section_end(&imports)?;
 
     environ.finish_imports();
     Ok(())
@@ -157,24 +171,90 @@ pub fn parse_table_section(
 }
 
 /// Parses the Memory section of the wasm module.
+///
+/// The section is read directly, since wasmparser rejects the memory64 flag of memory types.
 pub fn parse_memory_section(
-    memories: MemorySectionReader,
+    mut memories: BinaryReader,
     environ: &mut dyn ModuleEnvironment,
 ) -> WasmResult<()> {
-    environ.reserve_memories(memories.get_count());
-
-    for entry in memories {
-        let memory = entry?;
-        environ.declare_memory(Memory {
-            minimum: memory.limits.initial,
-            maximum: memory.limits.maximum,
-            shared: memory.shared,
-        });
+    let count = memories.read_var_u32()?;
+    environ.reserve_memories(count);
+
+    for _ in 0..count {
+        let memory = read_memory_type(&mut memories)?;
+        environ.declare_memory(memory);
     }
+    expect_section_end(&memories)?;
 
     Ok(())
 }
 
+/// Read the limits of a table type.
+fn read_table_limits(reader: &mut BinaryReader) -> WasmResult<(u32, Option<u32>)> {
+    let offset = reader.original_position();
+    let flags = reader.read_var_u32()?;
+    if flags & !0x1 != 0 {
+        return Err(WasmError::InvalidWebAssembly {
+            message: "invalid table resizable limits flags",
+            offset,
+        });
+    }
+    let minimum = reader.read_var_u32()?;
+    let maximum = if flags & 0x1 != 0 {
+        Some(reader.read_var_u32()?)
+    } else {
+        None
+    };
+    Ok((minimum, maximum))
+}
+
+/// Read a memory type, whose flags are: 0x1 if it has a maximum, 0x2 if it is shared, and 0x4
+/// if it is a 64-bit memory.
+fn read_memory_type(reader: &mut BinaryReader) -> WasmResult<Memory> {
+    let offset = reader.original_position();
+    let flags = reader.read_var_u32()?;
+    if flags & !0x7 != 0 {
+        return Err(WasmError::InvalidWebAssembly {
+            message: "invalid memory resizable limits flags",
+            offset,
+        });
+    }
+    let memory64 = flags & 0x4 != 0;
+    let minimum = read_memory_limit(reader, memory64)?;
+    let maximum = if flags & 0x1 != 0 {
+        Some(read_memory_limit(reader, memory64)?)
+    } else {
+        None
+    };
+    Ok(Memory {
+        minimum,
+        maximum,
+        shared: flags & 0x2 != 0,
+        memory64,
+    })
+}
+
+/// Read a limit of a memory type, which is a `u64` for a 64-bit memory.
+fn read_memory_limit(reader: &mut BinaryReader, memory64: bool) -> WasmResult<u32> {
+    if !memory64 {
+        return Ok(reader.read_var_u32()?);
+    }
+    let limit = read_var_u64(reader)?;
+    u32::try_from(limit).map_err(|_| WasmError::Unsupported("memories of 2^32 pages or more"))
+}
+
+/// Check that a section read directly has no trailing bytes.
+fn expect_section_end(reader: &BinaryReader) -> WasmResult<()> {
+    if reader.eof() {
+        Ok(())
+    } else {
+        Err(WasmError::InvalidWebAssembly {
+            message: "unexpected content at the end of the section",
+            offset: reader.original_position(),
+        })
+    }
+}
+
 /// Parses the Global section of the wasm module.
 pub fn parse_global_section(
     globals: GlobalSectionReader,
@@ -338,3 +418,35 @@ pub fn parse_data_section<'data>(
 
     Ok(())
 }
+
+/// Parses the "name" custom section of the wasm module.
+pub fn parse_name_section<'data>(
+    names: NameSectionReader<'data>,
+    environ: &mut dyn ModuleEnvironment<'data>,
+) -> WasmResult<()> {
+    for subsection in names {
+        match subsection? {
+            Name::Module(_) => {}
+            Name::Function(function_names) => {
+                let mut map = function_names.get_map()?;
+                for _ in 0..map.get_count() {
+                    let Naming { index, name } = map.read()?;
+                    environ.declare_func_name(FuncIndex::from_u32(index), name);
+                }
+            }
+            Name::Local(local_names) => {
+                let mut functions = local_names.get_function_local_reader()?;
+                for _ in 0..functions.get_count() {
+                    let function = functions.read()?;
+                    let func_index = FuncIndex::from_u32(function.func_index);
+                    let mut map = function.get_map()?;
+                    for _ in 0..map.get_count() {
+                        let Naming { index, name } = map.read()?;
+                        environ.declare_local_name(func_index, index, name);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
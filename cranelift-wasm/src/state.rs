@@ -6,6 +6,7 @@
 use super::{HashMap, Occupied, Vacant};
 use crate::environ::{FuncEnvironment, GlobalVariable, WasmResult};
 use crate::translation_utils::{FuncIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex};
+use cranelift_codegen::ir::types::{I32, I64};
 use cranelift_codegen::ir::{self, Ebb, Inst, Value};
 use std::vec::Vec;
 
@@ -310,7 +311,15 @@ impl TranslationState {
         let index = MemoryIndex::from_u32(index);
         match self.heaps.entry(index) {
             Occupied(entry) => Ok(*entry.get()),
-            Vacant(entry) => Ok(*entry.insert(environ.make_heap(func, index)?)),
+            Vacant(entry) => {
+                let index_type = if environ.is_memory64(index) { I64 } else { I32 };
+                let heap = environ.make_heap(func, index, index_type)?;
+                debug_assert!(
+                    func.heaps[heap].index_type.bits() >= index_type.bits(),
+                    "the heap of a memory can't be indexed by a narrower type than its addresses"
+                );
+                Ok(*entry.insert(heap))
+            }
         }
     }
 
@@ -5,7 +5,7 @@ use cranelift_codegen::entity::entity_impl;
 use cranelift_codegen::ir;
 #[cfg(feature = "enable-serde")]
 use serde::{Deserialize, Serialize};
-use wasmparser;
+use wasmparser::{self, BinaryReader};
 
 /// Index type of a function (imported or defined) inside the WebAssembly module.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
@@ -110,6 +110,8 @@ pub struct Memory {
     pub maximum: Option<u32>,
     /// Whether the memory may be shared between multiple threads.
     pub shared: bool,
+    /// Whether the memory is indexed with 64-bit addresses, as proposed by memory64.
+    pub memory64: bool,
 }
 
 /// Helper function translating wasmparser types to Cranelift types when possible.
@@ -178,3 +180,24 @@ pub fn get_vmctx_value_label() -> ir::ValueLabel {
     const VMCTX_LABEL: u32 = 0xffff_fffe;
     ir::ValueLabel::from_u32(VMCTX_LABEL)
 }
+
+/// Read a `varuint64`, which wasmparser can't read, as used by the memory64 proposal.
+pub(crate) fn read_var_u64(reader: &mut BinaryReader) -> WasmResult<u64> {
+    let offset = reader.original_position();
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        if shift == 63 && byte & 0x7e != 0 {
+            return Err(WasmError::InvalidWebAssembly {
+                message: "invalid var_u64",
+                offset,
+            });
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
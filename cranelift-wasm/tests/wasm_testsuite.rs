@@ -1,8 +1,9 @@
+use cranelift_codegen::ir::ExternalName;
 use cranelift_codegen::isa;
 use cranelift_codegen::print_errors::pretty_verifier_error;
 use cranelift_codegen::settings::{self, Flags};
 use cranelift_codegen::verifier;
-use cranelift_wasm::{translate_module, DummyEnvironment, ReturnMode};
+use cranelift_wasm::{translate_module, DummyEnvironment, FuncIndex, ReturnMode};
 use std::fs;
 use std::fs::File;
 use std::io;
@@ -10,7 +11,7 @@ use std::io::prelude::*;
 use std::path::Path;
 use std::str::FromStr;
 use target_lexicon::triple;
-use wabt::wat2wasm;
+use wabt::{wat2wasm, Wat2Wasm};
 
 #[test]
 fn testsuite() {
@@ -45,6 +46,44 @@ fn use_fallthrough_return() {
     );
 }
 
+#[test]
+fn use_name_section() {
+    let wasm = Wat2Wasm::new()
+        .write_debug_names(true)
+        .convert(
+            r#"
+            (module
+              (func $answer (param $x i32) (result i32)
+                (get_local $x))
+              (func (result i32)
+                (call $answer (i32.const 42)))
+              (func $a.b)
+              (func $a_b))
+            "#,
+        )
+        .unwrap();
+    let triple = triple!("riscv64");
+    let isa = isa::lookup(triple)
+        .unwrap()
+        .finish(Flags::new(settings::builder()));
+    let mut dummy_environ =
+        DummyEnvironment::new(isa.frontend_config(), ReturnMode::NormalReturns, false);
+
+    translate_module(wasm.as_ref(), &mut dummy_environ).unwrap();
+
+    let info = &dummy_environ.info;
+    assert_eq!(info.function_names[&FuncIndex::from_u32(0)], "answer");
+    assert_eq!(info.local_names[&FuncIndex::from_u32(0)][0].1, "x");
+    let bodies: Vec<_> = info.function_bodies.values().collect();
+    assert_eq!(bodies[0].name, ExternalName::testcase("answer"));
+    assert_eq!(bodies[1].name, ExternalName::user(0, 1));
+    let callee = bodies[1].dfg.ext_funcs.values().next().unwrap();
+    assert_eq!(callee.name, ExternalName::testcase("answer"));
+    // `a.b` and `a_b` would both be named `a_b`, so they are named after their index instead.
+    assert_eq!(bodies[2].name, ExternalName::user(0, 2));
+    assert_eq!(bodies[3].name, ExternalName::user(0, 3));
+}
+
 fn read_file(path: &Path) -> io::Result<Vec<u8>> {
     let mut buf: Vec<u8> = Vec::new();
     let mut file = File::open(path)?;
@@ -0,0 +1,81 @@
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+extern crate cranelift_interpreter;
+extern crate cranelift_reader;
+#[macro_use]
+extern crate target_lexicon;
+
+use cranelift_codegen::ir::Function;
+use cranelift_codegen::{isa, settings, Context};
+use cranelift_interpreter::{
+    DataValue, Interpreter, InterpreterError, InterpreterResult, NoMemory,
+};
+use std::str::{self, FromStr};
+
+/// Interpret `func` with `args`.
+fn interpret(func: &Function, args: &[DataValue]) -> InterpreterResult<Vec<DataValue>> {
+    let mut memory = NoMemory;
+    let mut interp = Interpreter::new(&mut memory);
+    interp.add_function(func);
+    interp.set_step_limit(Some(100_000));
+    interp.call(func, args)
+}
+
+// Check that the target-independent optimizations don't change the results of functions when
+// they are called with all their arguments set to zero.
+fuzz_target!(|data: &[u8]| {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let test_file = match cranelift_reader::parse_test(text, None, None) {
+        Ok(test_file) => test_file,
+        Err(_) => return,
+    };
+
+    let flags = settings::Flags::new(settings::builder());
+    let isa = isa::lookup(triple!("x86_64")).unwrap().finish(flags);
+
+    for (func, _) in test_file.functions {
+        let args: Option<Vec<DataValue>> = func
+            .signature
+            .params
+            .iter()
+            .map(|param| DataValue::from_bits(param.value_type, 0))
+            .collect();
+        let args = match args {
+            Some(args) => args,
+            None => continue,
+        };
+        let mut ctx = Context::for_function(func);
+        if ctx.verify(&*isa).is_err() {
+            continue;
+        }
+        let expected = match interpret(&ctx.func, &args) {
+            Err(InterpreterError::Unsupported(_)) | Err(InterpreterError::StepLimitExceeded) => {
+                continue
+            }
+            outcome => outcome,
+        };
+
+        ctx.compute_cfg();
+        ctx.preopt(&*isa).unwrap();
+        ctx.flowgraph();
+        ctx.compute_loop_analysis();
+        ctx.licm(&*isa).unwrap();
+        ctx.simple_gvn(&*isa).unwrap();
+        ctx.compute_domtree();
+        ctx.eliminate_unreachable_code(&*isa).unwrap();
+        ctx.dce(&*isa).unwrap();
+
+        assert_eq!(
+            interpret(&ctx.func, &args),
+            expected,
+            "optimized function:\n{}",
+            ctx.func.display(&*isa)
+        );
+    }
+});
@@ -40,8 +40,8 @@ echo git push origin v$version
 for crate in \
     entity bforest codegen/meta codegen frontend native \
     preopt \
-    reader wasm module \
-    faerie umbrella simplejit
+    reader interpreter wasm module \
+    faerie coff umbrella simplejit
 do
     echo cargo publish --manifest-path "cranelift-$crate/Cargo.toml"
 
@@ -9,11 +9,11 @@
 
 use crate::disasm::{print_all, PrintRelocs, PrintTraps};
 use crate::utils::{parse_sets_and_triple, read_to_end};
+use cranelift_codegen::ir::DisplayFunctionAnnotations;
 use cranelift_codegen::print_errors::{pretty_error, pretty_verifier_error};
 use cranelift_codegen::settings::FlagsOrIsa;
 use cranelift_codegen::timing;
 use cranelift_codegen::Context;
-use cranelift_codegen::ir::DisplayFunctionAnnotations;
 use cranelift_entity::EntityRef;
 use cranelift_wasm::{translate_module, DummyEnvironment, FuncIndex, ReturnMode};
 use std::path::Path;
@@ -113,7 +113,8 @@ fn handle_module(
     };
 
     let debug_info = flag_calc_value_ranges;
-    let mut dummy_environ = DummyEnvironment::new(isa.frontend_config(), ReturnMode::NormalReturns, debug_info);
+    let mut dummy_environ =
+        DummyEnvironment::new(isa.frontend_config(), ReturnMode::NormalReturns, debug_info);
     translate_module(&module_binary, &mut dummy_environ).map_err(|e| e.to_string())?;
 
     let _ = terminal.fg(term::color::GREEN);
@@ -141,6 +142,22 @@ fn handle_module(
             {
                 println!("; Exported as \"{}\"", export_name);
             }
+            if let Some(name) = dummy_environ
+                .info
+                .function_names
+                .get(&FuncIndex::new(func_index))
+            {
+                println!("; Named \"{}\"", name);
+            }
+            if let Some(locals) = dummy_environ
+                .info
+                .local_names
+                .get(&FuncIndex::new(func_index))
+            {
+                for (label, name) in locals {
+                    println!("; Local {} named \"{}\"", label, name);
+                }
+            }
             println!("{}", context.func.display(None));
             vprintln!(flag_verbose, "");
         }
@@ -194,7 +211,10 @@ fn handle_module(
             }
 
             if flag_print_disasm {
-                saved_sizes = Some((code_info.code_size, code_info.jumptables_size + code_info.rodata_size));
+                saved_sizes = Some((
+                    code_info.code_size,
+                    code_info.jumptables_size + code_info.rodata_size,
+                ));
             }
         }
 
@@ -210,15 +230,38 @@ fn handle_module(
             {
                 println!("; Exported as \"{}\"", export_name);
             }
+            if let Some(name) = dummy_environ
+                .info
+                .function_names
+                .get(&FuncIndex::new(func_index))
+            {
+                println!("; Named \"{}\"", name);
+            }
+            if let Some(locals) = dummy_environ
+                .info
+                .local_names
+                .get(&FuncIndex::new(func_index))
+            {
+                for (label, name) in locals {
+                    println!("; Local {} named \"{}\"", label, name);
+                }
+            }
             let value_ranges = if flag_calc_value_ranges {
-                Some(context.build_value_labels_ranges(isa).expect("value location ranges"))
+                Some(
+                    context
+                        .build_value_labels_ranges(isa)
+                        .expect("value location ranges"),
+                )
             } else {
                 None
             };
-            println!("{}", context.func.display_with(DisplayFunctionAnnotations {
-                isa: fisa.isa,
-                value_ranges: value_ranges.as_ref(),
-            }));
+            println!(
+                "{}",
+                context.func.display_with(DisplayFunctionAnnotations {
+                    isa: fisa.isa,
+                    value_ranges: value_ranges.as_ref(),
+                })
+            );
             vprintln!(flag_verbose, "");
         }
 